@@ -0,0 +1,172 @@
+use std::{
+  env, fmt,
+  fs::OpenOptions,
+  io,
+  os::fd::AsRawFd,
+  sync::atomic::{AtomicBool, Ordering},
+  time::Duration,
+};
+
+use anyhow::bail;
+use log::info;
+use tokio::{
+  process::{Child, Command},
+  signal::unix::{SignalKind, signal},
+};
+
+use crate::client_lock::ClientLock;
+
+/// Set in the environment of the re-executed client, which keeps the tunnel in the background
+const BACKGROUND_ENV: &str = "GPCLIENT_BACKGROUND";
+const CONNECTED_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Whether the background client has detached from the terminal
+static DETACHED: AtomicBool = AtomicBool::new(false);
+
+/// The background client exited before the tunnel was connected, with the exit code
+#[derive(Debug)]
+pub(crate) struct BackgroundExited(pub(crate) i32);
+
+impl fmt::Display for BackgroundExited {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "The background client exited with code {}", self.0)
+  }
+}
+
+impl std::error::Error for BackgroundExited {}
+
+/// Whether this is the re-executed client of `--background`
+pub(crate) fn is_background_client() -> bool {
+  env::var_os(BACKGROUND_ENV).is_some()
+}
+
+/// Re-run the command in a child process, which authenticates in the terminal and keeps the tunnel
+/// after this process exits. Return the PID of the child once its tunnel is connected.
+pub(crate) async fn spawn_background_client(lock: &ClientLock) -> anyhow::Result<u32> {
+  let mut command = Command::new(env::current_exe()?);
+  command.args(env::args_os().skip(1)).env(BACKGROUND_ENV, "1");
+  // Run in a new session without the controlling terminal, so that the client is not stopped or
+  // killed with the terminal. The terminal stdio is still inherited for the authentication.
+  unsafe {
+    command.pre_exec(|| match libc::setsid() {
+      -1 => Err(io::Error::last_os_error()),
+      _ => Ok(()),
+    });
+  }
+
+  wait_for_connected(command.spawn()?, lock, CONNECTED_POLL_INTERVAL).await
+}
+
+/// Detach the connected background client from the terminal by redirecting its standard streams to
+/// `/dev/null`, the logs go to the `--log-file`
+pub(crate) fn detach_stdio() -> io::Result<()> {
+  let null = OpenOptions::new().read(true).write(true).open("/dev/null")?;
+  for fd in 0..=2 {
+    if unsafe { libc::dup2(null.as_raw_fd(), fd) } == -1 {
+      return Err(io::Error::last_os_error());
+    }
+  }
+
+  DETACHED.store(true, Ordering::SeqCst);
+  Ok(())
+}
+
+/// Fail instead of prompting once the background client is detached from the terminal, e.g., for the
+/// MFA challenge of the re-login by `--auto-reconnect`
+pub(crate) fn ensure_attached(prompt: &str) -> anyhow::Result<()> {
+  if DETACHED.load(Ordering::SeqCst) {
+    bail!("Cannot prompt for `{}` in the background client", prompt);
+  }
+
+  Ok(())
+}
+
+/// The client writes its PID to the lock file once the tunnel is connected
+async fn wait_for_connected(mut child: Child, lock: &ClientLock, poll_interval: Duration) -> anyhow::Result<u32> {
+  let pid = child
+    .id()
+    .ok_or_else(|| anyhow::anyhow!("The background client exited immediately"))?;
+
+  loop {
+    tokio::select! {
+      status = child.wait() => {
+        let code = status?.code().unwrap_or(1);
+        return Err(BackgroundExited(code).into());
+      }
+      _ = tokio::time::sleep(poll_interval) => {
+        if lock.owner() == Some(pid) {
+          return Ok(pid);
+        }
+      }
+    }
+  }
+}
+
+/// Keep the background client running when the terminal it was started from is closed
+pub(crate) fn ignore_hangup() -> anyhow::Result<()> {
+  let mut hangup = signal(SignalKind::hangup())?;
+  tokio::spawn(async move {
+    while hangup.recv().await.is_some() {
+      info!("Ignoring the hangup signal in the background");
+    }
+  });
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+  fn spawn_sh(script: &str) -> Child {
+    Command::new("/bin/sh").arg("-c").arg(script).spawn().unwrap()
+  }
+
+  #[tokio::test]
+  async fn connected_client_is_detached() {
+    let dir = tempfile::tempdir().unwrap();
+    let lock = ClientLock::new(dir.path().join("gpclient.lock"));
+
+    // Connect after a while, as the client does once the tunnel is up
+    let script = format!("sleep 0.1; echo $$ > {}; sleep 5", lock.path().display());
+    let child = spawn_sh(&script);
+    let child_pid = child.id().unwrap();
+
+    let pid = wait_for_connected(child, &lock, POLL_INTERVAL).await.unwrap();
+    assert_eq!(pid, child_pid);
+    assert_eq!(lock.owner(), Some(pid));
+
+    // The client keeps running after this process stops waiting
+    let status = std::process::Command::new("kill")
+      .arg(pid.to_string())
+      .status()
+      .unwrap();
+    assert!(status.success());
+  }
+
+  #[test]
+  fn detached_client_does_not_prompt() {
+    assert!(ensure_attached("Enter the OTP").is_ok());
+
+    DETACHED.store(true, Ordering::SeqCst);
+    let err = ensure_attached("Enter the OTP").unwrap_err();
+    DETACHED.store(false, Ordering::SeqCst);
+    assert_eq!(
+      err.to_string(),
+      "Cannot prompt for `Enter the OTP` in the background client"
+    );
+  }
+
+  #[tokio::test]
+  async fn failed_client_exit_code_is_returned() {
+    let dir = tempfile::tempdir().unwrap();
+    let lock = ClientLock::new(dir.path().join("gpclient.lock"));
+
+    let err = wait_for_connected(spawn_sh("exit 3"), &lock, POLL_INTERVAL)
+      .await
+      .unwrap_err();
+    assert_eq!(err.downcast_ref::<BackgroundExited>().map(|exited| exited.0), Some(3));
+  }
+}
@@ -0,0 +1,208 @@
+use std::{
+  fmt,
+  sync::Arc,
+  time::{Duration, Instant},
+};
+
+use log::info;
+use tokio::sync::oneshot;
+
+use crate::connect::{Tunnel, run_tunnel};
+
+/// The throughput of the benchmark download
+#[derive(Debug)]
+pub(crate) struct BenchmarkReport {
+  bytes: u64,
+  elapsed: Duration,
+}
+
+impl BenchmarkReport {
+  pub(crate) fn mb_per_sec(&self) -> f64 {
+    let secs = self.elapsed.as_secs_f64();
+    if secs == 0.0 {
+      return 0.0;
+    }
+
+    self.bytes as f64 / 1_000_000.0 / secs
+  }
+}
+
+impl fmt::Display for BenchmarkReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "Downloaded {} bytes in {:.2}s, throughput: {:.2} MB/s",
+      self.bytes,
+      self.elapsed.as_secs_f64(),
+      self.mb_per_sec()
+    )
+  }
+}
+
+/// The benchmark failures, the download failure is reported separately from the connect failure
+#[derive(Debug)]
+pub(crate) enum BenchmarkError {
+  Connect(anyhow::Error),
+  Download(anyhow::Error),
+}
+
+impl fmt::Display for BenchmarkError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      BenchmarkError::Connect(err) => write!(f, "Benchmark aborted, failed to connect: {}", err),
+      BenchmarkError::Download(err) => write!(f, "Benchmark download failed: {}", err),
+    }
+  }
+}
+
+impl std::error::Error for BenchmarkError {}
+
+/// Connect the tunnel, download the file through it, then disconnect
+pub(crate) async fn run_benchmark<T: Tunnel + Send + Sync + 'static>(
+  tunnel: Arc<T>,
+  url: &str,
+  on_connected: impl FnOnce() + Send + Sync + 'static,
+) -> Result<BenchmarkReport, BenchmarkError> {
+  let (connected_tx, connected_rx) = oneshot::channel();
+
+  let tunnel_clone = Arc::clone(&tunnel);
+  let connection = tokio::task::spawn_blocking(move || {
+    run_tunnel(tunnel_clone.as_ref(), move || {
+      on_connected();
+      let _ = connected_tx.send(());
+    })
+  });
+
+  // The sender is dropped without sending if the tunnel exits before connected
+  if connected_rx.await.is_err() {
+    let err = match connection.await {
+      Ok(Err(err)) => err,
+      Ok(Ok(_)) => anyhow::anyhow!("The tunnel exited before connected"),
+      Err(err) => anyhow::anyhow!(err),
+    };
+    return Err(BenchmarkError::Connect(err));
+  }
+
+  info!("Connected, downloading {} through the tunnel", url);
+  let result = download(url).await.map_err(BenchmarkError::Download);
+
+  tunnel.disconnect();
+  let _ = connection.await;
+
+  result
+}
+
+async fn download(url: &str) -> anyhow::Result<BenchmarkReport> {
+  let start = Instant::now();
+  let mut res = reqwest::get(url).await?.error_for_status()?;
+
+  let mut bytes = 0;
+  while let Some(chunk) = res.chunk().await? {
+    bytes += chunk.len() as u64;
+  }
+
+  Ok(BenchmarkReport {
+    bytes,
+    elapsed: start.elapsed(),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{
+    Mutex,
+    mpsc::{self, Receiver, Sender},
+  };
+
+  use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+  };
+
+  use super::*;
+
+  struct MockTunnel {
+    connects: bool,
+    disconnect_tx: Sender<()>,
+    disconnect_rx: Mutex<Receiver<()>>,
+  }
+
+  impl MockTunnel {
+    fn new(connects: bool) -> Arc<Self> {
+      let (disconnect_tx, disconnect_rx) = mpsc::channel();
+      Arc::new(Self {
+        connects,
+        disconnect_tx,
+        disconnect_rx: Mutex::new(disconnect_rx),
+      })
+    }
+  }
+
+  impl Tunnel for MockTunnel {
+    // Emulate the openconnect mainloop, which blocks until disconnected
+    fn connect(&self, on_connected: Box<dyn FnOnce() + Send + Sync>) -> i32 {
+      if !self.connects {
+        return 1;
+      }
+
+      on_connected();
+      self.disconnect_rx.lock().unwrap().recv().unwrap();
+      0
+    }
+
+    fn disconnect(&self) {
+      self.disconnect_tx.send(()).unwrap();
+    }
+
+    fn cleanup_routes(&self) -> std::io::Result<()> {
+      Ok(())
+    }
+  }
+
+  async fn start_http_server(status: &'static str, body: &'static [u8]) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+      while let Ok((mut stream, _)) = listener.accept().await {
+        let mut buf = [0; 1024];
+        let _ = stream.read(&mut buf).await;
+        let header = format!(
+          "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+          status,
+          body.len()
+        );
+        let _ = stream.write_all(header.as_bytes()).await;
+        let _ = stream.write_all(body).await;
+      }
+    });
+
+    format!("http://{}/testfile", addr)
+  }
+
+  #[tokio::test(flavor = "multi_thread")]
+  async fn benchmark_downloads_and_disconnects() {
+    let url = start_http_server("200 OK", &[0; 4096]).await;
+    let report = run_benchmark(MockTunnel::new(true), &url, || {}).await.unwrap();
+
+    assert_eq!(report.bytes, 4096);
+    assert!(report.to_string().contains("MB/s"));
+  }
+
+  #[tokio::test(flavor = "multi_thread")]
+  async fn benchmark_reports_download_failure() {
+    let url = start_http_server("500 Internal Server Error", b"").await;
+    let err = run_benchmark(MockTunnel::new(true), &url, || {}).await.unwrap_err();
+
+    assert!(matches!(err, BenchmarkError::Download(_)));
+  }
+
+  #[tokio::test(flavor = "multi_thread")]
+  async fn benchmark_reports_connect_failure() {
+    let err = run_benchmark(MockTunnel::new(false), "http://127.0.0.1:1/", || {})
+      .await
+      .unwrap_err();
+
+    assert!(matches!(err, BenchmarkError::Connect(_)));
+  }
+}
@@ -1,4 +1,4 @@
-use std::{env::temp_dir, fs::File, str::FromStr};
+use std::{env::temp_dir, fs::File, sync::LazyLock};
 
 use anyhow::bail;
 use clap::{Parser, Subcommand};
@@ -7,21 +7,35 @@ use gpapi::{
   utils::openssl,
 };
 use log::info;
-use sysinfo::{Pid, System};
 use tempfile::NamedTempFile;
-use tokio::fs;
 
 use crate::{
   GP_CLIENT_LOCK_FILE,
+  background::{BackgroundExited, is_background_client, spawn_background_client},
+  client_lock::ClientLock,
   connect::{ConnectArgs, ConnectHandler},
   diagnose::{DiagnoseArgs, DiagnoseHandler},
   disconnect::{DisconnectArgs, DisconnectHandler},
+  health_check::{HEALTH_CHECK_EXIT_CODE, HealthCheckFailed},
   hip::{HipArgs, HipHandler},
+  idle_monitor::{IDLE_TIMEOUT_EXIT_CODE, IdleTimeout},
   launch_gui::{LaunchGuiArgs, LaunchGuiHandler},
+  log_file::{DEFAULT_ROTATED_FILES, RotatingFileWriter},
+  session_ttl::{SESSION_TTL_EXIT_CODE, SessionExpired},
 };
 
 const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), " (", compile_time::date_str!(), ")");
 
+/// The `--version` output, with the libopenconnect resolved when gpclient starts
+static LONG_VERSION: LazyLock<String> = LazyLock::new(|| {
+  let linked = openconnect::linked_openconnect_version();
+  format!(
+    "{}\nlibopenconnect: {}",
+    VERSION,
+    linked.as_deref().unwrap_or("<not-detected>")
+  )
+});
+
 pub(crate) struct SharedArgs<'a> {
   pub(crate) fix_openssl: bool,
   pub(crate) ignore_tls_errors: bool,
@@ -45,6 +59,7 @@ enum CliCommand {
 #[derive(Parser)]
 #[command(
   version = VERSION,
+  long_version = LONG_VERSION.as_str(),
   author,
   about = "The GlobalProtect VPN client, based on OpenConnect, supports the SSO authentication method.",
   help_template = "\
@@ -72,6 +87,15 @@ struct Cli {
   #[arg(long, help = "Ignore the TLS errors")]
   ignore_tls_errors: bool,
 
+  #[arg(long, help = "Write the logs to the specified file instead of the standard error")]
+  log_file: Option<String>,
+  #[arg(
+    long,
+    requires = "log_file",
+    help = "Rotate the log file when it exceeds the size in bytes, the last 2 rotated files are kept"
+  )]
+  log_file_max_size: Option<u64>,
+
   #[command(flatten)]
   verbose: InfoLevelVerbosity,
 }
@@ -87,25 +111,6 @@ impl Args for Cli {
 }
 
 impl Cli {
-  async fn is_running(&self) -> bool {
-    let Ok(c) = fs::read_to_string(GP_CLIENT_LOCK_FILE).await else {
-      return false;
-    };
-
-    let Ok(pid) = Pid::from_str(c.trim()) else {
-      return false;
-    };
-
-    let s = System::new_all();
-    let Some(p) = s.process(pid) else {
-      return false;
-    };
-
-    p.exe()
-      .map(|exe| exe.to_string_lossy().contains("gpclient"))
-      .unwrap_or(false)
-  }
-
   fn fix_openssl(&self) -> anyhow::Result<Option<NamedTempFile>> {
     if self.fix_openssl {
       let file = openssl::fix_openssl_env()?;
@@ -115,10 +120,30 @@ impl Cli {
     Ok(None)
   }
 
+  /// Hand the connection over to a background client, which keeps the tunnel after this one exits
+  async fn connect_in_background(&self) -> anyhow::Result<()> {
+    if self.log_file.is_none() {
+      bail!("The `--background` option requires the `--log-file` option");
+    }
+
+    let pid = spawn_background_client(&ClientLock::new(GP_CLIENT_LOCK_FILE)).await?;
+    println!(
+      "Connected in the background (PID {}), run `gpclient disconnect` to disconnect",
+      pid
+    );
+
+    Ok(())
+  }
+
   async fn run(&self) -> anyhow::Result<()> {
-    // check if an instance is running
-    if !matches!(self.command, CliCommand::Disconnect(_)) && self.is_running().await {
-      bail!("Another instance of the client is already running");
+    // check if an instance is running, the validation only and dry-run modes do not need the lock
+    let skip_lock = match &self.command {
+      CliCommand::Disconnect(_) => true,
+      CliCommand::Connect(args) => args.validate_only() || args.dry_run(),
+      _ => false,
+    };
+    if !skip_lock {
+      ClientLock::new(GP_CLIENT_LOCK_FILE).check()?;
     }
 
     // The temp file will be dropped automatically when the file handle is dropped
@@ -135,11 +160,12 @@ impl Cli {
     }
 
     match &self.command {
+      CliCommand::Connect(args) if args.background() && !is_background_client() => self.connect_in_background().await,
       CliCommand::Connect(args) => ConnectHandler::new(args, &shared_args).handle().await,
       CliCommand::Disconnect(args) => DisconnectHandler::new(args).handle().await,
       CliCommand::LaunchGui(args) => LaunchGuiHandler::new(args).handle().await,
       CliCommand::Hip(args) => HipHandler::new(args).handle().await,
-      CliCommand::Diagnose(args) => DiagnoseHandler::new(args).handle().await,
+      CliCommand::Diagnose(args) => DiagnoseHandler::new(args, &shared_args).handle().await,
     }
   }
 }
@@ -159,6 +185,18 @@ fn init_logger(cli: &Cli) {
     }
   }
 
+  // The log file is written by the background client only, the rotation is not shared between processes
+  let background_parent =
+    matches!(&cli.command, CliCommand::Connect(args) if args.background() && !is_background_client());
+  if let Some(log_file) = cli.log_file.as_deref().filter(|_| !background_parent) {
+    match RotatingFileWriter::new(log_file, cli.log_file_max_size, DEFAULT_ROTATED_FILES) {
+      Ok(writer) => {
+        builder.target(env_logger::Target::Pipe(Box::new(writer)));
+      }
+      Err(err) => eprintln!("Failed to open the log file {}: {}", log_file, err),
+    }
+  }
+
   builder.init();
 }
 
@@ -170,7 +208,22 @@ pub(crate) async fn run() {
   info!("gpclient started: {}", VERSION);
 
   if let Err(err) = cli.run().await {
+    // The background client has reported the error in the terminal
+    if let Some(exited) = err.downcast_ref::<BackgroundExited>() {
+      std::process::exit(exited.0);
+    }
+
+    let exit_code = if err.is::<IdleTimeout>() {
+      IDLE_TIMEOUT_EXIT_CODE
+    } else if err.is::<SessionExpired>() {
+      SESSION_TTL_EXIT_CODE
+    } else if err.is::<HealthCheckFailed>() {
+      HEALTH_CHECK_EXIT_CODE
+    } else {
+      1
+    };
+
     handle_error(err, &cli);
-    std::process::exit(1);
+    std::process::exit(exit_code);
   }
 }
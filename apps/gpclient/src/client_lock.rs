@@ -0,0 +1,234 @@
+use std::{
+  env, fmt,
+  fs::{self, OpenOptions},
+  io::{self, Write},
+  path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+use sysinfo::{Pid, System};
+
+/// Another live client holds the lock
+#[derive(Debug)]
+pub(crate) struct LockHeld(pub(crate) u32);
+
+impl fmt::Display for LockHeld {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "Another instance of the client is already running (PID {}), run `gpclient disconnect` to stop it",
+      self.0
+    )
+  }
+}
+
+impl std::error::Error for LockHeld {}
+
+/// The lock file of the CLI client, which holds the PID of the connected client
+pub(crate) struct ClientLock {
+  path: PathBuf,
+}
+
+impl ClientLock {
+  pub(crate) fn new<P: Into<PathBuf>>(path: P) -> Self {
+    Self { path: path.into() }
+  }
+
+  pub(crate) fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// The PID recorded in the lock file, if any
+  pub(crate) fn owner(&self) -> Option<u32> {
+    fs::read_to_string(&self.path).ok()?.trim().parse().ok()
+  }
+
+  /// Fail if a live client holds the lock, and reclaim the lock left by a client that is gone,
+  /// e.g., crashed before removing it
+  pub(crate) fn check(&self) -> Result<(), LockHeld> {
+    let Ok(content) = fs::read_to_string(&self.path) else {
+      return Ok(());
+    };
+
+    match content.trim().parse::<u32>() {
+      Ok(pid) if is_client_alive(pid) => return Err(LockHeld(pid)),
+      Ok(pid) => warn!("Reclaiming the lock file of the exited client (PID {})", pid),
+      Err(_) => warn!("Reclaiming the lock file {} with invalid content", self.path.display()),
+    }
+
+    if let Err(err) = self.remove() {
+      warn!("Failed to remove the stale lock file: {}", err);
+    }
+
+    Ok(())
+  }
+
+  /// Take the lock for the client of the PID. The file is created exclusively, so that only one of the
+  /// clients connecting at the same time gets the lock, and the lock of an exited client is reclaimed.
+  pub(crate) fn write(&self, pid: u32) -> io::Result<()> {
+    match self.create(pid) {
+      Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+      result => return result,
+    }
+
+    match self.owner() {
+      // Taken again by the reconnection
+      Some(owner) if owner == pid => return Ok(()),
+      Some(owner) if is_client_alive(owner) => return Err(io::Error::other(LockHeld(owner))),
+      Some(owner) => warn!("Reclaiming the lock file of the exited client (PID {})", owner),
+      // Created by another client, which has not written its PID yet
+      None => {
+        return Err(io::Error::other(format!(
+          "The lock file {} is being taken by another client",
+          self.path.display()
+        )));
+      }
+    }
+
+    self.remove()?;
+    self.create(pid)
+  }
+
+  fn create(&self, pid: u32) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(&self.path)?;
+    file.write_all(pid.to_string().as_bytes())?;
+    info!("Wrote PID {} to {}", pid, self.path.display());
+
+    Ok(())
+  }
+
+  /// Release the lock taken by this process, the lock of another client is kept
+  pub(crate) fn release(&self) -> io::Result<()> {
+    match self.owner() {
+      Some(owner) if owner != std::process::id() => {
+        warn!("The lock file is held by another client (PID {}), keeping it", owner);
+        Ok(())
+      }
+      _ => self.remove(),
+    }
+  }
+
+  /// Remove the lock file, it is not an error if the lock is not held
+  pub(crate) fn remove(&self) -> io::Result<()> {
+    match fs::remove_file(&self.path) {
+      Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+      _ => Ok(()),
+    }
+  }
+}
+
+/// The PID may have been reused by another process since the client exited, so the process must run
+/// the executable of this client
+fn is_client_alive(pid: u32) -> bool {
+  let Ok(current_exe) = env::current_exe().and_then(fs::canonicalize) else {
+    return false;
+  };
+
+  let s = System::new_all();
+  let Some(p) = s.process(Pid::from_u32(pid)) else {
+    return false;
+  };
+
+  p.exe().and_then(canonical_exe).is_some_and(|exe| exe == current_exe)
+}
+
+/// The executable replaced by an upgrade is reported with the ` (deleted)` suffix
+fn canonical_exe(exe: &Path) -> Option<PathBuf> {
+  let exe = exe.to_str()?;
+  fs::canonicalize(exe.strip_suffix(" (deleted)").unwrap_or(exe)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::process::Command;
+
+  use super::*;
+
+  fn lock_in(dir: &tempfile::TempDir) -> ClientLock {
+    ClientLock::new(dir.path().join("gpclient.lock"))
+  }
+
+  #[test]
+  fn missing_lock_is_free() {
+    let dir = tempfile::tempdir().unwrap();
+    let lock = lock_in(&dir);
+
+    assert!(lock.check().is_ok());
+    assert!(lock.remove().is_ok());
+  }
+
+  #[test]
+  fn live_client_holds_the_lock() {
+    let dir = tempfile::tempdir().unwrap();
+    let lock = lock_in(&dir);
+    // The test binary is named after the crate, i.e., gpclient-<hash>
+    let pid = std::process::id();
+    lock.write(pid).unwrap();
+
+    let err = lock.check().unwrap_err();
+    assert_eq!(err.0, pid);
+    assert!(err.to_string().contains(&format!("PID {}", pid)));
+    assert!(lock.path().exists());
+  }
+
+  #[test]
+  fn stale_lock_is_reclaimed() {
+    let dir = tempfile::tempdir().unwrap();
+    let lock = lock_in(&dir);
+
+    // No such process
+    lock.write(u32::MAX).unwrap();
+    assert!(lock.check().is_ok());
+    assert!(!lock.path().exists());
+
+    // The PID reused by another process
+    let mut other = Command::new("sleep").arg("10").spawn().unwrap();
+    lock.write(other.id()).unwrap();
+    assert!(lock.check().is_ok());
+    assert!(!lock.path().exists());
+    other.kill().unwrap();
+    other.wait().unwrap();
+
+    fs::write(lock.path(), "not-a-pid").unwrap();
+    assert!(lock.check().is_ok());
+    assert!(!lock.path().exists());
+  }
+
+  #[test]
+  fn lock_is_taken_by_one_client() {
+    let dir = tempfile::tempdir().unwrap();
+    let lock = lock_in(&dir);
+    let pid = std::process::id();
+    lock.write(pid).unwrap();
+    // Taken again by the reconnection
+    lock.write(pid).unwrap();
+
+    let err = lock.write(u32::MAX).unwrap_err();
+    assert!(err.to_string().contains(&format!("PID {}", pid)), "{}", err);
+    assert_eq!(lock.owner(), Some(pid));
+
+    // The lock of an exited client is reclaimed
+    fs::write(lock.path(), u32::MAX.to_string()).unwrap();
+    lock.write(pid).unwrap();
+    assert_eq!(lock.owner(), Some(pid));
+
+    // The file created by another client that has not written its PID yet
+    fs::write(lock.path(), "").unwrap();
+    assert!(lock.write(pid).is_err());
+  }
+
+  #[test]
+  fn lock_of_another_client_is_not_released() {
+    let dir = tempfile::tempdir().unwrap();
+    let lock = lock_in(&dir);
+
+    lock.write(u32::MAX).unwrap();
+    lock.release().unwrap();
+    assert_eq!(lock.owner(), Some(u32::MAX));
+
+    lock.remove().unwrap();
+    lock.write(std::process::id()).unwrap();
+    lock.release().unwrap();
+    assert!(!lock.path().exists());
+  }
+}
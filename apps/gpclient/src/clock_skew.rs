@@ -0,0 +1,120 @@
+use anyhow::bail;
+use chrono::{DateTime, TimeDelta, Utc};
+use gpapi::{gp_params::GpParams, utils::normalize_server};
+use log::{debug, info, warn};
+use reqwest::{Client, header::DATE};
+
+/// The SAML assertions and HIP reports are usually rejected beyond this skew
+const MAX_CLOCK_SKEW: TimeDelta = TimeDelta::minutes(5);
+
+/// Compare the local time to the `Date` header of the server. Return the skew if it exceeds
+/// the threshold, which is an error in the strict mode. The check is skipped if the server
+/// does not send a valid `Date` header.
+pub(crate) async fn check_clock_skew(
+  server: &str,
+  gp_params: &GpParams,
+  strict: bool,
+) -> anyhow::Result<Option<TimeDelta>> {
+  let server_date = match fetch_server_date(server, gp_params).await {
+    Ok(Some(date)) => date,
+    Ok(None) => {
+      debug!("No valid Date header from {}, skipping the clock skew check", server);
+      return Ok(None);
+    }
+    Err(err) => {
+      debug!("Failed to get the server time, skipping the clock skew check: {}", err);
+      return Ok(None);
+    }
+  };
+
+  let skew = (Utc::now() - server_date).abs();
+  if skew <= MAX_CLOCK_SKEW {
+    info!("Clock skew with the server: {}s", skew.num_seconds());
+    return Ok(None);
+  }
+
+  let message = format!(
+    "The local clock differs from the server by {}s, the SAML authentication and HIP report may fail",
+    skew.num_seconds()
+  );
+  if strict {
+    bail!(message);
+  }
+
+  warn!("{}", message);
+  Ok(Some(skew))
+}
+
+async fn fetch_server_date(server: &str, gp_params: &GpParams) -> anyhow::Result<Option<DateTime<Utc>>> {
+  let url = normalize_server(server)?;
+  let client = Client::try_from(gp_params)?;
+  let res = client.head(&url).send().await?;
+
+  let date = res
+    .headers()
+    .get(DATE)
+    .and_then(|v| v.to_str().ok())
+    .and_then(parse_http_date);
+
+  Ok(date)
+}
+
+/// Parse the HTTP date, e.g., `Sun, 06 Nov 1994 08:49:37 GMT`
+fn parse_http_date(date: &str) -> Option<DateTime<Utc>> {
+  DateTime::parse_from_rfc2822(date)
+    .ok()
+    .map(|date| date.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+  use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+  };
+
+  use super::*;
+
+  async fn start_server(date: String) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+      while let Ok((mut stream, _)) = listener.accept().await {
+        let mut buf = [0; 1024];
+        let _ = stream.read(&mut buf).await;
+        let response = format!(
+          "HTTP/1.1 200 OK\r\nDate: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+          date
+        );
+        let _ = stream.write_all(response.as_bytes()).await;
+      }
+    });
+
+    format!("http://{}", addr)
+  }
+
+  fn http_date(date: DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+  }
+
+  #[tokio::test]
+  async fn skewed_date_triggers_warning() {
+    let server = start_server(http_date(Utc::now() - TimeDelta::hours(1))).await;
+    let gp_params = GpParams::builder().build();
+
+    let skew = check_clock_skew(&server, &gp_params, false).await.unwrap();
+    assert!(skew.is_some_and(|skew| skew > MAX_CLOCK_SKEW));
+
+    let err = check_clock_skew(&server, &gp_params, true).await.unwrap_err();
+    assert!(err.to_string().contains("differs from the server"));
+  }
+
+  #[tokio::test]
+  async fn synced_date_passes() {
+    let server = start_server(http_date(Utc::now())).await;
+    let gp_params = GpParams::builder().build();
+
+    assert!(check_clock_skew(&server, &gp_params, true).await.unwrap().is_none());
+  }
+}
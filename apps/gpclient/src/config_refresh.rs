@@ -0,0 +1,58 @@
+use gpapi::{
+  credential::Credential,
+  gp_params::GpParams,
+  portal::{ConfigChange, PortalConfig, retrieve_config},
+};
+use log::{info, warn};
+use openconnect::TunnelInfo;
+
+/// The portal config the tunnel was connected with, to compare with the refreshed one
+#[derive(Clone)]
+pub(crate) struct ConfigRefresh {
+  portal: String,
+  applied: PortalConfig,
+  gp_params: GpParams,
+}
+
+impl ConfigRefresh {
+  pub(crate) fn new(portal: &str, applied: PortalConfig, gp_params: GpParams) -> Self {
+    Self {
+      portal: portal.to_string(),
+      applied,
+      gp_params,
+    }
+  }
+
+  /// Retrieve the portal config again with the portal auth cookie, and log how it differs from the
+  /// applied one and from the DNS settings of the live tunnel. The changes are not applied to the
+  /// live tunnel, which needs a reconnection.
+  pub(crate) async fn refresh(&self, tunnel: &TunnelInfo) -> anyhow::Result<Vec<ConfigChange>> {
+    let cred = Credential::from(self.applied.auth_cookie());
+    let refreshed = retrieve_config(&self.portal, &cred, &self.gp_params).await?;
+    let mut changes = self.applied.drift(&refreshed);
+    changes.extend(refreshed.live_drift(&tunnel.dns_servers, &tunnel.search_domains));
+
+    info!(
+      "The live tunnel: DNS servers {:?}, search domains {:?}, {} split-include and {} split-exclude route(s)",
+      tunnel.dns_servers,
+      tunnel.search_domains,
+      tunnel.split_includes.len(),
+      tunnel.split_excludes.len()
+    );
+    report_config_drift(&changes);
+
+    Ok(changes)
+  }
+}
+
+pub(crate) fn report_config_drift(changes: &[ConfigChange]) {
+  if changes.is_empty() {
+    info!("The refreshed portal config is unchanged");
+    return;
+  }
+
+  for change in changes {
+    warn!("Portal config drift, {}", change);
+  }
+  warn!("The portal config has changed, reconnect to apply the changes to the tunnel");
+}
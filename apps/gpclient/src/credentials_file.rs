@@ -0,0 +1,246 @@
+use std::{fmt, fs, os::unix::fs::PermissionsExt, path::Path};
+
+use anyhow::bail;
+
+/// The login of a server read from `--credentials-file`
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct FileCredential {
+  pub(crate) username: String,
+  pub(crate) password: Option<String>,
+}
+
+/// Keep the password out of the logs
+impl fmt::Debug for FileCredential {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("FileCredential")
+      .field("username", &self.username)
+      .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+      .finish()
+  }
+}
+
+#[derive(Default)]
+struct Entry {
+  /// None for the `default` entry
+  machine: Option<String>,
+  login: Option<String>,
+  password: Option<String>,
+}
+
+/// Keep the password out of the logs, also of the whole `CredentialsFile`
+impl fmt::Debug for Entry {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Entry")
+      .field("machine", &self.machine)
+      .field("login", &self.login)
+      .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+      .finish()
+  }
+}
+
+/// The credentials file in the netrc format, e.g.,
+///
+/// ```text
+/// machine vpn.example.com login alice password secret
+/// default login alice
+/// ```
+#[derive(Debug)]
+pub(crate) struct CredentialsFile {
+  entries: Vec<Entry>,
+}
+
+impl CredentialsFile {
+  /// Read the file, which must not be accessible by the group or others, i.e., mode 0600
+  pub(crate) fn read(path: &Path) -> anyhow::Result<Self> {
+    let metadata = fs::metadata(path)
+      .map_err(|err| anyhow::anyhow!("Failed to read the credentials file {}: {}", path.display(), err))?;
+
+    let mode = metadata.permissions().mode() & 0o777;
+    if mode & 0o077 != 0 {
+      bail!(
+        "The credentials file {} is accessible by other users (mode {:o}), run 'chmod 600' on it",
+        path.display(),
+        mode
+      );
+    }
+
+    let content = fs::read_to_string(path)
+      .map_err(|err| anyhow::anyhow!("Failed to read the credentials file {}: {}", path.display(), err))?;
+
+    Self::parse(&content).map_err(|err| anyhow::anyhow!("Invalid credentials file {}: {}", path.display(), err))
+  }
+
+  fn parse(content: &str) -> anyhow::Result<Self> {
+    let mut entries = Vec::new();
+    let mut tokens = tokenize(content).into_iter();
+
+    while let Some(token) = tokens.next() {
+      let mut value = |name: &str| {
+        tokens
+          .next()
+          .ok_or_else(|| anyhow::anyhow!("Missing the value of '{}'", name))
+      };
+
+      match token.as_str() {
+        "machine" => entries.push(Entry {
+          machine: Some(value("machine")?),
+          ..Default::default()
+        }),
+        "default" => entries.push(Entry::default()),
+        "login" | "password" | "account" => {
+          let value = value(&token)?;
+          let Some(entry) = entries.last_mut() else {
+            bail!("'{}' must follow a 'machine' or 'default'", token);
+          };
+
+          match token.as_str() {
+            "login" => entry.login = Some(value),
+            "password" => entry.password = Some(value),
+            _ => {}
+          }
+        }
+        _ => bail!("Unknown token '{}'", token),
+      }
+    }
+
+    Ok(Self { entries })
+  }
+
+  /// The login of the first server that matches, the port and scheme are ignored if the machine
+  /// does not have them. The `default` entry is used if no machine matches.
+  pub(crate) fn lookup<'a>(&self, servers: impl IntoIterator<Item = &'a str>) -> Option<FileCredential> {
+    let entry = servers
+      .into_iter()
+      .find_map(|server| self.find_machine(server))
+      .or_else(|| self.entries.iter().find(|entry| entry.machine.is_none()))?;
+
+    Some(FileCredential {
+      username: entry.login.clone()?,
+      password: entry.password.clone(),
+    })
+  }
+
+  fn find_machine(&self, server: &str) -> Option<&Entry> {
+    let host_port = host_port(server);
+    let host = match host_port.rsplit_once(':') {
+      Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+      _ => host_port.as_str(),
+    };
+
+    [host_port.as_str(), host].into_iter().find_map(|name| {
+      self.entries.iter().find(|entry| {
+        entry
+          .machine
+          .as_deref()
+          .is_some_and(|machine| machine.eq_ignore_ascii_case(name))
+      })
+    })
+  }
+}
+
+/// Split the content by whitespace, the `#` starts a comment to the end of the line. The quoted
+/// values are not supported, i.e., the password cannot contain whitespace or start with `#`.
+fn tokenize(content: &str) -> Vec<String> {
+  content
+    .lines()
+    .flat_map(|line| line.split_whitespace().take_while(|token| !token.starts_with('#')))
+    .map(String::from)
+    .collect()
+}
+
+/// The lowercase `host[:port]` of the server, e.g., `https://VPN.example.com:8443/` -> `vpn.example.com:8443`
+fn host_port(server: &str) -> String {
+  let server = server.trim();
+  let server = server.split_once("://").map_or(server, |(_, rest)| rest);
+  let server = server.split(['/', '?', '#']).next().unwrap_or_default();
+
+  server.to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{fs::Permissions, io::Write};
+
+  use super::*;
+
+  const CONTENT: &str = "# The VPN credentials\n\
+                         machine vpn.example.com login alice password s3cret\n\
+                         machine vpn.example.com:8443\n  login bob\n  password hunter2  # trailing comment\n\
+                         default login carol\n";
+
+  fn credential(username: &str, password: Option<&str>) -> Option<FileCredential> {
+    Some(FileCredential {
+      username: username.to_string(),
+      password: password.map(String::from),
+    })
+  }
+
+  #[test]
+  fn netrc_entries_are_parsed() {
+    let file = CredentialsFile::parse(CONTENT).unwrap();
+    assert_eq!(file.entries.len(), 3);
+    assert_eq!(file.entries[1].login.as_deref(), Some("bob"));
+    assert_eq!(file.entries[1].password.as_deref(), Some("hunter2"));
+
+    assert!(CredentialsFile::parse("machine").is_err());
+    assert!(CredentialsFile::parse("login alice").is_err());
+    assert!(CredentialsFile::parse("machine vpn.example.com user alice").is_err());
+    assert!(CredentialsFile::parse("").unwrap().entries.is_empty());
+  }
+
+  #[test]
+  fn servers_are_matched_by_host() {
+    let file = CredentialsFile::parse(CONTENT).unwrap();
+    let lookup = |server| file.lookup([server]);
+
+    assert_eq!(lookup("vpn.example.com"), credential("alice", Some("s3cret")));
+    assert_eq!(lookup("https://VPN.example.com/"), credential("alice", Some("s3cret")));
+    assert_eq!(
+      lookup("https://vpn.example.com:8443"),
+      credential("bob", Some("hunter2"))
+    );
+    // The machine without the port matches any port
+    assert_eq!(lookup("vpn.example.com:10443"), credential("alice", Some("s3cret")));
+    assert_eq!(lookup("other.example.com"), credential("carol", None));
+    // The gateway falls back to the login of the portal
+    assert_eq!(
+      file.lookup(["gw.example.com", "vpn.example.com"]),
+      credential("alice", Some("s3cret"))
+    );
+
+    let file = CredentialsFile::parse("machine vpn.example.com login alice").unwrap();
+    assert_eq!(file.lookup(["other.example.com"]), None);
+  }
+
+  #[test]
+  fn password_is_not_logged() {
+    let debug = format!("{:?}", credential("alice", Some("s3cret")).unwrap());
+    assert!(debug.contains("alice"));
+    assert!(!debug.contains("s3cret"));
+
+    let debug = format!("{:?}", CredentialsFile::parse(CONTENT).unwrap());
+    assert!(debug.contains("vpn.example.com") && debug.contains("bob"));
+    assert!(!debug.contains("s3cret") && !debug.contains("hunter2"));
+  }
+
+  #[test]
+  fn file_accessible_by_others_is_rejected() {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(CONTENT.as_bytes()).unwrap();
+
+    fs::set_permissions(file.path(), Permissions::from_mode(0o600)).unwrap();
+    let credentials = CredentialsFile::read(file.path()).unwrap();
+    assert!(credentials.lookup(["vpn.example.com"]).is_some());
+
+    for mode in [0o644, 0o640, 0o604] {
+      fs::set_permissions(file.path(), Permissions::from_mode(mode)).unwrap();
+      let err = CredentialsFile::read(file.path()).unwrap_err();
+      assert!(
+        err.to_string().contains("accessible by other users"),
+        "{:o}: {}",
+        mode,
+        err
+      );
+    }
+  }
+}
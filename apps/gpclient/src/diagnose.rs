@@ -1,54 +1,369 @@
 use clap::Args;
-use gpapi::utils::{host_utils, request::is_pkcs11_uri};
-use openconnect::{find_csd_wrapper, find_vpnc_script};
-use std::process::Command;
+use gpapi::{
+  error::PortalError,
+  gp_params::GpParams,
+  portal::{
+    DnsConfig, Prelogin, client_cert_required_from_xml, dns_config_from_xml, ipsec_supported_from_xml, prelogin,
+  },
+  utils::{host_utils, request::is_pkcs11_uri},
+};
+use openconnect::{find_csd_wrapper, find_vpnc_script, linked_openconnect_version};
+use serde_json::{Value, json};
+use std::{fmt, process::Command};
+
+use crate::{cli::SharedArgs, connect::parse_server, disconnect::OutputFormat};
+
+/// The version of the JSON output, bump it when the fields change incompatibly
+const DIAGNOSE_SCHEMA_VERSION: u32 = 1;
 
 #[derive(Args)]
 pub(crate) struct DiagnoseArgs {
   #[arg(long, help = "Optional certificate input to classify (file path or PKCS#11 URI)")]
   certificate: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "PATH",
+    help = "Optional portal config XML to check the gateway capabilities, e.g., saved from the debug log"
+  )]
+  portal_config: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "SERVER",
+    value_parser = parse_server,
+    help = "Optional portal to check with the prelogin, no credentials are sent"
+  )]
+  server: Option<String>,
+
+  #[arg(long, value_enum, default_value_t = OutputFormat::Text, help = "The output format of the diagnose report")]
+  format: OutputFormat,
 }
 
-pub(crate) struct DiagnoseHandler<'a> {
-  args: &'a DiagnoseArgs,
+/// How the certificate input is classified
+#[derive(Debug)]
+enum CertificateInfo {
+  NotSpecified,
+  Pkcs11Uri,
+  FilePath { path: String, exists: bool },
 }
 
-impl<'a> DiagnoseHandler<'a> {
-  pub(crate) fn new(args: &'a DiagnoseArgs) -> Self {
-    Self { args }
+impl CertificateInfo {
+  fn mode(&self) -> &str {
+    match self {
+      CertificateInfo::NotSpecified => "not-specified",
+      CertificateInfo::Pkcs11Uri => "pkcs11-uri",
+      CertificateInfo::FilePath { .. } => "file-path",
+    }
   }
+}
 
-  pub(crate) async fn handle(&self) -> anyhow::Result<()> {
-    println!("== gpclient diagnose ==");
-    println!("host.os_version={}", host_utils::get_linux_os_string());
-    println!("host.device={}", whoami::devicename());
-
-    match self.args.certificate.as_deref() {
-      Some(cert) if is_pkcs11_uri(cert) => println!("certificate.mode=pkcs11-uri"),
-      Some(cert) => {
-        let exists = std::path::Path::new(cert).exists();
-        println!("certificate.mode=file-path");
-        println!("certificate.path={cert}");
-        println!("certificate.exists={exists}");
+/// The gateway capabilities read from the portal config
+#[derive(Debug)]
+struct GatewayInfo {
+  ipsec_supported: Option<bool>,
+  dns: DnsConfig,
+  /// Reported as `portal.client_cert_required`, since it is a portal policy
+  client_cert_required: bool,
+}
+
+impl GatewayInfo {
+  fn read(path: &str) -> anyhow::Result<Self> {
+    let xml = std::fs::read_to_string(path)?;
+
+    Ok(Self {
+      ipsec_supported: ipsec_supported_from_xml(&xml)?,
+      dns: dns_config_from_xml(&xml)?,
+      client_cert_required: client_cert_required_from_xml(&xml)?,
+    })
+  }
+}
+
+/// The result of the read-only prelogin of the `--server` portal
+#[derive(Debug)]
+struct PortalCheck {
+  server: String,
+  /// `ok` once the portal answered, `failed` for the certificate and renegotiation errors
+  tls: &'static str,
+  outcome: PortalOutcome,
+}
+
+#[derive(Debug)]
+enum PortalOutcome {
+  Prelogin { auth: &'static str, region: String },
+  Failed { kind: &'static str, reason: String },
+}
+
+impl PortalCheck {
+  async fn run(server: &str, ignore_tls_errors: bool) -> Self {
+    let gp_params = GpParams::builder().ignore_tls_errors(ignore_tls_errors).build();
+
+    match prelogin(server, &gp_params).await {
+      Ok(prelogin) => Self::from_prelogin(server, &prelogin),
+      Err(err) => Self::from_error(server, &err),
+    }
+  }
+
+  fn from_prelogin(server: &str, prelogin: &Prelogin) -> Self {
+    let auth = match prelogin {
+      Prelogin::Saml(_) => "saml",
+      Prelogin::Standard(_) => "standard",
+    };
+
+    Self {
+      server: server.to_string(),
+      tls: "ok",
+      outcome: PortalOutcome::Prelogin {
+        auth,
+        region: prelogin.region().to_string(),
+      },
+    }
+  }
+
+  /// The portal errors keep the `x-private-pan-globalprotect` reason, e.g., `HIP_REQUIRED`, in the
+  /// message. The TLS result is unknown if the request failed before the portal answered.
+  fn from_error(server: &str, err: &PortalError) -> Self {
+    let (tls, kind) = match err {
+      err if err.is_legacy_openssl_error() => ("failed", "legacy-renegotiation"),
+      err if err.is_tls_error() => ("failed", "tls"),
+      PortalError::NetworkError(_) => ("unknown", "network"),
+      PortalError::Timeout(_) => ("unknown", "timeout"),
+      PortalError::ResponseTooLarge(_) => ("ok", "response-too-large"),
+      PortalError::Forbidden(_) => ("ok", "forbidden"),
+      PortalError::Maintenance(_) => ("ok", "maintenance"),
+      PortalError::HipCheckFailed { .. } => ("ok", "hip-check-failed"),
+      PortalError::OsNotAllowed { .. } => ("ok", "os-not-allowed"),
+      PortalError::RetryBudgetExhausted(_) => ("unknown", "retry-budget-exhausted"),
+      err if err.is_request_error() => ("ok", "prelogin"),
+      _ => ("unknown", "other"),
+    };
+
+    Self {
+      server: server.to_string(),
+      tls,
+      outcome: PortalOutcome::Failed {
+        kind,
+        reason: err.to_string(),
+      },
+    }
+  }
+
+  fn status(&self) -> &str {
+    match self.outcome {
+      PortalOutcome::Prelogin { .. } => "ok",
+      PortalOutcome::Failed { .. } => "failed",
+    }
+  }
+
+  fn to_json(&self) -> Value {
+    let (auth, region, error, reason) = match &self.outcome {
+      PortalOutcome::Prelogin { auth, region } => (Some(*auth), Some(region.as_str()), None, None),
+      PortalOutcome::Failed { kind, reason } => (None, None, Some(*kind), Some(reason.as_str())),
+    };
+
+    json!({
+      "server": self.server,
+      "status": self.status(),
+      "tls": self.tls,
+      "auth": auth,
+      "region": region,
+      "error": error,
+      "reason": reason,
+    })
+  }
+}
+
+/// The result of the diagnose command
+#[derive(Debug)]
+struct DiagnoseReport {
+  os_version: String,
+  device: String,
+  certificate: CertificateInfo,
+  gateway: Option<GatewayInfo>,
+  portal: Option<PortalCheck>,
+  vpnc_script: Option<String>,
+  hip_wrapper: Option<String>,
+  openconnect: Option<String>,
+  /// The libopenconnect gpclient is linked against, the CLI above may be another install
+  linked_openconnect: Option<String>,
+}
+
+impl DiagnoseReport {
+  fn collect(certificate: Option<&str>, gateway: Option<GatewayInfo>, portal: Option<PortalCheck>) -> Self {
+    let certificate = match certificate {
+      Some(cert) if is_pkcs11_uri(cert) => CertificateInfo::Pkcs11Uri,
+      Some(cert) => CertificateInfo::FilePath {
+        path: cert.to_string(),
+        exists: std::path::Path::new(cert).exists(),
+      },
+      None => CertificateInfo::NotSpecified,
+    };
+
+    Self {
+      os_version: host_utils::get_linux_os_string(),
+      device: host_utils::get_device_name().to_string(),
+      certificate,
+      gateway,
+      portal,
+      vpnc_script: find_vpnc_script(),
+      hip_wrapper: find_csd_wrapper(),
+      openconnect: detect_openconnect_version(),
+      linked_openconnect: linked_openconnect_version(),
+    }
+  }
+
+  fn to_json(&self) -> Value {
+    let (path, exists) = match &self.certificate {
+      CertificateInfo::FilePath { path, exists } => (Some(path.as_str()), Some(*exists)),
+      _ => (None, None),
+    };
+
+    json!({
+      "schema_version": DIAGNOSE_SCHEMA_VERSION,
+      "host": {
+        "os_version": self.os_version,
+        "device": self.device,
+      },
+      "certificate": {
+        "mode": self.certificate.mode(),
+        "path": path,
+        "exists": exists,
+      },
+      "portal": self.portal_json(),
+      "gateway": self.gateway.as_ref().map(|gateway| json!({
+        "ipsec_supported": gateway.ipsec_supported,
+        "dns_search_domains": gateway.dns.search_domains(),
+        "split_dns_domains": gateway.dns.split_dns_domains(),
+      })),
+      "runtime": {
+        "vpnc_script": self.vpnc_script,
+        "hip_wrapper": self.hip_wrapper,
+        "openconnect": self.openconnect,
+        "linked_openconnect": self.linked_openconnect,
+      },
+    })
+  }
+
+  /// The portal policy of the config and the prelogin check, merged as they are both optional
+  fn portal_json(&self) -> Value {
+    let mut portal = match &self.portal {
+      Some(portal) => portal.to_json(),
+      None if self.gateway.is_none() => return Value::Null,
+      None => json!({}),
+    };
+
+    if let Some(gateway) = &self.gateway {
+      portal["client_cert_required"] = json!(gateway.client_cert_required);
+    }
+
+    portal
+  }
+}
+
+impl fmt::Display for DiagnoseReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "== gpclient diagnose ==")?;
+    writeln!(f, "host.os_version={}", self.os_version)?;
+    writeln!(f, "host.device={}", self.device)?;
+
+    writeln!(f, "certificate.mode={}", self.certificate.mode())?;
+    if let CertificateInfo::FilePath { path, exists } = &self.certificate {
+      writeln!(f, "certificate.path={path}")?;
+      writeln!(f, "certificate.exists={exists}")?;
+    }
+
+    if let Some(portal) = &self.portal {
+      writeln!(f, "portal.server={}", portal.server)?;
+      writeln!(f, "portal.prelogin={}", portal.status())?;
+      writeln!(f, "portal.tls={}", portal.tls)?;
+      match &portal.outcome {
+        PortalOutcome::Prelogin { auth, region } => {
+          writeln!(f, "portal.auth={auth}")?;
+          writeln!(f, "portal.region={region}")?;
+        }
+        PortalOutcome::Failed { kind, reason } => {
+          writeln!(f, "portal.error={kind}")?;
+          writeln!(f, "portal.reason={reason}")?;
+        }
+      }
+    }
+
+    if let Some(gateway) = &self.gateway {
+      writeln!(f, "portal.client_cert_required={}", gateway.client_cert_required)?;
+      match gateway.ipsec_supported {
+        Some(ipsec_supported) => writeln!(f, "gateway.ipsec_supported={ipsec_supported}")?,
+        None => writeln!(f, "gateway.ipsec_supported=<unknown>")?,
       }
-      None => println!("certificate.mode=not-specified"),
+      writeln!(
+        f,
+        "gateway.dns_search_domains={}",
+        join_domains(gateway.dns.search_domains())
+      )?;
+      writeln!(
+        f,
+        "gateway.split_dns_domains={}",
+        join_domains(gateway.dns.split_dns_domains())
+      )?;
     }
 
-    println!("runtime.vpnc_script={}", find_vpnc_script().unwrap_or("<not-found>"));
-    println!(
+    writeln!(
+      f,
+      "runtime.vpnc_script={}",
+      self.vpnc_script.as_deref().unwrap_or("<not-found>")
+    )?;
+    writeln!(
+      f,
       "runtime.hip_wrapper={}",
-      find_csd_wrapper().unwrap_or_else(|| "<not-found>".to_string())
-    );
+      self.hip_wrapper.as_deref().unwrap_or("<not-found>")
+    )?;
+    writeln!(
+      f,
+      "runtime.openconnect={}",
+      self.openconnect.as_deref().unwrap_or("<not-detected>")
+    )?;
+    write!(
+      f,
+      "runtime.linked_openconnect={}",
+      self.linked_openconnect.as_deref().unwrap_or("<not-detected>")
+    )
+  }
+}
+
+pub(crate) struct DiagnoseHandler<'a> {
+  args: &'a DiagnoseArgs,
+  shared_args: &'a SharedArgs<'a>,
+}
+
+impl<'a> DiagnoseHandler<'a> {
+  pub(crate) fn new(args: &'a DiagnoseArgs, shared_args: &'a SharedArgs) -> Self {
+    Self { args, shared_args }
+  }
 
-    match detect_openconnect_version() {
-      Some(version) => println!("runtime.openconnect={version}"),
-      None => println!("runtime.openconnect=<not-detected>"),
+  pub(crate) async fn handle(&self) -> anyhow::Result<()> {
+    let gateway = self.args.portal_config.as_deref().map(GatewayInfo::read).transpose()?;
+    let portal = match self.args.server.as_deref() {
+      Some(server) => Some(PortalCheck::run(server, self.shared_args.ignore_tls_errors).await),
+      None => None,
+    };
+    let report = DiagnoseReport::collect(self.args.certificate.as_deref(), gateway, portal);
+
+    match self.args.format {
+      OutputFormat::Text => println!("{}", report),
+      OutputFormat::Json => println!("{}", report.to_json()),
     }
 
     Ok(())
   }
 }
 
+fn join_domains(domains: &[String]) -> String {
+  if domains.is_empty() {
+    String::from("<none>")
+  } else {
+    domains.join(",")
+  }
+}
+
 fn detect_openconnect_version() -> Option<String> {
   let output = Command::new("openconnect").arg("--version").output().ok()?;
   if !output.status.success() {
@@ -58,3 +373,178 @@ fn detect_openconnect_version() -> Option<String> {
   let stdout = String::from_utf8(output.stdout).ok()?;
   stdout.lines().next().map(|line| line.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+  use std::io::Write;
+
+  use super::*;
+
+  fn report(certificate: CertificateInfo) -> DiagnoseReport {
+    DiagnoseReport {
+      os_version: "Ubuntu 24.04".to_string(),
+      device: "host".to_string(),
+      certificate,
+      gateway: None,
+      portal: None,
+      vpnc_script: Some("/etc/vpnc/vpnc-script".to_string()),
+      hip_wrapper: None,
+      openconnect: None,
+      linked_openconnect: Some("v9.12".to_string()),
+    }
+  }
+
+  #[test]
+  fn text_format_is_unchanged() {
+    let report = report(CertificateInfo::FilePath {
+      path: "/tmp/cert.pem".to_string(),
+      exists: false,
+    });
+
+    assert_eq!(
+      report.to_string(),
+      "== gpclient diagnose ==\n\
+       host.os_version=Ubuntu 24.04\n\
+       host.device=host\n\
+       certificate.mode=file-path\n\
+       certificate.path=/tmp/cert.pem\n\
+       certificate.exists=false\n\
+       runtime.vpnc_script=/etc/vpnc/vpnc-script\n\
+       runtime.hip_wrapper=<not-found>\n\
+       runtime.openconnect=<not-detected>\n\
+       runtime.linked_openconnect=v9.12"
+    );
+  }
+
+  #[test]
+  fn json_format_uses_typed_values() {
+    let json = report(CertificateInfo::FilePath {
+      path: "/tmp/cert.pem".to_string(),
+      exists: true,
+    })
+    .to_json();
+
+    assert_eq!(json["schema_version"], DIAGNOSE_SCHEMA_VERSION);
+    assert_eq!(json["host"]["device"], "host");
+    assert_eq!(json["certificate"]["mode"], "file-path");
+    assert_eq!(json["certificate"]["exists"], true);
+    assert_eq!(json["runtime"]["vpnc_script"], "/etc/vpnc/vpnc-script");
+    assert!(json["runtime"]["hip_wrapper"].is_null());
+    assert_eq!(json["runtime"]["linked_openconnect"], "v9.12");
+
+    let json = report(CertificateInfo::Pkcs11Uri).to_json();
+    assert_eq!(json["certificate"]["mode"], "pkcs11-uri");
+    assert!(json["certificate"]["exists"].is_null());
+  }
+
+  #[test]
+  fn gateway_capabilities_are_reported() {
+    let mut report = report(CertificateInfo::NotSpecified);
+    assert!(report.to_json()["gateway"].is_null());
+    assert!(report.to_json()["portal"].is_null());
+
+    report.gateway = Some(GatewayInfo {
+      ipsec_supported: Some(true),
+      dns: DnsConfig::default(),
+      client_cert_required: false,
+    });
+    assert!(report.to_string().contains("\ngateway.ipsec_supported=true\n"));
+    assert!(report.to_string().contains("\ngateway.dns_search_domains=<none>\n"));
+    assert_eq!(report.to_json()["gateway"]["ipsec_supported"], true);
+
+    report.gateway = Some(GatewayInfo {
+      ipsec_supported: None,
+      dns: DnsConfig::default(),
+      client_cert_required: false,
+    });
+    assert!(report.to_string().contains("\ngateway.ipsec_supported=<unknown>\n"));
+    assert!(report.to_json()["gateway"]["ipsec_supported"].is_null());
+  }
+
+  #[test]
+  fn split_dns_is_reported() {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file
+      .write_all(
+        b"<policy><dns-suffix><member>corp.example.com</member></dns-suffix>\
+          <split-dns><member>internal.example.com</member><member>lab.example.net</member></split-dns></policy>",
+      )
+      .unwrap();
+
+    let mut report = report(CertificateInfo::NotSpecified);
+    report.gateway = Some(GatewayInfo::read(file.path().to_str().unwrap()).unwrap());
+
+    let text = report.to_string();
+    assert!(text.contains("\ngateway.dns_search_domains=corp.example.com\n"));
+    assert!(text.contains("\ngateway.split_dns_domains=internal.example.com,lab.example.net\n"));
+
+    let json = report.to_json();
+    assert_eq!(json["gateway"]["dns_search_domains"][0], "corp.example.com");
+    assert_eq!(json["gateway"]["split_dns_domains"][1], "lab.example.net");
+  }
+
+  #[test]
+  fn client_cert_requirement_is_reported() {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file
+      .write_all(b"<policy><client-cert-required>yes</client-cert-required></policy>")
+      .unwrap();
+
+    let mut report = report(CertificateInfo::NotSpecified);
+    report.gateway = Some(GatewayInfo::read(file.path().to_str().unwrap()).unwrap());
+    assert!(report.to_string().contains("\nportal.client_cert_required=true\n"));
+    assert_eq!(report.to_json()["portal"]["client_cert_required"], true);
+
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(b"<policy></policy>").unwrap();
+    report.gateway = Some(GatewayInfo::read(file.path().to_str().unwrap()).unwrap());
+    assert!(report.to_string().contains("\nportal.client_cert_required=false\n"));
+  }
+
+  #[test]
+  fn portal_prelogin_is_reported() {
+    let mut report = report(CertificateInfo::NotSpecified);
+    report.portal = Some(PortalCheck {
+      server: "vpn.example.com".to_string(),
+      tls: "ok",
+      outcome: PortalOutcome::Prelogin {
+        auth: "saml",
+        region: "US".to_string(),
+      },
+    });
+
+    let text = report.to_string();
+    assert!(text.contains(
+      "\nportal.server=vpn.example.com\nportal.prelogin=ok\nportal.tls=ok\nportal.auth=saml\nportal.region=US\n"
+    ));
+
+    let json = report.to_json();
+    assert_eq!(json["portal"]["status"], "ok");
+    assert_eq!(json["portal"]["auth"], "saml");
+    assert_eq!(json["portal"]["region"], "US");
+    assert!(json["portal"]["error"].is_null());
+    assert!(json["portal"]["client_cert_required"].is_null());
+  }
+
+  #[test]
+  fn portal_error_reason_is_reported() {
+    let err = PortalError::Forbidden("HIP_REQUIRED".to_string());
+    let check = PortalCheck::from_error("vpn.example.com", &err);
+    assert_eq!(check.tls, "ok");
+
+    let mut report = report(CertificateInfo::NotSpecified);
+    report.portal = Some(check);
+
+    let text = report.to_string();
+    assert!(text.contains("\nportal.prelogin=failed\nportal.tls=ok\n"));
+    assert!(text.contains("\nportal.error=forbidden\nportal.reason=Forbidden: HIP_REQUIRED\n"));
+    assert_eq!(report.to_json()["portal"]["reason"], "Forbidden: HIP_REQUIRED");
+
+    let check = PortalCheck::from_error("vpn.example.com", &PortalError::TlsError);
+    assert_eq!(check.tls, "failed");
+    assert!(matches!(check.outcome, PortalOutcome::Failed { kind: "tls", .. }));
+
+    let check = PortalCheck::from_error("vpn.example.com", &PortalError::Other(anyhow::anyhow!("timeout")));
+    assert_eq!(check.tls, "unknown");
+  }
+}
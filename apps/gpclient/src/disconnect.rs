@@ -1,10 +1,28 @@
-use crate::GP_CLIENT_LOCK_FILE;
-use clap::Args;
+use crate::{GP_CLIENT_LOCK_FILE, GP_CLIENT_SCRIPT_ENV_FILE};
+use clap::{Args, ValueEnum};
 use gpapi::utils::lock_file::gpservice_lock_info;
 use log::{info, warn};
-use std::{fs, str::FromStr, thread, time::Duration};
+use openconnect::SavedScriptEnv;
+use serde_json::{Value, json};
+use std::{
+  fmt, fs,
+  path::Path,
+  str::FromStr,
+  thread,
+  time::{Duration, Instant, SystemTime},
+};
 use sysinfo::{Pid, Signal, System};
 
+/// How long to wait for the client to force its own cleanup on the terminate signal before killing it
+const FORCE_CLEANUP_GRACE: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum OutputFormat {
+  #[default]
+  Text,
+  Json,
+}
+
 #[derive(Args)]
 pub struct DisconnectArgs {
   #[arg(
@@ -13,6 +31,74 @@ pub struct DisconnectArgs {
     help = "The time in seconds to wait for the VPN connection to disconnect"
   )]
   wait: Option<u64>,
+
+  #[arg(
+    long,
+    conflicts_with = "wait",
+    help = "The time in seconds to wait for the VPN connection to disconnect before killing the client"
+  )]
+  timeout: Option<u64>,
+
+  #[arg(long, value_enum, default_value_t = OutputFormat::Text, help = "The output format of the disconnect result")]
+  format: OutputFormat,
+}
+
+/// The result of the disconnect command
+#[derive(Debug, Default)]
+pub(crate) struct DisconnectResult {
+  /// The PID of the CLI client session, if any
+  client_pid: Option<u32>,
+  /// The PID of the GUI service session, if any
+  service_pid: Option<u32>,
+  /// Whether the tunnel has been closed, i.e., the interface and routes have been removed by the vpnc-script.
+  /// None if not waited for the session to exit.
+  tunnel_closed: Option<bool>,
+  /// How long the CLI client session was connected
+  duration: Option<Duration>,
+}
+
+impl DisconnectResult {
+  fn session_found(&self) -> bool {
+    self.client_pid.is_some() || self.service_pid.is_some()
+  }
+
+  fn to_json(&self) -> Value {
+    json!({
+      "session_found": self.session_found(),
+      "client_pid": self.client_pid,
+      "service_pid": self.service_pid,
+      "tunnel_closed": self.tunnel_closed,
+      "duration": self.duration.map(|d| d.as_secs()),
+    })
+  }
+}
+
+impl fmt::Display for DisconnectResult {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if !self.session_found() {
+      return write!(f, "No active session");
+    }
+
+    if let Some(pid) = self.client_pid {
+      write!(f, "Disconnecting the client session (pid {})", pid)?;
+      if let Some(duration) = self.duration {
+        write!(f, ", connected for {}s", duration.as_secs())?;
+      }
+    }
+
+    if let Some(pid) = self.service_pid {
+      if self.client_pid.is_some() {
+        write!(f, "; ")?;
+      }
+      write!(f, "Disconnecting the service session (pid {})", pid)?;
+    }
+
+    match self.tunnel_closed {
+      Some(true) => write!(f, "; tunnel closed"),
+      Some(false) => write!(f, "; tunnel still open"),
+      None => Ok(()),
+    }
+  }
 }
 
 pub struct DisconnectHandler<'a> {
@@ -26,28 +112,134 @@ impl<'a> DisconnectHandler<'a> {
 
   pub async fn handle(&self) -> anyhow::Result<()> {
     // Try to disconnect the CLI client
-    if let Ok(c) = fs::read_to_string(GP_CLIENT_LOCK_FILE) {
-      send_signal(c.trim(), Signal::Interrupt).unwrap_or_else(|err| {
-        warn!("Failed to send signal to client: {}", err);
-      });
-    };
+    let mut result = disconnect_client(Path::new(GP_CLIENT_LOCK_FILE));
 
     // Try to disconnect the GUI service
     if let Ok(c) = gpservice_lock_info().await {
+      result.service_pid = Some(c.pid);
       send_signal(&c.pid.to_string(), Signal::User1).unwrap_or_else(|err| {
         warn!("Failed to send signal to service: {}", err);
       });
     };
 
-    // sleep, to give the client and service time to disconnect
+    // wait, to give the client and service time to disconnect
     if let Some(wait) = self.args.wait {
-      thread::sleep(Duration::from_secs(wait));
+      result.tunnel_closed = Some(wait_for_exit(&result, Duration::from_secs(wait)));
+    }
+
+    // wait, and force the client to exit if the tunnel teardown hangs
+    if let Some(timeout) = self.args.timeout {
+      let closed = wait_for_pids(&[result.client_pid], Duration::from_secs(timeout));
+      if !closed {
+        warn!("The client session did not exit in {}s", timeout);
+        kill_client(
+          Path::new(GP_CLIENT_LOCK_FILE),
+          Path::new(GP_CLIENT_SCRIPT_ENV_FILE),
+          &result,
+          FORCE_CLEANUP_GRACE,
+        );
+      }
+      result.tunnel_closed = Some(closed);
+    }
+
+    match self.args.format {
+      OutputFormat::Text => println!("{}", result),
+      OutputFormat::Json => println!("{}", result.to_json()),
     }
 
     Ok(())
   }
 }
 
+/// Signal the CLI client recorded in the lock file, a missing lock file means no active session
+fn disconnect_client(lock_file: &Path) -> DisconnectResult {
+  let mut result = DisconnectResult::default();
+
+  let Ok(c) = fs::read_to_string(lock_file) else {
+    info!("No active client session found");
+    return result;
+  };
+
+  let Ok(pid) = c.trim().parse::<u32>() else {
+    warn!("Invalid PID in the lock file: {}", c.trim());
+    return result;
+  };
+
+  result.client_pid = Some(pid);
+  // The lock file is written when the tunnel is connected
+  result.duration = fs::metadata(lock_file)
+    .and_then(|m| m.modified())
+    .ok()
+    .and_then(|t| SystemTime::now().duration_since(t).ok());
+
+  send_signal(&pid.to_string(), Signal::Interrupt).unwrap_or_else(|err| {
+    warn!("Failed to send signal to client: {}", err);
+  });
+
+  result
+}
+
+/// Force the CLI client that did not exit in time: ask it to clean up with the terminate signal first,
+/// then run the vpnc-script teardown with the saved tunnel environment, kill it and remove its stale files
+fn kill_client(lock_file: &Path, env_file: &Path, result: &DisconnectResult, grace: Duration) {
+  let Some(pid) = result.client_pid else {
+    return;
+  };
+
+  info!("Terminating the client session (pid {})", pid);
+  send_signal(&pid.to_string(), Signal::Term).unwrap_or_else(|err| {
+    warn!("Failed to terminate the client: {}", err);
+  });
+  if wait_for_pids(&[Some(pid)], grace) {
+    return;
+  }
+
+  match SavedScriptEnv::load(env_file) {
+    Ok(Some(env)) => {
+      if let Err(err) = env.run_teardown() {
+        warn!("Failed to run the vpnc-script teardown: {}", err);
+      }
+    }
+    Ok(None) => info!("No saved tunnel environment found, skipping the vpnc-script teardown"),
+    Err(err) => warn!("Failed to load the saved tunnel environment: {}", err),
+  }
+
+  info!("Killing the client session (pid {})", pid);
+  send_signal(&pid.to_string(), Signal::Kill).unwrap_or_else(|err| {
+    warn!("Failed to kill the client: {}", err);
+  });
+
+  for file in [lock_file, env_file] {
+    if let Err(err) = fs::remove_file(file) {
+      if err.kind() != std::io::ErrorKind::NotFound {
+        warn!("Failed to remove {}: {}", file.display(), err);
+      }
+    }
+  }
+}
+
+fn wait_for_exit(result: &DisconnectResult, timeout: Duration) -> bool {
+  wait_for_pids(&[result.client_pid, result.service_pid], timeout)
+}
+
+fn wait_for_pids(pids: &[Option<u32>], timeout: Duration) -> bool {
+  let pids = pids.iter().flatten().copied().map(Pid::from_u32).collect::<Vec<_>>();
+  let deadline = Instant::now() + timeout;
+
+  loop {
+    let s = System::new_all();
+    if pids.iter().all(|pid| s.process(*pid).is_none()) {
+      return true;
+    }
+
+    if Instant::now() >= deadline {
+      return false;
+    }
+
+    thread::sleep(Duration::from_millis(500));
+  }
+}
+
 fn send_signal(pid: &str, signal: Signal) -> anyhow::Result<()> {
   let s = System::new_all();
   let pid = Pid::from_str(pid)?;
@@ -61,3 +253,94 @@ fn send_signal(pid: &str, signal: Signal) -> anyhow::Result<()> {
   }
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reports_no_active_session() {
+    let dir = tempfile::tempdir().unwrap();
+    let result = disconnect_client(&dir.path().join("gpclient.lock"));
+
+    assert!(!result.session_found());
+    assert_eq!(result.to_string(), "No active session");
+
+    let json = result.to_json();
+    assert_eq!(json["session_found"], false);
+    assert!(json["client_pid"].is_null());
+    assert!(json["duration"].is_null());
+  }
+
+  #[test]
+  fn kills_the_client_that_does_not_exit() {
+    use std::os::unix::{fs::PermissionsExt, process::ExitStatusExt};
+    use std::process::Command;
+
+    // A client stuck in the teardown, which ignores the interrupt and terminate signals
+    let mut client = Command::new("sh")
+      .args(["-c", "trap '' INT TERM; exec sleep 30"])
+      .spawn()
+      .unwrap();
+    // Give the shell time to install the trap
+    thread::sleep(Duration::from_millis(200));
+    let dir = tempfile::tempdir().unwrap();
+    let lock_file = dir.path().join("gpclient.lock");
+    fs::write(&lock_file, client.id().to_string()).unwrap();
+
+    // The environment saved by the vpnc-script when the tunnel was connected
+    let output = dir.path().join("output");
+    let script = dir.path().join("vpnc-script");
+    fs::write(
+      &script,
+      format!("#!/bin/sh\necho \"$reason $TUNDEV\" > {}\n", output.display()),
+    )
+    .unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    let env_file = dir.path().join("gpclient.env");
+    fs::write(
+      &env_file,
+      format!(
+        "GPCLIENT_VPNC_SCRIPT={}\nreason=connect\nTUNDEV=gpd0\n",
+        script.display()
+      ),
+    )
+    .unwrap();
+
+    let result = disconnect_client(&lock_file);
+    assert_eq!(result.client_pid, Some(client.id()));
+    assert!(!wait_for_exit(&result, Duration::from_millis(100)));
+
+    kill_client(&lock_file, &env_file, &result, Duration::from_millis(500));
+    assert_eq!(client.wait().unwrap().signal(), Some(9));
+    assert_eq!(fs::read_to_string(&output).unwrap().trim(), "disconnect gpd0");
+    assert!(!lock_file.exists());
+    assert!(!env_file.exists());
+  }
+
+  #[test]
+  fn terminated_client_is_not_killed() {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Command;
+
+    // A client that exits on the terminate signal, after its own cleanup
+    let mut client = Command::new("sh")
+      .args(["-c", "trap '' INT; exec sleep 30"])
+      .spawn()
+      .unwrap();
+    thread::sleep(Duration::from_millis(200));
+    let dir = tempfile::tempdir().unwrap();
+    let lock_file = dir.path().join("gpclient.lock");
+    fs::write(&lock_file, client.id().to_string()).unwrap();
+
+    let result = disconnect_client(&lock_file);
+    let waiter = thread::spawn(move || client.wait().unwrap().signal());
+    kill_client(
+      &lock_file,
+      &dir.path().join("gpclient.env"),
+      &result,
+      Duration::from_secs(5),
+    );
+    assert_eq!(waiter.join().unwrap(), Some(15));
+  }
+}
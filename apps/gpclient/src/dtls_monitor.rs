@@ -0,0 +1,97 @@
+use std::{
+  fmt,
+  sync::Arc,
+  time::{Duration, Instant},
+};
+
+use log::{info, warn};
+
+use crate::status_socket::ConnectionStatus;
+
+/// The default time for the UDP transport to be established with `--require-dtls`
+pub(crate) const DEFAULT_DTLS_TIMEOUT: u64 = 30;
+
+/// The tunnel was disconnected because the UDP transport was not established
+#[derive(Debug)]
+pub(crate) struct DtlsRequired(pub(crate) Duration);
+
+impl fmt::Display for DtlsRequired {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "Disconnected as DTLS/ESP was not established in {}s, the tunnel fell back to TLS",
+      self.0.as_secs()
+    )
+  }
+}
+
+impl std::error::Error for DtlsRequired {}
+
+/// Wait for the DTLS or ESP transport of the connected tunnel, i.e., its cipher is reported. Return
+/// false if it is not established in the timeout, the time before connected is not counted.
+pub(crate) async fn wait_for_dtls(
+  status: Arc<ConnectionStatus>,
+  dtls_cipher: impl Fn() -> Option<String>,
+  timeout: Duration,
+  poll_interval: Duration,
+) -> bool {
+  let mut connected_at = None;
+
+  loop {
+    tokio::time::sleep(poll_interval).await;
+
+    if !status.is_connected() {
+      continue;
+    }
+
+    if let Some(cipher) = dtls_cipher() {
+      info!("The UDP transport is established with {}", cipher);
+      return true;
+    }
+
+    if connected_at.get_or_insert_with(Instant::now).elapsed() >= timeout {
+      warn!("The UDP transport is not established in {}s", timeout.as_secs());
+      return false;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::atomic::{AtomicU32, Ordering};
+
+  use openconnect::VpnStats;
+
+  use super::*;
+
+  const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+  fn connected_status() -> Arc<ConnectionStatus> {
+    let status = Arc::new(ConnectionStatus::new("gw.example.com", VpnStats::default));
+    status.set_connected();
+    status
+  }
+
+  #[tokio::test]
+  async fn established_dtls_is_detected() {
+    let polls = AtomicU32::new(0);
+    let dtls_cipher = || (polls.fetch_add(1, Ordering::SeqCst) >= 2).then(|| String::from("AES256-GCM"));
+
+    let established = wait_for_dtls(connected_status(), dtls_cipher, Duration::from_secs(1), POLL_INTERVAL).await;
+    assert!(established);
+  }
+
+  #[tokio::test]
+  async fn missing_dtls_times_out() {
+    let established = wait_for_dtls(connected_status(), || None, Duration::from_millis(50), POLL_INTERVAL).await;
+    assert!(!established);
+  }
+
+  #[tokio::test]
+  async fn time_before_connected_is_not_counted() {
+    let status = Arc::new(ConnectionStatus::new("gw.example.com", VpnStats::default));
+
+    let dtls = wait_for_dtls(status, || None, Duration::from_millis(20), POLL_INTERVAL);
+    assert!(tokio::time::timeout(Duration::from_millis(100), dtls).await.is_err());
+  }
+}
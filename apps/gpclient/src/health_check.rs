@@ -0,0 +1,207 @@
+use std::{
+  fmt,
+  net::IpAddr,
+  process::{Command, Stdio},
+  sync::atomic::{AtomicBool, Ordering},
+  time::Duration,
+};
+
+use log::{debug, info, warn};
+use openconnect::is_valid_domain_name;
+
+/// The exit code when the tunnel is disconnected for failing the health check
+pub(crate) const HEALTH_CHECK_EXIT_CODE: i32 = 5;
+
+/// How many times to probe the host before the health check fails
+pub(crate) const HEALTH_CHECK_ATTEMPTS: u32 = 3;
+
+/// The routes and DNS of the tunnel may take a moment to be set up by the vpnc-script
+pub(crate) const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The tunnel was disconnected because the health check through it failed
+#[derive(Debug)]
+pub(crate) struct HealthCheckFailed(pub(crate) String);
+
+impl fmt::Display for HealthCheckFailed {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "Disconnected as the health check to {} failed, the tunnel does not carry traffic",
+      self.0
+    )
+  }
+}
+
+impl std::error::Error for HealthCheckFailed {}
+
+/// Send a small packet to the host through the tunnel, and wait for the reply
+pub(crate) trait HealthProber {
+  fn probe(&self, host: &str) -> bool;
+}
+
+/// Probe with a single ping, out of the tun device if known, so that the reply does not come
+/// through another route
+pub(crate) struct PingHealthProber {
+  pub(crate) interface: Option<String>,
+}
+
+impl HealthProber for PingHealthProber {
+  fn probe(&self, host: &str) -> bool {
+    let mut cmd = Command::new("ping");
+    cmd.args(["-c", "1", "-W", "2"]);
+    if let Some(interface) = &self.interface {
+      cmd.arg("-I").arg(interface);
+    }
+
+    cmd
+      .arg("--")
+      .arg(host)
+      .stdout(Stdio::null())
+      .stderr(Stdio::null())
+      .status()
+      .is_ok_and(|status| status.success())
+  }
+}
+
+/// The host of `--health-check`, an IP address or a host name, which is passed to ping
+pub(crate) fn parse_health_check_host(value: &str) -> Result<String, String> {
+  if value.parse::<IpAddr>().is_ok() || is_valid_domain_name(value) {
+    Ok(value.to_string())
+  } else {
+    Err(format!("Invalid health check host: {}", value))
+  }
+}
+
+/// Probe the host up to the attempts until it replies, return whether the tunnel carries traffic.
+/// The probing stops once the tunnel `ended`, which is not reported as a failure.
+pub(crate) fn check_health(
+  prober: &impl HealthProber,
+  host: &str,
+  attempts: u32,
+  interval: Duration,
+  ended: &AtomicBool,
+) -> bool {
+  for attempt in 1..=attempts {
+    if ended.load(Ordering::SeqCst) {
+      debug!("Stopped the health check to {}, the tunnel has ended", host);
+      return true;
+    }
+
+    if prober.probe(host) {
+      info!("Health check to {} passed", host);
+      return true;
+    }
+
+    debug!("Health check to {} failed ({}/{})", host, attempt, attempts);
+    if attempt < attempts {
+      std::thread::sleep(interval);
+    }
+  }
+
+  warn!("Health check to {} failed after {} attempt(s)", host, attempts);
+  false
+}
+
+#[cfg(test)]
+mod tests {
+  use std::cell::Cell;
+
+  use super::*;
+
+  /// Replies from the given attempt on, and counts the probes
+  struct MockProber {
+    replies_from: Option<u32>,
+    probes: Cell<u32>,
+  }
+
+  impl MockProber {
+    fn new(replies_from: Option<u32>) -> Self {
+      Self {
+        replies_from,
+        probes: Cell::new(0),
+      }
+    }
+  }
+
+  impl HealthProber for MockProber {
+    fn probe(&self, _host: &str) -> bool {
+      self.probes.set(self.probes.get() + 1);
+      self.replies_from.is_some_and(|attempt| self.probes.get() >= attempt)
+    }
+  }
+
+  #[test]
+  fn reachable_host_passes() {
+    let prober = MockProber::new(Some(1));
+    assert!(check_health(
+      &prober,
+      "10.0.0.53",
+      3,
+      Duration::ZERO,
+      &AtomicBool::new(false)
+    ));
+    assert_eq!(prober.probes.get(), 1);
+
+    // The routes set up after the first probe
+    let prober = MockProber::new(Some(3));
+    assert!(check_health(
+      &prober,
+      "10.0.0.53",
+      3,
+      Duration::ZERO,
+      &AtomicBool::new(false)
+    ));
+    assert_eq!(prober.probes.get(), 3);
+  }
+
+  #[test]
+  fn unreachable_host_fails() {
+    let prober = MockProber::new(None);
+    assert!(!check_health(
+      &prober,
+      "10.0.0.53",
+      3,
+      Duration::ZERO,
+      &AtomicBool::new(false)
+    ));
+    assert_eq!(prober.probes.get(), 3);
+
+    let prober = MockProber::new(Some(4));
+    assert!(!check_health(
+      &prober,
+      "10.0.0.53",
+      3,
+      Duration::ZERO,
+      &AtomicBool::new(false)
+    ));
+  }
+
+  #[test]
+  fn ended_tunnel_is_not_probed() {
+    let prober = MockProber::new(None);
+    assert!(check_health(
+      &prober,
+      "10.0.0.53",
+      3,
+      Duration::ZERO,
+      &AtomicBool::new(true)
+    ));
+    assert_eq!(prober.probes.get(), 0);
+  }
+
+  #[test]
+  fn host_is_validated() {
+    for host in ["10.0.0.1", "fd00::1", "intranet.example.com", "intranet"] {
+      assert_eq!(parse_health_check_host(host).as_deref(), Ok(host));
+    }
+    for host in ["-f", "", "a..b", "-i0.1.example.com", "host name", "$(id)"] {
+      assert!(parse_health_check_host(host).is_err(), "{:?}", host);
+    }
+  }
+
+  #[test]
+  fn failure_names_the_host() {
+    let err = HealthCheckFailed(String::from("10.0.0.53"));
+    assert!(err.to_string().contains("10.0.0.53"));
+  }
+}
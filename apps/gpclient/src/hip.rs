@@ -1,10 +1,26 @@
+use anyhow::bail;
 use askama::Template;
 use clap::Args;
-use gpapi::{clap::args::Os, utils::host_utils};
-use log::debug;
+use gpapi::{clap::args::Os, gateway::hip::merge_hip_reports, process::hip_launcher::HipLauncher, utils::host_utils};
+use log::{debug, info};
+use openconnect::find_csd_wrapper;
 use std::collections::HashMap;
 use xmltree::Element;
 
+/// The cookie fields that authenticate the session, never passed to the previewed HIP wrapper
+const SECRET_COOKIE_FIELDS: &[&str] = &[
+  "authcookie",
+  "persistent-cookie",
+  "portal-userauthcookie",
+  "portal-prelogonuserauthcookie",
+  "prelogin-cookie",
+];
+
+/// The client IP passed to the previewed HIP wrapper, the tunnel IP is only known after connecting
+const PREVIEW_CLIENT_IP: &str = "127.0.0.1";
+/// The MD5 passed to the previewed HIP wrapper, the real one is the digest of the session cookie
+const PREVIEW_MD5: &str = "00000000000000000000000000000000";
+
 #[derive(Template)]
 #[template(path = "hip_report.xml")]
 struct HipReportTemplate<'a> {
@@ -97,17 +113,25 @@ impl NetworkInterface {
 
 #[derive(Args)]
 pub(crate) struct HipArgs {
-  #[arg(long, help = "The GP client version, e.g., 6.2.4-49")]
-  client_version: String,
-
-  #[arg(long, value_enum, help = "The client OS")]
-  client_os: Os,
-
-  #[arg(long, help = "The OS version string, e.g., Apple Mac OS X 10.15.7")]
-  os_version: String,
-
-  #[arg(long, help = "The authentication cookie")]
-  cookie: String,
+  #[arg(
+    long,
+    required_unless_present = "preview",
+    help = "The GP client version, e.g., 6.2.4-49"
+  )]
+  client_version: Option<String>,
+
+  #[arg(long, value_enum, required_unless_present = "preview", help = "The client OS")]
+  client_os: Option<Os>,
+
+  #[arg(
+    long,
+    required_unless_present = "preview",
+    help = "The OS version string, e.g., Apple Mac OS X 10.15.7"
+  )]
+  os_version: Option<String>,
+
+  #[arg(long, required_unless_present = "preview", help = "The authentication cookie")]
+  cookie: Option<String>,
 
   #[arg(long, help = "The client IPv4 address")]
   client_ip: Option<String>,
@@ -115,8 +139,41 @@ pub(crate) struct HipArgs {
   #[arg(long, help = "The client IPv6 address")]
   client_ipv6: Option<String>,
 
-  #[arg(long, help = "The MD5 digest to encode into the HIP report")]
-  md5: String,
+  #[arg(
+    long,
+    required_unless_present = "preview",
+    help = "The MD5 digest to encode into the HIP report"
+  )]
+  md5: Option<String>,
+
+  #[arg(
+    long = "merge-section",
+    value_name = "FILE",
+    help = "A HIP report file whose sections are merged into the generated report, can be specified multiple times"
+  )]
+  merge_sections: Vec<String>,
+
+  #[arg(
+    long,
+    conflicts_with = "merge_sections",
+    help = "Run the HIP wrapper found for the connection with the arguments openconnect passes, and print its HIP report"
+  )]
+  preview: bool,
+
+  #[arg(
+    long,
+    requires = "preview",
+    help = "The HIP wrapper to preview instead of the one found"
+  )]
+  csd_wrapper: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "FILE",
+    requires = "preview",
+    help = "Write the previewed HIP report to the file instead of the standard output"
+  )]
+  output: Option<String>,
 }
 
 pub(crate) struct HipHandler<'a> {
@@ -129,8 +186,13 @@ impl<'a> HipHandler<'a> {
   }
 
   pub(crate) async fn handle(&self) -> anyhow::Result<()> {
+    if self.args.preview {
+      return self.preview().await;
+    }
+
     let cookie_params = self.parse_cookie();
     let report = self.generate_hip_report(&cookie_params)?;
+    let report = self.merge_sections(report)?;
 
     debug!("Generated HIP report:\n{}", report);
     println!("{}", report);
@@ -138,9 +200,62 @@ impl<'a> HipHandler<'a> {
     Ok(())
   }
 
+  /// Merge the sections of the additional report files into the generated report
+  fn merge_sections(&self, report: String) -> anyhow::Result<String> {
+    if self.args.merge_sections.is_empty() {
+      return Ok(report);
+    }
+
+    let mut reports = vec![report];
+    for path in &self.args.merge_sections {
+      let section = std::fs::read_to_string(path)
+        .map_err(|err| anyhow::anyhow!("Failed to read the HIP report section {}: {}", path, err))?;
+      reports.push(section);
+    }
+
+    format_xml(&merge_hip_reports(&reports)?)
+  }
+
+  /// Run the HIP wrapper as openconnect does, but with the session secrets removed from the cookie
+  async fn preview(&self) -> anyhow::Result<()> {
+    let Some(csd_wrapper) = self.args.csd_wrapper.clone().or_else(find_csd_wrapper) else {
+      bail!("No HIP wrapper found, install hipreport.sh or set GPCLIENT_HIP_WRAPPER to its path");
+    };
+
+    let cookie = preview_cookie(self.args.cookie.as_deref())?;
+    let md5 = self.args.md5.as_deref().unwrap_or(PREVIEW_MD5);
+    let client_ip = self.args.client_ip.as_deref().unwrap_or(PREVIEW_CLIENT_IP);
+    let client_os = self.args.client_os.as_ref().unwrap_or(&Os::Linux).as_str();
+
+    info!(
+      "Previewing the HIP wrapper {} with --cookie {} --client-ip {} --md5 {} --client-os {}",
+      csd_wrapper, cookie, client_ip, md5, client_os
+    );
+
+    let report = HipLauncher::new(&csd_wrapper)
+      .cookie(&cookie)
+      .md5(md5)
+      .client_ip(client_ip)
+      .client_os(client_os)
+      .client_version(self.args.client_version.as_deref())
+      .launch()
+      .await?;
+
+    match self.args.output.as_deref() {
+      Some(path) => {
+        std::fs::write(path, &report)
+          .map_err(|err| anyhow::anyhow!("Failed to write the HIP report to {}: {}", path, err))?;
+        info!("Wrote the HIP report to {}", path);
+      }
+      None => print!("{}", report),
+    }
+
+    Ok(())
+  }
+
   fn parse_cookie(&self) -> HashMap<String, String> {
     // Parse URL-encoded cookie string using serde_urlencoded
-    serde_urlencoded::from_str(&self.args.cookie).unwrap_or_default()
+    serde_urlencoded::from_str(self.args.cookie.as_deref().unwrap_or_default()).unwrap_or_default()
   }
 
   /// Generate the complete HIP report XML
@@ -150,14 +265,14 @@ impl<'a> HipHandler<'a> {
     let host_info = self.collect_host_info(cookie_params);
 
     let template = HipReportTemplate {
-      client_version: &self.args.client_version,
+      client_version: self.args.client_version.as_deref().unwrap_or_default(),
       generate_time,
       day,
       month,
       year,
       user_name,
       host_info,
-      md5: &self.args.md5,
+      md5: self.args.md5.as_deref().unwrap_or_default(),
     };
 
     let report = template.render()?;
@@ -168,7 +283,7 @@ impl<'a> HipHandler<'a> {
   fn collect_host_info(&self, cookie_params: &'a HashMap<String, String>) -> HostInfo<'a> {
     let collector = HostInfoCollector::new(self.args, cookie_params);
 
-    match self.args.client_os {
+    match self.args.client_os.as_ref().unwrap_or(&Os::Linux) {
       Os::Linux => collector.collect_linux(),
       Os::Mac => collector.collect_macos(),
       Os::Windows => collector.collect_windows(),
@@ -240,9 +355,9 @@ impl<'a> HostInfoCollector<'a> {
 
     HostInfo {
       os_vendor: "Linux",
-      os_version: &self.args.os_version,
+      os_version: self.args.os_version.as_deref().unwrap_or_default(),
       host_id: host_utils::derive_uuid(&[]),
-      host_name: whoami::devicename(),
+      host_name: host_utils::get_device_name().to_string(),
       software_version: "",
       domain: String::new(),
       network_interfaces: vec![iface],
@@ -278,9 +393,9 @@ impl<'a> HostInfoCollector<'a> {
 
     HostInfo {
       os_vendor: "Apple",
-      os_version: &self.args.os_version,
+      os_version: self.args.os_version.as_deref().unwrap_or_default(),
       host_id,
-      host_name: whoami::devicename(),
+      host_name: host_utils::get_device_name().to_string(),
       software_version: host_utils::get_macos_version(),
       domain: self.get_domain(),
       network_interfaces: vec![iface],
@@ -328,9 +443,9 @@ impl<'a> HostInfoCollector<'a> {
 
     HostInfo {
       os_vendor: "Microsoft",
-      os_version: &self.args.os_version,
+      os_version: self.args.os_version.as_deref().unwrap_or_default(),
       host_id,
-      host_name: whoami::devicename(),
+      host_name: host_utils::get_device_name().to_string(),
       software_version: host_utils::get_windows_version(),
       domain: self.get_domain(),
       network_interfaces: ifaces,
@@ -353,6 +468,24 @@ fn get_current_time_components() -> (String, String, String, String) {
   )
 }
 
+/// The cookie of the previewed HIP wrapper, the given one without the secrets or one with the local
+/// user and host
+fn preview_cookie(cookie: Option<&str>) -> anyhow::Result<String> {
+  let params = match cookie {
+    Some(cookie) => serde_urlencoded::from_str::<Vec<(String, String)>>(cookie)?
+      .into_iter()
+      .filter(|(key, _)| !SECRET_COOKIE_FIELDS.contains(&key.as_str()))
+      .collect(),
+    None => vec![
+      ("user".to_string(), whoami::username()),
+      ("domain".to_string(), String::new()),
+      ("computer".to_string(), host_utils::get_device_name().to_string()),
+    ],
+  };
+
+  Ok(serde_urlencoded::to_string(params)?)
+}
+
 /// Format XML string with proper indentation
 fn format_xml(xml_str: &str) -> anyhow::Result<String> {
   let xml = Element::parse(xml_str.as_bytes())?;
@@ -372,3 +505,70 @@ fn derive_windows_network_name(iface: &NetworkInterface) -> String {
 
   format!("{{{}}}", host_utils::derive_uuid(&seeds).to_uppercase())
 }
+
+#[cfg(test)]
+mod tests {
+  use std::{fs, os::unix::fs::PermissionsExt};
+
+  use clap::Parser;
+
+  use super::*;
+
+  #[derive(Parser)]
+  struct Cli {
+    #[command(flatten)]
+    args: HipArgs,
+  }
+
+  #[test]
+  fn preview_cookie_has_no_secrets() {
+    let cookie = "authcookie=secret&portal=gw&user=alice&domain=corp&computer=host&portal-userauthcookie=secret2";
+    assert_eq!(
+      preview_cookie(Some(cookie)).unwrap(),
+      "portal=gw&user=alice&domain=corp&computer=host"
+    );
+
+    let cookie = preview_cookie(None).unwrap();
+    assert!(cookie.starts_with(&format!("user={}&domain=&computer=", whoami::username())));
+  }
+
+  #[test]
+  fn report_arguments_are_required_without_preview() {
+    assert!(Cli::try_parse_from(["hip", "--client-os", "Linux"]).is_err());
+    assert!(Cli::try_parse_from(["hip", "--preview"]).is_ok());
+    assert!(Cli::try_parse_from(["hip", "--output", "report.xml"]).is_err());
+  }
+
+  #[tokio::test]
+  async fn preview_writes_the_wrapper_report() {
+    let dir = tempfile::tempdir().unwrap();
+    let wrapper = dir.path().join("hipreport.sh");
+    let output = dir.path().join("report.xml");
+    fs::write(&wrapper, "#!/bin/sh\necho \"<hip-report>$*</hip-report>\"\n").unwrap();
+    fs::set_permissions(&wrapper, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let cli = Cli::try_parse_from([
+      "hip",
+      "--preview",
+      "--csd-wrapper",
+      wrapper.to_str().unwrap(),
+      "--cookie",
+      "authcookie=secret&user=alice",
+      "--output",
+      output.to_str().unwrap(),
+    ])
+    .unwrap();
+    HipHandler::new(&cli.args).handle().await.unwrap();
+
+    assert_eq!(
+      fs::read_to_string(&output).unwrap(),
+      "<hip-report>--cookie user=alice --client-ip 127.0.0.1 --md5 00000000000000000000000000000000 --client-os Linux</hip-report>\n"
+    );
+  }
+
+  #[tokio::test]
+  async fn preview_fails_when_the_wrapper_cannot_run() {
+    let cli = Cli::try_parse_from(["hip", "--preview", "--csd-wrapper", "/nonexistent/hipreport.sh"]).unwrap();
+    assert!(HipHandler::new(&cli.args).handle().await.is_err());
+  }
+}
@@ -0,0 +1,87 @@
+use std::{
+  fmt,
+  sync::Arc,
+  time::{Duration, Instant},
+};
+
+use log::info;
+
+use crate::status_socket::ConnectionStatus;
+
+/// The exit code when the tunnel is disconnected for being idle
+pub(crate) const IDLE_TIMEOUT_EXIT_CODE: i32 = 3;
+
+/// The tunnel was disconnected for being idle
+#[derive(Debug)]
+pub(crate) struct IdleTimeout(pub(crate) Duration);
+
+impl fmt::Display for IdleTimeout {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Disconnected after no traffic for {}s", self.0.as_secs())
+  }
+}
+
+impl std::error::Error for IdleTimeout {}
+
+/// Return once the connected tunnel has had no traffic for the timeout, i.e., the byte counters
+/// have not changed. The time before connected is not counted.
+pub(crate) async fn wait_for_idle(status: Arc<ConnectionStatus>, timeout: Duration, poll_interval: Duration) {
+  let mut last_bytes = None;
+  let mut last_activity = Instant::now();
+
+  loop {
+    tokio::time::sleep(poll_interval).await;
+
+    if !status.is_connected() {
+      last_activity = Instant::now();
+      continue;
+    }
+
+    let stats = status.stats();
+    let bytes = stats.rx_bytes + stats.tx_bytes;
+    if last_bytes != Some(bytes) {
+      last_bytes = Some(bytes);
+      last_activity = Instant::now();
+    } else if last_activity.elapsed() >= timeout {
+      info!("No traffic for {}s, the tunnel is idle", timeout.as_secs());
+      return;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::atomic::{AtomicU64, Ordering};
+
+  use openconnect::VpnStats;
+
+  use super::*;
+
+  #[tokio::test]
+  async fn flat_counters_trigger_idle() {
+    let status = Arc::new(ConnectionStatus::new("gw.example.com", || VpnStats {
+      rx_bytes: 1024,
+      tx_bytes: 512,
+    }));
+    status.set_connected();
+
+    let idle = wait_for_idle(status, Duration::from_millis(50), Duration::from_millis(10));
+    tokio::time::timeout(Duration::from_secs(1), idle)
+      .await
+      .expect("the idle tunnel was not detected");
+  }
+
+  #[tokio::test]
+  async fn traffic_keeps_tunnel_active() {
+    let counter = Arc::new(AtomicU64::new(0));
+    let counter_clone = Arc::clone(&counter);
+    let status = Arc::new(ConnectionStatus::new("gw.example.com", move || VpnStats {
+      rx_bytes: counter_clone.fetch_add(1, Ordering::SeqCst),
+      tx_bytes: 0,
+    }));
+    status.set_connected();
+
+    let idle = wait_for_idle(status, Duration::from_millis(50), Duration::from_millis(10));
+    assert!(tokio::time::timeout(Duration::from_millis(200), idle).await.is_err());
+  }
+}
@@ -0,0 +1,173 @@
+use std::{
+  ffi::OsString,
+  fs::{self, File, OpenOptions},
+  io::{self, Write},
+  os::unix::fs::OpenOptionsExt,
+  path::{Path, PathBuf},
+};
+
+/// The default number of rotated log files to keep, e.g., `gpclient.log.1`, `gpclient.log.2`
+pub(crate) const DEFAULT_ROTATED_FILES: usize = 2;
+
+/// A log file writer with optional size-based rotation.
+/// When the file would exceed `max_size` bytes, it is renamed to `<path>.1`,
+/// the existing `<path>.1` to `<path>.2`, and so on, up to `max_files`.
+/// The log files are created readable by the owner only, the logs may hold the server details.
+pub(crate) struct RotatingFileWriter {
+  path: PathBuf,
+  max_size: Option<u64>,
+  max_files: usize,
+  file: File,
+  size: u64,
+}
+
+impl RotatingFileWriter {
+  pub(crate) fn new<P: Into<PathBuf>>(path: P, max_size: Option<u64>, max_files: usize) -> io::Result<Self> {
+    let path = path.into();
+    let file = open_log(&path, false)?;
+    let size = file.metadata()?.len();
+
+    Ok(Self {
+      path,
+      max_size,
+      max_files,
+      file,
+      size,
+    })
+  }
+
+  fn should_rotate(&self, incoming: usize) -> bool {
+    match self.max_size {
+      Some(max_size) => self.size > 0 && self.size + incoming as u64 > max_size,
+      None => false,
+    }
+  }
+
+  fn rotate(&mut self) -> io::Result<()> {
+    self.file.flush()?;
+
+    if self.max_files == 0 {
+      self.file = open_log(&self.path, true)?;
+      self.size = 0;
+      return Ok(());
+    }
+
+    for index in (1..self.max_files).rev() {
+      let from = rotated_path(&self.path, index);
+      if from.exists() {
+        fs::rename(&from, rotated_path(&self.path, index + 1))?;
+      }
+    }
+
+    fs::rename(&self.path, rotated_path(&self.path, 1))?;
+    self.file = open_log(&self.path, false)?;
+    self.size = 0;
+
+    Ok(())
+  }
+}
+
+impl Write for RotatingFileWriter {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    if self.should_rotate(buf.len()) {
+      self.rotate()?;
+    }
+
+    let written = self.file.write(buf)?;
+    self.size += written as u64;
+
+    Ok(written)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.file.flush()
+  }
+}
+
+/// Open the log file to append, or truncated, the mode applies only when the file is created
+fn open_log(path: &Path, truncate: bool) -> io::Result<File> {
+  OpenOptions::new()
+    .create(true)
+    .append(!truncate)
+    .write(true)
+    .truncate(truncate)
+    .mode(0o600)
+    .open(path)
+}
+
+/// Get the path of the rotated log file, e.g., `gpclient.log` -> `gpclient.log.1`
+pub(crate) fn rotated_path(path: &Path, index: usize) -> PathBuf {
+  let mut name = OsString::from(path.as_os_str());
+  name.push(format!(".{}", index));
+
+  PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::os::unix::fs::PermissionsExt;
+
+  use super::*;
+
+  #[test]
+  fn rotates_when_size_threshold_is_reached() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("gpclient.log");
+    let mut writer = RotatingFileWriter::new(&path, Some(10), DEFAULT_ROTATED_FILES).unwrap();
+
+    writer.write_all(b"12345678").unwrap();
+    assert!(!rotated_path(&path, 1).exists());
+
+    writer.write_all(b"abcdef").unwrap();
+    writer.flush().unwrap();
+
+    assert_eq!(fs::read_to_string(rotated_path(&path, 1)).unwrap(), "12345678");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "abcdef");
+  }
+
+  #[test]
+  fn keeps_at_most_max_rotated_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("gpclient.log");
+    let mut writer = RotatingFileWriter::new(&path, Some(4), 2).unwrap();
+
+    for chunk in ["aaaa", "bbbb", "cccc", "dddd"] {
+      writer.write_all(chunk.as_bytes()).unwrap();
+    }
+    writer.flush().unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "dddd");
+    assert_eq!(fs::read_to_string(rotated_path(&path, 1)).unwrap(), "cccc");
+    assert_eq!(fs::read_to_string(rotated_path(&path, 2)).unwrap(), "bbbb");
+    assert!(!rotated_path(&path, 3).exists());
+  }
+
+  #[test]
+  fn does_not_rotate_without_max_size() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("gpclient.log");
+    let mut writer = RotatingFileWriter::new(&path, None, DEFAULT_ROTATED_FILES).unwrap();
+
+    writer.write_all(&[b'x'; 1024]).unwrap();
+    writer.flush().unwrap();
+
+    assert!(!rotated_path(&path, 1).exists());
+  }
+
+  #[test]
+  fn log_files_are_readable_by_the_owner_only() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("gpclient.log");
+    let mut writer = RotatingFileWriter::new(&path, Some(4), DEFAULT_ROTATED_FILES).unwrap();
+
+    for chunk in ["aaaa", "bbbb"] {
+      writer.write_all(chunk.as_bytes()).unwrap();
+    }
+    writer.flush().unwrap();
+
+    for path in [path.clone(), rotated_path(&path, 1)] {
+      let mode = fs::metadata(&path).unwrap().permissions().mode();
+      assert_eq!(mode & 0o777, 0o600, "{}", path.display());
+    }
+  }
+}
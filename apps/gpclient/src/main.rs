@@ -1,11 +1,32 @@
+mod background;
+mod benchmark;
 mod cli;
+mod client_lock;
+mod clock_skew;
+mod config_refresh;
 mod connect;
+mod credentials_file;
 mod diagnose;
 mod disconnect;
+mod dtls_monitor;
+mod health_check;
 mod hip;
+mod idle_monitor;
 mod launch_gui;
+mod log_file;
+mod metrics;
+mod mtu_probe;
+mod resolv_conf;
+mod saml_browser;
+mod session_cache;
+mod session_ttl;
+mod ssh_forward;
+mod status_socket;
+mod validate;
 
 pub(crate) const GP_CLIENT_LOCK_FILE: &str = "/var/run/gpclient.lock";
+/// The vpnc-script environment of the connected tunnel, to run the teardown if the client is stuck
+pub(crate) const GP_CLIENT_SCRIPT_ENV_FILE: &str = "/var/run/gpclient.env";
 
 #[tokio::main]
 async fn main() {
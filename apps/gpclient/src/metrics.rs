@@ -0,0 +1,207 @@
+use std::{
+  collections::BTreeMap,
+  fmt::Write,
+  sync::{
+    Arc, Mutex,
+    atomic::{AtomicU64, Ordering},
+  },
+};
+
+use log::{info, warn};
+use tokio::{
+  io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+  net::{TcpListener, TcpStream},
+};
+
+use crate::status_socket::ConnectionStatus;
+
+/// The phases of the connect flow, used to label the failures
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Phase {
+  Prelogin,
+  PortalConfig,
+  GatewayLogin,
+  Tunnel,
+}
+
+impl Phase {
+  fn as_str(&self) -> &'static str {
+    match self {
+      Phase::Prelogin => "prelogin",
+      Phase::PortalConfig => "portal_config",
+      Phase::GatewayLogin => "gateway_login",
+      Phase::Tunnel => "tunnel",
+    }
+  }
+}
+
+/// The connection metrics, rendered in the Prometheus text format
+#[derive(Default)]
+pub(crate) struct Metrics {
+  connect_attempts: AtomicU64,
+  phase: Mutex<Option<Phase>>,
+  failures: Mutex<BTreeMap<Phase, u64>>,
+  status: Mutex<Option<Arc<ConnectionStatus>>>,
+}
+
+impl Metrics {
+  pub(crate) fn record_attempt(&self) {
+    self.connect_attempts.fetch_add(1, Ordering::Relaxed);
+  }
+
+  /// Enter a phase, a failure recorded later is attributed to it
+  pub(crate) fn enter_phase(&self, phase: Phase) {
+    self.phase.lock().unwrap().replace(phase);
+  }
+
+  pub(crate) fn record_failure(&self) {
+    let phase = self.phase.lock().unwrap().unwrap_or(Phase::Prelogin);
+    *self.failures.lock().unwrap().entry(phase).or_default() += 1;
+  }
+
+  /// Track the tunnel status and traffic of the current connection
+  pub(crate) fn set_status(&self, status: Arc<ConnectionStatus>) {
+    self.status.lock().unwrap().replace(status);
+  }
+
+  pub(crate) fn render(&self) -> String {
+    let mut out = String::new();
+
+    let _ = writeln!(
+      out,
+      "# HELP gpclient_connect_attempts_total The number of connect attempts"
+    );
+    let _ = writeln!(out, "# TYPE gpclient_connect_attempts_total counter");
+    let _ = writeln!(
+      out,
+      "gpclient_connect_attempts_total {}",
+      self.connect_attempts.load(Ordering::Relaxed)
+    );
+
+    let _ = writeln!(
+      out,
+      "# HELP gpclient_connect_failures_total The number of connect failures by phase"
+    );
+    let _ = writeln!(out, "# TYPE gpclient_connect_failures_total counter");
+    for (phase, count) in self.failures.lock().unwrap().iter() {
+      let _ = writeln!(
+        out,
+        "gpclient_connect_failures_total{{phase=\"{}\"}} {}",
+        phase.as_str(),
+        count
+      );
+    }
+
+    let status = self.status.lock().unwrap().clone();
+    let tunnel_up = status.as_ref().is_some_and(|s| s.is_connected());
+    let stats = status.map(|s| s.stats()).unwrap_or_default();
+
+    let _ = writeln!(out, "# HELP gpclient_tunnel_up Whether the tunnel is up");
+    let _ = writeln!(out, "# TYPE gpclient_tunnel_up gauge");
+    let _ = writeln!(out, "gpclient_tunnel_up {}", tunnel_up as u8);
+
+    let _ = writeln!(
+      out,
+      "# HELP gpclient_tunnel_rx_bytes_total The bytes received through the tunnel"
+    );
+    let _ = writeln!(out, "# TYPE gpclient_tunnel_rx_bytes_total counter");
+    let _ = writeln!(out, "gpclient_tunnel_rx_bytes_total {}", stats.rx_bytes);
+
+    let _ = writeln!(
+      out,
+      "# HELP gpclient_tunnel_tx_bytes_total The bytes sent through the tunnel"
+    );
+    let _ = writeln!(out, "# TYPE gpclient_tunnel_tx_bytes_total counter");
+    let _ = writeln!(out, "gpclient_tunnel_tx_bytes_total {}", stats.tx_bytes);
+
+    out
+  }
+}
+
+/// Serve the metrics on `GET /metrics` at the address
+pub(crate) async fn serve_metrics(addr: &str, metrics: Arc<Metrics>) -> anyhow::Result<()> {
+  let listener = TcpListener::bind(addr).await?;
+  info!("Serving the metrics on http://{}/metrics", listener.local_addr()?);
+
+  tokio::spawn(async move {
+    loop {
+      match listener.accept().await {
+        Ok((stream, _)) => {
+          tokio::spawn(handle_client(stream, Arc::clone(&metrics)));
+        }
+        Err(err) => {
+          warn!("Failed to accept metrics connection: {}", err);
+          break;
+        }
+      }
+    }
+  });
+
+  Ok(())
+}
+
+async fn handle_client(stream: TcpStream, metrics: Arc<Metrics>) {
+  let (reader, mut writer) = stream.into_split();
+
+  let mut request_line = String::new();
+  if let Err(err) = BufReader::new(reader).read_line(&mut request_line).await {
+    warn!("Failed to read metrics request: {}", err);
+    return;
+  }
+
+  let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+  let response = if path == "/metrics" {
+    let body = metrics.render();
+    format!(
+      "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+      body.len(),
+      body
+    )
+  } else {
+    String::from("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+  };
+
+  if let Err(err) = writer.write_all(response.as_bytes()).await {
+    warn!("Failed to write metrics response: {}", err);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use openconnect::VpnStats;
+  use tokio::io::AsyncReadExt;
+
+  use super::*;
+
+  #[tokio::test]
+  async fn metrics_endpoint_renders_expected_metrics() {
+    let metrics = Arc::new(Metrics::default());
+    metrics.record_attempt();
+    metrics.enter_phase(Phase::GatewayLogin);
+    metrics.record_failure();
+
+    let status = Arc::new(ConnectionStatus::new("gw.example.com", || VpnStats {
+      rx_bytes: 2048,
+      tx_bytes: 1024,
+    }));
+    status.set_connected();
+    metrics.set_status(status);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    drop(listener);
+    serve_metrics(&addr, Arc::clone(&metrics)).await.unwrap();
+
+    let mut stream = TcpStream::connect(&addr).await.unwrap();
+    stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("gpclient_connect_attempts_total 1"));
+    assert!(response.contains("gpclient_connect_failures_total{phase=\"gateway_login\"} 1"));
+    assert!(response.contains("gpclient_tunnel_up 1"));
+    assert!(response.contains("gpclient_tunnel_rx_bytes_total 2048"));
+    assert!(response.contains("gpclient_tunnel_tx_bytes_total 1024"));
+  }
+}
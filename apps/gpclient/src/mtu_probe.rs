@@ -0,0 +1,282 @@
+use std::{
+  fs,
+  net::ToSocketAddrs,
+  process::{Command, Stdio},
+};
+
+use log::{debug, info, warn};
+
+/// The largest MTU to probe when the MTU of the path is unknown
+pub(crate) const ETHERNET_MTU: u32 = 1500;
+
+/// The MTU sizes to probe, in decreasing order, 576 is the minimum MTU openconnect accepts
+const PROBE_MTUS: &[u32] = &[
+  ETHERNET_MTU,
+  1450,
+  1400,
+  1350,
+  1300,
+  1200,
+  1100,
+  1000,
+  900,
+  800,
+  700,
+  576,
+];
+
+/// The IPv4 header (20 bytes) and the ICMP header (8 bytes) added to the ping payload
+const IPV4_ICMP_HEADER_SIZE: u32 = 28;
+
+/// The largest overhead of the tunnel over IPv4, i.e., ESP over UDP with the IV, the padding and the ICV
+const TUNNEL_OVERHEAD: u32 = 88;
+
+/// Send a packet of the MTU size to the host, without fragmenting it
+pub(crate) trait MtuProber {
+  fn probe(&self, host: &str, mtu: u32) -> bool;
+}
+
+/// Probe with the DF-set ping
+pub(crate) struct PingProber;
+
+impl MtuProber for PingProber {
+  fn probe(&self, host: &str, mtu: u32) -> bool {
+    ping_with_df(host, mtu - IPV4_ICMP_HEADER_SIZE)
+  }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum MtuProbeOutcome {
+  /// The largest probed packet went through, no black hole detected
+  Healthy(u32),
+  /// The large packets were dropped silently, the MTU is the largest that went through
+  BlackHole(u32),
+  /// No probe went through, the host may not answer the ping at all
+  Unreachable,
+}
+
+/// Interpret the probe results, ordered by decreasing MTU as `(mtu, succeeded)`
+pub(crate) fn interpret_probe(results: &[(u32, bool)]) -> MtuProbeOutcome {
+  let Some(index) = results.iter().position(|(_, ok)| *ok) else {
+    return MtuProbeOutcome::Unreachable;
+  };
+
+  let safe_mtu = results[index].0;
+  if index == 0 {
+    MtuProbeOutcome::Healthy(safe_mtu)
+  } else {
+    MtuProbeOutcome::BlackHole(safe_mtu)
+  }
+}
+
+/// Probe the largest MTU first, then binary search the smaller ones for the largest that gets a reply.
+/// The probes start at `max_mtu`, e.g., the tunnel MTU, the larger packets can never go through.
+pub(crate) fn probe_mtu(prober: &impl MtuProber, host: &str, max_mtu: u32) -> MtuProbeOutcome {
+  let candidates: Vec<u32> = std::iter::once(max_mtu)
+    .chain(PROBE_MTUS.iter().copied().filter(|mtu| *mtu < max_mtu))
+    .collect();
+  let mut results = Vec::new();
+  let mut probe = |mtu: u32| {
+    let ok = prober.probe(host, mtu);
+    debug!(
+      "MTU probe to {} with MTU {}: {}",
+      host,
+      mtu,
+      if ok { "ok" } else { "failed" }
+    );
+    results.push((mtu, ok));
+    ok
+  };
+
+  if !probe(candidates[0]) {
+    // The candidates in decreasing order, the ones before `low` failed
+    let (mut low, mut high) = (1, candidates.len());
+    while low < high {
+      let mid = (low + high) / 2;
+      if probe(candidates[mid]) {
+        high = mid;
+      } else {
+        low = mid + 1;
+      }
+    }
+  }
+
+  results.sort_by(|a, b| b.0.cmp(&a.0));
+  interpret_probe(&results)
+}
+
+/// The tunnel MTU for the path MTU to the gateway, `None` if the gateway does not answer the ping.
+/// The probes start at `max_mtu`, e.g., the `route_mtu` to the gateway.
+pub(crate) fn probe_tunnel_mtu(prober: &impl MtuProber, gateway_host: &str, max_mtu: u32) -> Option<u32> {
+  let outcome = probe_mtu(prober, gateway_host, max_mtu);
+  report_mtu_probe(gateway_host, &outcome);
+
+  match outcome {
+    MtuProbeOutcome::Healthy(mtu) | MtuProbeOutcome::BlackHole(mtu) => {
+      let min_mtu = PROBE_MTUS[PROBE_MTUS.len() - 1];
+      Some(mtu.saturating_sub(TUNNEL_OVERHEAD).max(min_mtu))
+    }
+    MtuProbeOutcome::Unreachable => None,
+  }
+}
+
+/// The MTU of the route to the host, i.e., the route MTU or the MTU of its interface, capped by the
+/// Ethernet MTU, which the Internet paths rarely exceed
+pub(crate) fn route_mtu(host: &str) -> Option<u32> {
+  let addr = (host, 0).to_socket_addrs().ok()?.next()?.ip();
+  let output = Command::new("ip")
+    .args(["route", "get"])
+    .arg(addr.to_string())
+    .stderr(Stdio::null())
+    .output()
+    .ok()?;
+  let route = String::from_utf8_lossy(&output.stdout);
+
+  let mtu = match parse_route(&route) {
+    (Some(mtu), _) => mtu,
+    (None, Some(dev)) => fs::read_to_string(format!("/sys/class/net/{}/mtu", dev))
+      .ok()?
+      .trim()
+      .parse()
+      .ok()?,
+    (None, None) => return None,
+  };
+  debug!("The MTU of the route to {}: {}", host, mtu);
+
+  Some(mtu.min(ETHERNET_MTU))
+}
+
+/// Parse the `mtu` and the `dev` of the `ip route get` output, e.g.,
+/// `1.2.3.4 via 192.168.1.1 dev eth0 src 192.168.1.2 uid 0`
+fn parse_route(route: &str) -> (Option<u32>, Option<&str>) {
+  let words = route.split_whitespace().collect::<Vec<_>>();
+  let value = |name: &str| words.windows(2).find(|pair| pair[0] == name).map(|pair| pair[1]);
+
+  (value("mtu").and_then(|mtu| mtu.parse().ok()), value("dev"))
+}
+
+pub(crate) fn report_mtu_probe(host: &str, outcome: &MtuProbeOutcome) {
+  match outcome {
+    MtuProbeOutcome::Healthy(mtu) => info!("MTU probe to {}: packets of MTU {} went through", host, mtu),
+    MtuProbeOutcome::BlackHole(mtu) => warn!(
+      "MTU probe to {}: larger packets are dropped, the safe MTU is {}, consider connecting with `--mtu {}`",
+      host, mtu, mtu
+    ),
+    MtuProbeOutcome::Unreachable => warn!("MTU probe to {}: no reply received, the host may not answer ping", host),
+  }
+}
+
+fn ping_with_df(host: &str, payload_size: u32) -> bool {
+  Command::new("ping")
+    .args(["-M", "do", "-c", "1", "-W", "2", "-s"])
+    .arg(payload_size.to_string())
+    .arg(host)
+    .stdout(Stdio::null())
+    .stderr(Stdio::null())
+    .status()
+    .is_ok_and(|status| status.success())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::cell::RefCell;
+
+  use super::*;
+
+  /// Replies to the packets up to the path MTU, and records the probed MTUs
+  struct MockProber {
+    path_mtu: Option<u32>,
+    probed: RefCell<Vec<u32>>,
+  }
+
+  impl MockProber {
+    fn new(path_mtu: Option<u32>) -> Self {
+      Self {
+        path_mtu,
+        probed: RefCell::default(),
+      }
+    }
+  }
+
+  impl MtuProber for MockProber {
+    fn probe(&self, _host: &str, mtu: u32) -> bool {
+      self.probed.borrow_mut().push(mtu);
+      self.path_mtu.is_some_and(|path_mtu| mtu <= path_mtu)
+    }
+  }
+
+  #[test]
+  fn probes_the_largest_mtu_that_goes_through() {
+    let prober = MockProber::new(Some(1500));
+    assert_eq!(probe_mtu(&prober, "gw", 1500), MtuProbeOutcome::Healthy(1500));
+    assert_eq!(*prober.probed.borrow(), [1500]);
+
+    for (path_mtu, expected) in [(1492, 1450), (1400, 1400), (1000, 1000), (576, 576)] {
+      let prober = MockProber::new(Some(path_mtu));
+      assert_eq!(probe_mtu(&prober, "gw", 1500), MtuProbeOutcome::BlackHole(expected));
+      // The binary search instead of probing every size
+      assert!(prober.probed.borrow().len() <= 5, "{:?}", prober.probed.borrow());
+    }
+
+    assert_eq!(
+      probe_mtu(&MockProber::new(None), "gw", 1500),
+      MtuProbeOutcome::Unreachable
+    );
+  }
+
+  #[test]
+  fn probes_start_at_the_tunnel_mtu() {
+    // The packets up to the tunnel MTU go through the tunnel
+    let prober = MockProber::new(Some(1400));
+    assert_eq!(probe_mtu(&prober, "host", 1400), MtuProbeOutcome::Healthy(1400));
+    assert_eq!(*prober.probed.borrow(), [1400]);
+
+    let prober = MockProber::new(Some(1412));
+    assert_eq!(probe_mtu(&prober, "host", 1412), MtuProbeOutcome::Healthy(1412));
+
+    // The black hole below the tunnel MTU
+    let prober = MockProber::new(Some(1300));
+    assert_eq!(probe_mtu(&prober, "host", 1412), MtuProbeOutcome::BlackHole(1300));
+    assert!(prober.probed.borrow().iter().all(|mtu| *mtu <= 1412));
+  }
+
+  #[test]
+  fn tunnel_mtu_leaves_room_for_the_overhead() {
+    assert_eq!(probe_tunnel_mtu(&MockProber::new(Some(1500)), "gw", 1500), Some(1412));
+    assert_eq!(probe_tunnel_mtu(&MockProber::new(Some(1400)), "gw", 1500), Some(1312));
+    assert_eq!(probe_tunnel_mtu(&MockProber::new(Some(576)), "gw", 1500), Some(576));
+    assert_eq!(probe_tunnel_mtu(&MockProber::new(None), "gw", 1500), None);
+
+    // The PPPoE link of the MTU 1492 is not taken for a black hole
+    let prober = MockProber::new(Some(1492));
+    assert_eq!(probe_tunnel_mtu(&prober, "gw", 1492), Some(1404));
+    assert_eq!(*prober.probed.borrow(), [1492]);
+  }
+
+  #[test]
+  fn route_mtu_and_device_are_parsed() {
+    assert_eq!(
+      parse_route("1.2.3.4 via 192.168.1.1 dev eth0 src 192.168.1.2 uid 0 \n    cache "),
+      (None, Some("eth0"))
+    );
+    assert_eq!(
+      parse_route("1.2.3.4 dev ppp0 src 10.0.0.2 uid 0 \n    cache mtu 1492 "),
+      (Some(1492), Some("ppp0"))
+    );
+    assert_eq!(parse_route(""), (None, None));
+  }
+
+  #[test]
+  fn interprets_probe_results() {
+    assert_eq!(interpret_probe(&[(1500, true)]), MtuProbeOutcome::Healthy(1500));
+    assert_eq!(
+      interpret_probe(&[(1500, false), (1450, false), (1400, true)]),
+      MtuProbeOutcome::BlackHole(1400)
+    );
+    assert_eq!(
+      interpret_probe(&[(1500, false), (576, false)]),
+      MtuProbeOutcome::Unreachable
+    );
+    assert_eq!(interpret_probe(&[]), MtuProbeOutcome::Unreachable);
+  }
+}
@@ -0,0 +1,137 @@
+use std::{
+  fs,
+  io::{self, Write},
+  os::unix::fs::PermissionsExt,
+  path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+use openconnect::VpnDnsInfo;
+
+/// Render the DNS servers and search domains in the resolv.conf format
+pub(crate) fn render_resolv_conf(dns_info: &VpnDnsInfo) -> String {
+  let mut content = String::from("# Generated by gpclient\n");
+
+  if !dns_info.search_domains.is_empty() {
+    content.push_str(&format!("search {}\n", dns_info.search_domains.join(" ")));
+  }
+
+  for server in &dns_info.servers {
+    content.push_str(&format!("nameserver {}\n", server));
+  }
+
+  content
+}
+
+/// The resolv.conf written by `--write-resolv`, with the original content to restore
+/// if `--restore-resolv` is set.
+pub(crate) struct ResolvConfWriter {
+  path: PathBuf,
+  /// The original content, the inner None means the file did not exist
+  backup: Option<Option<Vec<u8>>>,
+}
+
+impl ResolvConfWriter {
+  pub(crate) fn new(path: impl Into<PathBuf>) -> Self {
+    Self {
+      path: path.into(),
+      backup: None,
+    }
+  }
+
+  pub(crate) fn write(&mut self, dns_info: &VpnDnsInfo, restore: bool) -> io::Result<()> {
+    if restore && self.backup.is_none() {
+      let original = match fs::read(&self.path) {
+        Ok(content) => Some(content),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err),
+      };
+      self.backup = Some(original);
+    }
+
+    write_atomic(&self.path, render_resolv_conf(dns_info).as_bytes())?;
+    info!("Wrote the tunnel DNS settings to {}", self.path.display());
+
+    Ok(())
+  }
+
+  /// Restore the original file, no-op if `write()` was not called with `restore`
+  pub(crate) fn restore(&mut self) {
+    let Some(original) = self.backup.take() else {
+      return;
+    };
+
+    let result = match original {
+      Some(content) => write_atomic(&self.path, &content),
+      None => fs::remove_file(&self.path),
+    };
+
+    match result {
+      Ok(()) => info!("Restored {}", self.path.display()),
+      Err(err) => warn!("Failed to restore {}: {}", self.path.display(), err),
+    }
+  }
+}
+
+/// Write to a temporary file in the same directory then rename it, so that the readers never
+/// see a partially written file.
+fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+  let file_name = path
+    .file_name()
+    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid resolv.conf path"))?;
+  let tmp_path = path.with_file_name(format!(".{}.{}.tmp", file_name.to_string_lossy(), std::process::id()));
+
+  let result = (|| {
+    let mut file = fs::File::create(&tmp_path)?;
+    file.write_all(content)?;
+    file.set_permissions(fs::Permissions::from_mode(0o644))?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)
+  })();
+
+  if result.is_err() {
+    let _ = fs::remove_file(&tmp_path);
+  }
+
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn dns_info() -> VpnDnsInfo {
+    VpnDnsInfo {
+      servers: vec!["10.0.0.53".parse().unwrap(), "fd00::53".parse().unwrap()],
+      search_domains: vec!["corp.example.com".to_string(), "example.com".to_string()],
+    }
+  }
+
+  #[test]
+  fn renders_resolv_conf() {
+    assert_eq!(
+      render_resolv_conf(&dns_info()),
+      "# Generated by gpclient\n\
+       search corp.example.com example.com\n\
+       nameserver 10.0.0.53\n\
+       nameserver fd00::53\n"
+    );
+  }
+
+  #[test]
+  fn writes_and_restores_resolv_conf() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("resolv.conf");
+    fs::write(&path, "nameserver 192.168.1.1\n").unwrap();
+
+    let mut writer = ResolvConfWriter::new(&path);
+    writer.write(&dns_info(), true).unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), render_resolv_conf(&dns_info()));
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o644);
+
+    writer.restore();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "nameserver 192.168.1.1\n");
+  }
+}
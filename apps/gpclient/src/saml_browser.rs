@@ -0,0 +1,152 @@
+use anyhow::bail;
+use clap::ValueEnum;
+
+/// The `--saml-browser` option
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum SamlBrowserMode {
+  /// Follow the server capability and the browser options
+  #[default]
+  Auto,
+  /// Always use the embedded webview
+  Embedded,
+  /// Always use the system browser
+  External,
+}
+
+/// The capabilities that decide how the SAML page can be opened
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BrowserCapabilities {
+  /// Whether the server supports the authentication via the default browser
+  pub(crate) server_supports_browser: bool,
+  /// Whether gpclient is built with the `webview-auth` feature
+  pub(crate) webview: bool,
+  /// Whether a graphical display is available
+  pub(crate) display: bool,
+}
+
+impl BrowserCapabilities {
+  pub(crate) fn detect(server_supports_browser: bool) -> Self {
+    Self {
+      server_supports_browser,
+      webview: cfg!(feature = "webview-auth"),
+      display: cfg!(target_os = "macos")
+        || std::env::var_os("DISPLAY").is_some()
+        || std::env::var_os("WAYLAND_DISPLAY").is_some(),
+    }
+  }
+}
+
+/// How the SAML page is opened by gpauth
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum SamlBrowser<'a> {
+  /// The embedded webview, i.e., no browser option is passed to gpauth
+  Embedded,
+  /// The default browser, i.e., `--default-browser` is passed to gpauth
+  DefaultBrowser,
+  /// The given browser, e.g., `firefox`, `remote`
+  Browser(&'a str),
+}
+
+/// Select how to open the SAML page, `browser` and `default_browser` are the `--browser` and
+/// `--default-browser` options.
+pub(crate) fn select_saml_browser<'a>(
+  mode: SamlBrowserMode,
+  capabilities: BrowserCapabilities,
+  browser: Option<&'a str>,
+  default_browser: bool,
+) -> anyhow::Result<SamlBrowser<'a>> {
+  match mode {
+    SamlBrowserMode::Auto if capabilities.server_supports_browser => Ok(match browser {
+      Some(browser) => SamlBrowser::Browser(browser),
+      None if default_browser => SamlBrowser::DefaultBrowser,
+      None => SamlBrowser::Embedded,
+    }),
+    SamlBrowserMode::Auto | SamlBrowserMode::Embedded => {
+      if !capabilities.webview {
+        if mode == SamlBrowserMode::Auto {
+          bail!(
+            "The server does not support authentication via the default browser and the gpclient is not built with the `webview-auth` feature"
+          );
+        }
+        bail!("Cannot use the embedded webview, the gpclient is not built with the `webview-auth` feature");
+      }
+      Ok(SamlBrowser::Embedded)
+    }
+    SamlBrowserMode::External => match browser {
+      // The remote browser does not need a local display
+      Some("remote") => Ok(SamlBrowser::Browser("remote")),
+      _ if !capabilities.display => {
+        bail!("Cannot open the external browser, no display is available. Use `--browser remote` on headless hosts")
+      }
+      Some(browser) => Ok(SamlBrowser::Browser(browser)),
+      None => Ok(SamlBrowser::Browser("default")),
+    },
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn capabilities(server_supports_browser: bool, webview: bool, display: bool) -> BrowserCapabilities {
+    BrowserCapabilities {
+      server_supports_browser,
+      webview,
+      display,
+    }
+  }
+
+  #[test]
+  fn auto_follows_server_capability() {
+    let select =
+      |caps, browser, default_browser| select_saml_browser(SamlBrowserMode::Auto, caps, browser, default_browser);
+
+    assert_eq!(
+      select(capabilities(true, true, true), Some("firefox"), false).unwrap(),
+      SamlBrowser::Browser("firefox")
+    );
+    assert_eq!(
+      select(capabilities(true, true, true), None, true).unwrap(),
+      SamlBrowser::DefaultBrowser
+    );
+    assert_eq!(
+      select(capabilities(true, true, true), None, false).unwrap(),
+      SamlBrowser::Embedded
+    );
+    // The browser option is ignored if the server does not support it
+    assert_eq!(
+      select(capabilities(false, true, true), Some("firefox"), false).unwrap(),
+      SamlBrowser::Embedded
+    );
+    assert!(select(capabilities(false, false, true), None, false).is_err());
+  }
+
+  #[test]
+  fn embedded_overrides_server_capability() {
+    let select = |caps| select_saml_browser(SamlBrowserMode::Embedded, caps, Some("firefox"), true);
+
+    assert_eq!(select(capabilities(true, true, true)).unwrap(), SamlBrowser::Embedded);
+    assert!(select(capabilities(true, false, true)).is_err());
+  }
+
+  #[test]
+  fn external_overrides_server_capability() {
+    let select = |caps, browser| select_saml_browser(SamlBrowserMode::External, caps, browser, false);
+
+    assert_eq!(
+      select(capabilities(false, true, true), None).unwrap(),
+      SamlBrowser::Browser("default")
+    );
+    assert_eq!(
+      select(capabilities(false, false, true), Some("chrome")).unwrap(),
+      SamlBrowser::Browser("chrome")
+    );
+    // Headless hosts can only use the remote browser
+    let err = select(capabilities(true, true, false), None).unwrap_err();
+    assert!(err.to_string().contains("--browser remote"));
+    assert_eq!(
+      select(capabilities(true, true, false), Some("remote")).unwrap(),
+      SamlBrowser::Browser("remote")
+    );
+  }
+}
@@ -0,0 +1,345 @@
+use std::{
+  fs::{self, DirBuilder, OpenOptions},
+  io::{self, Write},
+  os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt},
+  path::PathBuf,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use directories::ProjectDirs;
+use log::{info, warn};
+use serde_json::{Value, json};
+
+/// How long the cached session is reused, the gateway may reject the cookie earlier,
+/// which falls back to the full login
+pub(crate) const SESSION_CACHE_TTL: Duration = Duration::from_secs(8 * 60 * 60);
+
+/// The gateway cookie and the portal config settings to reconnect without the login
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CachedSession {
+  pub(crate) server: String,
+  pub(crate) username: String,
+  pub(crate) gateway: String,
+  /// The name of the gateway in the portal config, the same as the address without the portal
+  pub(crate) gateway_name: String,
+  pub(crate) cookie: String,
+  pub(crate) client_version: Option<String>,
+  pub(crate) mtu: u32,
+  pub(crate) hip: bool,
+  /// The expiry in seconds since the Unix epoch
+  pub(crate) expires_at: u64,
+}
+
+impl CachedSession {
+  fn to_json(&self) -> Value {
+    json!({
+      "server": self.server,
+      "username": self.username,
+      "gateway": self.gateway,
+      "gateway_name": self.gateway_name,
+      "cookie": self.cookie,
+      "client_version": self.client_version,
+      "mtu": self.mtu,
+      "hip": self.hip,
+      "expires_at": self.expires_at,
+    })
+  }
+
+  fn from_json(value: &Value) -> Option<Self> {
+    let text = |key: &str| value[key].as_str().map(String::from);
+    let gateway = text("gateway")?;
+
+    Some(Self {
+      server: text("server")?,
+      username: text("username")?,
+      gateway_name: text("gateway_name").unwrap_or_else(|| gateway.clone()),
+      gateway,
+      cookie: text("cookie")?,
+      client_version: text("client_version"),
+      mtu: value["mtu"].as_u64().and_then(|mtu| mtu.try_into().ok())?,
+      hip: value["hip"].as_bool()?,
+      expires_at: value["expires_at"].as_u64()?,
+    })
+  }
+
+  fn is_expired(&self, now: SystemTime) -> bool {
+    unix_secs(now) >= self.expires_at
+  }
+
+  /// The session of the same server, user and gateway is replaced when stored again
+  fn same_key(&self, other: &CachedSession) -> bool {
+    self.server == other.server && self.username == other.username && self.gateway == other.gateway
+  }
+
+  /// The gateway is either the address or the name of the portal config, e.g., from `--gateway`
+  fn is_gateway(&self, gateway: &str) -> bool {
+    self.gateway.eq_ignore_ascii_case(gateway) || self.gateway_name.eq_ignore_ascii_case(gateway)
+  }
+}
+
+/// The sessions cached by `--use-cache`, keyed by the server, the username and the gateway, in one
+/// file per server readable only by the user
+pub(crate) struct SessionCache {
+  dir: PathBuf,
+}
+
+impl SessionCache {
+  pub(crate) fn new(dir: impl Into<PathBuf>) -> Self {
+    Self { dir: dir.into() }
+  }
+
+  /// The cache under `$XDG_CACHE_HOME/gpclient`
+  pub(crate) fn user_default() -> anyhow::Result<Self> {
+    let dirs = ProjectDirs::from("com.yuezk", "GlobalProtect-openconnect", "gpclient")
+      .ok_or_else(|| anyhow::anyhow!("Failed to get project dirs"))?;
+
+    Ok(Self::new(dirs.cache_dir()))
+  }
+
+  fn path(&self, server: &str) -> PathBuf {
+    let name: String = server
+      .chars()
+      .map(|c| {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+          c
+        } else {
+          '_'
+        }
+      })
+      .collect();
+
+    self.dir.join(format!("session-{}.json", name))
+  }
+
+  /// The session of the server, and of the username and the gateway if known, that has not expired,
+  /// the latest one if there are several. The expired sessions are removed.
+  pub(crate) fn load(
+    &self,
+    server: &str,
+    username: Option<&str>,
+    gateway: Option<&str>,
+    now: SystemTime,
+  ) -> Option<CachedSession> {
+    let sessions = self.read(server)?;
+    let (expired, sessions): (Vec<_>, Vec<_>) = sessions.into_iter().partition(|session| session.is_expired(now));
+    if !expired.is_empty() {
+      info!("Removing {} expired cached session(s)", expired.len());
+      self.write_or_remove(server, &sessions);
+    }
+
+    let session = sessions
+      .into_iter()
+      .filter(|session| session.server == server)
+      .filter(|session| username.is_none_or(|username| username == session.username))
+      .filter(|session| gateway.is_none_or(|gateway| session.is_gateway(gateway)))
+      .max_by_key(|session| session.expires_at);
+    if session.is_none() {
+      info!("No cached session for the server, user and gateway");
+    }
+
+    session
+  }
+
+  /// Write the session with the 0600 permission, along with the other cached sessions of the server
+  pub(crate) fn store(&self, session: &CachedSession) -> io::Result<()> {
+    let mut sessions = self.read(&session.server).unwrap_or_default();
+    sessions.retain(|cached| !cached.same_key(session));
+    sessions.push(session.clone());
+
+    self.write(&session.server, &sessions)?;
+    info!("Cached the session in {}", self.path(&session.server).display());
+
+    Ok(())
+  }
+
+  /// Remove the session, e.g., rejected by the gateway, the other sessions of the server are kept
+  pub(crate) fn remove(&self, session: &CachedSession) {
+    let mut sessions = self.read(&session.server).unwrap_or_default();
+    sessions.retain(|cached| !cached.same_key(session));
+
+    self.write_or_remove(&session.server, &sessions);
+  }
+
+  /// The cached sessions of the server, the file of a single session is also accepted
+  fn read(&self, server: &str) -> Option<Vec<CachedSession>> {
+    let path = self.path(server);
+    let content = fs::read_to_string(&path).ok()?;
+    let value = serde_json::from_str::<Value>(&content).ok();
+    let sessions = match &value {
+      Some(Value::Array(values)) => values.iter().map(CachedSession::from_json).collect(),
+      Some(value) => CachedSession::from_json(value).map(|session| vec![session]),
+      None => None,
+    };
+
+    if sessions.is_none() {
+      warn!("Ignoring the malformed session cache {}", path.display());
+    }
+    sessions
+  }
+
+  fn write(&self, server: &str, sessions: &[CachedSession]) -> io::Result<()> {
+    DirBuilder::new().recursive(true).mode(0o700).create(&self.dir)?;
+
+    let path = self.path(server);
+    let mut file = OpenOptions::new()
+      .write(true)
+      .create(true)
+      .truncate(true)
+      .mode(0o600)
+      .open(&path)?;
+    // The mode applies only when the file is created
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    let sessions = sessions.iter().map(CachedSession::to_json).collect::<Vec<_>>();
+    file.write_all(Value::Array(sessions).to_string().as_bytes())?;
+
+    Ok(())
+  }
+
+  fn write_or_remove(&self, server: &str, sessions: &[CachedSession]) {
+    if !sessions.is_empty() {
+      if let Err(err) = self.write(server, sessions) {
+        warn!("Failed to update the session cache: {}", err);
+      }
+      return;
+    }
+
+    let path = self.path(server);
+    match fs::remove_file(&path) {
+      Err(err) if err.kind() != io::ErrorKind::NotFound => {
+        warn!("Failed to remove the session cache {}: {}", path.display(), err);
+      }
+      _ => {}
+    }
+  }
+}
+
+/// The expiry of the session cached at `now`
+pub(crate) fn expires_at(now: SystemTime, ttl: Duration) -> u64 {
+  unix_secs(now) + ttl.as_secs()
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+  time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn session(expires_at: u64) -> CachedSession {
+    CachedSession {
+      server: "https://vpn.example.com".to_string(),
+      username: "alice".to_string(),
+      gateway: "gw1.example.com".to_string(),
+      gateway_name: "US East".to_string(),
+      cookie: "authcookie=secret&portal=gw1&user=alice".to_string(),
+      client_version: Some("6.1.0".to_string()),
+      mtu: 1400,
+      hip: true,
+      expires_at,
+    }
+  }
+
+  #[test]
+  fn session_is_written_and_read_back() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = SessionCache::new(dir.path().join("gpclient"));
+    let now = SystemTime::now();
+    let session = session(expires_at(now, SESSION_CACHE_TTL));
+
+    cache.store(&session).unwrap();
+    let path = cache.path(&session.server);
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+    assert_eq!(cache.load(&session.server, None, None, now), Some(session.clone()));
+    assert_eq!(
+      cache.load(&session.server, Some("alice"), None, now),
+      Some(session.clone())
+    );
+    // The session is keyed by the server and the username
+    assert_eq!(cache.load(&session.server, Some("bob"), None, now), None);
+    assert_eq!(cache.load("https://other.example.com", None, None, now), None);
+
+    cache.remove(&session);
+    assert_eq!(cache.load(&session.server, None, None, now), None);
+    assert!(!path.exists());
+  }
+
+  #[test]
+  fn sessions_are_keyed_by_the_user_and_the_gateway() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = SessionCache::new(dir.path());
+    let now = SystemTime::now();
+    let alice = session(expires_at(now, SESSION_CACHE_TTL));
+    let gw2 = CachedSession {
+      gateway: "gw2.example.com".to_string(),
+      gateway_name: "US West".to_string(),
+      expires_at: alice.expires_at + 1,
+      ..alice.clone()
+    };
+    let bob = CachedSession {
+      username: "bob".to_string(),
+      ..alice.clone()
+    };
+    for session in [&alice, &gw2, &bob] {
+      cache.store(session).unwrap();
+    }
+
+    let load = |username, gateway| cache.load(&alice.server, username, gateway, now);
+    assert_eq!(load(Some("alice"), Some("gw1.example.com")), Some(alice.clone()));
+    // By the address or the name from `--gateway`
+    assert_eq!(load(Some("alice"), Some("US West")), Some(gw2.clone()));
+    assert_eq!(load(Some("bob"), None), Some(bob.clone()));
+    assert_eq!(load(Some("bob"), Some("gw2.example.com")), None);
+    // The latest one without the user and the gateway
+    assert_eq!(load(None, None), Some(gw2.clone()));
+
+    // The session stored again is replaced, the others are kept
+    let renewed = CachedSession {
+      cookie: "authcookie=renewed&portal=gw1&user=alice".to_string(),
+      ..alice.clone()
+    };
+    cache.store(&renewed).unwrap();
+    cache.remove(&gw2);
+    assert_eq!(load(Some("alice"), None), Some(renewed));
+    assert_eq!(load(Some("bob"), None), Some(bob));
+  }
+
+  #[test]
+  fn expired_session_is_removed() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = SessionCache::new(dir.path());
+    let now = SystemTime::now();
+    let session = session(expires_at(now, Duration::from_secs(60)));
+    cache.store(&session).unwrap();
+
+    assert!(
+      cache
+        .load(&session.server, None, None, now + Duration::from_secs(59))
+        .is_some()
+    );
+    assert!(
+      cache
+        .load(&session.server, None, None, now + Duration::from_secs(60))
+        .is_none()
+    );
+    assert!(!cache.path(&session.server).exists());
+  }
+
+  #[test]
+  fn malformed_session_is_ignored() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = SessionCache::new(dir.path());
+    fs::write(cache.path("vpn.example.com"), "{\"server\":\"vpn.example.com\"}").unwrap();
+
+    assert!(cache.load("vpn.example.com", None, None, SystemTime::now()).is_none());
+
+    // The cache of a single session is still read
+    let session = session(expires_at(SystemTime::now(), SESSION_CACHE_TTL));
+    fs::write(cache.path(&session.server), session.to_json().to_string()).unwrap();
+    assert_eq!(
+      cache.load(&session.server, None, None, SystemTime::now()),
+      Some(session)
+    );
+  }
+}
@@ -0,0 +1,175 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use log::info;
+
+use crate::status_socket::ConnectionStatus;
+
+/// The exit code when the tunnel is disconnected for reaching the session TTL
+pub(crate) const SESSION_TTL_EXIT_CODE: i32 = 4;
+
+/// The tunnel was disconnected for reaching the session TTL
+#[derive(Debug)]
+pub(crate) struct SessionExpired(pub(crate) Duration);
+
+impl fmt::Display for SessionExpired {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Disconnected after the session TTL of {}s", self.0.as_secs())
+  }
+}
+
+impl std::error::Error for SessionExpired {}
+
+/// Parse the `--session-ttl` value, e.g., `90`, `45s`, `30m`, `8h`, `1d` or `1h30m`.
+/// A number without the unit is in seconds.
+pub(crate) fn parse_session_ttl(value: &str) -> Result<Duration, String> {
+  let value = value.trim();
+  if let Ok(secs) = value.parse::<u64>() {
+    return non_zero(Duration::from_secs(secs));
+  }
+
+  let mut total = 0u64;
+  let mut digits = String::new();
+  for c in value.chars() {
+    if c.is_ascii_digit() {
+      digits.push(c);
+      continue;
+    }
+
+    let unit = match c {
+      's' => 1,
+      'm' => 60,
+      'h' => 60 * 60,
+      'd' => 24 * 60 * 60,
+      _ => return Err(format!("Invalid unit '{}' in '{}', expected s, m, h or d", c, value)),
+    };
+    let amount = digits
+      .parse::<u64>()
+      .map_err(|_| format!("Missing the number before '{}' in '{}'", c, value))?;
+    digits.clear();
+
+    total = amount
+      .checked_mul(unit)
+      .and_then(|secs| total.checked_add(secs))
+      .ok_or_else(|| format!("The duration '{}' is too long", value))?;
+  }
+
+  if !digits.is_empty() {
+    return Err(format!("Missing the unit after '{}' in '{}'", digits, value));
+  }
+
+  non_zero(Duration::from_secs(total))
+}
+
+fn non_zero(duration: Duration) -> Result<Duration, String> {
+  if duration.is_zero() {
+    Err(String::from("The session TTL must be greater than 0"))
+  } else {
+    Ok(duration)
+  }
+}
+
+/// Return once the tunnel has been connected for the TTL, regardless of the traffic.
+/// The time before connected is not counted.
+pub(crate) async fn wait_for_session_ttl(status: Arc<ConnectionStatus>, ttl: Duration, poll_interval: Duration) {
+  while !status.is_connected() {
+    tokio::time::sleep(poll_interval).await;
+  }
+
+  tokio::time::sleep(ttl).await;
+  info!("The session TTL of {}s is reached", ttl.as_secs());
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    sync::{
+      Mutex,
+      atomic::{AtomicU64, Ordering},
+      mpsc::{self, Receiver, Sender},
+    },
+    time::Instant,
+  };
+
+  use openconnect::VpnStats;
+
+  use super::*;
+  use crate::connect::{Tunnel, run_tunnel};
+
+  /// A tunnel that stays connected until disconnected
+  struct MockTunnel {
+    disconnect_tx: Sender<()>,
+    disconnect_rx: Mutex<Receiver<()>>,
+  }
+
+  impl MockTunnel {
+    fn new() -> Self {
+      let (disconnect_tx, disconnect_rx) = mpsc::channel();
+      Self {
+        disconnect_tx,
+        disconnect_rx: Mutex::new(disconnect_rx),
+      }
+    }
+  }
+
+  impl Tunnel for MockTunnel {
+    fn connect(&self, on_connected: Box<dyn FnOnce() + Send + Sync>) -> i32 {
+      on_connected();
+      let _ = self.disconnect_rx.lock().unwrap().recv();
+      0
+    }
+
+    fn disconnect(&self) {
+      let _ = self.disconnect_tx.send(());
+    }
+
+    fn cleanup_routes(&self) -> std::io::Result<()> {
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn parses_session_ttl() {
+    assert_eq!(parse_session_ttl("90"), Ok(Duration::from_secs(90)));
+    assert_eq!(parse_session_ttl("45s"), Ok(Duration::from_secs(45)));
+    assert_eq!(parse_session_ttl("30m"), Ok(Duration::from_secs(30 * 60)));
+    assert_eq!(parse_session_ttl("8h"), Ok(Duration::from_secs(8 * 3600)));
+    assert_eq!(parse_session_ttl("1d"), Ok(Duration::from_secs(86400)));
+    assert_eq!(parse_session_ttl("1h30m"), Ok(Duration::from_secs(5400)));
+
+    assert!(parse_session_ttl("0").is_err());
+    assert!(parse_session_ttl("1h30").is_err());
+    assert!(parse_session_ttl("10w").is_err());
+    assert!(parse_session_ttl("h").is_err());
+  }
+
+  #[tokio::test]
+  async fn session_ends_at_ttl() {
+    let ttl = Duration::from_millis(200);
+    let tunnel = Arc::new(MockTunnel::new());
+    // The traffic keeps changing, the TTL applies regardless of the activity
+    let counter = Arc::new(AtomicU64::new(0));
+    let status = Arc::new(ConnectionStatus::new("gw.example.com", move || VpnStats {
+      rx_bytes: counter.fetch_add(1, Ordering::SeqCst),
+      tx_bytes: 0,
+    }));
+
+    let monitor = {
+      let (status, tunnel) = (Arc::clone(&status), Arc::clone(&tunnel));
+      tokio::spawn(async move {
+        wait_for_session_ttl(status, ttl, Duration::from_millis(10)).await;
+        tunnel.disconnect();
+      })
+    };
+
+    let start = Instant::now();
+    let result = tokio::task::spawn_blocking(move || run_tunnel(tunnel.as_ref(), move || status.set_connected()))
+      .await
+      .unwrap();
+    let elapsed = start.elapsed();
+
+    assert!(result.is_ok());
+    assert!(monitor.await.is_ok());
+    assert!(elapsed >= ttl, "the session ended early: {:?}", elapsed);
+    assert!(elapsed < ttl * 5, "the session did not end at the TTL: {:?}", elapsed);
+  }
+}
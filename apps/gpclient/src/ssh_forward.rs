@@ -0,0 +1,156 @@
+use std::{
+  net::{Ipv4Addr, SocketAddr},
+  time::{Duration, Instant},
+};
+
+use anyhow::bail;
+use gpapi::utils::normalize_server;
+use log::info;
+use reqwest::Url;
+use tokio::{
+  net::{TcpListener, TcpStream},
+  process::{Child, Command},
+};
+
+/// The time to wait for the SSH connection, including the host key and passphrase prompts
+const SSH_READY_TIMEOUT: Duration = Duration::from_secs(60);
+const SSH_READY_POLL: Duration = Duration::from_millis(200);
+
+/// A local port forward to the server through the SSH jump host, i.e., `ssh -L`.
+/// The SSH process is killed when the forward is stopped or dropped.
+pub(crate) struct SshForward {
+  child: Child,
+  host: String,
+  local_addr: SocketAddr,
+}
+
+impl SshForward {
+  /// Forward a local port to the server via the `destination`, e.g., `user@bastion`
+  pub(crate) async fn start(destination: &str, server: &str) -> anyhow::Result<Self> {
+    let url = Url::parse(&normalize_server(server)?)?;
+    let Some(host) = url.host_str() else {
+      bail!("Invalid server URL: missing host");
+    };
+    let Some(port) = url.port_or_known_default() else {
+      bail!("Invalid server URL: missing port");
+    };
+
+    let local_addr = unused_local_addr().await?;
+    info!("Forwarding {} to {}:{} via SSH {}", local_addr, host, port, destination);
+
+    let mut command = Command::new("ssh");
+    command
+      .args(["-N", "-o", "ExitOnForwardFailure=yes", "-L"])
+      .arg(format!("{}:{}:{}:{}", local_addr.ip(), local_addr.port(), host, port))
+      .arg(destination);
+
+    Self::spawn(command, host, local_addr, SSH_READY_TIMEOUT).await
+  }
+
+  async fn spawn(mut command: Command, host: &str, local_addr: SocketAddr, timeout: Duration) -> anyhow::Result<Self> {
+    let child = command.kill_on_drop(true).spawn()?;
+    let mut forward = Self {
+      child,
+      host: host.to_string(),
+      local_addr,
+    };
+
+    forward.wait_ready(timeout).await?;
+    Ok(forward)
+  }
+
+  /// Wait until the local port accepts connections, or fail if the SSH process exits
+  async fn wait_ready(&mut self, timeout: Duration) -> anyhow::Result<()> {
+    let start = Instant::now();
+
+    loop {
+      if let Some(status) = self.child.try_wait()? {
+        bail!("The SSH port forward exited before it was ready: {}", status);
+      }
+
+      if TcpStream::connect(self.local_addr).await.is_ok() {
+        return Ok(());
+      }
+
+      if start.elapsed() > timeout {
+        self.stop().await?;
+        bail!("The SSH port forward was not ready in {}s", timeout.as_secs());
+      }
+
+      tokio::time::sleep(SSH_READY_POLL).await;
+    }
+  }
+
+  /// Route the requests to the server through the forward, the TLS server name is unchanged
+  pub(crate) fn resolve_override(&self) -> (String, SocketAddr) {
+    (self.host.clone(), self.local_addr)
+  }
+
+  pub(crate) async fn stop(&mut self) -> anyhow::Result<()> {
+    if self.child.id().is_some() {
+      info!("Stopping the SSH port forward on {}", self.local_addr);
+      self.child.kill().await?;
+    }
+
+    Ok(())
+  }
+}
+
+/// Whether the `server` is on the forwarded `host`, i.e., its requests go through the forward
+pub(crate) fn is_same_host(host: &str, server: &str) -> bool {
+  let Ok(server) = normalize_server(server) else {
+    return false;
+  };
+
+  Url::parse(&server).is_ok_and(|url| url.host_str().is_some_and(|h| h.eq_ignore_ascii_case(host)))
+}
+
+/// Pick an unused local port for the forward
+async fn unused_local_addr() -> anyhow::Result<SocketAddr> {
+  let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+  Ok(listener.local_addr()?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn forward_is_ready_once_listening_and_stopped() -> anyhow::Result<()> {
+    // The local listener stands in for the port opened by SSH
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let local_addr = listener.local_addr()?;
+
+    let mut command = Command::new("sleep");
+    command.arg("30");
+
+    let mut forward = SshForward::spawn(command, "vpn.example.com", local_addr, Duration::from_secs(5)).await?;
+    assert_eq!(forward.resolve_override(), ("vpn.example.com".to_string(), local_addr));
+
+    forward.stop().await?;
+    assert!(forward.child.id().is_none());
+
+    Ok(())
+  }
+
+  #[test]
+  fn gateway_on_the_forwarded_host_is_detected() {
+    assert!(is_same_host("vpn.example.com", "vpn.example.com"));
+    assert!(is_same_host("vpn.example.com", "https://VPN.example.com:8443"));
+    assert!(!is_same_host("vpn.example.com", "gw1.example.com"));
+    assert!(!is_same_host("vpn.example.com", "10.0.0.1"));
+  }
+
+  #[tokio::test]
+  async fn forward_fails_when_ssh_exits() -> anyhow::Result<()> {
+    let local_addr = unused_local_addr().await?;
+
+    let mut command = Command::new("sh");
+    command.args(["-c", "exit 255"]);
+
+    let result = SshForward::spawn(command, "vpn.example.com", local_addr, Duration::from_secs(5)).await;
+    assert!(result.is_err());
+
+    Ok(())
+  }
+}
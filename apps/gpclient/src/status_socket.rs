@@ -0,0 +1,421 @@
+use std::{
+  fs::{self, Permissions},
+  io::ErrorKind,
+  net::IpAddr,
+  os::unix::fs::{FileTypeExt, PermissionsExt},
+  path::PathBuf,
+  sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, Ordering},
+  },
+  time::Instant,
+};
+
+use anyhow::bail;
+use log::{info, warn};
+use openconnect::{TunnelInfo, VpnStats};
+use serde_json::{Value, json};
+use tokio::{
+  io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+  net::{UnixListener, UnixStream},
+  sync::{
+    Notify,
+    broadcast::{self, error::RecvError},
+  },
+};
+
+/// How many events are buffered for a slow status socket client before it misses some
+const EVENT_CAPACITY: usize = 16;
+
+/// The request to refresh the portal config, any other request is answered with the status
+const REFRESH_REQUEST: &str = "refresh";
+
+type StatsProvider = Box<dyn Fn() -> VpnStats + Send + Sync>;
+
+/// The connection status served through the status socket
+pub(crate) struct ConnectionStatus {
+  gateway: String,
+  connected_at: Mutex<Option<Instant>>,
+  stats: StatsProvider,
+}
+
+impl ConnectionStatus {
+  pub(crate) fn new(gateway: &str, stats: impl Fn() -> VpnStats + Send + Sync + 'static) -> Self {
+    Self {
+      gateway: gateway.to_string(),
+      connected_at: Default::default(),
+      stats: Box::new(stats),
+    }
+  }
+
+  pub(crate) fn set_connected(&self) {
+    self.connected_at.lock().unwrap().replace(Instant::now());
+  }
+
+  pub(crate) fn is_connected(&self) -> bool {
+    self.connected_at.lock().unwrap().is_some()
+  }
+
+  /// The traffic statistics, all zero if not connected yet
+  pub(crate) fn stats(&self) -> VpnStats {
+    if self.is_connected() {
+      (self.stats)()
+    } else {
+      VpnStats::default()
+    }
+  }
+
+  fn to_json(&self) -> Value {
+    let connected_at = *self.connected_at.lock().unwrap();
+    let uptime = connected_at.map(|t| t.elapsed().as_secs()).unwrap_or_default();
+    let stats = self.stats();
+
+    json!({
+      "connected": connected_at.is_some(),
+      "gateway": self.gateway,
+      "uptime": uptime,
+      "rx_bytes": stats.rx_bytes,
+      "tx_bytes": stats.tx_bytes,
+    })
+  }
+}
+
+/// The session events streamed to the status socket clients
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum StatusEvent {
+  Connecting { gateway: String },
+  Connected { gateway: String, tunnel: TunnelInfo },
+  Reconnecting { attempt: u32, max_attempts: u32 },
+  ConfigRefreshed { changes: Vec<String> },
+  ConfigRefreshFailed { error: String },
+  Disconnected,
+}
+
+impl StatusEvent {
+  fn to_json(&self) -> Value {
+    match self {
+      StatusEvent::Connecting { gateway } => json!({ "event": "connecting", "gateway": gateway }),
+      StatusEvent::Connected { gateway, tunnel } => {
+        let to_strings = |ips: &[IpAddr]| ips.iter().map(|ip| ip.to_string()).collect::<Vec<_>>();
+        json!({
+          "event": "connected",
+          "gateway": gateway,
+          "ip_addresses": to_strings(&tunnel.ip_addresses()),
+          "netmask": tunnel.netmask.map(|netmask| netmask.to_string()),
+          "netmask6": tunnel.netmask6,
+          "dns_servers": to_strings(&tunnel.dns_servers),
+          "search_domains": tunnel.search_domains,
+          "split_includes": tunnel.split_includes,
+          "split_excludes": tunnel.split_excludes,
+          "mtu": tunnel.mtu,
+        })
+      }
+      StatusEvent::Reconnecting { attempt, max_attempts } => json!({
+        "event": "reconnecting",
+        "attempt": attempt,
+        "max_attempts": max_attempts,
+      }),
+      StatusEvent::ConfigRefreshed { changes } => json!({ "event": "config_refreshed", "changes": changes }),
+      StatusEvent::ConfigRefreshFailed { error } => json!({ "event": "config_refresh_failed", "error": error }),
+      StatusEvent::Disconnected => json!({ "event": "disconnected" }),
+    }
+  }
+}
+
+/// The status of the current tunnel and the session events, which outlive the reconnections
+pub(crate) struct StatusFeed {
+  status: Mutex<Option<Arc<ConnectionStatus>>>,
+  events: broadcast::Sender<String>,
+  refreshable: AtomicBool,
+  refresh: Notify,
+}
+
+impl Default for StatusFeed {
+  fn default() -> Self {
+    let (events, _) = broadcast::channel(EVENT_CAPACITY);
+
+    Self {
+      status: Default::default(),
+      events,
+      refreshable: Default::default(),
+      refresh: Notify::new(),
+    }
+  }
+}
+
+impl StatusFeed {
+  /// Track the status of the current tunnel
+  pub(crate) fn set_status(&self, status: Arc<ConnectionStatus>) {
+    self.status.lock().unwrap().replace(status);
+  }
+
+  /// Send the event to the connected clients, it is dropped if there is none
+  pub(crate) fn publish(&self, event: StatusEvent) {
+    info!("Status event: {:?}", event);
+    let _ = self.events.send(event.to_json().to_string());
+  }
+
+  /// Accept the `refresh` requests, i.e., the session has the portal config to refresh
+  pub(crate) fn set_refreshable(&self) {
+    self.refreshable.store(true, Ordering::SeqCst);
+  }
+
+  /// Wait for a `refresh` request, the one received while nobody waits is kept for the next wait
+  pub(crate) async fn refresh_requested(&self) {
+    self.refresh.notified().await;
+  }
+
+  fn request_refresh(&self) -> Value {
+    if !self.refreshable.load(Ordering::SeqCst) {
+      return json!({ "refresh": "unsupported" });
+    }
+
+    info!("Portal config refresh requested through the status socket");
+    self.refresh.notify_one();
+    json!({ "refresh": "requested" })
+  }
+
+  fn status_json(&self) -> Value {
+    match self.status.lock().unwrap().as_ref() {
+      Some(status) => status.to_json(),
+      None => json!({ "connected": false }),
+    }
+  }
+}
+
+/// A unix socket that streams the session events as newline-delimited JSON, and answers each
+/// single-line request with the connection status JSON in the same stream. The `refresh` request
+/// refreshes the portal config instead, and the result is streamed as an event.
+/// The socket file is created with 0600 permission and removed when dropped.
+pub(crate) struct StatusSocket {
+  path: PathBuf,
+}
+
+impl StatusSocket {
+  pub(crate) fn bind(path: &str, feed: Arc<StatusFeed>) -> anyhow::Result<Self> {
+    let path = PathBuf::from(path);
+
+    // Remove the stale socket left by a previous run, but nothing else, as it runs as root
+    match fs::symlink_metadata(&path) {
+      Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(&path)?,
+      Ok(_) => bail!("Refusing to replace {}, it is not a socket", path.display()),
+      Err(err) if err.kind() == ErrorKind::NotFound => {}
+      Err(err) => return Err(err.into()),
+    }
+
+    let listener = UnixListener::bind(&path)?;
+    fs::set_permissions(&path, Permissions::from_mode(0o600))?;
+    info!("Serving the connection status on {}", path.display());
+
+    tokio::spawn(async move {
+      loop {
+        match listener.accept().await {
+          Ok((stream, _)) => {
+            tokio::spawn(handle_client(stream, Arc::clone(&feed)));
+          }
+          Err(err) => {
+            warn!("Failed to accept status socket connection: {}", err);
+            break;
+          }
+        }
+      }
+    });
+
+    Ok(Self { path })
+  }
+}
+
+impl Drop for StatusSocket {
+  fn drop(&mut self) {
+    if let Err(err) = fs::remove_file(&self.path) {
+      warn!("Failed to remove the status socket {}: {}", self.path.display(), err);
+    }
+  }
+}
+
+/// Serve the client until it closes the connection or the session ends
+async fn handle_client(stream: UnixStream, feed: Arc<StatusFeed>) {
+  let mut events = feed.events.subscribe();
+  let (reader, mut writer) = stream.into_split();
+  let mut requests = BufReader::new(reader).lines();
+
+  loop {
+    let line = tokio::select! {
+      request = requests.next_line() => match request {
+        Ok(Some(request)) if request.trim() == REFRESH_REQUEST => feed.request_refresh().to_string(),
+        Ok(Some(_)) => feed.status_json().to_string(),
+        Ok(None) => break,
+        Err(err) => {
+          warn!("Failed to read status request: {}", err);
+          break;
+        }
+      },
+      event = events.recv() => match event {
+        Ok(event) => event,
+        Err(RecvError::Lagged(missed)) => {
+          warn!("The status socket client missed {} event(s)", missed);
+          continue;
+        }
+        Err(RecvError::Closed) => break,
+      },
+    };
+
+    if let Err(err) = writer.write_all(format!("{}\n", line).as_bytes()).await {
+      warn!("Failed to write to the status socket client: {}", err);
+      break;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use tokio::io::{AsyncBufRead, Lines};
+
+  use super::*;
+
+  #[tokio::test]
+  async fn status_query_returns_expected_fields() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("gpclient.sock");
+    let status = Arc::new(ConnectionStatus::new("gw.example.com", || VpnStats {
+      rx_bytes: 1024,
+      tx_bytes: 512,
+    }));
+    status.set_connected();
+    let feed = Arc::new(StatusFeed::default());
+    feed.set_status(status);
+
+    let socket = StatusSocket::bind(path.to_str().unwrap(), feed).unwrap();
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    let mut stream = UnixStream::connect(&path).await.unwrap();
+    stream.write_all(b"status\n").await.unwrap();
+
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response).await.unwrap();
+    let response: Value = serde_json::from_str(&response).unwrap();
+
+    assert_eq!(response["connected"], true);
+    assert_eq!(response["gateway"], "gw.example.com");
+    assert!(response["uptime"].is_u64());
+    assert_eq!(response["rx_bytes"], 1024);
+    assert_eq!(response["tx_bytes"], 512);
+
+    drop(socket);
+    assert!(!path.exists());
+  }
+
+  #[tokio::test]
+  async fn only_a_stale_socket_is_replaced() {
+    let dir = tempfile::tempdir().unwrap();
+    let feed = Arc::new(StatusFeed::default());
+
+    let path = dir.path().join("gpclient.sock");
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    let socket = StatusSocket::bind(path.to_str().unwrap(), Arc::clone(&feed)).unwrap();
+    UnixStream::connect(&path).await.unwrap();
+    drop(socket);
+
+    let file = dir.path().join("important");
+    fs::write(&file, "keep").unwrap();
+    let link = dir.path().join("link.sock");
+    std::os::unix::fs::symlink(&file, &link).unwrap();
+
+    for path in [&file, &link] {
+      let err = StatusSocket::bind(path.to_str().unwrap(), Arc::clone(&feed)).unwrap_err();
+      assert!(err.to_string().contains("is not a socket"), "{}", err);
+    }
+    assert_eq!(fs::read_to_string(&file).unwrap(), "keep");
+    assert!(fs::symlink_metadata(&link).is_ok());
+  }
+
+  async fn read_json(lines: &mut Lines<impl AsyncBufRead + Unpin>) -> Value {
+    let line = lines.next_line().await.unwrap().unwrap();
+    serde_json::from_str(&line).unwrap()
+  }
+
+  #[tokio::test]
+  async fn events_are_streamed_to_the_clients() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("gpclient.sock");
+    let feed = Arc::new(StatusFeed::default());
+    let _socket = StatusSocket::bind(path.to_str().unwrap(), Arc::clone(&feed)).unwrap();
+
+    let (reader, mut writer) = UnixStream::connect(&path).await.unwrap().into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    // The status is answered before any tunnel is set up, and the client is subscribed after it
+    writer.write_all(b"status\n").await.unwrap();
+    assert_eq!(read_json(&mut lines).await["connected"], false);
+
+    feed.publish(StatusEvent::Connecting {
+      gateway: "gw.example.com".to_string(),
+    });
+    feed.publish(StatusEvent::Connected {
+      gateway: "gw.example.com".to_string(),
+      tunnel: TunnelInfo {
+        ipv4: Some("10.0.0.2".parse().unwrap()),
+        netmask: Some("255.255.255.0".parse().unwrap()),
+        dns_servers: vec!["10.0.0.53".parse().unwrap()],
+        split_includes: vec!["10.0.0.0/255.0.0.0".to_string()],
+        ..Default::default()
+      },
+    });
+    feed.publish(StatusEvent::Reconnecting {
+      attempt: 1,
+      max_attempts: 3,
+    });
+    feed.publish(StatusEvent::Disconnected);
+
+    let event = read_json(&mut lines).await;
+    assert_eq!(event["event"], "connecting");
+    assert_eq!(event["gateway"], "gw.example.com");
+
+    let event = read_json(&mut lines).await;
+    assert_eq!(event["event"], "connected");
+    assert_eq!(event["ip_addresses"], json!(["10.0.0.2"]));
+    assert_eq!(event["netmask"], "255.255.255.0");
+    assert_eq!(event["dns_servers"], json!(["10.0.0.53"]));
+    assert_eq!(event["split_includes"], json!(["10.0.0.0/255.0.0.0"]));
+    assert_eq!(event["mtu"], Value::Null);
+
+    let event = read_json(&mut lines).await;
+    assert_eq!(event["event"], "reconnecting");
+    assert_eq!(event["attempt"], 1);
+    assert_eq!(event["max_attempts"], 3);
+
+    assert_eq!(read_json(&mut lines).await["event"], "disconnected");
+  }
+
+  #[tokio::test]
+  async fn refresh_is_requested_through_the_socket() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("gpclient.sock");
+    let feed = Arc::new(StatusFeed::default());
+    let _socket = StatusSocket::bind(path.to_str().unwrap(), Arc::clone(&feed)).unwrap();
+
+    let (reader, mut writer) = UnixStream::connect(&path).await.unwrap().into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    // Without the portal config, e.g., connected to the gateway directly
+    writer.write_all(b"refresh\n").await.unwrap();
+    assert_eq!(read_json(&mut lines).await["refresh"], "unsupported");
+
+    feed.set_refreshable();
+    writer.write_all(b"refresh\n").await.unwrap();
+    assert_eq!(read_json(&mut lines).await["refresh"], "requested");
+    tokio::time::timeout(Duration::from_secs(1), feed.refresh_requested())
+      .await
+      .expect("the refresh was not requested");
+
+    feed.publish(StatusEvent::ConfigRefreshed {
+      changes: vec!["search domain added: corp.example.com".to_string()],
+    });
+    let event = read_json(&mut lines).await;
+    assert_eq!(event["event"], "config_refreshed");
+    assert_eq!(event["changes"], json!(["search domain added: corp.example.com"]));
+  }
+}
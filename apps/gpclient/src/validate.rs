@@ -0,0 +1,121 @@
+use std::{fs::File, ops::RangeInclusive, path::Path};
+
+use gpapi::utils::request::is_pkcs11_uri;
+use openconnect::{check_executable, find_csd_wrapper, find_vpnc_script, validate_interface_name};
+
+/// The MTU range accepted by the tunnel, openconnect raises any lower value to 576
+const MTU_RANGE: RangeInclusive<u32> = 576..=65535;
+
+/// The local prerequisites of a connection, checked by `gpclient connect --validate-only`
+#[derive(Default)]
+pub(crate) struct LocalPrerequisites<'a> {
+  pub(crate) script: Option<&'a str>,
+  pub(crate) hip: bool,
+  pub(crate) csd_wrapper: Option<&'a str>,
+  pub(crate) certificate: Option<&'a str>,
+  pub(crate) sslkey: Option<&'a str>,
+  pub(crate) interface: Option<&'a str>,
+  pub(crate) mtu: Option<u32>,
+}
+
+impl LocalPrerequisites<'_> {
+  /// Check all the prerequisites and return every problem found, without touching the network
+  pub(crate) fn validate(&self) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    match self.script {
+      Some(script) => check_file_executable("vpnc-script", script, &mut problems),
+      None if find_vpnc_script().is_none() => problems.push(String::from("vpnc-script: not found")),
+      None => {}
+    }
+
+    match self.csd_wrapper {
+      Some(csd_wrapper) => check_file_executable("HIP wrapper", csd_wrapper, &mut problems),
+      None if self.hip && find_csd_wrapper().is_none() => problems.push(String::from("HIP wrapper: not found")),
+      None => {}
+    }
+
+    if let Some(certificate) = self.certificate.filter(|cert| !is_pkcs11_uri(cert)) {
+      check_file_readable("certificate", certificate, &mut problems);
+    }
+
+    if let Some(sslkey) = self.sslkey.filter(|key| !is_pkcs11_uri(key)) {
+      check_file_readable("SSL key", sslkey, &mut problems);
+    }
+
+    if let Some(interface) = self.interface {
+      if let Err(err) = validate_interface_name(interface) {
+        problems.push(format!("interface: {}", err));
+      }
+    }
+
+    if let Some(mtu) = self.mtu {
+      if !MTU_RANGE.contains(&mtu) {
+        problems.push(format!(
+          "MTU: {} is out of range {}-{}",
+          mtu,
+          MTU_RANGE.start(),
+          MTU_RANGE.end()
+        ));
+      }
+    }
+
+    problems
+  }
+}
+
+fn check_file_executable(name: &str, file: &str, problems: &mut Vec<String>) {
+  if !Path::new(file).is_file() {
+    problems.push(format!("{}: {} does not exist", name, file));
+  } else if let Err(err) = check_executable(file) {
+    problems.push(format!("{}: {}", name, err));
+  }
+}
+
+fn check_file_readable(name: &str, file: &str, problems: &mut Vec<String>) {
+  if let Err(err) = File::open(file) {
+    problems.push(format!("{}: cannot read {}: {}", name, file, err));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reports_all_problems_at_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let missing = dir.path().join("missing");
+    let missing = missing.to_str().unwrap();
+
+    let prerequisites = LocalPrerequisites {
+      script: Some(missing),
+      csd_wrapper: Some(missing),
+      certificate: Some(missing),
+      interface: Some("tun/invalid"),
+      mtu: Some(100),
+      ..Default::default()
+    };
+
+    let problems = prerequisites.validate();
+    assert_eq!(problems.len(), 5, "{:?}", problems);
+    assert!(problems[0].starts_with("vpnc-script:"));
+    assert!(problems[1].starts_with("HIP wrapper:"));
+    assert!(problems[2].starts_with("certificate:"));
+    assert!(problems[3].starts_with("interface:"));
+    assert!(problems[4].starts_with("MTU:"));
+  }
+
+  #[test]
+  fn accepts_valid_prerequisites() {
+    let prerequisites = LocalPrerequisites {
+      script: Some("/bin/sh"),
+      certificate: Some("pkcs11:token=TOKEN;type=cert"),
+      interface: Some("gpd0"),
+      mtu: Some(1400),
+      ..Default::default()
+    };
+
+    assert!(prerequisites.validate().is_empty());
+  }
+}
@@ -112,6 +112,21 @@ impl SamlAuthData {
     Ok(auth_data)
   }
 
+  /// Validate the auth data obtained elsewhere, e.g., from a SAML assertion file
+  pub fn validate(&self) -> anyhow::Result<()> {
+    if self.username.trim().is_empty() {
+      bail!("Invalid username: <empty>");
+    }
+
+    let has_prelogin_cookie = self.prelogin_cookie.as_deref().is_some_and(|c| c.len() > 5);
+    let has_token = self.token.as_deref().is_some_and(|t| !t.is_empty());
+    if !has_prelogin_cookie && !has_token {
+      bail!("Invalid SAML auth data: missing prelogin-cookie or token");
+    }
+
+    Ok(())
+  }
+
   pub fn username(&self) -> &str {
     &self.username
   }
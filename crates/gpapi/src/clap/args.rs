@@ -1,6 +1,6 @@
 use clap::{builder::PossibleValue, ValueEnum};
 
-use crate::gp_params::ClientOs;
+use crate::gp_params::{ClientOs, TlsVersion};
 
 #[derive(Debug, Clone)]
 pub enum Os {
@@ -62,3 +62,13 @@ impl ValueEnum for Os {
     }
   }
 }
+
+impl ValueEnum for TlsVersion {
+  fn value_variants<'a>() -> &'a [Self] {
+    &[TlsVersion::Tls1_0, TlsVersion::Tls1_1, TlsVersion::Tls1_2]
+  }
+
+  fn to_possible_value(&self) -> Option<PossibleValue> {
+    Some(PossibleValue::new(self.as_str()))
+  }
+}
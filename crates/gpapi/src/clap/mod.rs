@@ -28,6 +28,38 @@ pub fn handle_error(err: anyhow::Error, args: &impl Args) {
     let args = std::env::args().collect::<Vec<_>>();
     eprintln!("{} --ignore-tls-errors {}\n", args[0], args[1..].join(" "));
   }
+
+  if let Some(url) = err.remediation_url() {
+    eprintln!("\nVisit the following URL to fix the HIP issues, then reconnect:\n");
+    eprintln!("{}\n", url);
+  }
+
+  if let Some(os) = err.suggested_os() {
+    eprintln!("\nRe-run it with the `--os` option to report an OS allowed by the portal, e.g.:\n");
+    let args = std::env::args().collect::<Vec<_>>();
+    eprintln!("{}\n", with_os_option(&args, os.as_str()).join(" "));
+  }
+}
+
+/// Put `--os` after the `connect` subcommand, which defines it, or after the program name without
+/// the subcommand, e.g., for gpauth. The `--os` already in the arguments is replaced.
+fn with_os_option(args: &[String], os: &str) -> Vec<String> {
+  let mut rest = args.iter().skip(1);
+  let mut kept = vec![];
+  while let Some(arg) = rest.next() {
+    if arg == "--os" {
+      rest.next();
+    } else if !arg.starts_with("--os=") {
+      kept.push(arg.clone());
+    }
+  }
+
+  let index = kept
+    .iter()
+    .position(|arg| arg == "connect")
+    .map_or(0, |index| index + 1);
+  kept.splice(index..index, ["--os".to_string(), os.to_string()]);
+  args.iter().take(1).cloned().chain(kept).collect()
 }
 
 #[derive(Debug)]
@@ -79,3 +111,30 @@ impl ToVerboseArg for Level {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn os_option_follows_the_subcommand() {
+    let args = |args: &str| args.split_whitespace().map(String::from).collect::<Vec<_>>();
+
+    assert_eq!(
+      with_os_option(&args("gpclient -v connect vpn.example.com"), "Windows"),
+      args("gpclient -v connect --os Windows vpn.example.com")
+    );
+    assert_eq!(
+      with_os_option(&args("gpclient connect --os Linux vpn.example.com"), "Mac"),
+      args("gpclient connect --os Mac vpn.example.com")
+    );
+    assert_eq!(
+      with_os_option(&args("gpclient connect --os=Linux vpn.example.com"), "Mac"),
+      args("gpclient connect --os Mac vpn.example.com")
+    );
+    assert_eq!(
+      with_os_option(&args("gpauth vpn.example.com"), "Windows"),
+      args("gpauth --os Windows vpn.example.com")
+    );
+  }
+}
@@ -64,6 +64,31 @@ impl PreloginCredential {
   pub fn token(&self) -> Option<&str> {
     self.token.as_deref()
   }
+
+  /// Same as `Credential::from_saml_assertion`
+  pub fn from_saml_assertion(assertion: &str) -> anyhow::Result<Self> {
+    let assertion = assertion.trim();
+    if assertion.is_empty() {
+      bail!("Empty SAML assertion");
+    }
+
+    let auth_data = if assertion.starts_with('{') {
+      match serde_json::from_str::<SamlAuthResult>(assertion) {
+        Ok(SamlAuthResult::Success(auth_data)) => auth_data,
+        Ok(SamlAuthResult::Failure(err)) => bail!("SAML authentication failed: {}", err),
+        Err(_) => serde_json::from_str::<SamlAuthData>(assertion)
+          .map_err(|err| anyhow::anyhow!("Invalid SAML assertion JSON: {}", err))?,
+      }
+    } else if assertion.starts_with("globalprotectcallback:") {
+      SamlAuthData::from_gpcallback(assertion)?
+    } else {
+      SamlAuthData::from_html(assertion)?
+    };
+
+    auth_data.validate()?;
+
+    Ok(Self::from(auth_data))
+  }
 }
 
 impl From<SamlAuthData> for PreloginCredential {
@@ -171,6 +196,12 @@ impl Credential {
     Ok(Self::from(auth_data))
   }
 
+  /// Create a credential from a pre-obtained SAML assertion, which can be the JSON auth result
+  /// printed by gpauth, the globalprotectcallback data, or the SAML response HTML.
+  pub fn from_saml_assertion(assertion: &str) -> anyhow::Result<Self> {
+    Ok(Self::Prelogin(PreloginCredential::from_saml_assertion(assertion)?))
+  }
+
   pub fn username(&self) -> &str {
     match self {
       Credential::Password(cred) => cred.username(),
@@ -180,6 +211,15 @@ impl Credential {
     }
   }
 
+  /// The portal auth cookies carried by the credential, if any
+  pub fn auth_cookie(&self) -> Option<&AuthCookieCredential> {
+    match self {
+      Credential::AuthCookie(cred) => Some(cred),
+      Credential::Cached(cred) => cred.auth_cookie(),
+      _ => None,
+    }
+  }
+
   pub fn to_params(&self) -> HashMap<&str, &str> {
     let mut params = HashMap::new();
     params.insert("user", self.username());
@@ -255,3 +295,33 @@ impl From<&CachedCredential> for Credential {
     Self::Cached(value.clone())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn saml_assertion_from_json() {
+    let assertion = r#"{"success":{"username":"alice","preloginCookie":"prelogin-cookie"}}"#;
+    let cred = Credential::from_saml_assertion(assertion).unwrap();
+
+    assert_eq!(cred.username(), "alice");
+    assert_eq!(cred.to_params().get("prelogin-cookie"), Some(&"prelogin-cookie"));
+  }
+
+  #[test]
+  fn saml_assertion_from_html() {
+    let assertion = "<html><!-- <saml-auth-status>1</saml-auth-status><prelogin-cookie>prelogin-cookie</prelogin-cookie><saml-username>alice</saml-username> --></html>";
+    let cred = Credential::from_saml_assertion(assertion).unwrap();
+
+    assert_eq!(cred.username(), "alice");
+  }
+
+  #[test]
+  fn saml_assertion_rejects_invalid_content() {
+    assert!(Credential::from_saml_assertion("").is_err());
+    assert!(Credential::from_saml_assertion(r#"{"username":"alice"}"#).is_err());
+    assert!(Credential::from_saml_assertion(r#"{"failure":"denied"}"#).is_err());
+    assert!(Credential::from_saml_assertion("not an assertion").is_err());
+  }
+}
@@ -1,5 +1,125 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
 use thiserror::Error;
 
+use crate::{
+  gp_params::ClientOs,
+  utils::{GpResponseError, retry_budget::RetryBudgetExhausted},
+};
+
+/// Keep the typed portal errors, e.g., for the TLS hints, when the gateway login error is
+/// propagated with the other API errors
+impl From<GatewayLoginError> for PortalError {
+  fn from(err: GatewayLoginError) -> Self {
+    match err {
+      GatewayLoginError::Network(err) => PortalError::NetworkError(err),
+      GatewayLoginError::Maintenance(message) => PortalError::Maintenance(message),
+      GatewayLoginError::RetryBudgetExhausted(err) => PortalError::RetryBudgetExhausted(err),
+      GatewayLoginError::Portal(err) => err,
+      GatewayLoginError::Unexpected(err) => PortalError::from(err),
+      err => PortalError::Other(anyhow::Error::new(err)),
+    }
+  }
+}
+
+/// The error returned by the gateway login, to tell the rejected credential from the other failures
+#[derive(Error, Debug)]
+pub enum GatewayLoginError {
+  #[error(transparent)]
+  Network(reqwest::Error),
+
+  #[error("The server is in maintenance: {0}")]
+  Maintenance(String),
+
+  #[error("Gateway login error: {reason}")]
+  AuthRejected { status: StatusCode, reason: String },
+
+  #[error("The authentication cookie has expired or is invalid: {0}")]
+  CookieExpired(String),
+
+  #[error("Failed to parse MFA challenge: {0}")]
+  MfaParse(String),
+
+  #[error("The gateway locked the account out: {0}")]
+  LockedOut(String),
+
+  #[error("Gave up the MFA after {0} attempts")]
+  MfaAttemptsExceeded(u32),
+
+  #[error(transparent)]
+  TokenParse(anyhow::Error),
+
+  #[error(transparent)]
+  RetryBudgetExhausted(#[from] RetryBudgetExhausted),
+
+  /// The other portal errors of the request, e.g., `Timeout` or `ResponseTooLarge`
+  #[error(transparent)]
+  Portal(PortalError),
+
+  #[error(transparent)]
+  Unexpected(anyhow::Error),
+}
+
+impl GatewayLoginError {
+  /// The gateway rejected the credential, retrying with the same one will not succeed
+  pub fn is_auth_rejected(&self) -> bool {
+    matches!(self, GatewayLoginError::AuthRejected { .. })
+  }
+
+  /// The gateway rejected the cookie, e.g., the cached or the stdin one, a fresh login is needed
+  pub fn is_cookie_expired(&self) -> bool {
+    matches!(self, GatewayLoginError::CookieExpired(_))
+  }
+
+  /// Prompting for another MFA response is pointless, or may lock the account out
+  pub fn is_mfa_exhausted(&self) -> bool {
+    matches!(
+      self,
+      GatewayLoginError::LockedOut(_) | GatewayLoginError::MfaAttemptsExceeded(_)
+    )
+  }
+}
+
+/// Recover the typed errors from the internal `anyhow::Error`
+impl From<anyhow::Error> for GatewayLoginError {
+  fn from(err: anyhow::Error) -> Self {
+    let err = match err.downcast::<GatewayLoginError>() {
+      Ok(err) => return err,
+      Err(err) => err,
+    };
+
+    let err = match err.downcast::<PortalError>() {
+      Ok(PortalError::NetworkError(err)) => return GatewayLoginError::Network(err),
+      Ok(PortalError::Maintenance(message)) => return GatewayLoginError::Maintenance(message),
+      Ok(PortalError::RetryBudgetExhausted(err)) => return GatewayLoginError::RetryBudgetExhausted(err),
+      Ok(PortalError::Other(err)) => err,
+      Ok(err) => return GatewayLoginError::Portal(err),
+      Err(err) => err,
+    };
+
+    if let Some(err) = err.chain().find_map(|err| err.downcast_ref::<RetryBudgetExhausted>()) {
+      return GatewayLoginError::RetryBudgetExhausted(*err);
+    }
+
+    // The server errors are only rejected after the retries, they are not about the credential,
+    // except the 512 of the authentication failure
+    let rejected = err
+      .chain()
+      .find_map(|err| err.downcast_ref::<GpResponseError>())
+      .filter(|err| err.status.is_client_error() || err.is_auth_failed());
+    if let Some(err) = rejected {
+      return GatewayLoginError::AuthRejected {
+        status: err.status,
+        reason: err.reason.clone(),
+      };
+    }
+
+    GatewayLoginError::Unexpected(err)
+  }
+}
+
+/// The error returned by the public portal and gateway APIs
 #[derive(Error, Debug)]
 pub enum PortalError {
   #[error("Prelogin error: {0}")]
@@ -8,14 +128,130 @@ pub enum PortalError {
   #[error("Portal config error: {0}")]
   ConfigError(String),
 
+  #[error("Forbidden: {0}")]
+  Forbidden(String),
+
+  #[error("The server is in maintenance: {0}")]
+  Maintenance(String),
+
+  #[error("HIP check failed: {message}")]
+  HipCheckFailed {
+    message: String,
+    remediation_url: Option<String>,
+  },
+
+  #[error("The portal does not allow the client OS {os}, allowed: {}", .allowed.join(", "))]
+  OsNotAllowed { os: String, allowed: Vec<String> },
+
   #[error(transparent)]
   NetworkError(#[from] reqwest::Error),
 
   #[error("TLS error")]
   TlsError,
+
+  #[error("The response exceeds the size limit of {0} bytes")]
+  ResponseTooLarge(usize),
+
+  #[error("The request timed out after {0:?}")]
+  Timeout(Duration),
+
+  #[error("The authentication cookie has expired or is invalid: {0}")]
+  CookieExpired(String),
+
+  #[error(transparent)]
+  RetryBudgetExhausted(#[from] RetryBudgetExhausted),
+
+  #[error("Failed to discover the portal of {domain}, tried: {}", .candidates.join(", "))]
+  DiscoveryFailed { domain: String, candidates: Vec<String> },
+
+  #[error(transparent)]
+  Other(anyhow::Error),
+}
+
+/// Recover the typed errors from the internal `anyhow::Error`
+impl From<anyhow::Error> for PortalError {
+  fn from(err: anyhow::Error) -> Self {
+    let err = match err.downcast::<PortalError>() {
+      Ok(err) => return err,
+      Err(err) => err,
+    };
+
+    // The budget error may be wrapped with the context, e.g., when building the client
+    if let Some(err) = err.chain().find_map(|err| err.downcast_ref::<RetryBudgetExhausted>()) {
+      return PortalError::RetryBudgetExhausted(*err);
+    }
+
+    PortalError::Other(err)
+  }
 }
 
 impl PortalError {
+  /// The error is reported by the server or the request to it, rather than e.g. the retry budget or
+  /// the portal discovery
+  pub fn is_request_error(&self) -> bool {
+    !matches!(
+      self,
+      PortalError::RetryBudgetExhausted(_) | PortalError::DiscoveryFailed { .. } | PortalError::Other(_)
+    )
+  }
+
+  pub fn is_retry_budget_exhausted(&self) -> bool {
+    matches!(self, PortalError::RetryBudgetExhausted(_))
+  }
+
+  /// The gateway login error kept as the other error, e.g., `CookieExpired`
+  pub fn gateway_login_error(&self) -> Option<&GatewayLoginError> {
+    match self {
+      PortalError::Other(err) => err.downcast_ref::<GatewayLoginError>(),
+      _ => None,
+    }
+  }
+
+  pub fn is_forbidden(&self) -> bool {
+    matches!(self, PortalError::Forbidden(_))
+  }
+
+  /// The server is temporarily unavailable, the request can be retried later
+  pub fn is_maintenance(&self) -> bool {
+    matches!(self, PortalError::Maintenance(_))
+  }
+
+  pub fn is_hip_check_failed(&self) -> bool {
+    matches!(self, PortalError::HipCheckFailed { .. })
+  }
+
+  /// The URL to fix the HIP issues, if provided by the gateway
+  pub fn remediation_url(&self) -> Option<&str> {
+    match self {
+      PortalError::HipCheckFailed { remediation_url, .. } => remediation_url.as_deref(),
+      _ => None,
+    }
+  }
+
+  pub fn is_timeout(&self) -> bool {
+    matches!(self, PortalError::Timeout(_))
+  }
+
+  /// The cookie was rejected as expired, either by the portal or by the gateway, a fresh login is needed
+  pub fn is_cookie_expired(&self) -> bool {
+    matches!(self, PortalError::CookieExpired(_))
+      || self
+        .gateway_login_error()
+        .is_some_and(GatewayLoginError::is_cookie_expired)
+  }
+
+  pub fn is_os_not_allowed(&self) -> bool {
+    matches!(self, PortalError::OsNotAllowed { .. })
+  }
+
+  /// The first OS allowed by the portal that can be reported with `--os`
+  pub fn suggested_os(&self) -> Option<ClientOs> {
+    match self {
+      PortalError::OsNotAllowed { allowed, .. } => allowed.iter().find_map(|os| ClientOs::from_policy_name(os)),
+      _ => None,
+    }
+  }
+
   pub fn is_legacy_openssl_error(&self) -> bool {
     format!("{:?}", self).contains("unsafe legacy renegotiation")
   }
@@ -38,3 +274,80 @@ impl AuthDataParseError {
     matches!(self, AuthDataParseError::Invalid(_))
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn recovers_portal_error_from_anyhow() {
+    let err = PortalError::from(anyhow::anyhow!(PortalError::Forbidden("denied".to_string())));
+    assert!(matches!(err, PortalError::Forbidden(reason) if reason == "denied"));
+  }
+
+  #[test]
+  fn keeps_other_errors_from_anyhow() {
+    let err = PortalError::from(anyhow::anyhow!("Invalid server URL"));
+    assert!(matches!(err, PortalError::Other(_)));
+    assert!(!err.is_request_error());
+    assert_eq!(err.to_string(), "Invalid server URL");
+  }
+
+  #[test]
+  fn recovers_gateway_login_error_from_anyhow() {
+    let response_error = |status: StatusCode, reason: &str| GpResponseError {
+      status,
+      reason: reason.to_string(),
+      body: String::new(),
+      maintenance: None,
+    };
+
+    let rejected = response_error(StatusCode::FORBIDDEN, "auth-failed");
+    let err = GatewayLoginError::from(anyhow::anyhow!(rejected).context("login"));
+    assert!(err.is_auth_rejected());
+    assert_eq!(err.to_string(), "Gateway login error: auth-failed");
+
+    // The 512 of the authentication failure
+    let rejected = response_error(StatusCode::from_u16(512).unwrap(), "<none>");
+    let err = GatewayLoginError::from(anyhow::anyhow!(rejected).context("login"));
+    assert!(matches!(err, GatewayLoginError::AuthRejected { status, .. } if status.as_u16() == 512));
+
+    // Not caused by the credential
+    let unavailable = response_error(StatusCode::BAD_GATEWAY, "<none>");
+    let err = GatewayLoginError::from(anyhow::anyhow!(unavailable).context("login"));
+    assert!(matches!(err, GatewayLoginError::Unexpected(_)));
+    assert_eq!(err.to_string(), "login");
+
+    let err = GatewayLoginError::from(anyhow::anyhow!(PortalError::Timeout(Duration::from_secs(30))));
+    assert!(matches!(err, GatewayLoginError::Portal(PortalError::Timeout(_))));
+    assert!(PortalError::from(err).is_timeout());
+
+    let err = GatewayLoginError::from(anyhow::anyhow!(PortalError::Maintenance("down".to_string())));
+    assert!(matches!(err, GatewayLoginError::Maintenance(message) if message == "down"));
+
+    let err = GatewayLoginError::from(anyhow::anyhow!(GatewayLoginError::MfaParse("body".to_string())));
+    assert!(matches!(err, GatewayLoginError::MfaParse(_)));
+  }
+
+  #[test]
+  fn gateway_login_error_keeps_portal_errors() {
+    let err = PortalError::from(GatewayLoginError::Maintenance("down".to_string()));
+    assert!(err.is_maintenance());
+
+    let err = PortalError::from(GatewayLoginError::Unexpected(anyhow::anyhow!(PortalError::TlsError)));
+    assert!(matches!(err, PortalError::TlsError));
+
+    let err = PortalError::from(GatewayLoginError::CookieExpired("auth-failed".to_string()));
+    assert!(
+      err
+        .gateway_login_error()
+        .is_some_and(GatewayLoginError::is_cookie_expired)
+    );
+    assert!(err.is_cookie_expired());
+    assert!(PortalError::CookieExpired("Invalid Cookie".to_string()).is_cookie_expired());
+
+    let err = PortalError::from(GatewayLoginError::MfaParse("body".to_string()));
+    assert!(matches!(err, PortalError::Other(_)));
+    assert_eq!(err.to_string(), "Failed to parse MFA challenge: body");
+  }
+}
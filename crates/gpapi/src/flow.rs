@@ -0,0 +1,213 @@
+use std::future::Future;
+
+use anyhow::{anyhow, bail};
+use log::{info, warn};
+
+use crate::{
+  credential::{Credential, PreloginCredential},
+  error::{GatewayLoginError, PortalError},
+  gateway::{Gateway, GatewayLogin, GatewayToken, MfaChallenge, MfaLimiter, gateway_login},
+  gp_params::GpParams,
+  portal::{PortalConfig, Prelogin, prelogin, retrieve_config},
+};
+
+/// The result of the whole login flow, i.e., the gateway to connect and its cookie
+#[derive(Debug, Clone)]
+pub struct LoginResult {
+  gateway: Gateway,
+  token: GatewayToken,
+  portal_config: Option<PortalConfig>,
+}
+
+impl LoginResult {
+  pub fn gateway(&self) -> &Gateway {
+    &self.gateway
+  }
+
+  pub fn token(&self) -> &GatewayToken {
+    &self.token
+  }
+
+  /// The cookie to connect to the gateway with openconnect
+  pub fn cookie(&self) -> String {
+    self.token.to_string()
+  }
+
+  /// The portal config, None if the server is logged in as a gateway
+  pub fn portal_config(&self) -> Option<&PortalConfig> {
+    self.portal_config.as_ref()
+  }
+}
+
+/// The interactions of the login flow, implemented by the frontends
+pub trait LoginHandler {
+  /// Obtain the credential of the server for the prelogin, e.g., the password of the standard
+  /// authentication, or the result of the SAML authentication
+  fn credential(&self, server: &str, prelogin: &Prelogin) -> impl Future<Output = anyhow::Result<Credential>>;
+
+  /// Respond to the MFA challenge of the gateway, it fails by default
+  fn mfa_response(&self, challenge: &MfaChallenge) -> impl Future<Output = anyhow::Result<String>> {
+    let message = challenge.message().to_string();
+    async move { bail!("The gateway requires the MFA response: {}", message) }
+  }
+
+  /// Select the gateway of the portal, the preferred one of the region by default
+  fn select_gateway<'a>(&self, portal_config: &'a PortalConfig, region: &str) -> &'a Gateway {
+    portal_config.find_preferred_gateway(region)
+  }
+
+  /// Limit the MFA responses of the gateway login, the default attempts and delay by default
+  fn mfa_limiter(&self) -> MfaLimiter {
+    MfaLimiter::default()
+  }
+}
+
+/// Complete the SAML authentication done in an external browser, e.g., the default browser if the
+/// SAML prelogin `support_default_browser()`, with the token handed back by the browser. The token
+/// is one of:
+///
+/// - `globalprotectcallback:<base64>`, the SAML response HTML encoded, with the `<saml-username>`
+///   and `<prelogin-cookie>` tags
+/// - `globalprotectcallback:cas-as=1&un=<username>&token=<token>`, of the CAS authentication
+/// - the JSON result printed by gpauth, e.g., `{"success":{"username":"...","preloginCookie":"..."}}`
+/// - the SAML response HTML
+///
+/// The server is checked to use the SAML authentication first, so that the token is not sent to a
+/// wrong server. The credential is for `retrieve_config`, or `gateway_login` if
+/// `gp_params.is_gateway()`.
+pub async fn complete_saml(server: &str, token: &str, gp_params: &GpParams) -> Result<PreloginCredential, PortalError> {
+  let cred = PreloginCredential::from_saml_assertion(token)?;
+
+  let Prelogin::Saml(_) = prelogin(server, gp_params).await? else {
+    return Err(PortalError::Other(anyhow!(
+      "The server {} does not use the SAML authentication",
+      server
+    )));
+  };
+
+  info!("SAML authentication completed for {}", cred.username());
+  Ok(cred)
+}
+
+/// Log in to the server with the credential, it is a portal unless `gp_params.is_gateway()`.
+/// The credential must match the authentication method of the prelogin, e.g., the SAML
+/// authentication cannot be done with the password.
+pub async fn login(server: &str, cred: &Credential, gp_params: &GpParams) -> Result<LoginResult, PortalError> {
+  login_with(server, gp_params, &CredentialHandler(cred)).await
+}
+
+/// Same as `login`, but the credential and the MFA responses are obtained with the handler
+pub async fn login_with(
+  server: &str,
+  gp_params: &GpParams,
+  handler: &impl LoginHandler,
+) -> Result<LoginResult, PortalError> {
+  if gp_params.is_gateway() {
+    return login_gateway_with_prelogin(server, gp_params, handler).await;
+  }
+
+  info!("[{}] Portal prelogin started for {}", gp_params.request_id(), server);
+  let prelogin = prelogin(server, gp_params).await?;
+  let cred = handler.credential(server, &prelogin).await?;
+
+  let portal_config = retrieve_config(server, &cred, gp_params).await?;
+  let gateway = handler.select_gateway(&portal_config, prelogin.region()).clone();
+  info!("[{}] Gateway login started for {}", gp_params.request_id(), gateway);
+
+  let cred = Credential::from(portal_config.auth_cookie());
+  let token = match login_gateway(gateway.server(), &cred, gp_params, handler).await {
+    Ok(token) => token,
+    Err(PortalError::Other(err)) if is_auth_rejected(&err) => {
+      warn!(
+        "[{}] The gateway rejected the portal cookie, logging in to the gateway: {}",
+        gp_params.request_id(),
+        err
+      );
+      let mut gp_params = gp_params.clone();
+      gp_params.set_is_gateway(true);
+      return login_gateway_with_prelogin(gateway.server(), &gp_params, handler)
+        .await
+        .map(|result| LoginResult {
+          portal_config: Some(portal_config),
+          ..result
+        });
+    }
+    Err(err) => return Err(err),
+  };
+
+  Ok(LoginResult {
+    gateway,
+    token,
+    portal_config: Some(portal_config),
+  })
+}
+
+async fn login_gateway_with_prelogin(
+  gateway: &str,
+  gp_params: &GpParams,
+  handler: &impl LoginHandler,
+) -> Result<LoginResult, PortalError> {
+  info!("[{}] Gateway prelogin started for {}", gp_params.request_id(), gateway);
+  let prelogin = prelogin(gateway, gp_params).await?;
+  let cred = handler.credential(gateway, &prelogin).await?;
+  let token = login_gateway(gateway, &cred, gp_params, handler).await?;
+
+  Ok(LoginResult {
+    gateway: Gateway::new(gateway.to_string(), gateway.to_string()),
+    token,
+    portal_config: None,
+  })
+}
+
+/// Log in to the gateway, and respond to the MFA challenges with the handler until the cookie is
+/// issued, up to the attempts of `LoginHandler::mfa_limiter`
+pub async fn login_gateway(
+  gateway: &str,
+  cred: &Credential,
+  gp_params: &GpParams,
+  handler: &impl LoginHandler,
+) -> Result<GatewayToken, PortalError> {
+  let mut login = gateway_login(gateway, cred, gp_params).await?;
+  let mut mfa = handler.mfa_limiter();
+
+  loop {
+    match login {
+      GatewayLogin::Cookie(token) => return Ok(token),
+      GatewayLogin::Mfa(challenge) => {
+        mfa.check()?;
+        let response = handler.mfa_response(&challenge).await?;
+        login = mfa.submit(gateway, cred, &challenge, &response, gp_params).await?;
+      }
+    }
+  }
+}
+
+/// The portal cookie may also be reported as expired, e.g., when the gateway does not accept it
+fn is_auth_rejected(err: &anyhow::Error) -> bool {
+  err
+    .downcast_ref::<GatewayLoginError>()
+    .is_some_and(|err| err.is_auth_rejected() || err.is_cookie_expired())
+}
+
+/// The handler of `login`, which checks the credential against the authentication method
+struct CredentialHandler<'a>(&'a Credential);
+
+impl LoginHandler for CredentialHandler<'_> {
+  async fn credential(&self, server: &str, prelogin: &Prelogin) -> anyhow::Result<Credential> {
+    match (prelogin, self.0) {
+      (Prelogin::Saml(_), Credential::Password(_)) => {
+        bail!(
+          "{} requires the SAML authentication, the password cannot be used",
+          server
+        )
+      }
+      (Prelogin::Standard(_), Credential::Prelogin(_)) => {
+        bail!(
+          "{} requires the standard authentication, the SAML credential cannot be used",
+          server
+        )
+      }
+      _ => Ok(self.0.clone()),
+    }
+  }
+}
@@ -1,20 +1,22 @@
 use std::collections::HashMap;
 
+use anyhow::bail;
 use log::{info, warn};
 use reqwest::Client;
-use xmltree::Element;
+use xmltree::{Element, XMLNode};
 
 use crate::{
+  error::PortalError,
   gp_params::GpParams,
   process::hip_launcher::HipLauncher,
-  utils::{normalize_server, xml::ElementExt},
+  utils::{esp_url, normalize_server, read_body, xml::ElementExt},
 };
 
 struct HipReporter<'a> {
   server: String,
   cookie: &'a str,
   md5: &'a str,
-  csd_wrapper: &'a str,
+  csd_wrappers: &'a [&'a str],
   gp_params: &'a GpParams,
   client: Client,
 }
@@ -40,6 +42,13 @@ impl HipReporter<'_> {
     let report = self.generate_report(&client_ip).await?;
 
     if let Err(err) = self.submit_hip(&client_ip, &report).await {
+      // The user needs to fix the HIP issues, e.g., by visiting the remediation URL
+      if err
+        .downcast_ref::<PortalError>()
+        .is_some_and(|err| err.is_hip_check_failed())
+      {
+        return Err(err);
+      }
       warn!("Failed to submit HIP report: {}", err);
     }
 
@@ -47,7 +56,7 @@ impl HipReporter<'_> {
   }
 
   async fn retrieve_client_ip(&self) -> anyhow::Result<String> {
-    let config_url = format!("{}/ssl-vpn/getconfig.esp", self.server);
+    let config_url = esp_url(&self.server, "ssl-vpn/getconfig.esp")?;
     let mut params: HashMap<&str, &str> = HashMap::new();
 
     params.insert("client-type", "1");
@@ -68,7 +77,7 @@ impl HipReporter<'_> {
     let params = merge_cookie_params(self.cookie, &params)?;
 
     let res = self.client.post(&config_url).form(&params).send().await?;
-    let res_xml = res.error_for_status()?.text().await?;
+    let res_xml = read_body(res.error_for_status()?, self.gp_params).await?;
     let root = Element::parse(res_xml.as_bytes())?;
 
     // Get <ip-address>
@@ -80,7 +89,7 @@ impl HipReporter<'_> {
   }
 
   async fn check_hip(&self, client_ip: &str) -> anyhow::Result<bool> {
-    let url = format!("{}/ssl-vpn/hipreportcheck.esp", self.server);
+    let url = esp_url(&self.server, "ssl-vpn/hipreportcheck.esp")?;
     let mut params = HashMap::new();
 
     params.insert("client-role", "global-protect-full");
@@ -89,24 +98,30 @@ impl HipReporter<'_> {
 
     let params = merge_cookie_params(self.cookie, &params)?;
     let res = self.client.post(&url).form(&params).send().await?;
-    let res_xml = res.error_for_status()?.text().await?;
+    let res_xml = read_body(res.error_for_status()?, self.gp_params).await?;
 
     is_hip_needed(&res_xml)
   }
 
   async fn generate_report(&self, client_ip: &str) -> anyhow::Result<String> {
-    let launcher = HipLauncher::new(self.csd_wrapper)
-      .cookie(self.cookie)
-      .md5(self.md5)
-      .client_ip(client_ip)
-      .client_os(self.gp_params.client_os())
-      .client_version(self.gp_params.client_version());
-
-    launcher.launch().await
+    let mut reports = Vec::with_capacity(self.csd_wrappers.len());
+
+    for csd_wrapper in self.csd_wrappers {
+      let launcher = HipLauncher::new(csd_wrapper)
+        .cookie(self.cookie)
+        .md5(self.md5)
+        .client_ip(client_ip)
+        .client_os(self.gp_params.client_os())
+        .client_version(self.gp_params.client_version());
+
+      reports.push(launcher.launch().await?);
+    }
+
+    merge_hip_reports(&reports)
   }
 
   async fn submit_hip(&self, client_ip: &str, report: &str) -> anyhow::Result<()> {
-    let url = format!("{}/ssl-vpn/hipreport.esp", self.server);
+    let url = esp_url(&self.server, "ssl-vpn/hipreport.esp")?;
 
     let mut params = HashMap::new();
     params.insert("client-role", "global-protect-full");
@@ -115,14 +130,64 @@ impl HipReporter<'_> {
 
     let params = merge_cookie_params(self.cookie, &params)?;
     let res = self.client.post(&url).form(&params).send().await?;
-    let res_xml = res.error_for_status()?.text().await?;
+    let res_xml = read_body(res.error_for_status()?, self.gp_params).await?;
 
     info!("HIP check response: {}", res_xml);
+    check_hip_report_response(&res_xml)?;
 
     Ok(())
   }
 }
 
+/// Check whether the gateway rejected the HIP report, the rejection may carry the quarantine
+/// or remediation URL that the user should visit.
+fn check_hip_report_response(res_xml: &str) -> Result<(), PortalError> {
+  let Ok(root) = Element::parse(res_xml.as_bytes()) else {
+    return Ok(());
+  };
+
+  let status = root
+    .attr("status")
+    .map(|s| s.to_string())
+    .or_else(|| root.descendant_text("status"))
+    .map(|s| s.trim().to_lowercase());
+  let remediation_url = parse_remediation_url(&root);
+
+  let failed = matches!(status.as_deref(), Some("error" | "failure" | "failed"));
+  if !failed && remediation_url.is_none() {
+    return Ok(());
+  }
+
+  let message = ["error", "message", "reason"]
+    .iter()
+    .find_map(|name| root.descendant_text(name))
+    .map(|s| s.trim().to_string())
+    .filter(|s| !s.is_empty())
+    .unwrap_or_else(|| String::from("The HIP report is rejected by the gateway"));
+
+  Err(PortalError::HipCheckFailed {
+    message,
+    remediation_url,
+  })
+}
+
+/// The URL is either in `<remediation-url>`, or in `<quarantine>` as the `url` child, attribute or text
+fn parse_remediation_url(root: &Element) -> Option<String> {
+  let quarantine_url = || {
+    let quarantine = root.descendant("quarantine")?;
+    quarantine
+      .child_text("url")
+      .or_else(|| quarantine.attr("url").map(|s| s.to_string()))
+      .or_else(|| quarantine.get_text().map(|s| s.to_string()))
+  };
+
+  root
+    .descendant_text("remediation-url")
+    .or_else(quarantine_url)
+    .map(|url| url.trim().to_string())
+    .filter(|url| url.starts_with("https://") || url.starts_with("http://"))
+}
+
 fn is_hip_needed(res_xml: &str) -> anyhow::Result<bool> {
   let root = Element::parse(res_xml.as_bytes())?;
   let hip_needed = root
@@ -132,6 +197,69 @@ fn is_hip_needed(res_xml: &str) -> anyhow::Result<bool> {
   Ok(hip_needed == "yes")
 }
 
+/// Merge the HIP reports into a composite report. The first report is the base, the top-level
+/// sections and the category entries missing from it are appended from the other reports in order.
+pub fn merge_hip_reports<T: AsRef<str>>(reports: &[T]) -> anyhow::Result<String> {
+  let mut reports = reports
+    .iter()
+    .map(|report| Element::parse(report.as_ref().trim().as_bytes()))
+    .collect::<Result<Vec<_>, _>>()?
+    .into_iter();
+
+  let Some(mut merged) = reports.next() else {
+    bail!("No HIP report to merge");
+  };
+
+  for report in reports {
+    for section in report.children.into_iter().filter_map(into_element) {
+      if section.name == "categories" {
+        merge_categories(&mut merged, section);
+      } else if merged.get_child(section.name.as_str()).is_none() {
+        merged.children.push(XMLNode::Element(section));
+      }
+    }
+  }
+
+  let mut buf = Vec::new();
+  merged.write(&mut buf)?;
+  let merged = String::from_utf8(buf)?;
+
+  // Make sure the composite report is well-formed before submitting it
+  Element::parse(merged.as_bytes())?;
+
+  Ok(merged)
+}
+
+fn merge_categories(report: &mut Element, categories: Element) {
+  if report.get_child("categories").is_none() {
+    report.children.push(XMLNode::Element(categories));
+    return;
+  }
+
+  let Some(merged) = report.get_mut_child("categories") else {
+    return;
+  };
+
+  for entry in categories.children.into_iter().filter_map(into_element) {
+    let exists = merged
+      .children
+      .iter()
+      .filter_map(XMLNode::as_element)
+      .any(|e| e.name == entry.name && e.attr("name") == entry.attr("name"));
+
+    if !exists {
+      merged.children.push(XMLNode::Element(entry));
+    }
+  }
+}
+
+fn into_element(node: XMLNode) -> Option<Element> {
+  match node {
+    XMLNode::Element(element) => Some(element),
+    _ => None,
+  }
+}
+
 fn merge_cookie_params(cookie: &str, params: &HashMap<&str, &str>) -> anyhow::Result<HashMap<String, String>> {
   let cookie_params = serde_urlencoded::from_str::<HashMap<String, String>>(cookie)?;
   let params = params
@@ -154,7 +282,33 @@ fn build_csd_token(cookie: &str) -> anyhow::Result<String> {
   Ok(md5)
 }
 
-pub async fn hip_report(gateway: &str, cookie: &str, csd_wrapper: &str, gp_params: &GpParams) -> anyhow::Result<()> {
+pub async fn hip_report(
+  gateway: &str,
+  cookie: &str,
+  csd_wrapper: &str,
+  gp_params: &GpParams,
+) -> Result<(), PortalError> {
+  hip_report_composite(gateway, cookie, &[csd_wrapper], gp_params).await
+}
+
+/// Submit a composite HIP report merged from the outputs of multiple CSD wrappers
+pub async fn hip_report_composite(
+  gateway: &str,
+  cookie: &str,
+  csd_wrappers: &[&str],
+  gp_params: &GpParams,
+) -> Result<(), PortalError> {
+  hip_report_impl(gateway, cookie, csd_wrappers, gp_params).await?;
+
+  Ok(())
+}
+
+async fn hip_report_impl(
+  gateway: &str,
+  cookie: &str,
+  csd_wrappers: &[&str],
+  gp_params: &GpParams,
+) -> anyhow::Result<()> {
   let client = Client::try_from(gp_params)?;
   let md5 = build_csd_token(cookie)?;
 
@@ -164,7 +318,7 @@ pub async fn hip_report(gateway: &str, cookie: &str, csd_wrapper: &str, gp_param
     server: normalize_server(gateway)?,
     cookie,
     md5: &md5,
-    csd_wrapper,
+    csd_wrappers,
     gp_params,
     client,
   };
@@ -1,28 +1,68 @@
-use std::borrow::Cow;
+use std::collections::HashMap;
 
 use anyhow::bail;
 use log::{debug, info, warn};
 use reqwest::Client;
-use urlencoding::{decode, encode};
 use xmltree::Element;
 
+use super::GatewayToken;
 use crate::{
   credential::Credential,
-  error::PortalError,
+  error::{GatewayLoginError, PortalError},
   gp_params::GpParams,
-  utils::{normalize_server, parse_gp_response, remove_url_scheme, xml::ElementExt},
+  utils::{
+    esp_url, normalize_server, parse_gp_response, remove_url_scheme, retry::with_retry_except_timeout, xml::ElementExt,
+  },
 };
 
 pub enum GatewayLogin {
-  Cookie(String),
-  Mfa(String, String),
+  Cookie(GatewayToken),
+  Mfa(MfaChallenge),
 }
 
-pub async fn gateway_login(gateway: &str, cred: &Credential, gp_params: &GpParams) -> anyhow::Result<GatewayLogin> {
+/// The MFA challenge returned by the gateway login
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MfaChallenge {
+  message: String,
+  input_str: String,
+  masked: bool,
+}
+
+impl MfaChallenge {
+  pub fn message(&self) -> &str {
+    &self.message
+  }
+
+  /// The challenge ID to be sent back with the response
+  pub fn input_str(&self) -> &str {
+    &self.input_str
+  }
+
+  /// Whether the response is a secret that should not be echoed, e.g., a typed code
+  /// rather than a push notification approval
+  pub fn masked(&self) -> bool {
+    self.masked
+  }
+}
+
+pub async fn gateway_login(
+  gateway: &str,
+  cred: &Credential,
+  gp_params: &GpParams,
+) -> Result<GatewayLogin, GatewayLoginError> {
+  let login = with_retry_except_timeout(gp_params.retry_policy(), || {
+    gateway_login_impl(gateway, cred, gp_params)
+  })
+  .await?;
+
+  Ok(login)
+}
+
+async fn gateway_login_impl(gateway: &str, cred: &Credential, gp_params: &GpParams) -> anyhow::Result<GatewayLogin> {
   let url = normalize_server(gateway)?;
   let gateway = remove_url_scheme(&url);
 
-  let login_url = format!("{}/ssl-vpn/login.esp", url);
+  let login_url = esp_url(&url, "ssl-vpn/login.esp")?;
   let client = Client::try_from(gp_params)?;
 
   let mut params = cred.to_params();
@@ -31,101 +71,244 @@ pub async fn gateway_login(gateway: &str, cred: &Credential, gp_params: &GpParam
   params.extend(extra_params);
   params.insert("server", &gateway);
 
-  info!("Perform gateway login, user_agent: {}", gp_params.user_agent());
+  let request_id = gp_params.request_id();
+  info!(
+    "[{}] Perform gateway login, user_agent: {}",
+    request_id,
+    gp_params.user_agent()
+  );
 
   let res = client.post(&login_url).form(&params).send().await.map_err(|e| {
-    warn!("Network error: {:?}", e);
+    warn!("[{}] Network error: {:?}", request_id, e);
     anyhow::anyhow!(PortalError::NetworkError(e))
   })?;
 
-  let res = parse_gp_response(res).await.map_err(|err| {
-    warn!("{err}");
-    anyhow::anyhow!("Gateway login error: {}", err.reason)
+  let res = parse_gp_response(res, gp_params).await?.map_err(|err| {
+    warn!("[{}] {}", request_id, err);
+    if let Some(message) = &err.maintenance {
+      return anyhow::anyhow!(PortalError::Maintenance(message.clone()));
+    }
+
+    if let Some(message) = [&err.reason, &err.body]
+      .into_iter()
+      .find(|text| is_lockout_message(text))
+    {
+      return GatewayLoginError::LockedOut(truncate_message(message)).into();
+    }
+
+    if let Some(message) = [&err.reason, &err.body]
+      .into_iter()
+      .find(|text| is_cookie_expired(cred, text))
+    {
+      return GatewayLoginError::CookieExpired(truncate_message(message)).into();
+    }
+
+    let message = format!("Gateway login error: {}", err.reason);
+    anyhow::anyhow!(err).context(message)
   })?;
 
   // MFA detected
   if res.contains("Challenge") {
-    let Some((message, input_str)) = parse_mfa(&res) else {
-      bail!("Failed to parse MFA challenge: {res}");
+    let Some(challenge) = parse_mfa(&res) else {
+      return Err(GatewayLoginError::MfaParse(res).into());
     };
 
-    return Ok(GatewayLogin::Mfa(message, input_str));
+    if is_lockout_message(challenge.message()) {
+      return Err(GatewayLoginError::LockedOut(challenge.message.clone()).into());
+    }
+
+    return Ok(GatewayLogin::Mfa(challenge));
   }
 
-  debug!("Gateway login response: {}", res);
+  // Some gateways report the error with 200, rather than the JNLP
+  if !res.contains("<jnlp") && is_cookie_expired_message(&res) {
+    warn!("[{}] {}", request_id, res);
+    return Err(GatewayLoginError::CookieExpired(truncate_message(&res)).into());
+  }
 
-  let root = Element::parse(res.as_bytes())?;
+  debug!("[{}] Gateway login response: {}", request_id, res);
 
-  let cookie = build_gateway_token(&root, gp_params.computer())?;
+  let root = Element::parse(res.as_bytes()).map_err(|err| GatewayLoginError::TokenParse(err.into()))?;
+
+  let cookie = build_gateway_token(&root, gp_params.computer(), !gp_params.no_preferred_ip())
+    .map_err(GatewayLoginError::TokenParse)?;
 
   Ok(GatewayLogin::Cookie(cookie))
 }
 
-fn build_gateway_token(element: &Element, computer: &str) -> anyhow::Result<String> {
-  let args = element
-    .descendants("argument")
-    .iter()
-    .map(|e| e.get_text().unwrap_or_default())
-    .collect::<Vec<_>>();
+/// Respond to the MFA challenge returned by `gateway_login`. The server may issue another challenge
+/// for the chained MFA, which is returned as `GatewayLogin::Mfa` to be responded again.
+pub async fn submit_mfa(
+  gateway: &str,
+  cred: &Credential,
+  input_str: &str,
+  otp: &str,
+  gp_params: &GpParams,
+) -> Result<GatewayLogin, GatewayLoginError> {
+  let mut gp_params = gp_params.clone();
+  gp_params.set_input_str(input_str);
+  gp_params.set_otp(otp);
+
+  info!("[{}] Submit the MFA challenge response", gp_params.request_id());
+  gateway_login(gateway, cred, &gp_params).await
+}
 
-  let mut params = vec![
-    read_args(&args, 1, "authcookie")?,
-    read_args(&args, 3, "portal")?,
-    read_args(&args, 4, "user")?,
-    read_args(&args, 7, "domain")?,
-    read_args(&args, 15, "preferred-ip")?,
-    ("computer", computer),
-  ];
+/// An argument of the JNLP in the gateway login response, by its position and name
+struct JnlpArg {
+  index: usize,
+  name: &'static str,
+}
 
-  if let Some(persistent_cookie) = read_optional_arg(&args, 2) {
-    params.push(("persistent-cookie", persistent_cookie));
+impl JnlpArg {
+  const fn new(index: usize, name: &'static str) -> Self {
+    Self { index, name }
   }
+}
+
+const AUTHCOOKIE_ARG: JnlpArg = JnlpArg::new(1, "authcookie");
+const PERSISTENT_COOKIE_ARG: JnlpArg = JnlpArg::new(2, "persistent-cookie");
+const PORTAL_ARG: JnlpArg = JnlpArg::new(3, "portal");
+const USER_ARG: JnlpArg = JnlpArg::new(4, "user");
+const DOMAIN_ARG: JnlpArg = JnlpArg::new(7, "domain");
+const PREFERRED_IP_ARG: JnlpArg = JnlpArg::new(15, "preferred-ip");
+const PORTAL_USERAUTHCOOKIE_ARG: JnlpArg = JnlpArg::new(16, "portal-userauthcookie");
+const PORTAL_PRELOGONUSERAUTHCOOKIE_ARG: JnlpArg = JnlpArg::new(17, "portal-prelogonuserauthcookie");
+
+/// The JNLP arguments, read by the `name` attribute if the gateway names them, e.g.,
+/// `<argument name="authcookie">`, and by the position otherwise, also when the gateway leaves
+/// some of them unnamed
+struct JnlpArgs {
+  // None for the named arguments, which are not read by the position
+  positional: Vec<Option<String>>,
+  named: HashMap<String, String>,
+}
 
-  if let Some(portal_userauthcookie) = read_optional_arg(&args, 16) {
-    params.push(("portal-userauthcookie", portal_userauthcookie));
+impl JnlpArgs {
+  fn parse(element: &Element) -> Self {
+    let arguments = element.descendants("argument");
+    let positional = arguments
+      .iter()
+      .map(|e| e.attr("name").is_none().then(|| e.text_content().unwrap_or_default()))
+      .collect::<Vec<_>>();
+    let named = arguments
+      .iter()
+      .filter_map(|e| Some((e.attr("name")?.to_string(), e.text_content().unwrap_or_default())))
+      .collect();
+
+    Self { positional, named }
   }
 
-  if let Some(portal_prelogonuserauthcookie) = read_optional_arg(&args, 17) {
-    params.push(("portal-prelogonuserauthcookie", portal_prelogonuserauthcookie));
+  fn get(&self, arg: &JnlpArg) -> Option<&str> {
+    self
+      .named
+      .get(arg.name)
+      .or_else(|| self.positional.get(arg.index)?.as_ref())
+      .map(String::as_str)
   }
 
-  let token = params
-    .iter()
-    .map(|(k, v)| {
-      let value = normalize_token_value(v);
-      format!("{}={}", k, encode(value.as_ref()))
+  /// Fail with the missing argument, rather than reading the wrong one from a short argument list
+  fn required(&self, arg: &JnlpArg) -> anyhow::Result<&str> {
+    self.get(arg).ok_or_else(|| {
+      if self.named.is_empty() {
+        anyhow::anyhow!(
+          "Failed to read {} from args: index {} is missing, the response has {} argument(s)",
+          arg.name,
+          arg.index,
+          self.positional.len()
+        )
+      } else {
+        anyhow::anyhow!("Failed to read {} from args: the named argument is missing", arg.name)
+      }
     })
-    .collect::<Vec<_>>()
-    .join("&");
+  }
 
-  Ok(token)
+  fn optional(&self, arg: &JnlpArg) -> Option<&str> {
+    self.get(arg).filter(|value| !is_null_arg(value))
+  }
 }
 
-fn read_optional_arg<'a>(args: &'a [Cow<'_, str>], index: usize) -> Option<&'a str> {
-  let value = args.get(index)?.as_ref();
-  if value.is_empty() || value == "(null)" || value == "-1" || value == "empty" {
-    None
-  } else {
-    Some(value)
+fn is_null_arg(value: &str) -> bool {
+  value.is_empty() || value == "(null)" || value == "-1" || value == "empty"
+}
+
+/// Build the gateway token from the JNLP arguments, the `preferred-ip` is omitted unless
+/// `send_preferred_ip`, so that the gateway assigns a fresh IP.
+fn build_gateway_token(element: &Element, computer: &str, send_preferred_ip: bool) -> anyhow::Result<GatewayToken> {
+  let args = JnlpArgs::parse(element);
+
+  let authcookie = args.required(&AUTHCOOKIE_ARG)?;
+  if is_null_arg(authcookie) {
+    bail!("Failed to read authcookie from args: the value is empty");
   }
+  let portal = args.required(&PORTAL_ARG)?;
+  let user = args.required(&USER_ARG)?;
+  let domain = args.required(&DOMAIN_ARG)?;
+  let preferred_ip = if send_preferred_ip {
+    Some(args.required(&PREFERRED_IP_ARG)?)
+  } else {
+    None
+  };
+
+  let token = GatewayToken::new(authcookie, portal, user, domain, preferred_ip, computer)
+    .with_persistent_cookie(args.optional(&PERSISTENT_COOKIE_ARG))
+    .with_portal_userauthcookie(args.optional(&PORTAL_USERAUTHCOOKIE_ARG))
+    .with_portal_prelogonuserauthcookie(args.optional(&PORTAL_PRELOGONUSERAUTHCOOKIE_ARG));
+
+  Ok(token)
 }
 
-fn normalize_token_value<'a>(value: &'a str) -> Cow<'a, str> {
-  if value.contains('%') {
-    return decode(value).unwrap_or_else(|_| Cow::Borrowed(value));
+/// The gateway reports the lockout in the error reason, the error page or the challenge message
+fn is_lockout_message(message: &str) -> bool {
+  let message = message.to_lowercase();
+  ["locked out", "account locked", "account is locked", "too many failed"]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
+
+/// The gateway reports the expired or revoked cookie either explicitly, or as the generic
+/// authentication failure, which is only told from the rejected password by the credential
+pub(crate) fn is_cookie_expired(cred: &Credential, message: &str) -> bool {
+  if is_cookie_expired_message(message) {
+    return true;
   }
 
-  Cow::Borrowed(value)
+  let is_cookie = matches!(cred, Credential::Prelogin(_)) || cred.auth_cookie().is_some();
+  let message = message.to_lowercase();
+  is_cookie
+    && ["auth-failed", "authentication failed"]
+      .iter()
+      .any(|pattern| message.contains(pattern))
 }
 
-fn read_args<'a>(args: &'a [Cow<'_, str>], index: usize, key: &'a str) -> anyhow::Result<(&'a str, &'a str)> {
-  args
-    .get(index)
-    .ok_or_else(|| anyhow::anyhow!("Failed to read {key} from args"))
-    .map(|s| (key, s.as_ref()))
+fn is_cookie_expired_message(message: &str) -> bool {
+  let message = message.to_lowercase();
+  [
+    "cookie expired",
+    "cookie has expired",
+    "expired cookie",
+    "invalid cookie",
+    "cookie is invalid",
+  ]
+  .iter()
+  .any(|pattern| message.contains(pattern))
 }
 
-fn parse_mfa(res: &str) -> Option<(String, String)> {
+/// The error page may be long, keep the beginning of it
+pub(crate) fn truncate_message(message: &str) -> String {
+  const MAX_LEN: usize = 200;
+  message.trim().chars().take(MAX_LEN).collect()
+}
+
+/// Parse the MFA challenge, returned either as the JavaScript variables or as the XML elements
+fn parse_mfa(res: &str) -> Option<MfaChallenge> {
+  if res.contains("respMsg =") && res.contains("inputStr.value") {
+    parse_mfa_js(res)
+  } else {
+    parse_mfa_xml(res)
+  }
+}
+
+fn parse_mfa_js(res: &str) -> Option<MfaChallenge> {
   let message = res
     .lines()
     .find(|l| l.contains("respMsg"))
@@ -136,12 +319,49 @@ fn parse_mfa(res: &str) -> Option<(String, String)> {
     .find(|l| l.contains("inputStr"))
     .and_then(|l| l.split('"').nth(1).map(|s| s.to_string()))?;
 
-  Some((message, input_str))
+  // The input is echoed unless the `secString` or input type hints a secret
+  let masked = res
+    .lines()
+    .filter(|l| l.contains("secString") || l.contains("inputType") || l.contains(".type"))
+    .filter_map(|l| l.split('"').nth(1))
+    .any(is_secret_hint);
+
+  Some(MfaChallenge {
+    message,
+    input_str,
+    masked,
+  })
+}
+
+fn parse_mfa_xml(res: &str) -> Option<MfaChallenge> {
+  let root = Element::parse(res.trim().as_bytes()).ok()?;
+  let text = |name: &str| root.descendant(name).and_then(|e| e.text_content());
+
+  let message = text("respMsg")?;
+  let input_str = text("inputStr")?;
+  let masked = ["secString", "inputType"]
+    .into_iter()
+    .filter_map(text)
+    .any(|v| is_secret_hint(&v));
+
+  Some(MfaChallenge {
+    message,
+    input_str,
+    masked,
+  })
+}
+
+fn is_secret_hint(value: &str) -> bool {
+  matches!(value.trim().to_lowercase().as_str(), "1" | "yes" | "true" | "password")
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::{
+    credential::{AuthCookieCredential, PasswordCredential},
+    gateway::token::normalize_token_value,
+  };
 
   #[test]
   fn mfa() {
@@ -149,9 +369,52 @@ mod tests {
 var respMsg = "MFA message";
 thisForm.inputStr.value = "5ef64e83000119ed";"#;
 
-    let (message, input_str) = parse_mfa(res).unwrap();
-    assert_eq!(message, "MFA message");
-    assert_eq!(input_str, "5ef64e83000119ed");
+    let challenge = parse_mfa(res).unwrap();
+    assert_eq!(challenge.message(), "MFA message");
+    assert_eq!(challenge.input_str(), "5ef64e83000119ed");
+    assert!(!challenge.masked());
+  }
+
+  #[test]
+  fn mfa_masked() {
+    let res = r#"var respStatus = "Challenge";
+var respMsg = "Enter the token code";
+var secString = "1";
+thisForm.inputStr.value = "5ef64e83000119ed";"#;
+
+    let challenge = parse_mfa(res).unwrap();
+    assert_eq!(challenge.message(), "Enter the token code");
+    assert!(challenge.masked());
+
+    let res = r#"var respStatus = "Challenge";
+var respMsg = "Approve the push notification";
+thisForm.inputStr.value = "5ef64e83000119ed";
+thisForm.passwd.type = "text";"#;
+
+    assert!(!parse_mfa(res).unwrap().masked());
+  }
+
+  #[test]
+  fn mfa_xml() {
+    let res = r#"<?xml version="1.0" encoding="UTF-8"?>
+<challenge>
+  <respStatus>Challenge</respStatus>
+  <respMsg>Enter the "token" code</respMsg>
+  <inputStr><![CDATA[5ef64e83000119ed]]></inputStr>
+  <secString>yes</secString>
+</challenge>"#;
+
+    let challenge = parse_mfa(res).unwrap();
+    assert_eq!(challenge.message(), r#"Enter the "token" code"#);
+    assert_eq!(challenge.input_str(), "5ef64e83000119ed");
+    assert!(challenge.masked());
+
+    let res = "<challenge><respMsg>Approve the push notification</respMsg><inputStr>5ef6</inputStr></challenge>";
+    let challenge = parse_mfa(res).unwrap();
+    assert_eq!(challenge.message(), "Approve the push notification");
+    assert!(!challenge.masked());
+
+    assert!(parse_mfa("<challenge><respMsg>No input</respMsg></challenge>").is_none());
   }
 
   #[test]
@@ -185,11 +448,41 @@ thisForm.inputStr.value = "5ef64e83000119ed";"#;
 </jnlp>"#;
 
     let root = Element::parse(res.as_bytes()).unwrap();
-    let token = build_gateway_token(&root, "test-host").unwrap();
+    let token = build_gateway_token(&root, "test-host", true).unwrap().to_string();
     assert!(token.contains("domain=%28empty_domain%29"));
     assert!(!token.contains("domain=%2528empty_domain%2529"));
   }
 
+  #[test]
+  fn gateway_token_avoids_double_encoding_for_encoded_user() {
+    let res = r#"<?xml version="1.0" encoding="utf-8"?>
+<jnlp>
+    <application-desc>
+        <argument>(null)</argument>
+        <argument>cookie-value</argument>
+        <argument>x</argument>
+        <argument>GP-Gateway-N</argument>
+        <argument>user%40example.com</argument>
+        <argument>x</argument>
+        <argument>x</argument>
+        <argument>corp.example.com</argument>
+        <argument>x</argument>
+        <argument>x</argument>
+        <argument>x</argument>
+        <argument>x</argument>
+        <argument>x</argument>
+        <argument>x</argument>
+        <argument>x</argument>
+        <argument>198.51.100.12</argument>
+    </application-desc>
+</jnlp>"#;
+
+    let root = Element::parse(res.as_bytes()).unwrap();
+    let token = build_gateway_token(&root, "test-host", true).unwrap().to_string();
+    assert!(token.contains("user=user%40example.com"));
+    assert!(!token.contains("user=user%2540example.com"));
+  }
+
   #[test]
   fn gateway_token_includes_optional_portal_cookie_fields() {
     let res = r#"<?xml version="1.0" encoding="utf-8"?>
@@ -217,9 +510,229 @@ thisForm.inputStr.value = "5ef64e83000119ed";"#;
 </jnlp>"#;
 
     let root = Element::parse(res.as_bytes()).unwrap();
-    let token = build_gateway_token(&root, "test-host").unwrap();
+    let token = build_gateway_token(&root, "test-host", true).unwrap().to_string();
     assert!(token.contains("persistent-cookie=persistent-cookie-value"));
     assert!(token.contains("portal-userauthcookie=portal-user-cookie-value"));
     assert!(token.contains("portal-prelogonuserauthcookie=portal-prelogon-cookie-value"));
   }
+
+  #[test]
+  fn gateway_token_reads_cdata_arguments() {
+    let res = r#"<?xml version="1.0" encoding="utf-8"?>
+<jnlp>
+    <application-desc>
+        <argument><![CDATA[(null)]]></argument>
+        <argument>
+            <![CDATA[cookie-value]]>
+        </argument>
+        <argument><![CDATA[persistent-cookie-value]]></argument>
+        <argument><![CDATA[GP-Gateway-N]]></argument>
+        <argument><![CDATA[user@example.com]]></argument>
+        <argument>x</argument>
+        <argument>x</argument>
+        <argument><![CDATA[corp.example.com]]></argument>
+        <argument>x</argument>
+        <argument>x</argument>
+        <argument>x</argument>
+        <argument>x</argument>
+        <argument>x</argument>
+        <argument>x</argument>
+        <argument>x</argument>
+        <argument><![CDATA[198.51.100.12]]></argument>
+        <argument><![CDATA[portal-user-cookie-value]]></argument>
+    </application-desc>
+</jnlp>"#;
+
+    let root = Element::parse(res.as_bytes()).unwrap();
+    let token = build_gateway_token(&root, "test-host", true).unwrap();
+    assert_eq!(token.authcookie(), "cookie-value");
+    assert_eq!(token.persistent_cookie(), Some("persistent-cookie-value"));
+    assert_eq!(token.portal(), "GP-Gateway-N");
+    assert_eq!(token.user(), "user@example.com");
+    assert_eq!(token.domain(), "corp.example.com");
+    assert_eq!(token.preferred_ip(), Some("198.51.100.12"));
+    assert_eq!(token.portal_userauthcookie(), Some("portal-user-cookie-value"));
+    assert_eq!(token.portal_prelogonuserauthcookie(), None);
+  }
+
+  #[test]
+  fn gateway_token_omits_preferred_ip_when_disabled() {
+    let res = include_str!("../../tests/files/gateway_login.xml");
+    let root = Element::parse(res.as_bytes()).unwrap();
+
+    let token = build_gateway_token(&root, "test-host", false).unwrap();
+    assert_eq!(token.preferred_ip(), None);
+    assert!(!token.to_string().contains("preferred-ip"));
+
+    let token = build_gateway_token(&root, "test-host", true).unwrap();
+    assert!(token.to_string().contains("preferred-ip="));
+  }
+
+  #[test]
+  fn gateway_token_exposes_individual_cookies() {
+    let res = include_str!("../../tests/files/gateway_login.xml");
+
+    let root = Element::parse(res.as_bytes()).unwrap();
+    let token = build_gateway_token(&root, "test-host", true).unwrap();
+    assert_eq!(token.authcookie(), "xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx");
+    assert_eq!(token.portal(), "XXX-GP-Gateway-N");
+    assert_eq!(token.user(), "user");
+    assert_eq!(token.domain(), "corp.example.com");
+    assert_eq!(token.preferred_ip(), Some(""));
+    assert_eq!(token.computer(), "test-host");
+    assert_eq!(
+      token.persistent_cookie(),
+      Some("xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx")
+    );
+    assert_eq!(token.portal_userauthcookie(), Some("xxxxxx"));
+    assert_eq!(token.portal_prelogonuserauthcookie(), Some("aaaaaa"));
+  }
+
+  #[test]
+  fn gateway_token_round_trips_through_string() {
+    let res = include_str!("../../tests/files/gateway_login.xml");
+
+    let root = Element::parse(res.as_bytes()).unwrap();
+    let token = build_gateway_token(&root, "test-host", true).unwrap();
+    let parsed = token.to_string().parse::<GatewayToken>().unwrap();
+    assert_eq!(parsed.to_string(), token.to_string());
+    assert_eq!(parsed.portal_prelogonuserauthcookie(), Some("aaaaaa"));
+  }
+
+  #[test]
+  fn gateway_token_debug_redacts_the_cookies() {
+    let token = "authcookie=secret-auth&portal=gw&user=alice&persistent-cookie=secret-persistent\
+                 &portal-userauthcookie=secret-user&portal-prelogonuserauthcookie=secret-prelogon"
+      .parse::<GatewayToken>()
+      .unwrap();
+
+    let debug = format!("{:?}", token);
+    assert!(debug.contains("alice") && debug.contains("gw"), "{debug}");
+    assert!(!debug.contains("secret"), "{debug}");
+  }
+
+  fn jnlp(arguments: &[&str]) -> Element {
+    let arguments = arguments
+      .iter()
+      .map(|arg| format!("<argument>{}</argument>", arg))
+      .collect::<String>();
+    let res = format!("<jnlp><application-desc>{}</application-desc></jnlp>", arguments);
+    Element::parse(res.as_bytes()).unwrap()
+  }
+
+  #[test]
+  fn gateway_token_reports_the_missing_argument() {
+    let root = jnlp(&["(null)", "cookie", "x", "GP-Gateway-N", "user"]);
+    let err = build_gateway_token(&root, "test-host", false).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "Failed to read domain from args: index 7 is missing, the response has 5 argument(s)"
+    );
+
+    // The preferred IP is only required when it is sent
+    let root = jnlp(&["(null)", "cookie", "x", "GP-Gateway-N", "user", "x", "x", "corp"]);
+    let token = build_gateway_token(&root, "test-host", false).unwrap();
+    assert_eq!(token.domain(), "corp");
+    assert_eq!(token.persistent_cookie(), None);
+    assert_eq!(token.portal_userauthcookie(), None);
+
+    let err = build_gateway_token(&root, "test-host", true).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "Failed to read preferred-ip from args: index 15 is missing, the response has 8 argument(s)"
+    );
+
+    let root = jnlp(&["(null)", "(null)", "x", "GP-Gateway-N", "user", "x", "x", "corp"]);
+    let err = build_gateway_token(&root, "test-host", false).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "Failed to read authcookie from args: the value is empty"
+    );
+  }
+
+  #[test]
+  fn gateway_token_reads_named_arguments() {
+    let res = r#"<jnlp><application-desc>
+        <argument name="user">alice</argument>
+        <argument name="authcookie">cookie-value</argument>
+        <argument name="portal">GP-Gateway-N</argument>
+        <argument name="domain">corp.example.com</argument>
+        <argument name="portal-userauthcookie">portal-cookie</argument>
+    </application-desc></jnlp>"#;
+
+    let root = Element::parse(res.as_bytes()).unwrap();
+    let token = build_gateway_token(&root, "test-host", false).unwrap();
+    assert_eq!(token.authcookie(), "cookie-value");
+    assert_eq!(token.portal(), "GP-Gateway-N");
+    assert_eq!(token.user(), "alice");
+    assert_eq!(token.domain(), "corp.example.com");
+    assert_eq!(token.portal_userauthcookie(), Some("portal-cookie"));
+    assert_eq!(token.persistent_cookie(), None);
+
+    let err = build_gateway_token(&root, "test-host", true).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "Failed to read preferred-ip from args: the named argument is missing"
+    );
+  }
+
+  #[test]
+  fn gateway_token_reads_unnamed_arguments_by_position() {
+    let res = r#"<jnlp><application-desc>
+        <argument>(null)</argument>
+        <argument name="authcookie">cookie-value</argument>
+        <argument>persistent-value</argument>
+        <argument name="portal">GP-Gateway-N</argument>
+        <argument>alice</argument>
+        <argument>x</argument>
+        <argument>x</argument>
+        <argument>corp.example.com</argument>
+    </application-desc></jnlp>"#;
+
+    let root = Element::parse(res.as_bytes()).unwrap();
+    let token = build_gateway_token(&root, "test-host", false).unwrap();
+    assert_eq!(token.authcookie(), "cookie-value");
+    assert_eq!(token.persistent_cookie(), Some("persistent-value"));
+    assert_eq!(token.portal(), "GP-Gateway-N");
+    assert_eq!(token.user(), "alice");
+    assert_eq!(token.domain(), "corp.example.com");
+  }
+
+  #[test]
+  fn gateway_token_rejects_malformed_string() {
+    let err = "portal=gw&user=alice".parse::<GatewayToken>().unwrap_err();
+    assert_eq!(err, "Missing required key(s): authcookie");
+
+    let err = "authcookie&portal=gw&user=alice".parse::<GatewayToken>().unwrap_err();
+    assert_eq!(err, "Malformed parameter: authcookie");
+  }
+
+  #[test]
+  fn lockout_messages_are_detected() {
+    assert!(is_lockout_message("Your account is locked, contact the administrator"));
+    assert!(is_lockout_message("User Locked Out"));
+    assert!(is_lockout_message("Too many failed attempts"));
+    assert!(!is_lockout_message("Enter the TOTP code"));
+    assert!(!is_lockout_message("auth-failed"));
+
+    assert_eq!(truncate_message(&"x".repeat(300)).len(), 200);
+  }
+
+  #[test]
+  fn cookie_expiry_is_detected_by_the_credential() {
+    let password = Credential::from(PasswordCredential::new("alice", "secret"));
+    let cookie = Credential::from(&AuthCookieCredential::new("alice", "cookie", ""));
+
+    assert!(is_cookie_expired(&password, "Authentication cookie has expired"));
+    assert!(is_cookie_expired(&cookie, "Invalid Cookie"));
+    assert!(is_cookie_expired(&cookie, "auth-failed"));
+    assert!(is_cookie_expired(
+      &cookie,
+      "Authentication failed: Invalid username or password"
+    ));
+
+    // The rejected password is not about the cookie
+    assert!(!is_cookie_expired(&password, "auth-failed"));
+    assert!(!is_cookie_expired(&cookie, "Gateway is busy"));
+  }
 }
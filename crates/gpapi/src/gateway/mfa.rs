@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use log::info;
+use tokio::time::Instant;
+
+use super::{GatewayLogin, MfaChallenge, submit_mfa};
+use crate::{
+  credential::Credential,
+  error::GatewayLoginError,
+  gp_params::{GpParams, RetryPolicy},
+};
+
+pub const DEFAULT_MFA_ATTEMPTS: u32 = 3;
+pub const DEFAULT_MFA_DELAY: Duration = Duration::from_secs(1);
+
+/// Limit the MFA responses, as the gateway may lock the account out after too many wrong ones.
+/// The responses are spaced with the exponential backoff and jitter, and the responses to the
+/// chained challenges count as attempts too.
+#[derive(Debug)]
+pub struct MfaLimiter {
+  policy: RetryPolicy,
+  attempts: u32,
+  last_attempt: Option<Instant>,
+}
+
+impl Default for MfaLimiter {
+  fn default() -> Self {
+    Self::new(DEFAULT_MFA_ATTEMPTS, DEFAULT_MFA_DELAY)
+  }
+}
+
+impl MfaLimiter {
+  /// The delay is doubled after each attempt, starting from `min_delay`
+  pub fn new(max_attempts: u32, min_delay: Duration) -> Self {
+    Self {
+      policy: RetryPolicy::new(max_attempts, min_delay),
+      attempts: 0,
+      last_attempt: None,
+    }
+  }
+
+  pub fn max_attempts(&self) -> u32 {
+    self.policy.max_attempts()
+  }
+
+  pub fn attempts(&self) -> u32 {
+    self.attempts
+  }
+
+  /// Fail if no attempt is left, to check before prompting for the response
+  pub fn check(&self) -> Result<(), GatewayLoginError> {
+    if self.attempts >= self.policy.max_attempts() {
+      return Err(GatewayLoginError::MfaAttemptsExceeded(self.attempts));
+    }
+
+    Ok(())
+  }
+
+  /// Submit the response with `submit_mfa`, after the delay since the previous attempt
+  pub async fn submit(
+    &mut self,
+    gateway: &str,
+    cred: &Credential,
+    challenge: &MfaChallenge,
+    otp: &str,
+    gp_params: &GpParams,
+  ) -> Result<GatewayLogin, GatewayLoginError> {
+    self.check()?;
+
+    if let Some(last_attempt) = self.last_attempt {
+      let next_attempt = last_attempt + self.policy.delay(self.attempts);
+      if next_attempt > Instant::now() {
+        info!(
+          "[{}] Waiting {}ms before the MFA attempt {}/{}",
+          gp_params.request_id(),
+          (next_attempt - Instant::now()).as_millis(),
+          self.attempts + 1,
+          self.policy.max_attempts()
+        );
+        tokio::time::sleep_until(next_attempt).await;
+      }
+    }
+
+    self.attempts += 1;
+    self.last_attempt = Some(Instant::now());
+
+    submit_mfa(gateway, cred, challenge.input_str(), otp, gp_params).await
+  }
+}
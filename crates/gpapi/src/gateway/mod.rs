@@ -1,9 +1,16 @@
 mod login;
+mod mfa;
 mod parse_gateways;
+mod select;
+mod token;
 pub mod hip;
+pub mod probe;
 
 pub use login::*;
+pub use mfa::*;
 pub(crate) use parse_gateways::*;
+pub use select::*;
+pub use token::*;
 
 use serde::{Deserialize, Serialize};
 use specta::Type;
@@ -23,6 +30,9 @@ pub struct Gateway {
   pub(crate) address: String,
   pub(crate) priority: u32,
   pub(crate) priority_rules: Vec<PriorityRule>,
+  /// Only connected when selected by the user, never picked automatically
+  #[serde(default)]
+  pub(crate) manual: bool,
 }
 
 impl Display for Gateway {
@@ -38,6 +48,7 @@ impl Gateway {
       address,
       priority: 0,
       priority_rules: vec![],
+      manual: false,
     }
   }
 
@@ -48,4 +59,28 @@ impl Gateway {
   pub fn server(&self) -> &str {
     &self.address
   }
+
+  /// The address of the gateway, same as `server()`
+  pub fn address(&self) -> &str {
+    &self.address
+  }
+
+  /// The priority of the gateway, the lower the value the higher the priority
+  pub fn priority(&self) -> u32 {
+    self.priority
+  }
+
+  pub fn manual(&self) -> bool {
+    self.manual
+  }
+
+  /// The priority of the gateway in the region, if the region is explicitly listed in its priority rules
+  pub fn region_priority(&self, region: &str) -> Option<u32> {
+    self
+      .priority_rules
+      .iter()
+      .filter(|rule| rule.name.eq_ignore_ascii_case(region))
+      .map(|rule| rule.priority)
+      .min()
+  }
 }
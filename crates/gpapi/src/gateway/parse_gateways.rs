@@ -30,12 +30,16 @@ pub(crate) fn parse_gateways(element: &Element, prefer_internal: bool) -> Option
         .child("priority-rule")
         .map(|n| parse_priority_rules(n))
         .unwrap_or_default();
+      let manual = gateway_item
+        .child_text("manual")
+        .is_some_and(|s| s.trim().eq_ignore_ascii_case("yes"));
 
       Gateway {
         name,
         address,
         priority,
         priority_rules,
+        manual,
       }
     })
     .collect();
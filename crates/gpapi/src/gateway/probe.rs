@@ -0,0 +1,96 @@
+use std::{
+  future::Future,
+  time::{Duration, Instant},
+};
+
+use futures_util::future::join_all;
+use log::debug;
+use tokio::{net::TcpStream, sync::Semaphore};
+use url::Url;
+
+use crate::utils::normalize_server;
+
+/// The default number of the gateway probes that run at once
+pub const DEFAULT_PROBE_CONCURRENCY: usize = 8;
+
+/// Run the probes with at most `concurrency` of them at once, so that a large gateway list
+/// does not open too many sockets. The results keep the order of the items.
+pub async fn probe_concurrently<I, T, F, Fut>(items: I, concurrency: usize, probe: F) -> Vec<T>
+where
+  I: IntoIterator,
+  F: Fn(I::Item) -> Fut,
+  Fut: Future<Output = T>,
+{
+  let semaphore = Semaphore::new(concurrency.max(1));
+  let probes = items.into_iter().map(|item| {
+    let semaphore = &semaphore;
+    let probe = probe(item);
+
+    async move {
+      // The semaphore is never closed, the permit is held until the probe completes
+      let _permit = semaphore.acquire().await;
+      probe.await
+    }
+  });
+
+  join_all(probes).await
+}
+
+/// Measure the TCP connect time to the gateway address, None if it cannot be connected in time
+pub async fn probe_latency(address: &str, timeout: Duration) -> Option<Duration> {
+  let url = Url::parse(&normalize_server(address).ok()?).ok()?;
+  let host = url.host_str()?;
+  let port = url.port_or_known_default()?;
+
+  let start = Instant::now();
+  match tokio::time::timeout(timeout, TcpStream::connect((host, port))).await {
+    Ok(Ok(_)) => Some(start.elapsed()),
+    Ok(Err(err)) => {
+      debug!("Failed to connect to {}: {}", address, err);
+      None
+    }
+    Err(_) => {
+      debug!("Timed out connecting to {}", address);
+      None
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  use super::*;
+
+  #[tokio::test]
+  async fn probes_are_capped_by_concurrency() {
+    let running = AtomicUsize::new(0);
+    let max_running = AtomicUsize::new(0);
+
+    let results = probe_concurrently(0..20, 3, |i| {
+      let (running, max_running) = (&running, &max_running);
+      async move {
+        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+        max_running.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        running.fetch_sub(1, Ordering::SeqCst);
+        i
+      }
+    })
+    .await;
+
+    assert_eq!(results, (0..20).collect::<Vec<_>>());
+    assert_eq!(max_running.load(Ordering::SeqCst), 3);
+  }
+
+  #[tokio::test]
+  async fn unreachable_gateway_has_no_latency() {
+    // Bind then drop to get a local port that refuses the connection
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+
+    let latency = probe_latency(&format!("http://{}", addr), Duration::from_secs(1)).await;
+    assert!(latency.is_none());
+  }
+}
@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use log::info;
+
+use crate::error::PortalError;
+
+use super::{
+  Gateway,
+  probe::{probe_concurrently, probe_latency},
+};
+
+/// How to pick the gateway to connect to
+#[derive(Debug, Clone)]
+pub enum GatewaySelection {
+  /// The gateway with the name or address
+  Manual(String),
+  /// The gateway with the lowest priority value
+  HighestPriority,
+  /// The gateway with the fastest TCP connect, probing at most `concurrency` gateways at once
+  LowestLatency { timeout: Duration, concurrency: usize },
+}
+
+/// Select the gateway with the strategy, the manual-only gateways are not picked automatically
+/// unless they are all the portal provides.
+pub async fn select_gateway(gateways: &[Gateway], strategy: GatewaySelection) -> Result<&Gateway, PortalError> {
+  let candidates = auto_candidates(gateways);
+
+  let gateway = match strategy {
+    GatewaySelection::Manual(name) => gateways
+      .iter()
+      .find(|gateway| gateway.name == name || gateway.address == name)
+      .ok_or_else(|| anyhow!("Cannot find gateway specified: {}", name))?,
+    GatewaySelection::HighestPriority => candidates
+      .into_iter()
+      .min_by_key(|gateway| gateway.priority)
+      .ok_or_else(|| anyhow!("No gateway is available"))?,
+    GatewaySelection::LowestLatency { timeout, concurrency } => {
+      let latencies = probe_concurrently(candidates.iter().copied(), concurrency, |gateway| async move {
+        let latency = probe_latency(&gateway.address, timeout).await;
+        if let Some(latency) = latency {
+          info!("Gateway {} latency: {}ms", gateway, latency.as_millis());
+        }
+        latency
+      })
+      .await;
+
+      candidates
+        .into_iter()
+        .zip(latencies)
+        .filter_map(|(gateway, latency)| latency.map(|latency| (latency, gateway)))
+        .min_by_key(|(latency, _)| *latency)
+        .map(|(_, gateway)| gateway)
+        .ok_or_else(|| anyhow!("None of the gateways is reachable in {}ms", timeout.as_millis()))?
+    }
+  };
+
+  Ok(gateway)
+}
+
+fn auto_candidates(gateways: &[Gateway]) -> Vec<&Gateway> {
+  let candidates: Vec<_> = gateways.iter().filter(|gateway| !gateway.manual).collect();
+
+  if candidates.is_empty() {
+    gateways.iter().collect()
+  } else {
+    candidates
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::TcpListener;
+
+  use super::*;
+
+  fn gateway(name: &str, address: &str, priority: u32) -> Gateway {
+    Gateway {
+      priority,
+      ..Gateway::new(name.to_string(), address.to_string())
+    }
+  }
+
+  /// A local address that refuses the connection
+  fn unreachable_address() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    format!("http://{}", listener.local_addr().unwrap())
+  }
+
+  #[tokio::test]
+  async fn select_by_name_or_priority() {
+    let mut manual = gateway("gw0", "gw0.example.com", 0);
+    manual.manual = true;
+    let gateways = [
+      manual,
+      gateway("gw1", "gw1.example.com", 2),
+      gateway("gw2", "gw2.example.com", 1),
+    ];
+
+    let selected = select_gateway(&gateways, GatewaySelection::HighestPriority)
+      .await
+      .unwrap();
+    assert_eq!(selected.name(), "gw2");
+
+    let selected = select_gateway(&gateways, GatewaySelection::Manual("gw0.example.com".to_string()))
+      .await
+      .unwrap();
+    assert_eq!(selected.name(), "gw0");
+
+    let result = select_gateway(&gateways, GatewaySelection::Manual("gw3".to_string())).await;
+    assert!(result.is_err());
+  }
+
+  #[tokio::test]
+  async fn select_the_reachable_gateway() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let reachable = format!("http://{}", listener.local_addr().unwrap());
+    let strategy = GatewaySelection::LowestLatency {
+      timeout: Duration::from_secs(1),
+      concurrency: 2,
+    };
+
+    let gateways = [gateway("gw1", &unreachable_address(), 1), gateway("gw2", &reachable, 2)];
+    let selected = select_gateway(&gateways, strategy.clone()).await.unwrap();
+    assert_eq!(selected.name(), "gw2");
+
+    let gateways = [gateway("gw1", &unreachable_address(), 1)];
+    assert!(select_gateway(&gateways, strategy).await.is_err());
+  }
+}
@@ -0,0 +1,210 @@
+use std::{
+  borrow::Cow,
+  collections::HashMap,
+  fmt::{self, Display},
+  str::FromStr,
+};
+
+use urlencoding::{decode, encode};
+
+/// The gateway token, i.e., the cookie passed to openconnect.
+/// It combines several cookies and the user information returned by the gateway login,
+/// all values are stored decoded and will be encoded when formatting the token.
+#[derive(Clone)]
+pub struct GatewayToken {
+  authcookie: String,
+  portal: String,
+  user: String,
+  domain: String,
+  preferred_ip: Option<String>,
+  computer: String,
+  persistent_cookie: Option<String>,
+  portal_userauthcookie: Option<String>,
+  portal_prelogonuserauthcookie: Option<String>,
+}
+
+impl GatewayToken {
+  pub(crate) fn new(
+    authcookie: &str,
+    portal: &str,
+    user: &str,
+    domain: &str,
+    preferred_ip: Option<&str>,
+    computer: &str,
+  ) -> Self {
+    Self {
+      authcookie: normalize_token_value(authcookie).into_owned(),
+      portal: normalize_token_value(portal).into_owned(),
+      user: normalize_token_value(user).into_owned(),
+      domain: normalize_token_value(domain).into_owned(),
+      preferred_ip: preferred_ip.map(|v| normalize_token_value(v).into_owned()),
+      computer: normalize_token_value(computer).into_owned(),
+      persistent_cookie: None,
+      portal_userauthcookie: None,
+      portal_prelogonuserauthcookie: None,
+    }
+  }
+
+  pub(crate) fn with_persistent_cookie(mut self, persistent_cookie: Option<&str>) -> Self {
+    self.persistent_cookie = persistent_cookie.map(|v| normalize_token_value(v).into_owned());
+    self
+  }
+
+  pub(crate) fn with_portal_userauthcookie(mut self, portal_userauthcookie: Option<&str>) -> Self {
+    self.portal_userauthcookie = portal_userauthcookie.map(|v| normalize_token_value(v).into_owned());
+    self
+  }
+
+  pub(crate) fn with_portal_prelogonuserauthcookie(mut self, portal_prelogonuserauthcookie: Option<&str>) -> Self {
+    self.portal_prelogonuserauthcookie = portal_prelogonuserauthcookie.map(|v| normalize_token_value(v).into_owned());
+    self
+  }
+
+  pub fn authcookie(&self) -> &str {
+    &self.authcookie
+  }
+
+  pub fn portal(&self) -> &str {
+    &self.portal
+  }
+
+  pub fn user(&self) -> &str {
+    &self.user
+  }
+
+  pub fn domain(&self) -> &str {
+    &self.domain
+  }
+
+  pub fn preferred_ip(&self) -> Option<&str> {
+    self.preferred_ip.as_deref()
+  }
+
+  pub fn computer(&self) -> &str {
+    &self.computer
+  }
+
+  pub fn persistent_cookie(&self) -> Option<&str> {
+    self.persistent_cookie.as_deref()
+  }
+
+  pub fn portal_userauthcookie(&self) -> Option<&str> {
+    self.portal_userauthcookie.as_deref()
+  }
+
+  pub fn portal_prelogonuserauthcookie(&self) -> Option<&str> {
+    self.portal_prelogonuserauthcookie.as_deref()
+  }
+
+  fn params(&self) -> Vec<(&str, &str)> {
+    let mut params = vec![
+      ("authcookie", self.authcookie.as_str()),
+      ("portal", self.portal.as_str()),
+      ("user", self.user.as_str()),
+      ("domain", self.domain.as_str()),
+    ];
+
+    if let Some(preferred_ip) = self.preferred_ip.as_deref() {
+      params.push(("preferred-ip", preferred_ip));
+    }
+    params.push(("computer", self.computer.as_str()));
+
+    if let Some(persistent_cookie) = self.persistent_cookie.as_deref() {
+      params.push(("persistent-cookie", persistent_cookie));
+    }
+
+    if let Some(portal_userauthcookie) = self.portal_userauthcookie.as_deref() {
+      params.push(("portal-userauthcookie", portal_userauthcookie));
+    }
+
+    if let Some(portal_prelogonuserauthcookie) = self.portal_prelogonuserauthcookie.as_deref() {
+      params.push(("portal-prelogonuserauthcookie", portal_prelogonuserauthcookie));
+    }
+
+    params
+  }
+}
+
+/// Keep the cookies out of the logs
+impl fmt::Debug for GatewayToken {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let redacted = |cookie: &Option<String>| cookie.as_ref().map(|_| "<redacted>");
+
+    f.debug_struct("GatewayToken")
+      .field("authcookie", &"<redacted>")
+      .field("portal", &self.portal)
+      .field("user", &self.user)
+      .field("domain", &self.domain)
+      .field("preferred_ip", &self.preferred_ip)
+      .field("computer", &self.computer)
+      .field("persistent_cookie", &redacted(&self.persistent_cookie))
+      .field("portal_userauthcookie", &redacted(&self.portal_userauthcookie))
+      .field(
+        "portal_prelogonuserauthcookie",
+        &redacted(&self.portal_prelogonuserauthcookie),
+      )
+      .finish()
+  }
+}
+
+impl Display for GatewayToken {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let token = self
+      .params()
+      .iter()
+      .map(|(k, v)| format!("{}={}", k, encode(v)))
+      .collect::<Vec<_>>()
+      .join("&");
+
+    write!(f, "{}", token)
+  }
+}
+
+/// The keys that a token parsed from the string must have
+const REQUIRED_KEYS: [&str; 3] = ["authcookie", "portal", "user"];
+
+/// Parse the token in the format of `Display`, e.g., obtained by another tool
+impl FromStr for GatewayToken {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut params = HashMap::new();
+    for pair in s.trim().split('&').filter(|pair| !pair.is_empty()) {
+      let Some((key, value)) = pair.split_once('=') else {
+        return Err(format!("Malformed parameter: {}", pair));
+      };
+      params.insert(key, value);
+    }
+
+    let missing_keys = REQUIRED_KEYS
+      .into_iter()
+      .filter(|key| params.get(key).is_none_or(|value| value.is_empty()))
+      .collect::<Vec<_>>();
+    if !missing_keys.is_empty() {
+      return Err(format!("Missing required key(s): {}", missing_keys.join(", ")));
+    }
+
+    let param = |key: &str| params.get(key).copied();
+    let token = GatewayToken::new(
+      param("authcookie").unwrap_or_default(),
+      param("portal").unwrap_or_default(),
+      param("user").unwrap_or_default(),
+      param("domain").unwrap_or_default(),
+      param("preferred-ip"),
+      param("computer").unwrap_or_default(),
+    )
+    .with_persistent_cookie(param("persistent-cookie"))
+    .with_portal_userauthcookie(param("portal-userauthcookie"))
+    .with_portal_prelogonuserauthcookie(param("portal-prelogonuserauthcookie"));
+
+    Ok(token)
+  }
+}
+
+pub(crate) fn normalize_token_value(value: &str) -> Cow<'_, str> {
+  if value.contains('%') {
+    return decode(value).unwrap_or_else(|_| Cow::Borrowed(value));
+  }
+
+  Cow::Borrowed(value)
+}
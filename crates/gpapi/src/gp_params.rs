@@ -1,14 +1,28 @@
-use std::collections::HashMap;
+use std::{
+  collections::HashMap,
+  fmt,
+  net::{IpAddr, SocketAddr},
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use common::constants::GP_USER_AGENT;
 use log::info;
-use reqwest::Client;
+use reqwest::{
+  Client, Proxy,
+  header::{ACCEPT_LANGUAGE, HeaderMap, HeaderValue},
+  tls,
+};
 use serde::{Deserialize, Serialize};
 use specta::Type;
+use uuid::Uuid;
 
-use crate::utils::request::{create_identity, is_pkcs11_uri};
+use crate::utils::{
+  host_utils,
+  request::{create_identity, is_pkcs11_uri, load_ca_bundle},
+  retry_budget::RetryBudget,
+};
 
-#[derive(Debug, Serialize, Deserialize, Clone, Type, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Type, Default)]
 pub enum ClientOs {
   #[cfg_attr(not(target_os = "macos"), default)]
   Linux,
@@ -37,6 +51,33 @@ impl ClientOs {
     }
   }
 
+  /// Parse the OS name used in the portal policy, e.g., `Windows`, `win`, `macOS`, `Linux`
+  pub fn from_policy_name(name: &str) -> Option<Self> {
+    let name = name.trim().to_lowercase();
+    if name.starts_with("win") {
+      Some(ClientOs::Windows)
+    } else if name.starts_with("mac") || name == "darwin" || name == "osx" {
+      Some(ClientOs::Mac)
+    } else if name == "linux" {
+      Some(ClientOs::Linux)
+    } else {
+      None
+    }
+  }
+
+  /// Whether the OS version looks like the one reported by the GlobalProtect client of the OS,
+  /// e.g., `Microsoft Windows 11 Pro , 64-bit`. The gateway may reject the unusual ones.
+  pub fn is_usual_os_version(&self, os_version: &str) -> bool {
+    let os_version = os_version.trim().to_lowercase();
+    let prefixes: &[&str] = match self {
+      ClientOs::Linux => &["linux"],
+      ClientOs::Windows => &["microsoft windows"],
+      ClientOs::Mac => &["apple mac os x", "macos"],
+    };
+
+    prefixes.iter().any(|prefix| os_version.starts_with(prefix))
+  }
+
   pub fn to_openconnect_os(&self) -> &str {
     match self {
       ClientOs::Linux => "linux",
@@ -46,21 +87,200 @@ impl ClientOs {
   }
 }
 
+/// The minimum TLS version of the portal and gateway requests, it does not apply to the tunnel.
+/// TLS 1.3 cannot be enforced with the native TLS backend.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Type, Default)]
+pub enum TlsVersion {
+  Tls1_0,
+  Tls1_1,
+  #[default]
+  Tls1_2,
+}
+
+impl TlsVersion {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      TlsVersion::Tls1_0 => "1.0",
+      TlsVersion::Tls1_1 => "1.1",
+      TlsVersion::Tls1_2 => "1.2",
+    }
+  }
+
+  fn to_reqwest(self) -> tls::Version {
+    match self {
+      TlsVersion::Tls1_0 => tls::Version::TLS_1_0,
+      TlsVersion::Tls1_1 => tls::Version::TLS_1_1,
+      TlsVersion::Tls1_2 => tls::Version::TLS_1_2,
+    }
+  }
+}
+
+/// The default size limit of the portal and gateway responses
+pub const DEFAULT_MAX_RESPONSE_SIZE: usize = 4 * 1024 * 1024;
+/// The default timeout of the portal and gateway requests, the gateway login may wait for the
+/// push MFA to be approved
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Bound the portal and gateway responses, so that a misbehaving server cannot exhaust the memory
+/// or block the client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ResponseLimits {
+  max_size: usize,
+  timeout: Duration,
+}
+
+impl Default for ResponseLimits {
+  fn default() -> Self {
+    Self {
+      max_size: DEFAULT_MAX_RESPONSE_SIZE,
+      timeout: DEFAULT_REQUEST_TIMEOUT,
+    }
+  }
+}
+
+/// How to retry the requests failed with the transient errors, i.e., the connection errors,
+/// timeouts and 5xx responses. The delay doubles after each attempt, plus a random jitter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+  max_attempts: u32,
+  base_delay: Duration,
+  jitter: Duration,
+}
+
+impl Default for RetryPolicy {
+  /// A single attempt, i.e., no retry
+  fn default() -> Self {
+    Self::new(1, Duration::from_secs(1))
+  }
+}
+
+impl RetryPolicy {
+  /// The jitter defaults to a quarter of the base delay
+  pub fn new(max_attempts: u32, base_delay: Duration) -> Self {
+    Self {
+      max_attempts: max_attempts.max(1),
+      base_delay,
+      jitter: base_delay / 4,
+    }
+  }
+
+  pub fn with_jitter(self, jitter: Duration) -> Self {
+    Self { jitter, ..self }
+  }
+
+  pub fn max_attempts(&self) -> u32 {
+    self.max_attempts
+  }
+
+  pub fn base_delay(&self) -> Duration {
+    self.base_delay
+  }
+
+  pub fn jitter(&self) -> Duration {
+    self.jitter
+  }
+
+  /// The delay before the retry after the given failed attempt, starting from 1
+  pub fn delay(&self, attempt: u32) -> Duration {
+    let backoff = self
+      .base_delay
+      .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+
+    // The sub-second part of the clock is random enough to spread the retries
+    let nanos = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.subsec_nanos())
+      .unwrap_or_default();
+    let jitter = self.jitter.mul_f64(nanos as f64 / 1_000_000_000.0);
+
+    backoff + jitter
+  }
+}
+
+/// The ID of a login flow, prefixed to the log lines of its prelogin, portal config and gateway
+/// login, so that the flow can be told apart from the others in the interleaved logs
+#[derive(Debug, Serialize, Deserialize, Type, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct RequestId(String);
+
+impl RequestId {
+  pub fn new() -> Self {
+    Self(Uuid::new_v4().hyphenated().to_string())
+  }
+
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+}
+
+impl Default for RequestId {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for RequestId {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.0)
+  }
+}
+
+impl From<&str> for RequestId {
+  fn from(value: &str) -> Self {
+    Self(value.to_string())
+  }
+}
+
 #[derive(Debug, Serialize, Deserialize, Type, Default, Clone)]
 pub struct GpParams {
+  // A new one for each built params, unless the flow passes its own
+  #[serde(default)]
+  request_id: RequestId,
   is_gateway: bool,
   user_agent: String,
+  // The user agents to retry with when the portal rejects the user agent with 403
+  user_agent_fallbacks: Vec<String>,
   client_os: ClientOs,
   os_version: Option<String>,
   client_version: Option<String>,
   computer: String,
   ignore_tls_errors: bool,
+  min_tls_version: TlsVersion,
   certificate: Option<String>,
   sslkey: Option<String>,
   key_password: Option<String>,
+  // The PEM bundle of the additional root certificates, e.g., of a private CA
+  ca_bundle: Option<String>,
+  accept_language: Option<String>,
+  // The domain-join status reported to the server, not reported if None
+  domain_joined: Option<bool>,
+  // Omit the previously assigned IP from the gateway token, so that the gateway issues a fresh one
+  no_preferred_ip: bool,
+  // The proxy of the portal and gateway requests, the proxy environment variables are used if None
+  proxy: Option<String>,
+  // The source address of the portal and gateway requests, chosen by the routing table if None
+  local_address: Option<IpAddr>,
+  // The path of the prelogin endpoint, e.g., `/vhost/global-protect/prelogin.esp`, derived if None
+  prelogin_path: Option<String>,
   // Used for MFA
   input_str: Option<String>,
   otp: Option<String>,
+  // The deadline shared by all requests of the connect flow
+  #[serde(skip)]
+  #[specta(skip)]
+  retry_budget: Option<RetryBudget>,
+  // Connect to the host at the address instead of resolving it, e.g., through a local port forward
+  #[serde(skip)]
+  #[specta(skip)]
+  resolve_override: Option<(String, SocketAddr)>,
+  // Retry the transient failures of prelogin, getconfig and gateway login
+  #[serde(skip)]
+  #[specta(skip)]
+  retry_policy: RetryPolicy,
+  // The size limit and timeout of the responses
+  #[serde(skip)]
+  #[specta(skip)]
+  response_limits: ResponseLimits,
 }
 
 impl GpParams {
@@ -76,10 +296,26 @@ impl GpParams {
     self.is_gateway = is_gateway;
   }
 
+  pub fn request_id(&self) -> &RequestId {
+    &self.request_id
+  }
+
   pub(crate) fn user_agent(&self) -> &str {
     &self.user_agent
   }
 
+  pub fn user_agent_fallbacks(&self) -> &[String] {
+    &self.user_agent_fallbacks
+  }
+
+  /// Clone the params with a different user agent
+  pub(crate) fn with_user_agent(&self, user_agent: &str) -> Self {
+    Self {
+      user_agent: user_agent.to_string(),
+      ..self.clone()
+    }
+  }
+
   pub(crate) fn computer(&self) -> &str {
     &self.computer
   }
@@ -88,6 +324,10 @@ impl GpParams {
     self.ignore_tls_errors
   }
 
+  pub fn min_tls_version(&self) -> TlsVersion {
+    self.min_tls_version
+  }
+
   pub fn client_os(&self) -> &str {
     self.client_os.as_str()
   }
@@ -108,6 +348,54 @@ impl GpParams {
     self.otp = Some(otp.to_string());
   }
 
+  pub fn ca_bundle(&self) -> Option<&str> {
+    self.ca_bundle.as_deref()
+  }
+
+  pub fn accept_language(&self) -> Option<&str> {
+    self.accept_language.as_deref()
+  }
+
+  pub fn domain_joined(&self) -> Option<bool> {
+    self.domain_joined
+  }
+
+  pub fn no_preferred_ip(&self) -> bool {
+    self.no_preferred_ip
+  }
+
+  pub fn proxy(&self) -> Option<&str> {
+    self.proxy.as_deref()
+  }
+
+  pub fn local_address(&self) -> Option<IpAddr> {
+    self.local_address
+  }
+
+  pub fn prelogin_path(&self) -> Option<&str> {
+    self.prelogin_path.as_deref()
+  }
+
+  pub fn retry_budget(&self) -> Option<&RetryBudget> {
+    self.retry_budget.as_ref()
+  }
+
+  pub fn resolve_override(&self) -> Option<&(String, SocketAddr)> {
+    self.resolve_override.as_ref()
+  }
+
+  pub fn retry_policy(&self) -> &RetryPolicy {
+    &self.retry_policy
+  }
+
+  pub fn max_response_size(&self) -> usize {
+    self.response_limits.max_size
+  }
+
+  pub fn request_timeout(&self) -> Duration {
+    self.response_limits.timeout
+  }
+
   pub(crate) fn to_params(&self) -> HashMap<&str, &str> {
     let mut params: HashMap<&str, &str> = HashMap::new();
     let client_os = self.client_os.as_str();
@@ -132,6 +420,10 @@ impl GpParams {
       params.insert("os-version", os_version);
     }
 
+    if let Some(domain_joined) = self.domain_joined {
+      params.insert("domain-joined", if domain_joined { "yes" } else { "no" });
+    }
+
     // NOTE: Do not include clientgpversion for now
     // if let Some(client_version) = &self.client_version {
     //   params.insert("clientgpversion", client_version);
@@ -142,43 +434,105 @@ impl GpParams {
 }
 
 pub struct GpParamsBuilder {
+  request_id: Option<RequestId>,
   is_gateway: bool,
   user_agent: String,
+  user_agent_template: Option<String>,
+  user_agent_fallbacks: Vec<String>,
   client_os: ClientOs,
   os_version: Option<String>,
   client_version: Option<String>,
   computer: String,
   ignore_tls_errors: bool,
+  min_tls_version: TlsVersion,
   certificate: Option<String>,
   sslkey: Option<String>,
   key_password: Option<String>,
+  ca_bundle: Option<String>,
+  accept_language: Option<String>,
+  domain_joined: Option<bool>,
+  no_preferred_ip: bool,
+  proxy: Option<String>,
+  local_address: Option<IpAddr>,
+  prelogin_path: Option<String>,
+  retry_budget: Option<RetryBudget>,
+  resolve_override: Option<(String, SocketAddr)>,
+  retry_policy: RetryPolicy,
+  response_limits: ResponseLimits,
 }
 
 impl GpParamsBuilder {
   pub fn new() -> Self {
-    let computer = whoami::fallible::hostname().unwrap_or_else(|_| String::from("localhost"));
+    let computer = host_utils::get_device_name().to_string();
 
     Self {
+      request_id: Default::default(),
       is_gateway: false,
       user_agent: GP_USER_AGENT.to_string(),
+      user_agent_template: Default::default(),
+      user_agent_fallbacks: Default::default(),
       client_os: ClientOs::Linux,
       os_version: Default::default(),
       client_version: Default::default(),
       computer,
       ignore_tls_errors: false,
+      min_tls_version: Default::default(),
       certificate: Default::default(),
       sslkey: Default::default(),
       key_password: Default::default(),
+      ca_bundle: Default::default(),
+      accept_language: Default::default(),
+      domain_joined: Default::default(),
+      no_preferred_ip: false,
+      proxy: Default::default(),
+      local_address: Default::default(),
+      prelogin_path: Default::default(),
+      retry_budget: Default::default(),
+      resolve_override: Default::default(),
+      retry_policy: Default::default(),
+      response_limits: Default::default(),
     }
   }
 
+  /// The ID of the login flow, a new one is generated for each `build` if not set
+  pub fn request_id<T: Into<RequestId>>(&mut self, request_id: T) -> &mut Self {
+    self.request_id = Some(request_id.into());
+    self
+  }
+
   pub fn is_gateway(&mut self, is_gateway: bool) -> &mut Self {
     self.is_gateway = is_gateway;
     self
   }
 
+  /// The exact user agent, it replaces the `user_agent_template` set before
   pub fn user_agent(&mut self, user_agent: &str) -> &mut Self {
     self.user_agent = user_agent.to_string();
+    self.user_agent_template = None;
+    self
+  }
+
+  /// The user agent with the `{os}` and `{os_version}` placeholders, which are populated from
+  /// the `client_os` and `os_version` when built, e.g., `PAN GlobalProtect/6.0 ({os} {os_version})`
+  pub fn user_agent_template(&mut self, template: &str) -> &mut Self {
+    self.user_agent_template = Some(template.to_string());
+    self
+  }
+
+  fn render_user_agent(&self) -> String {
+    let Some(template) = self.user_agent_template.as_deref() else {
+      return self.user_agent.clone();
+    };
+
+    template
+      .replace("{os}", self.client_os.as_str())
+      .replace("{os_version}", self.os_version.as_deref().unwrap_or_default())
+      .trim()
+      .to_string()
+  }
+
+  pub fn user_agent_fallbacks(&mut self, user_agent_fallbacks: Vec<String>) -> &mut Self {
+    self.user_agent_fallbacks = user_agent_fallbacks;
     self
   }
 
@@ -207,6 +561,12 @@ impl GpParamsBuilder {
     self
   }
 
+  /// The minimum TLS version of the portal and gateway requests, TLS 1.2 by default
+  pub fn min_tls_version(&mut self, min_tls_version: TlsVersion) -> &mut Self {
+    self.min_tls_version = min_tls_version;
+    self
+  }
+
   pub fn certificate<T: Into<Option<String>>>(&mut self, certificate: T) -> &mut Self {
     self.certificate = certificate.into();
     self
@@ -222,20 +582,105 @@ impl GpParamsBuilder {
     self
   }
 
+  /// Trust the root certificates of the PEM bundle for the portal and gateway requests, in
+  /// addition to the system ones. The bundle is loaded when the client is built.
+  pub fn ca_bundle<T: Into<Option<String>>>(&mut self, ca_bundle: T) -> &mut Self {
+    self.ca_bundle = ca_bundle.into();
+    self
+  }
+
+  pub fn accept_language<T: Into<Option<String>>>(&mut self, accept_language: T) -> &mut Self {
+    self.accept_language = accept_language.into();
+    self
+  }
+
+  pub fn domain_joined(&mut self, domain_joined: Option<bool>) -> &mut Self {
+    self.domain_joined = domain_joined;
+    self
+  }
+
+  pub fn no_preferred_ip(&mut self, no_preferred_ip: bool) -> &mut Self {
+    self.no_preferred_ip = no_preferred_ip;
+    self
+  }
+
+  /// The HTTP or HTTPS proxy of the portal and gateway requests, e.g., `http://proxy:3128`.
+  /// It does not apply to the tunnel.
+  pub fn proxy<T: Into<Option<String>>>(&mut self, proxy: T) -> &mut Self {
+    self.proxy = proxy.into();
+    self
+  }
+
+  /// Bind the portal and gateway requests to the local address, e.g., to egress on an interface
+  /// of a multi-homed host. It does not apply to the tunnel.
+  pub fn local_address<T: Into<Option<IpAddr>>>(&mut self, local_address: T) -> &mut Self {
+    self.local_address = local_address.into();
+    self
+  }
+
+  /// The path of the prelogin endpoint, for the servers hosted under a path prefix, e.g.,
+  /// `/vhost/global-protect/prelogin.esp`. No other path is tried when it is set.
+  pub fn prelogin_path<T: Into<Option<String>>>(&mut self, prelogin_path: T) -> &mut Self {
+    self.prelogin_path = prelogin_path.into();
+    self
+  }
+
+  pub fn retry_budget(&mut self, retry_budget: Option<RetryBudget>) -> &mut Self {
+    self.retry_budget = retry_budget;
+    self
+  }
+
+  /// Connect to the host at the given address, the TLS server name is still the host
+  pub fn resolve_override(&mut self, resolve_override: Option<(String, SocketAddr)>) -> &mut Self {
+    self.resolve_override = resolve_override;
+    self
+  }
+
+  pub fn retry_policy(&mut self, retry_policy: RetryPolicy) -> &mut Self {
+    self.retry_policy = retry_policy;
+    self
+  }
+
+  /// The size limit of the response body in bytes, the larger response fails the request
+  pub fn max_response_size(&mut self, max_response_size: usize) -> &mut Self {
+    self.response_limits.max_size = max_response_size;
+    self
+  }
+
+  /// The timeout of each request, including reading the response body
+  pub fn request_timeout(&mut self, request_timeout: Duration) -> &mut Self {
+    self.response_limits.timeout = request_timeout;
+    self
+  }
+
   pub fn build(&self) -> GpParams {
     GpParams {
+      request_id: self.request_id.clone().unwrap_or_default(),
       is_gateway: self.is_gateway,
-      user_agent: self.user_agent.clone(),
+      user_agent: self.render_user_agent(),
+      user_agent_fallbacks: self.user_agent_fallbacks.clone(),
       client_os: self.client_os.clone(),
       os_version: self.os_version.clone(),
       client_version: self.client_version.clone(),
       computer: self.computer.clone(),
       ignore_tls_errors: self.ignore_tls_errors,
+      min_tls_version: self.min_tls_version,
       certificate: self.certificate.clone(),
       sslkey: self.sslkey.clone(),
       key_password: self.key_password.clone(),
+      ca_bundle: self.ca_bundle.clone(),
+      accept_language: self.accept_language.clone(),
+      domain_joined: self.domain_joined,
+      no_preferred_ip: self.no_preferred_ip,
+      proxy: self.proxy.clone(),
+      local_address: self.local_address,
+      prelogin_path: self.prelogin_path.clone(),
       input_str: Default::default(),
       otp: Default::default(),
+      retry_budget: self.retry_budget.clone(),
+      resolve_override: self.resolve_override.clone(),
+      retry_policy: self.retry_policy,
+      response_limits: self.response_limits,
     }
   }
 }
@@ -252,8 +697,44 @@ impl TryFrom<&GpParams> for Client {
   fn try_from(value: &GpParams) -> Result<Self, Self::Error> {
     let mut builder = Client::builder()
       .danger_accept_invalid_certs(value.ignore_tls_errors)
+      .min_tls_version(value.min_tls_version.to_reqwest())
       .user_agent(&value.user_agent);
 
+    if let Some(accept_language) = value.accept_language.as_deref() {
+      let mut headers = HeaderMap::new();
+      headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_str(accept_language)?);
+      builder = builder.default_headers(headers);
+    }
+
+    // Bound the request by the remaining budget, and fail fast once it is exhausted
+    let timeout = match &value.retry_budget {
+      Some(budget) => budget.remaining()?.min(value.response_limits.timeout),
+      None => value.response_limits.timeout,
+    };
+    builder = builder.timeout(timeout);
+
+    // reqwest falls back to the `https_proxy` and `all_proxy` environment variables
+    if let Some(proxy) = value.proxy.as_deref() {
+      info!("Using proxy {} for the portal and gateway requests", proxy);
+      builder = builder.proxy(Proxy::all(proxy)?);
+    }
+
+    if let Some(local_address) = value.local_address {
+      info!("Binding the portal and gateway requests to {}", local_address);
+      builder = builder.local_address(local_address);
+    }
+
+    if let Some(ca_bundle) = value.ca_bundle.as_deref() {
+      info!("Trusting the CA certificates of {}", ca_bundle);
+      for cert in load_ca_bundle(ca_bundle)? {
+        builder = builder.add_root_certificate(cert);
+      }
+    }
+
+    if let Some((host, addr)) = &value.resolve_override {
+      builder = builder.resolve(host, *addr);
+    }
+
     if let Some(cert) = value.certificate.as_deref() {
       info!("Using client certificate authentication...");
       if is_pkcs11_uri(cert) {
@@ -1,6 +1,7 @@
 pub mod auth;
 pub mod credential;
 pub mod error;
+pub mod flow;
 pub mod gateway;
 pub mod gp_params;
 pub mod portal;
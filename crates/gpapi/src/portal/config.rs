@@ -1,20 +1,59 @@
+use std::{
+  collections::{HashMap, HashSet, VecDeque},
+  fmt,
+  net::IpAddr,
+  sync::Mutex,
+};
+
 use anyhow::bail;
 use dns_lookup::lookup_addr;
 use log::{debug, info, warn};
 use reqwest::{Client, StatusCode};
 use serde::Serialize;
 use specta::Type;
+use url::Url;
 use xmltree::Element;
 
 use crate::{
   credential::{AuthCookieCredential, Credential},
   error::PortalError,
-  gateway::{Gateway, parse_gateways},
-  gp_params::GpParams,
-  utils::{normalize_server, parse_gp_response, remove_url_scheme, xml::ElementExt},
+  gateway::{Gateway, is_cookie_expired, parse_gateways, truncate_message},
+  gp_params::{ClientOs, GpParams},
+  portal::with_user_agent_fallback,
+  utils::{esp_url, normalize_server, parse_gp_response, remove_url_scheme, retry::with_retry, xml::ElementExt},
 };
 
-#[derive(Debug, Serialize, Type)]
+/// The DNS settings of the config, which the vpnc-script applies with the ones of the gateway
+#[derive(Debug, Serialize, Type, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DnsConfig {
+  /// The DNS servers, i.e., `<dns>`, the same element as in the gateway config
+  dns_servers: Vec<String>,
+  /// The DNS search domains, i.e., `<dns-suffix>`
+  search_domains: Vec<String>,
+  /// The domains resolved by the VPN DNS servers only, i.e., `<split-dns>`
+  split_dns_domains: Vec<String>,
+}
+
+impl DnsConfig {
+  pub fn dns_servers(&self) -> &[String] {
+    &self.dns_servers
+  }
+
+  pub fn search_domains(&self) -> &[String] {
+    &self.search_domains
+  }
+
+  pub fn split_dns_domains(&self) -> &[String] {
+    &self.split_dns_domains
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.dns_servers.is_empty() && self.search_domains.is_empty() && self.split_dns_domains.is_empty()
+  }
+}
+
+#[derive(Debug, Serialize, Type, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PortalConfig {
   portal: String,
@@ -33,6 +72,30 @@ pub struct PortalConfig {
    * The version returned by the portal config, if any
    */
   version: Option<String>,
+  /**
+   * Whether the portal asks the client to collect and submit the HIP report
+   */
+  hip_collection: bool,
+  /**
+   * The tunnel MTU suggested by the config, if any
+   */
+  suggested_mtu: Option<u32>,
+  /**
+   * The `portal-config-version`, the cached config is reused while it is unchanged
+   */
+  config_version: Option<String>,
+  /**
+   * Whether the gateways support IPsec (ESP), None if the config has no hint
+   */
+  ipsec_supported: Option<bool>,
+  /**
+   * The DNS search domains and split-DNS domains
+   */
+  dns_config: DnsConfig,
+  /**
+   * Whether the portal requires the client certificate to connect to the gateways
+   */
+  client_cert_required: bool,
 }
 
 impl PortalConfig {
@@ -40,8 +103,8 @@ impl PortalConfig {
     &self.portal
   }
 
-  pub fn gateways(&self) -> Vec<&Gateway> {
-    self.gateways.iter().collect()
+  pub fn gateways(&self) -> &[Gateway] {
+    &self.gateways
   }
 
   pub fn auth_cookie(&self) -> &AuthCookieCredential {
@@ -60,6 +123,92 @@ impl PortalConfig {
     self.version.as_deref()
   }
 
+  pub fn hip_collection(&self) -> bool {
+    self.hip_collection
+  }
+
+  pub fn suggested_mtu(&self) -> Option<u32> {
+    self.suggested_mtu
+  }
+
+  pub fn config_version(&self) -> Option<&str> {
+    self.config_version.as_deref()
+  }
+
+  pub fn ipsec_supported(&self) -> Option<bool> {
+    self.ipsec_supported
+  }
+
+  pub fn dns_config(&self) -> &DnsConfig {
+    &self.dns_config
+  }
+
+  pub fn client_cert_required(&self) -> bool {
+    self.client_cert_required
+  }
+
+  /// The changes of the DNS settings, the suggested MTU and the gateways in the refreshed config.
+  /// The split-tunnel routes are pushed by the gateway, they are not in the portal config.
+  pub fn drift(&self, refreshed: &PortalConfig) -> Vec<ConfigChange> {
+    let mut changes = Vec::new();
+
+    let (old, new) = (&self.dns_config, &refreshed.dns_config);
+    let (added, removed) = diff_members(&old.dns_servers, &new.dns_servers);
+    changes.extend(added.into_iter().map(ConfigChange::DnsServerAdded));
+    changes.extend(removed.into_iter().map(ConfigChange::DnsServerRemoved));
+
+    let (added, removed) = diff_members(&old.search_domains, &new.search_domains);
+    changes.extend(added.into_iter().map(ConfigChange::SearchDomainAdded));
+    changes.extend(removed.into_iter().map(ConfigChange::SearchDomainRemoved));
+
+    let (added, removed) = diff_members(&old.split_dns_domains, &new.split_dns_domains);
+    changes.extend(added.into_iter().map(ConfigChange::SplitDnsDomainAdded));
+    changes.extend(removed.into_iter().map(ConfigChange::SplitDnsDomainRemoved));
+
+    if self.suggested_mtu != refreshed.suggested_mtu {
+      changes.push(ConfigChange::SuggestedMtu {
+        old: self.suggested_mtu,
+        new: refreshed.suggested_mtu,
+      });
+    }
+
+    let addresses = |config: &PortalConfig| {
+      let mut addresses = Vec::new();
+      for gateway in &config.gateways {
+        if !addresses.contains(&gateway.address) {
+          addresses.push(gateway.address.clone());
+        }
+      }
+      addresses
+    };
+    let (added, removed) = diff_members(&addresses(self), &addresses(refreshed));
+    changes.extend(added.into_iter().map(ConfigChange::GatewayAdded));
+    changes.extend(removed.into_iter().map(ConfigChange::GatewayRemoved));
+
+    changes
+  }
+
+  /// The DNS servers and search domains of the config that the live tunnel does not use, e.g., when
+  /// the portal changed them after the tunnel was connected
+  pub fn live_drift(&self, dns_servers: &[IpAddr], search_domains: &[String]) -> Vec<ConfigChange> {
+    let dns_servers = self
+      .dns_config
+      .dns_servers
+      .iter()
+      .filter(|server| !dns_servers.iter().any(|live| live.to_string() == **server))
+      .cloned()
+      .map(ConfigChange::DnsServerNotLive);
+    let search_domains = self
+      .dns_config
+      .search_domains
+      .iter()
+      .filter(|domain| !search_domains.iter().any(|live| live.eq_ignore_ascii_case(domain)))
+      .cloned()
+      .map(ConfigChange::SearchDomainNotLive);
+
+    dns_servers.chain(search_domains).collect()
+  }
+
   /// In-place sort the gateways by region
   pub fn sort_gateways(&mut self, region: &str) {
     let preferred_gateway = self.find_preferred_gateway(region);
@@ -81,6 +230,17 @@ impl PortalConfig {
       .find(|gateway| gateway.name == name_or_address || gateway.address == name_or_address)
   }
 
+  /// Find the gateway with the highest priority, i.e., the lowest priority value, in the region.
+  /// Unlike `find_preferred_gateway`, the `Any` rule does not match and there is no fallback.
+  pub fn find_gateway_by_region(&self, region: &str) -> Option<&Gateway> {
+    self
+      .gateways
+      .iter()
+      .filter_map(|gateway| gateway.region_priority(region).map(|priority| (priority, gateway)))
+      .min_by_key(|(priority, _)| *priority)
+      .map(|(_, gateway)| gateway)
+  }
+
   /// Find the preferred gateway for the given region
   /// Iterates over the gateways and find the first one that
   /// has the lowest priority for the given region.
@@ -103,11 +263,123 @@ impl PortalConfig {
   }
 }
 
-pub async fn retrieve_config(portal: &str, cred: &Credential, gp_params: &GpParams) -> anyhow::Result<PortalConfig> {
+/// A change of the refreshed portal config from the one the tunnel was connected with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigChange {
+  DnsServerAdded(String),
+  DnsServerRemoved(String),
+  SearchDomainAdded(String),
+  SearchDomainRemoved(String),
+  SplitDnsDomainAdded(String),
+  SplitDnsDomainRemoved(String),
+  SuggestedMtu {
+    old: Option<u32>,
+    new: Option<u32>,
+  },
+  GatewayAdded(String),
+  GatewayRemoved(String),
+  /// Compared with the live tunnel by `PortalConfig::live_drift`
+  DnsServerNotLive(String),
+  SearchDomainNotLive(String),
+}
+
+impl fmt::Display for ConfigChange {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mtu = |mtu: &Option<u32>| mtu.map_or(String::from("<none>"), |mtu| mtu.to_string());
+
+    match self {
+      ConfigChange::DnsServerAdded(server) => write!(f, "DNS server added: {}", server),
+      ConfigChange::DnsServerRemoved(server) => write!(f, "DNS server removed: {}", server),
+      ConfigChange::SearchDomainAdded(domain) => write!(f, "search domain added: {}", domain),
+      ConfigChange::SearchDomainRemoved(domain) => write!(f, "search domain removed: {}", domain),
+      ConfigChange::SplitDnsDomainAdded(domain) => write!(f, "split DNS domain added: {}", domain),
+      ConfigChange::SplitDnsDomainRemoved(domain) => write!(f, "split DNS domain removed: {}", domain),
+      ConfigChange::SuggestedMtu { old, new } => write!(f, "suggested MTU changed: {} -> {}", mtu(old), mtu(new)),
+      ConfigChange::GatewayAdded(address) => write!(f, "gateway added: {}", address),
+      ConfigChange::GatewayRemoved(address) => write!(f, "gateway removed: {}", address),
+      ConfigChange::DnsServerNotLive(server) => write!(f, "DNS server not used by the live tunnel: {}", server),
+      ConfigChange::SearchDomainNotLive(domain) => write!(f, "search domain not used by the live tunnel: {}", domain),
+    }
+  }
+}
+
+/// The members added to and removed from the old list, in the order of the lists
+fn diff_members(old: &[String], new: &[String]) -> (Vec<String>, Vec<String>) {
+  let added = new.iter().filter(|member| !old.contains(member)).cloned().collect();
+  let removed = old.iter().filter(|member| !new.contains(member)).cloned().collect();
+
+  (added, removed)
+}
+
+/// The parsed portal configs by portal, so that a reconnect can skip re-parsing the config
+/// if the `portal-config-version` is unchanged
+#[derive(Default)]
+pub struct PortalConfigCache {
+  configs: Mutex<HashMap<String, PortalConfig>>,
+}
+
+impl PortalConfigCache {
+  fn get(&self, portal: &str, config_version: &str) -> Option<PortalConfig> {
+    let configs = self.configs.lock().unwrap();
+    configs
+      .get(portal)
+      .filter(|config| config.config_version.as_deref() == Some(config_version))
+      .cloned()
+  }
+
+  fn insert(&self, config: &PortalConfig) {
+    if config.config_version.is_some() {
+      let mut configs = self.configs.lock().unwrap();
+      configs.insert(config.portal.clone(), config.clone());
+    }
+  }
+}
+
+pub async fn retrieve_config(
+  portal: &str,
+  cred: &Credential,
+  gp_params: &GpParams,
+) -> Result<PortalConfig, PortalError> {
+  let config = with_user_agent_fallback(gp_params, |gp_params| async move {
+    with_retry(gp_params.retry_policy(), || {
+      retrieve_config_impl(portal, cred, &gp_params, None)
+    })
+    .await
+  })
+  .await?;
+
+  Ok(config)
+}
+
+/// Same as `retrieve_config`, but reuse the cached config if the `portal-config-version` is unchanged
+pub async fn retrieve_config_cached(
+  portal: &str,
+  cred: &Credential,
+  gp_params: &GpParams,
+  cache: &PortalConfigCache,
+) -> Result<PortalConfig, PortalError> {
+  let config = with_user_agent_fallback(gp_params, |gp_params| async move {
+    with_retry(gp_params.retry_policy(), || {
+      retrieve_config_impl(portal, cred, &gp_params, Some(cache))
+    })
+    .await
+  })
+  .await?;
+
+  Ok(config)
+}
+
+async fn retrieve_config_impl(
+  portal: &str,
+  cred: &Credential,
+  gp_params: &GpParams,
+  cache: Option<&PortalConfigCache>,
+) -> anyhow::Result<PortalConfig> {
+  let request_id = gp_params.request_id();
   let portal = normalize_server(portal)?;
   let server = remove_url_scheme(&portal);
 
-  let url = format!("{}/global-protect/getconfig.esp", portal);
+  let url = esp_url(&portal, "global-protect/getconfig.esp")?;
   let client = Client::try_from(gp_params)?;
 
   let mut params = cred.to_params();
@@ -117,21 +389,41 @@ pub async fn retrieve_config(portal: &str, cred: &Credential, gp_params: &GpPara
   params.insert("server", &server);
   params.insert("host", &server);
 
-  info!("Retrieve the portal config, user_agent: {}", gp_params.user_agent());
+  info!(
+    "[{}] Retrieve the portal config, user_agent: {}",
+    request_id,
+    gp_params.user_agent()
+  );
 
   let res = client.post(&url).form(&params).send().await.map_err(|e| {
-    warn!("Network error: {:?}", e);
+    warn!("[{}] Network error: {:?}", request_id, e);
     anyhow::anyhow!(PortalError::NetworkError(e))
   })?;
 
-  let res_xml = parse_gp_response(res).await.or_else(|err| {
+  let res_xml = parse_gp_response(res, gp_params).await?.or_else(|err| {
+    if let Some(message) = &err.maintenance {
+      bail!(PortalError::Maintenance(message.clone()));
+    }
+
     if err.status == StatusCode::NOT_FOUND {
       bail!(PortalError::ConfigError("Config endpoint not found".to_string()));
     }
 
+    if err.status == StatusCode::FORBIDDEN {
+      bail!(PortalError::Forbidden(err.reason));
+    }
+
+    if let Some(message) = [&err.reason, &err.body]
+      .into_iter()
+      .find(|message| is_cookie_expired(cred, message))
+    {
+      bail!(PortalError::CookieExpired(truncate_message(message)));
+    }
+
     if err.is_status_error() {
-      warn!("{err}");
-      bail!("Portal config error: {}", err.reason);
+      warn!("[{}] {}", request_id, err);
+      let message = format!("Portal config error: {}", err.reason);
+      return Err(anyhow::anyhow!(err).context(message));
     }
 
     Err(anyhow::anyhow!(PortalError::ConfigError(err.reason)))
@@ -141,9 +433,31 @@ pub async fn retrieve_config(portal: &str, cred: &Credential, gp_params: &GpPara
     bail!(PortalError::ConfigError("Empty portal config response".to_string()))
   }
 
-  debug!("Portal config response: {}", res_xml);
+  debug!("[{}] Portal config response: {}", request_id, res_xml);
   let root = Element::parse(res_xml.as_bytes()).map_err(|e| PortalError::ConfigError(e.to_string()))?;
 
+  check_allowed_os(&root, gp_params.client_os())?;
+
+  let auth_cookie = refresh_auth_cookie(&root, cred);
+
+  let config_version = root
+    .descendant_text("portal-config-version")
+    .map(|s| s.trim().to_string())
+    .filter(|s| !s.is_empty());
+
+  // The auth cookies are issued per login, only the rest of the config is reused
+  if let (Some(cache), Some(config_version)) = (cache, config_version.as_deref()) {
+    if let Some(mut config) = cache.get(&server, config_version) {
+      info!(
+        "[{}] Portal config version {} unchanged, reusing the cached config",
+        request_id, config_version
+      );
+      config.auth_cookie = auth_cookie;
+      config.config_cred = cred.clone();
+      return Ok(config);
+    }
+  }
+
   let mut ihd_enabled = false;
   let mut prefer_internal = false;
   if let Some(ihd_node) = root.descendant("internal-host-detection") {
@@ -152,14 +466,17 @@ pub async fn retrieve_config(portal: &str, cred: &Credential, gp_params: &GpPara
   }
 
   let mut gateways = parse_gateways(&root, prefer_internal).unwrap_or_else(|| {
-    info!("No gateways found in portal config");
+    info!("[{}] No gateways found in portal config", request_id);
     vec![]
   });
 
-  let user_auth_cookie = root.descendant_text("portal-userauthcookie").unwrap_or_default();
-  let prelogon_user_auth_cookie = root
-    .descendant_text("portal-prelogonuserauthcookie")
-    .unwrap_or_default();
+  let gateway_list_urls = parse_gateway_list_urls(&root);
+  if !gateway_list_urls.is_empty() {
+    let extra_gateways =
+      fetch_gateway_lists(&client, &portal, gateway_list_urls, &params, gp_params, prefer_internal).await;
+    merge_gateways(&mut gateways, extra_gateways);
+  }
+
   let config_digest = root.descendant_text("config-digest");
 
   if gateways.is_empty() {
@@ -170,19 +487,333 @@ pub async fn retrieve_config(portal: &str, cred: &Credential, gp_params: &GpPara
     .descendant_text("version")
     .map(|s| s.trim().to_string())
     .filter(|s| !s.is_empty());
-  info!("Detected portal version: {:?}", version);
+  info!("[{}] Detected portal version: {:?}", request_id, version);
 
-  Ok(PortalConfig {
+  let hip_collection = root
+    .descendant("hip-collection")
+    .and_then(|n| n.child_text("collect-hip-data"))
+    .is_some_and(|s| s.trim().eq_ignore_ascii_case("yes"));
+  info!("[{}] Portal requests HIP collection: {}", request_id, hip_collection);
+
+  let suggested_mtu = parse_suggested_mtu(&root);
+  info!("[{}] Portal suggested MTU: {:?}", request_id, suggested_mtu);
+
+  let ipsec_supported = parse_ipsec_supported(&root);
+  info!("[{}] Portal IPsec support: {:?}", request_id, ipsec_supported);
+
+  let dns_config = parse_dns_config(&root);
+  info!("[{}] Portal DNS config: {:?}", request_id, dns_config);
+
+  let client_cert_required = parse_client_cert_required(&root);
+  info!(
+    "[{}] Portal requires client certificate: {}",
+    request_id, client_cert_required
+  );
+
+  let config = PortalConfig {
     portal: server.to_string(),
-    auth_cookie: AuthCookieCredential::new(cred.username(), &user_auth_cookie, &prelogon_user_auth_cookie),
+    auth_cookie,
     config_cred: cred.clone(),
     gateways,
     config_digest: config_digest.map(|s| s.to_string()),
     internal_host_detection: if ihd_enabled { Some(prefer_internal) } else { None },
     version,
+    hip_collection,
+    suggested_mtu,
+    config_version,
+    ipsec_supported,
+    dns_config,
+    client_cert_required,
+  };
+
+  if let Some(cache) = cache {
+    cache.insert(&config);
+  }
+
+  Ok(config)
+}
+
+/// The auth cookies returned by getconfig replace the ones of the credential, which are kept
+/// for the gateway login if the portal does not refresh them.
+fn refresh_auth_cookie(root: &Element, cred: &Credential) -> AuthCookieCredential {
+  let current = cred.auth_cookie();
+  let refreshed = |name: &str, current: Option<&str>| {
+    root
+      .descendant_text(name)
+      .filter(|s| !s.trim().is_empty())
+      .or_else(|| current.map(str::to_string))
+      .unwrap_or_default()
+  };
+
+  let user_auth_cookie = refreshed(
+    "portal-userauthcookie",
+    current.map(AuthCookieCredential::user_auth_cookie),
+  );
+  let prelogon_user_auth_cookie = refreshed(
+    "portal-prelogonuserauthcookie",
+    current.map(AuthCookieCredential::prelogon_user_auth_cookie),
+  );
+
+  AuthCookieCredential::new(cred.username(), &user_auth_cookie, &prelogon_user_auth_cookie)
+}
+
+/// Fail early with a clear error if the portal restricts the client OS and the reported one is not
+/// allowed, otherwise the gateway rejects the login with a generic error.
+fn check_allowed_os(root: &Element, client_os: &str) -> Result<(), PortalError> {
+  let allowed = parse_allowed_os(root);
+  if allowed.is_empty() {
+    return Ok(());
+  }
+
+  let client_os = ClientOs::from(client_os);
+  if allowed
+    .iter()
+    .any(|os| ClientOs::from_policy_name(os).as_ref() == Some(&client_os))
+  {
+    return Ok(());
+  }
+
+  warn!(
+    "The client OS {} is not allowed by the portal: {:?}",
+    client_os.as_str(),
+    allowed
+  );
+  Err(PortalError::OsNotAllowed {
+    os: client_os.as_str().to_string(),
+    allowed,
   })
 }
 
+/// The allowed OS are listed as `<member>` or `<entry name="">`, or as the comma separated text
+fn parse_allowed_os(root: &Element) -> Vec<String> {
+  root.descendant("allowed-os").map(parse_members).unwrap_or_default()
+}
+
+/// The values listed as `<member>` or `<entry name="">`, or as the comma separated text
+fn parse_members(node: &Element) -> Vec<String> {
+  let members = node
+    .children("member")
+    .into_iter()
+    .filter_map(|member| member.get_text().map(|s| s.trim().to_string()))
+    .chain(
+      node
+        .children("entry")
+        .into_iter()
+        .filter_map(|entry| entry.attr("name").map(|s| s.trim().to_string())),
+    )
+    .filter(|value| !value.is_empty())
+    .collect::<Vec<_>>();
+
+  if !members.is_empty() {
+    return members;
+  }
+
+  node
+    .get_text()
+    .map(|text| {
+      text
+        .split(',')
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+fn parse_dns_config(root: &Element) -> DnsConfig {
+  let domains = |name: &str| {
+    let mut domains = Vec::new();
+    for domain in root.descendants(name).into_iter().flat_map(parse_members) {
+      if !domains.contains(&domain) {
+        domains.push(domain);
+      }
+    }
+    domains
+  };
+
+  // Normalized, so that they are compared with the live tunnel ones
+  let dns_servers = domains("dns")
+    .iter()
+    .filter_map(|server| server.parse::<IpAddr>().ok())
+    .map(|server| server.to_string())
+    .collect();
+
+  DnsConfig {
+    dns_servers,
+    search_domains: domains("dns-suffix"),
+    split_dns_domains: domains("split-dns"),
+  }
+}
+
+/// Read the DNS settings from the portal config XML, e.g., the one saved from the debug log
+pub fn dns_config_from_xml(xml: &str) -> Result<DnsConfig, PortalError> {
+  let root = Element::parse(xml.as_bytes()).map_err(|e| PortalError::ConfigError(e.to_string()))?;
+
+  Ok(parse_dns_config(&root))
+}
+
+fn parse_client_cert_required(root: &Element) -> bool {
+  root.descendant_text("client-cert-required").is_some_and(|s| {
+    let s = s.trim();
+    s.eq_ignore_ascii_case("yes") || s.eq_ignore_ascii_case("true")
+  })
+}
+
+/// Read the client certificate requirement from the portal config XML, e.g., the one saved from the debug log
+pub fn client_cert_required_from_xml(xml: &str) -> Result<bool, PortalError> {
+  let root = Element::parse(xml.as_bytes()).map_err(|e| PortalError::ConfigError(e.to_string()))?;
+
+  Ok(parse_client_cert_required(&root))
+}
+
+/// The MTU suggested by the `<mtu>` of the policy, the ones nested in e.g. the gateway entries are not
+/// meant for the tunnel
+fn parse_suggested_mtu(root: &Element) -> Option<u32> {
+  root
+    .child_text("mtu")
+    .and_then(|s| s.trim().parse::<u32>().ok())
+    .filter(|mtu| *mtu > 0)
+}
+
+/// Whether the config advertises IPsec (ESP), None if there is no hint.
+/// `<no-dtls>yes</no-dtls>` keeps the tunnel on SSL, it takes precedence over `<ipsec>`.
+fn parse_ipsec_supported(root: &Element) -> Option<bool> {
+  let no_dtls = root
+    .descendant_text("no-dtls")
+    .is_some_and(|s| s.trim().eq_ignore_ascii_case("yes"));
+  if no_dtls {
+    return Some(false);
+  }
+
+  let ipsec = root.descendant("ipsec")?;
+  let text = ipsec.get_text().unwrap_or_default();
+  match text.trim().to_ascii_lowercase().as_str() {
+    "yes" | "true" => Some(true),
+    "no" | "false" => Some(false),
+    // The IPsec settings are listed, e.g., `<udp-port>`
+    _ => Some(ipsec.children.iter().any(|node| node.as_element().is_some())),
+  }
+}
+
+/// Read the IPsec capability from the portal config XML, e.g., the one saved from the debug log
+pub fn ipsec_supported_from_xml(xml: &str) -> Result<Option<bool>, PortalError> {
+  let root = Element::parse(xml.as_bytes()).map_err(|e| PortalError::ConfigError(e.to_string()))?;
+
+  Ok(parse_ipsec_supported(&root))
+}
+
+/// The maximum number of secondary gateway lists to fetch, to guard against reference loops
+const MAX_GATEWAY_LISTS: usize = 16;
+
+/// Large portals may split the gateway list and reference the rest with `<gateway-list-url>`
+fn parse_gateway_list_urls(root: &Element) -> Vec<String> {
+  root
+    .descendants("gateway-list-url")
+    .iter()
+    .filter_map(|node| node.get_text())
+    .map(|url| url.trim().to_string())
+    .filter(|url| !url.is_empty())
+    .collect()
+}
+
+/// Resolve the gateway list URL against the portal, only the portal host is allowed
+/// since the request carries the portal credential.
+fn resolve_gateway_list_url(portal: &str, url: &str) -> anyhow::Result<Url> {
+  let base = Url::parse(portal)?;
+  let resolved = base.join(url)?;
+
+  if resolved.host_str() != base.host_str() || resolved.port_or_known_default() != base.port_or_known_default() {
+    bail!("Gateway list URL {} is not on the portal host", resolved);
+  }
+
+  Ok(resolved)
+}
+
+/// Fetch the gateway lists referenced by the portal config and the lists themselves, the lists that
+/// cannot be fetched are skipped
+async fn fetch_gateway_lists(
+  client: &Client,
+  portal: &str,
+  urls: Vec<String>,
+  params: &HashMap<&str, &str>,
+  gp_params: &GpParams,
+  prefer_internal: bool,
+) -> Vec<Gateway> {
+  let request_id = gp_params.request_id();
+  let mut gateways = Vec::new();
+  let mut pending = VecDeque::from(urls);
+  let mut visited = HashSet::new();
+
+  while let Some(url) = pending.pop_front() {
+    let url = match resolve_gateway_list_url(portal, &url) {
+      Ok(url) => url,
+      Err(err) => {
+        warn!("[{}] Skipping the gateway list: {}", request_id, err);
+        continue;
+      }
+    };
+
+    if !visited.insert(url.clone()) {
+      debug!("[{}] Gateway list {} has been fetched, skipping", request_id, url);
+      continue;
+    }
+
+    if visited.len() > MAX_GATEWAY_LISTS {
+      warn!(
+        "[{}] Too many gateway lists referenced, stopping at {}",
+        request_id, MAX_GATEWAY_LISTS
+      );
+      break;
+    }
+
+    let root = match fetch_gateway_list(client, &url, params, gp_params).await {
+      Ok(root) => root,
+      Err(err) => {
+        warn!("[{}] Skipping the gateway list {}: {}", request_id, url, err);
+        continue;
+      }
+    };
+    gateways.extend(parse_gateways(&root, prefer_internal).unwrap_or_default());
+    pending.extend(parse_gateway_list_urls(&root));
+  }
+
+  gateways
+}
+
+async fn fetch_gateway_list(
+  client: &Client,
+  url: &Url,
+  params: &HashMap<&str, &str>,
+  gp_params: &GpParams,
+) -> anyhow::Result<Element> {
+  let request_id = gp_params.request_id();
+
+  info!("[{}] Fetching the gateway list from {}", request_id, url);
+  let res = client
+    .post(url.as_str())
+    .form(params)
+    .send()
+    .await
+    .map_err(PortalError::NetworkError)?;
+
+  let res_xml = parse_gp_response(res, gp_params)
+    .await?
+    .map_err(|err| PortalError::ConfigError(format!("Failed to fetch the gateway list: {}", err.reason)))?;
+  debug!("[{}] Gateway list response: {}", request_id, res_xml);
+
+  let root = Element::parse(res_xml.as_bytes()).map_err(|e| PortalError::ConfigError(e.to_string()))?;
+  Ok(root)
+}
+
+/// Append the gateways that are not in the list yet, matched by address
+fn merge_gateways(gateways: &mut Vec<Gateway>, extra_gateways: Vec<Gateway>) {
+  for gateway in extra_gateways {
+    if !gateways.iter().any(|g| g.address == gateway.address) {
+      gateways.push(gateway);
+    }
+  }
+}
+
 // Perform DNS lookup and compare the result with the expected hostname
 fn internal_host_detect(element: &Element) -> bool {
   let ip_info = [
@@ -216,3 +847,108 @@ fn internal_host_detect(element: &Element) -> bool {
 
   false
 }
+
+#[cfg(test)]
+mod tests {
+  use crate::credential::PasswordCredential;
+
+  use super::*;
+
+  fn portal_config_with_gateways(xml: &str) -> PortalConfig {
+    let root = Element::parse(xml.as_bytes()).unwrap();
+
+    PortalConfig {
+      portal: String::from("vpn.example.com"),
+      auth_cookie: AuthCookieCredential::new("user", "", ""),
+      config_cred: Credential::from(PasswordCredential::new("user", "")),
+      gateways: parse_gateways(&root, false).unwrap(),
+      config_digest: None,
+      internal_host_detection: None,
+      version: None,
+      hip_collection: false,
+      suggested_mtu: None,
+      config_version: None,
+      ipsec_supported: None,
+      dns_config: Default::default(),
+      client_cert_required: false,
+    }
+  }
+
+  #[test]
+  fn parse_suggested_mtu_from_config() {
+    let parse = |xml: &str| parse_suggested_mtu(&Element::parse(xml.as_bytes()).unwrap());
+
+    assert_eq!(parse("<policy><mtu>1400</mtu></policy>"), Some(1400));
+    assert_eq!(parse("<policy><mtu> 1300 </mtu></policy>"), Some(1300));
+    assert_eq!(parse("<policy><gateways><mtu>1300</mtu></gateways></policy>"), None);
+    assert_eq!(
+      parse("<policy><gateways><mtu>1300</mtu></gateways><mtu>1400</mtu></policy>"),
+      Some(1400)
+    );
+    assert_eq!(parse("<policy><mtu>0</mtu></policy>"), None);
+    assert_eq!(parse("<policy><mtu>auto</mtu></policy>"), None);
+    assert_eq!(parse("<policy></policy>"), None);
+  }
+
+  #[test]
+  fn parse_ipsec_supported_from_config() {
+    let parse = |xml: &str| ipsec_supported_from_xml(xml).unwrap();
+
+    assert_eq!(parse("<policy><ipsec>yes</ipsec></policy>"), Some(true));
+    assert_eq!(
+      parse("<policy><ipsec><udp-port>4501</udp-port></ipsec></policy>"),
+      Some(true)
+    );
+    assert_eq!(parse("<policy><ipsec>no</ipsec></policy>"), Some(false));
+    assert_eq!(
+      parse("<policy><ipsec>yes</ipsec><no-dtls>yes</no-dtls></policy>"),
+      Some(false)
+    );
+    assert_eq!(parse("<policy></policy>"), None);
+    assert!(ipsec_supported_from_xml("<policy>").is_err());
+  }
+
+  #[test]
+  fn parse_dns_config_from_config() {
+    let parse = |xml: &str| dns_config_from_xml(xml).unwrap();
+
+    let config = parse(
+      r#"<policy>
+        <dns-suffix><member>corp.example.com</member><member> example.com </member></dns-suffix>
+        <gateways><dns-suffix><member>corp.example.com</member></dns-suffix></gateways>
+        <split-dns><entry name="internal.example.com"/></split-dns>
+        <dns><member>10.0.0.53</member><member>fd00:0::53</member><member>ns.example.com</member></dns>
+      </policy>"#,
+    );
+    assert_eq!(config.dns_servers(), ["10.0.0.53", "fd00::53"]);
+    assert_eq!(config.search_domains(), ["corp.example.com", "example.com"]);
+    assert_eq!(config.split_dns_domains(), ["internal.example.com"]);
+
+    let config = parse("<policy><split-dns>a.example.com, b.example.com</split-dns></policy>");
+    assert!(config.search_domains().is_empty());
+    assert_eq!(config.split_dns_domains(), ["a.example.com", "b.example.com"]);
+
+    assert!(parse("<policy></policy>").is_empty());
+  }
+
+  #[test]
+  fn find_gateway_by_region_picks_highest_priority() {
+    let config = portal_config_with_gateways(
+      r#"<policy><gateways><external><list>
+        <entry name="us1.example.com"><description>us1</description>
+          <priority-rule><entry name="US"><priority>3</priority></entry></priority-rule></entry>
+        <entry name="us2.example.com"><description>us2</description>
+          <priority-rule><entry name="US"><priority>1</priority></entry><entry name="CA"><priority>2</priority></entry></priority-rule></entry>
+        <entry name="de1.example.com"><description>de1</description>
+          <priority-rule><entry name="DE"><priority>1</priority></entry></priority-rule></entry>
+        <entry name="any.example.com"><description>any</description>
+          <priority-rule><entry name="Any"><priority>1</priority></entry></priority-rule></entry>
+      </list></external></gateways></policy>"#,
+    );
+
+    assert_eq!(config.find_gateway_by_region("US").unwrap().name(), "us2");
+    assert_eq!(config.find_gateway_by_region("ca").unwrap().name(), "us2");
+    assert_eq!(config.find_gateway_by_region("DE").unwrap().name(), "de1");
+    assert!(config.find_gateway_by_region("JP").is_none());
+  }
+}
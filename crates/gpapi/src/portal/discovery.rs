@@ -0,0 +1,59 @@
+use log::{info, warn};
+use reqwest::Url;
+use tokio::net::lookup_host;
+
+use crate::{error::PortalError, gp_params::GpParams, portal::prelogin, utils::normalize_server};
+
+/// The common portal hostname prefixes tried for a bare domain
+const PORTAL_PREFIXES: &[&str] = &["vpn", "gp", "portal", "globalprotect"];
+
+/// Discover the portal of a bare domain, e.g., `example.com` -> `vpn.example.com`,
+/// by trying the common portal hostnames and returning the first one that answers the prelogin.
+pub async fn discover_portal(domain: &str, gp_params: &GpParams) -> Result<String, PortalError> {
+  let domain = domain.trim().trim_end_matches('.');
+  let candidates = PORTAL_PREFIXES
+    .iter()
+    .map(|prefix| format!("{}.{}", prefix, domain))
+    .collect::<Vec<_>>();
+
+  discover_from_candidates(&candidates, gp_params)
+    .await
+    .ok_or_else(|| PortalError::DiscoveryFailed {
+      domain: domain.to_string(),
+      candidates: candidates.clone(),
+    })
+}
+
+/// Try the candidates in order, a candidate is only probed when its host can be resolved
+pub async fn discover_from_candidates(candidates: &[String], gp_params: &GpParams) -> Option<String> {
+  for candidate in candidates {
+    if !resolves(candidate).await {
+      info!("Skipping the portal candidate {}, cannot resolve the host", candidate);
+      continue;
+    }
+
+    match prelogin(candidate, gp_params).await {
+      Ok(_) => {
+        info!("Discovered the portal: {}", candidate);
+        return Some(candidate.to_string());
+      }
+      Err(err) => warn!("Portal candidate {} did not answer the prelogin: {}", candidate, err),
+    }
+  }
+
+  None
+}
+
+async fn resolves(candidate: &str) -> bool {
+  let Some(url) = normalize_server(candidate).ok().and_then(|url| Url::parse(&url).ok()) else {
+    return false;
+  };
+
+  let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+    return false;
+  };
+
+  lookup_host((host, port))
+    .await
+    .is_ok_and(|mut addrs| addrs.next().is_some())
+}
@@ -0,0 +1,203 @@
+use std::{cmp::Reverse, future::Future};
+
+use hickory_resolver::TokioAsyncResolver;
+use log::{info, warn};
+
+/// The SRV record name of the portal, i.e., `_globalprotect._tcp.<domain>`
+const PORTAL_SRV_PREFIX: &str = "_globalprotect._tcp";
+/// The TXT record name of the portal, i.e., `_globalprotect.<domain>` with the `portal=<host>` value
+const PORTAL_TXT_PREFIX: &str = "_globalprotect";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrvRecord {
+  pub priority: u16,
+  pub weight: u16,
+  pub port: u16,
+  pub target: String,
+}
+
+/// The DNS queries used to discover the portal
+pub trait DnsResolver {
+  fn lookup_srv(&self, name: &str) -> impl Future<Output = anyhow::Result<Vec<SrvRecord>>> + Send;
+
+  fn lookup_txt(&self, name: &str) -> impl Future<Output = anyhow::Result<Vec<String>>> + Send;
+}
+
+/// The resolver using the system DNS configuration, i.e., `/etc/resolv.conf`
+pub struct SystemDnsResolver(TokioAsyncResolver);
+
+impl SystemDnsResolver {
+  pub fn new() -> anyhow::Result<Self> {
+    Ok(Self(TokioAsyncResolver::tokio_from_system_conf()?))
+  }
+}
+
+impl DnsResolver for SystemDnsResolver {
+  async fn lookup_srv(&self, name: &str) -> anyhow::Result<Vec<SrvRecord>> {
+    let lookup = self.0.srv_lookup(name).await?;
+    let records = lookup
+      .iter()
+      .map(|srv| SrvRecord {
+        priority: srv.priority(),
+        weight: srv.weight(),
+        port: srv.port(),
+        target: srv.target().to_utf8(),
+      })
+      .collect();
+
+    Ok(records)
+  }
+
+  async fn lookup_txt(&self, name: &str) -> anyhow::Result<Vec<String>> {
+    let lookup = self.0.txt_lookup(name).await?;
+    let records = lookup
+      .iter()
+      .map(|txt| {
+        txt
+          .txt_data()
+          .iter()
+          .map(|data| String::from_utf8_lossy(data))
+          .collect::<String>()
+      })
+      .collect();
+
+    Ok(records)
+  }
+}
+
+/// Discover the portal of the domain from the DNS SRV or TXT records with the system resolver,
+/// None if no record is published.
+pub async fn discover_portal_from_dns(domain: &str) -> Option<String> {
+  match SystemDnsResolver::new() {
+    Ok(resolver) => discover_from_dns(domain, &resolver).await,
+    Err(err) => {
+      warn!("Failed to create the DNS resolver: {}", err);
+      None
+    }
+  }
+}
+
+/// Query the SRV record first, then the TXT record
+pub async fn discover_from_dns(domain: &str, resolver: &impl DnsResolver) -> Option<String> {
+  let domain = domain.trim().trim_end_matches('.');
+
+  let srv_name = format!("{}.{}", PORTAL_SRV_PREFIX, domain);
+  match resolver.lookup_srv(&srv_name).await {
+    Ok(records) => {
+      if let Some(portal) = select_srv_record(records) {
+        info!("Discovered the portal from the SRV record {}: {}", srv_name, portal);
+        return Some(portal);
+      }
+    }
+    Err(err) => info!("No SRV record {}: {}", srv_name, err),
+  }
+
+  let txt_name = format!("{}.{}", PORTAL_TXT_PREFIX, domain);
+  match resolver.lookup_txt(&txt_name).await {
+    Ok(records) => {
+      if let Some(portal) = records.iter().find_map(|record| parse_txt_record(record)) {
+        info!("Discovered the portal from the TXT record {}: {}", txt_name, portal);
+        return Some(portal);
+      }
+    }
+    Err(err) => info!("No TXT record {}: {}", txt_name, err),
+  }
+
+  None
+}
+
+/// Pick the record with the lowest priority, then the highest weight.
+/// The `.` target means the service is not available, see RFC 2782.
+fn select_srv_record(records: Vec<SrvRecord>) -> Option<String> {
+  records
+    .into_iter()
+    .filter(|record| !record.target.trim_end_matches('.').is_empty())
+    .min_by_key(|record| (record.priority, Reverse(record.weight)))
+    .map(|record| {
+      let host = record.target.trim_end_matches('.');
+      if record.port == 443 {
+        host.to_string()
+      } else {
+        format!("{}:{}", host, record.port)
+      }
+    })
+}
+
+fn parse_txt_record(record: &str) -> Option<String> {
+  record
+    .trim()
+    .strip_prefix("portal=")
+    .map(str::trim)
+    .filter(|portal| !portal.is_empty())
+    .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use super::*;
+
+  #[derive(Default)]
+  struct MockResolver {
+    srv: HashMap<String, Vec<SrvRecord>>,
+    txt: HashMap<String, Vec<String>>,
+  }
+
+  impl DnsResolver for MockResolver {
+    async fn lookup_srv(&self, name: &str) -> anyhow::Result<Vec<SrvRecord>> {
+      self.srv.get(name).cloned().ok_or_else(|| anyhow::anyhow!("NXDOMAIN"))
+    }
+
+    async fn lookup_txt(&self, name: &str) -> anyhow::Result<Vec<String>> {
+      self.txt.get(name).cloned().ok_or_else(|| anyhow::anyhow!("NXDOMAIN"))
+    }
+  }
+
+  fn srv(priority: u16, weight: u16, port: u16, target: &str) -> SrvRecord {
+    SrvRecord {
+      priority,
+      weight,
+      port,
+      target: target.to_string(),
+    }
+  }
+
+  #[tokio::test]
+  async fn discovers_portal_from_srv_records() {
+    let mut resolver = MockResolver::default();
+    resolver.srv.insert(
+      "_globalprotect._tcp.example.com".to_string(),
+      vec![
+        srv(20, 0, 443, "backup.example.com."),
+        srv(10, 10, 443, "vpn2.example.com."),
+        srv(10, 50, 8443, "vpn1.example.com."),
+      ],
+    );
+
+    let portal = discover_from_dns("example.com", &resolver).await;
+    assert_eq!(portal.as_deref(), Some("vpn1.example.com:8443"));
+  }
+
+  #[tokio::test]
+  async fn falls_back_to_txt_record() {
+    let mut resolver = MockResolver::default();
+    // The service is explicitly not available via SRV
+    resolver
+      .srv
+      .insert("_globalprotect._tcp.example.com".to_string(), vec![srv(0, 0, 0, ".")]);
+    resolver.txt.insert(
+      "_globalprotect.example.com".to_string(),
+      vec!["v=spf1 -all".to_string(), "portal=gp.example.com".to_string()],
+    );
+
+    let portal = discover_from_dns("example.com.", &resolver).await;
+    assert_eq!(portal.as_deref(), Some("gp.example.com"));
+  }
+
+  #[tokio::test]
+  async fn returns_none_without_records() {
+    let resolver = MockResolver::default();
+    assert!(discover_from_dns("example.com", &resolver).await.is_none());
+  }
+}
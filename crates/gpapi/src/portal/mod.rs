@@ -1,5 +1,48 @@
 mod config;
+mod discovery;
+mod dns_discovery;
 mod prelogin;
 
 pub use config::*;
+pub use discovery::*;
+pub use dns_discovery::*;
 pub use prelogin::*;
+
+use std::future::Future;
+
+use log::info;
+
+use crate::{error::PortalError, gp_params::GpParams};
+
+/// Run the request, and retry it with each of the fallback user agents in order
+/// if the portal rejects the user agent with 403.
+pub(crate) async fn with_user_agent_fallback<T, F, Fut>(gp_params: &GpParams, request: F) -> anyhow::Result<T>
+where
+  F: Fn(GpParams) -> Fut,
+  Fut: Future<Output = anyhow::Result<T>>,
+{
+  let mut result = request(gp_params.clone()).await;
+
+  for user_agent in gp_params.user_agent_fallbacks() {
+    if !is_forbidden(&result) {
+      break;
+    }
+
+    info!(
+      "[{}] Request forbidden, retrying with the fallback user agent: {}",
+      gp_params.request_id(),
+      user_agent
+    );
+    result = request(gp_params.with_user_agent(user_agent)).await;
+  }
+
+  result
+}
+
+fn is_forbidden<T>(result: &anyhow::Result<T>) -> bool {
+  result
+    .as_ref()
+    .err()
+    .and_then(|err| err.downcast_ref::<PortalError>())
+    .is_some_and(PortalError::is_forbidden)
+}
@@ -1,16 +1,23 @@
 use anyhow::{anyhow, bail};
+use std::collections::HashMap;
+
+use futures_util::stream::{FuturesUnordered, StreamExt};
 use log::{debug, info, warn};
-use reqwest::{Client, StatusCode};
+use reqwest::{Client, Response, StatusCode};
 use serde::Serialize;
 use specta::Type;
 use xmltree::Element;
 
 use crate::{
   error::PortalError,
-  gp_params::GpParams,
-  utils::{base64, normalize_server, parse_gp_response, xml::ElementExt},
+  gp_params::{GpParams, RequestId},
+  portal::with_user_agent_fallback,
+  utils::{base64, esp_url, normalize_server, parse_gp_response, retry::with_retry, xml::ElementExt},
 };
 
+const PORTAL_PRELOGIN_PATH: &str = "global-protect/prelogin.esp";
+const GATEWAY_PRELOGIN_PATH: &str = "ssl-vpn/prelogin.esp";
+
 const REQUIRED_PARAMS: [&str; 8] = [
   "tmp",
   "clientVer",
@@ -29,6 +36,9 @@ pub struct SamlPrelogin {
   is_gateway: bool,
   saml_request: String,
   support_default_browser: bool,
+  autosubmit: bool,
+  /// The SAML request timeout in seconds, if provided by the server
+  request_timeout: Option<u32>,
 }
 
 impl SamlPrelogin {
@@ -43,6 +53,14 @@ impl SamlPrelogin {
   pub fn support_default_browser(&self) -> bool {
     self.support_default_browser
   }
+
+  pub fn autosubmit(&self) -> bool {
+    self.autosubmit
+  }
+
+  pub fn request_timeout(&self) -> Option<u32> {
+    self.request_timeout
+  }
 }
 
 #[derive(Debug, Serialize, Type, Clone)]
@@ -53,6 +71,11 @@ pub struct StandardPrelogin {
   auth_message: String,
   label_username: String,
   label_password: String,
+  autosubmit: bool,
+  /// The username pre-filled by the server, i.e., `<ccusername>`
+  username: Option<String>,
+  /// Whether the password field expects a second factor, e.g., an OTP
+  second_factor: bool,
 }
 
 impl StandardPrelogin {
@@ -71,6 +94,18 @@ impl StandardPrelogin {
   pub fn label_password(&self) -> &str {
     &self.label_password
   }
+
+  pub fn autosubmit(&self) -> bool {
+    self.autosubmit
+  }
+
+  pub fn username(&self) -> Option<&str> {
+    self.username.as_deref()
+  }
+
+  pub fn second_factor(&self) -> bool {
+    self.second_factor
+  }
 }
 
 #[derive(Debug, Serialize, Type, Clone)]
@@ -94,18 +129,78 @@ impl Prelogin {
       Prelogin::Standard(standard) => standard.is_gateway,
     }
   }
+
+  /// Whether the client is expected to submit the authentication without user interaction
+  pub fn autosubmit(&self) -> bool {
+    match self {
+      Prelogin::Saml(saml) => saml.autosubmit,
+      Prelogin::Standard(standard) => standard.autosubmit,
+    }
+  }
 }
 
-pub async fn prelogin(portal: &str, gp_params: &GpParams) -> anyhow::Result<Prelogin> {
+pub async fn prelogin(portal: &str, gp_params: &GpParams) -> Result<Prelogin, PortalError> {
+  let prelogin = with_user_agent_fallback(gp_params, |gp_params| async move {
+    with_retry(gp_params.retry_policy(), || prelogin_impl(portal, &gp_params)).await
+  })
+  .await?;
+
+  Ok(prelogin)
+}
+
+/// Run the prelogin of the redundant portals concurrently, and return the first portal that answers
+/// with its prelogin. The other prelogins are cancelled once one succeeds. If all of them fail, the
+/// error of the first portal is returned.
+pub async fn prelogin_race(portals: &[String], gp_params: &GpParams) -> Result<(String, Prelogin), PortalError> {
+  let mut pending = portals
+    .iter()
+    .enumerate()
+    .map(|(index, portal)| async move { (index, prelogin(portal, gp_params).await) })
+    .collect::<FuturesUnordered<_>>();
+
+  let mut first_err = None;
+  while let Some((index, result)) = pending.next().await {
+    match result {
+      Ok(prelogin) => {
+        info!(
+          "[{}] Portal {} answered the prelogin first",
+          gp_params.request_id(),
+          portals[index]
+        );
+        return Ok((portals[index].clone(), prelogin));
+      }
+      Err(err) => {
+        warn!(
+          "[{}] Portal {} failed the prelogin: {}",
+          gp_params.request_id(),
+          portals[index],
+          err
+        );
+        if first_err.as_ref().is_none_or(|(first, _)| index < *first) {
+          first_err = Some((index, err));
+        }
+      }
+    }
+  }
+
+  match first_err {
+    Some((_, err)) => Err(err),
+    None => Err(PortalError::Other(anyhow!("No portal to prelogin"))),
+  }
+}
+
+async fn prelogin_impl(portal: &str, gp_params: &GpParams) -> anyhow::Result<Prelogin> {
   let user_agent = gp_params.user_agent();
   let is_gateway = gp_params.is_gateway();
   let prelogin_type = if is_gateway { "Gateway" } else { "Portal" };
+  let request_id = gp_params.request_id();
 
-  info!("{} prelogin with user_agent: {}", prelogin_type, user_agent);
+  info!(
+    "[{}] {} prelogin with user_agent: {}",
+    request_id, prelogin_type, user_agent
+  );
 
   let portal = normalize_server(portal)?;
-  let path = if is_gateway { "ssl-vpn" } else { "global-protect" };
-  let prelogin_url = format!("{portal}/{}/prelogin.esp", path);
   let mut params = gp_params.to_params();
 
   params.insert("tmp", "tmp");
@@ -116,34 +211,75 @@ pub async fn prelogin(portal: &str, gp_params: &GpParams) -> anyhow::Result<Prel
 
   let client = Client::try_from(gp_params)?;
 
-  let res = client.post(&prelogin_url).form(&params).send().await.map_err(|e| {
-    warn!("Network error: {:?}", e);
-    anyhow::anyhow!(PortalError::NetworkError(e))
-  })?;
+  // Some portals only serve the gateway-style prelogin endpoint
+  let (path, fallback_path) = match gp_params.prelogin_path() {
+    Some(path) => (path, None),
+    None if is_gateway => (GATEWAY_PRELOGIN_PATH, None),
+    None => (PORTAL_PRELOGIN_PATH, Some(GATEWAY_PRELOGIN_PATH)),
+  };
+
+  let res = send_prelogin(&client, &portal, path, &params, request_id).await?;
+  let (path, res) = match fallback_path {
+    Some(fallback_path) if res.status() == StatusCode::NOT_FOUND => {
+      info!(
+        "[{}] Prelogin endpoint {} not found, trying {}",
+        request_id, path, fallback_path
+      );
+      let res = send_prelogin(&client, &portal, fallback_path, &params, request_id).await?;
+      (fallback_path, res)
+    }
+    _ => (path, res),
+  };
+
+  let res_xml = parse_gp_response(res, gp_params).await?.or_else(|err| {
+    if let Some(message) = &err.maintenance {
+      bail!(PortalError::Maintenance(message.clone()))
+    }
 
-  let res_xml = parse_gp_response(res).await.or_else(|err| {
     if err.status == StatusCode::NOT_FOUND {
       bail!(PortalError::PreloginError("Prelogin endpoint not found".to_string()))
     }
 
+    if err.status == StatusCode::FORBIDDEN {
+      bail!(PortalError::Forbidden(err.reason))
+    }
+
+    // Keep the response error as the source to tell the 5xx errors apart for the retry
     if err.is_status_error() {
-      warn!("{err}");
-      bail!("Prelogin error: {}", err.reason)
+      warn!("[{}] {}", request_id, err);
+      let message = format!("Prelogin error: {}", err.reason);
+      return Err(anyhow!(err).context(message));
     }
 
     Err(anyhow!(PortalError::PreloginError(err.reason)))
   })?;
 
-  debug!("Prelogin response XML: {}", res_xml);
+  debug!("[{}] Prelogin succeeded with path: {}", request_id, path);
+  debug!("[{}] Prelogin response XML: {}", request_id, res_xml);
 
   let prelogin = parse_res_xml(&res_xml, is_gateway).map_err(|err| {
-    warn!("Parse response error, response: {}", res_xml);
+    warn!("[{}] Parse response error, response: {}", request_id, res_xml);
     PortalError::PreloginError(err.to_string())
   })?;
 
   Ok(prelogin)
 }
 
+async fn send_prelogin(
+  client: &Client,
+  portal: &str,
+  path: &str,
+  params: &HashMap<&str, &str>,
+  request_id: &RequestId,
+) -> anyhow::Result<Response> {
+  let prelogin_url = esp_url(portal, path)?;
+
+  client.post(&prelogin_url).form(params).send().await.map_err(|e| {
+    warn!("[{}] Network error: {:?}", request_id, e);
+    anyhow::anyhow!(PortalError::NetworkError(e))
+  })
+}
+
 fn parse_res_xml(res_xml: &str, is_gateway: bool) -> anyhow::Result<Prelogin> {
   let root = Element::parse(res_xml.as_bytes())?;
 
@@ -163,6 +299,10 @@ fn parse_res_xml(res_xml: &str, is_gateway: bool) -> anyhow::Result<Prelogin> {
     String::from("Unknown")
   });
 
+  let autosubmit = root
+    .descendant_text("autosubmit")
+    .is_some_and(|s| s.trim().eq_ignore_ascii_case("true") || s.trim().eq_ignore_ascii_case("yes"));
+
   let saml_method = root.descendant_text("saml-auth-method");
   let saml_request = root.descendant_text("saml-request");
   let saml_default_browser = root.descendant_text("saml-default-browser");
@@ -170,12 +310,18 @@ fn parse_res_xml(res_xml: &str, is_gateway: bool) -> anyhow::Result<Prelogin> {
   if saml_method.is_some() && saml_request.is_some() {
     let saml_request = base64::decode_to_string(&saml_request.unwrap())?;
     let support_default_browser = saml_default_browser.map(|s| s.to_lowercase() == "yes").unwrap_or(false);
+    let request_timeout = root
+      .descendant_text("saml-request-timeout")
+      .and_then(|s| s.trim().parse().ok())
+      .filter(|timeout| *timeout > 0);
 
     let saml_prelogin = SamlPrelogin {
       region,
       is_gateway,
       saml_request,
       support_default_browser,
+      autosubmit,
+      request_timeout,
     };
 
     return Ok(Prelogin::Saml(saml_prelogin));
@@ -193,13 +339,90 @@ fn parse_res_xml(res_xml: &str, is_gateway: bool) -> anyhow::Result<Prelogin> {
   let auth_message = root
     .descendant_text("authentication-message")
     .unwrap_or_else(|| String::from("Please enter the login credentials"));
+  let username = root
+    .descendant_text("ccusername")
+    .map(|s| s.trim().to_string())
+    .filter(|s| !s.is_empty());
+  let second_factor = expects_second_factor(&label_password);
+
   let standard_prelogin = StandardPrelogin {
     region,
     is_gateway,
     auth_message,
     label_username,
     label_password,
+    autosubmit,
+    username,
+    second_factor,
   };
 
   Ok(Prelogin::Standard(standard_prelogin))
 }
+
+/// The prelogin has no explicit second factor flag, the server relabels the password field instead
+fn expects_second_factor(label_password: &str) -> bool {
+  let label = label_password.to_lowercase();
+  ["otp", "passcode", "token", "code"]
+    .iter()
+    .any(|keyword| label.contains(keyword))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn saml_prelogin_exposes_hints() {
+    let res = include_str!("../../tests/files/prelogin_saml.xml");
+    let Prelogin::Saml(saml) = parse_res_xml(res, false).unwrap() else {
+      panic!("expected SAML prelogin");
+    };
+
+    assert!(!saml.autosubmit());
+    assert_eq!(saml.request_timeout(), Some(600));
+  }
+
+  #[test]
+  fn standard_prelogin_exposes_autosubmit_hint() {
+    let res = include_str!("../../tests/files/prelogin_standard_autosubmit.xml");
+    let prelogin = parse_res_xml(res, false).unwrap();
+
+    assert!(prelogin.autosubmit());
+    let Prelogin::Standard(standard) = prelogin else {
+      panic!("expected standard prelogin");
+    };
+    assert_eq!(standard.auth_message(), "Insert your smart card");
+  }
+
+  #[test]
+  fn prelogin_hints_default_when_absent() {
+    let res = r#"<prelogin-response><status>Success</status><region>US</region></prelogin-response>"#;
+    let prelogin = parse_res_xml(res, false).unwrap();
+
+    assert!(!prelogin.autosubmit());
+  }
+
+  #[test]
+  fn standard_prelogin_parses_form_fields() {
+    let res = include_str!("../../tests/files/prelogin_standard.xml");
+    let Prelogin::Standard(standard) = parse_res_xml(res, false).unwrap() else {
+      panic!("expected standard prelogin");
+    };
+
+    assert_eq!(standard.auth_message(), "Enter login credentials");
+    assert_eq!(standard.label_username(), "Username");
+    assert_eq!(standard.label_password(), "Password");
+    assert_eq!(standard.username(), None);
+    assert!(!standard.second_factor());
+
+    let res = include_str!("../../tests/files/prelogin_standard_otp.xml");
+    let Prelogin::Standard(standard) = parse_res_xml(res, true).unwrap() else {
+      panic!("expected standard prelogin");
+    };
+
+    assert_eq!(standard.label_username(), "Login");
+    assert_eq!(standard.label_password(), "Passcode");
+    assert_eq!(standard.username(), Some("jdoe"));
+    assert!(standard.second_factor());
+  }
+}
@@ -8,7 +8,11 @@ const DEFAULT_WINDOWS_DISTRO: &str = "Windows 11 Pro";
 const DEFAULT_WINDOWS_VERSION: &str = "10.0.22631.0";
 const DEFAULT_MACHINE_ID: &str = "DEADBEEF-DEAD-BEEF-DEAD-BEEFDEADBEEF";
 
+/// The values reported by minimal containers that do not identify the device
+const PLACEHOLDER_DEVICE_NAMES: &[&str] = &["localhost", "localhost.localdomain", "(none)", "unknown"];
+
 static MACHINE_ID: OnceLock<&'static str> = OnceLock::new();
+static DEVICE_NAME: OnceLock<String> = OnceLock::new();
 static MACOS_VERSION: OnceLock<&'static str> = OnceLock::new();
 static MACOS_OS_STRING: OnceLock<String> = OnceLock::new();
 static LINUX_OS_STRING: OnceLock<String> = OnceLock::new();
@@ -106,3 +110,48 @@ pub fn derive_uuid(seeds: &[&str]) -> String {
   let uuid = Uuid::new_v5(&namespace, name.as_bytes());
   uuid.hyphenated().to_string()
 }
+
+/// Get the device name, falling back to the hostname file, the `HOSTNAME` env var,
+/// and finally a name generated from the machine ID if the device name is unavailable
+pub fn get_device_name() -> &'static str {
+  DEVICE_NAME.get_or_init(|| {
+    resolve_device_name([
+      whoami::fallible::hostname().ok(),
+      Some(whoami::devicename()),
+      std::fs::read_to_string("/etc/hostname").ok(),
+      std::env::var("HOSTNAME").ok(),
+    ])
+  })
+}
+
+/// Pick the first valid candidate, or generate one
+fn resolve_device_name(candidates: impl IntoIterator<Item = Option<String>>) -> String {
+  candidates
+    .into_iter()
+    .flatten()
+    .map(|name| name.trim().to_string())
+    .find(|name| is_valid_device_name(name))
+    .unwrap_or_else(|| format!("linux-{}", &derive_uuid(&["device-name"])[..8]))
+}
+
+fn is_valid_device_name(name: &str) -> bool {
+  !name.is_empty()
+    && !PLACEHOLDER_DEVICE_NAMES
+      .iter()
+      .any(|placeholder| name.eq_ignore_ascii_case(placeholder))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn empty_device_name_falls_back() {
+    let name = resolve_device_name([Some(String::new()), Some("localhost".into()), Some("web-01\n".into())]);
+    assert_eq!(name, "web-01");
+
+    let name = resolve_device_name([Some(" ".into()), None, Some("(none)".into())]);
+    assert!(name.starts_with("linux-"));
+    assert_eq!(name.len(), "linux-".len() + 8);
+  }
+}
@@ -10,6 +10,9 @@ pub mod lock_file;
 pub mod openssl;
 pub mod redact;
 pub mod request;
+pub(crate) mod retry;
+pub mod retry_budget;
+pub mod totp;
 #[cfg(feature = "tauri")]
 pub mod window;
 
@@ -18,18 +21,67 @@ mod shutdown_signal;
 use log::warn;
 pub use shutdown_signal::shutdown_signal;
 
+use std::{net::Ipv6Addr, sync::LazyLock};
+
+use regex::Regex;
 use reqwest::{Response, StatusCode, Url};
 use thiserror::Error;
+use url::ParseError;
 
-/// Normalize the server URL to the format `https://<host>:<port>`
-pub fn normalize_server(server: &str) -> anyhow::Result<String> {
-  let server = if server.starts_with("https://") || server.starts_with("http://") {
-    server.to_string()
-  } else {
-    format!("https://{}", server)
+use crate::{error::PortalError, gp_params::GpParams};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ServerUrlError {
+  #[error("The server is empty")]
+  Empty,
+  #[error("Unsupported scheme '{0}' of the server, use https:// or http://")]
+  UnsupportedScheme(String),
+  #[error("Missing host in the server '{0}'")]
+  MissingHost(String),
+  #[error("Invalid port in the server '{0}', expected 1-65535")]
+  InvalidPort(String),
+  #[error("Invalid server '{0}': {1}")]
+  Invalid(String, ParseError),
+}
+
+/// Validate the server given by the user, e.g., `vpn.example.com`, `vpn.example.com:8443/gp` or
+/// `https://vpn.example.com/`, the scheme defaults to `https`. The IPv6 literal may be bracketed
+/// or not, e.g., `[2001:db8::1]:8443` or `2001:db8::1`.
+pub fn validate_server(input: &str) -> Result<Url, ServerUrlError> {
+  let input = input.trim();
+  if input.is_empty() {
+    return Err(ServerUrlError::Empty);
+  }
+
+  let server = match input.split_once("://") {
+    Some((scheme, _)) if scheme.eq_ignore_ascii_case("https") || scheme.eq_ignore_ascii_case("http") => {
+      input.to_string()
+    }
+    Some((scheme, _)) => return Err(ServerUrlError::UnsupportedScheme(scheme.to_string())),
+    // The bare IPv6 literal, the colons of which would be taken as the port otherwise
+    None if input.parse::<Ipv6Addr>().is_ok() => format!("https://[{}]", input),
+    None => format!("https://{}", input),
   };
 
-  let normalized_url = Url::parse(&server)?;
+  let url = Url::parse(&server).map_err(|err| match err {
+    ParseError::EmptyHost => ServerUrlError::MissingHost(input.to_string()),
+    ParseError::InvalidPort => ServerUrlError::InvalidPort(input.to_string()),
+    err => ServerUrlError::Invalid(input.to_string(), err),
+  })?;
+
+  if url.host_str().is_none_or(str::is_empty) {
+    return Err(ServerUrlError::MissingHost(input.to_string()));
+  }
+  if url.port() == Some(0) {
+    return Err(ServerUrlError::InvalidPort(input.to_string()));
+  }
+
+  Ok(url)
+}
+
+/// Normalize the server URL to the format `https://<host>:<port>`
+pub fn normalize_server(server: &str) -> anyhow::Result<String> {
+  let normalized_url = validate_server(server)?;
   let scheme = normalized_url.scheme();
   let host = normalized_url
     .host_str()
@@ -42,55 +94,365 @@ pub fn normalize_server(server: &str) -> anyhow::Result<String> {
   Ok(normalized_url)
 }
 
+/// Build the URL of the ESP endpoint on the base URL, e.g., `ssl-vpn/login.esp`. The scheme defaults to
+/// `https`, the port and the path prefix of the base URL are kept, the query and fragment are dropped.
+pub fn esp_url(base: &str, endpoint: &str) -> anyhow::Result<String> {
+  let base = base.trim();
+  let base = if base.starts_with("https://") || base.starts_with("http://") {
+    base.to_string()
+  } else {
+    format!("https://{}", base)
+  };
+
+  let url = Url::parse(&base)?;
+  let host = url
+    .host_str()
+    .ok_or(anyhow::anyhow!("Invalid server URL: missing host"))?;
+  let port = url.port().map_or("".into(), |port| format!(":{}", port));
+  let prefix = url.path().trim_end_matches('/');
+  let endpoint = endpoint.trim_start_matches('/');
+
+  Ok(format!("{}://{}{}{}/{}", url.scheme(), host, port, prefix, endpoint))
+}
+
+/// Remove the leading `http://` or `https://` of the URL, the rest is kept, e.g., the bracketed IPv6 host
 pub fn remove_url_scheme(s: &str) -> String {
-  s.replace("http://", "").replace("https://", "")
+  match s.split_once("://") {
+    Some((scheme, rest)) if scheme.eq_ignore_ascii_case("https") || scheme.eq_ignore_ascii_case("http") => {
+      rest.to_string()
+    }
+    _ => s.to_string(),
+  }
 }
 
 #[derive(Error, Debug)]
 #[error("GP response error: reason={reason}, status={status}, body={body}")]
-pub(crate) struct GpError {
+pub(crate) struct GpResponseError {
   pub status: StatusCode,
   pub reason: String,
-  body: String,
+  pub body: String,
+  /// The message of the maintenance page, if the server is in maintenance
+  pub maintenance: Option<String>,
 }
 
-impl GpError {
+impl GpResponseError {
   pub fn is_status_error(&self) -> bool {
     self.status.is_client_error() || self.status.is_server_error()
   }
+
+  /// GlobalProtect rejects the credential with the non-standard 512 status, or with the
+  /// `auth-failed` reason
+  pub fn is_auth_failed(&self) -> bool {
+    self.status.as_u16() == 512 || self.reason == "auth-failed"
+  }
 }
 
-pub(crate) async fn parse_gp_response(res: Response) -> anyhow::Result<String, GpError> {
+/// Read the response, the error response is returned as the inner error. The response that exceeds
+/// the size limit or the timeout of the params fails with the outer error.
+pub(crate) async fn parse_gp_response(
+  res: Response,
+  gp_params: &GpParams,
+) -> Result<Result<String, GpResponseError>, PortalError> {
   let status = res.status();
 
   if status.is_client_error() || status.is_server_error() {
-    let (reason, body) = parse_gp_error(res).await;
+    let (reason, body) = parse_gp_error(res, gp_params).await?;
+    let maintenance = detect_maintenance(&body);
 
-    return Err(GpError { status, reason, body });
+    return Ok(Err(GpResponseError {
+      status,
+      reason,
+      body,
+      maintenance,
+    }));
   }
 
-  res.text().await.map_err(|err| {
-    warn!("Failed to read response: {}", err);
+  let body = match read_body(res, gp_params).await {
+    Ok(body) => body,
+    Err(PortalError::NetworkError(err)) => {
+      warn!("Failed to read response: {}", err);
 
-    GpError {
+      return Ok(Err(GpResponseError {
+        status,
+        reason: "failed to read response".to_string(),
+        body: "<failed to read response>".to_string(),
+        maintenance: None,
+      }));
+    }
+    Err(err) => return Err(err),
+  };
+
+  // The maintenance page is usually returned with 200 instead of the XML response
+  if let Some(message) = detect_maintenance(&body) {
+    return Ok(Err(GpResponseError {
       status,
-      reason: "failed to read response".to_string(),
-      body: "<failed to read response>".to_string(),
+      reason: message.clone(),
+      body,
+      maintenance: Some(message),
+    }));
+  }
+
+  Ok(Ok(body))
+}
+
+/// Read the body chunk by chunk, to stop once it exceeds the size limit
+pub(crate) async fn read_body(mut res: Response, gp_params: &GpParams) -> Result<String, PortalError> {
+  let max_size = gp_params.max_response_size();
+  let timeout = gp_params.request_timeout();
+
+  if let Some(len) = res.content_length().filter(|len| *len > max_size as u64) {
+    warn!("The response of {} bytes exceeds the size limit", len);
+    return Err(PortalError::ResponseTooLarge(max_size));
+  }
+
+  let read = async {
+    let mut body = Vec::new();
+    while let Some(chunk) = res.chunk().await? {
+      if body.len() + chunk.len() > max_size {
+        return Err(PortalError::ResponseTooLarge(max_size));
+      }
+      body.extend_from_slice(&chunk);
     }
-  })
+
+    Ok::<_, PortalError>(body)
+  };
+
+  // The client timeout may fire first, which is reported the same
+  let body = match tokio::time::timeout(timeout, read).await {
+    Ok(Err(PortalError::NetworkError(err))) if err.is_timeout() => return Err(PortalError::Timeout(timeout)),
+    Ok(body) => body?,
+    Err(_) => return Err(PortalError::Timeout(timeout)),
+  };
+
+  Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// The elements of the maintenance page that may hold its message, in the order of preference
+static MAINTENANCE_TEXT_TAGS: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+  ["p", "h1", "h2", "title"]
+    .iter()
+    .map(|tag| Regex::new(&format!(r"(?is)<{}(?:\s[^>]*)?>(.*?)</{}\s*>", tag, tag)).unwrap())
+    .collect()
+});
+
+static NESTED_TAGS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").unwrap());
+
+/// Detect the HTML maintenance page and extract its message
+fn detect_maintenance(body: &str) -> Option<String> {
+  let lower = body.to_lowercase();
+  let is_html = lower.contains("<html") || lower.contains("<!doctype html");
+  let is_maintenance = lower.contains("maintenance") || lower.contains("temporarily unavailable");
+  if !is_html || !is_maintenance {
+    return None;
+  }
+
+  let texts = MAINTENANCE_TEXT_TAGS
+    .iter()
+    .flat_map(|re| html_tag_texts(body, re))
+    .collect::<Vec<_>>();
+
+  let message = texts
+    .iter()
+    .find(|text| {
+      let text = text.to_lowercase();
+      text.contains("maintenance") || text.contains("unavailable")
+    })
+    .or_else(|| texts.first())
+    .cloned()
+    .unwrap_or_else(|| String::from("The server is in maintenance mode"));
+
+  Some(message)
 }
 
-async fn parse_gp_error(res: Response) -> (String, String) {
+/// The texts of the HTML elements matched by the regex, with the nested tags removed
+fn html_tag_texts(html: &str, re: &Regex) -> Vec<String> {
+  re.captures_iter(html)
+    .filter_map(|captures| captures.get(1))
+    .map(|m| {
+      let text = NESTED_TAGS.replace_all(m.as_str(), " ");
+      text.split_whitespace().collect::<Vec<_>>().join(" ")
+    })
+    .filter(|text| !text.is_empty())
+    .collect()
+}
+
+async fn parse_gp_error(res: Response, gp_params: &GpParams) -> Result<(String, String), PortalError> {
   let reason = res
     .headers()
     .get("x-private-pan-globalprotect")
     .map_or_else(|| "<none>", |v| v.to_str().unwrap_or("<invalid header>"))
     .to_string();
 
-  let res = res.text().await.map_or_else(
-    |_| "<failed to read response>".to_string(),
-    |v| if v.is_empty() { "<empty>".to_string() } else { v },
-  );
+  let body = match read_body(res, gp_params).await {
+    Ok(body) if body.is_empty() => "<empty>".to_string(),
+    Ok(body) => body,
+    Err(PortalError::NetworkError(_)) => "<failed to read response>".to_string(),
+    Err(err) => return Err(err),
+  };
+
+  Ok((reason, body))
+}
 
-  (reason, res)
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn esp_url_handles_base_url_variations() {
+    let login_url = |base| esp_url(base, "ssl-vpn/login.esp").unwrap();
+
+    assert_eq!(
+      login_url("vpn.example.com"),
+      "https://vpn.example.com/ssl-vpn/login.esp"
+    );
+    assert_eq!(
+      login_url("https://vpn.example.com/"),
+      "https://vpn.example.com/ssl-vpn/login.esp"
+    );
+    assert_eq!(
+      login_url("http://vpn.example.com"),
+      "http://vpn.example.com/ssl-vpn/login.esp"
+    );
+    assert_eq!(
+      login_url("vpn.example.com:8443"),
+      "https://vpn.example.com:8443/ssl-vpn/login.esp"
+    );
+    // The default port is omitted
+    assert_eq!(
+      login_url("https://vpn.example.com:443"),
+      "https://vpn.example.com/ssl-vpn/login.esp"
+    );
+    assert_eq!(
+      login_url("https://vpn.example.com/gp/"),
+      "https://vpn.example.com/gp/ssl-vpn/login.esp"
+    );
+    assert_eq!(
+      login_url("https://vpn.example.com:8443/gp//"),
+      "https://vpn.example.com:8443/gp/ssl-vpn/login.esp"
+    );
+    assert_eq!(
+      login_url("https://vpn.example.com/gp?x=1#y"),
+      "https://vpn.example.com/gp/ssl-vpn/login.esp"
+    );
+    assert_eq!(
+      login_url("https://[2001:db8::1]:8443"),
+      "https://[2001:db8::1]:8443/ssl-vpn/login.esp"
+    );
+
+    // The leading slash of the endpoint is optional
+    assert_eq!(
+      esp_url("vpn.example.com", "/global-protect/prelogin.esp").unwrap(),
+      "https://vpn.example.com/global-protect/prelogin.esp"
+    );
+    assert!(esp_url("https://", "ssl-vpn/login.esp").is_err());
+  }
+
+  #[test]
+  fn validate_server_accepts_the_server_forms() {
+    let validate = |input| validate_server(input).map(|url| url.to_string());
+
+    assert_eq!(validate("vpn.example.com"), Ok("https://vpn.example.com/".to_string()));
+    assert_eq!(
+      validate(" vpn.example.com:8443/gp "),
+      Ok("https://vpn.example.com:8443/gp".to_string())
+    );
+    assert_eq!(
+      validate("http://vpn.example.com"),
+      Ok("http://vpn.example.com/".to_string())
+    );
+    assert_eq!(
+      validate("HTTPS://vpn.example.com/"),
+      Ok("https://vpn.example.com/".to_string())
+    );
+    assert_eq!(validate("198.51.100.1:443"), Ok("https://198.51.100.1/".to_string()));
+    assert_eq!(
+      validate("[2001:db8::1]:8443"),
+      Ok("https://[2001:db8::1]:8443/".to_string())
+    );
+
+    assert_eq!(
+      normalize_server("vpn.example.com:8443/gp/").unwrap(),
+      "https://vpn.example.com:8443"
+    );
+  }
+
+  #[test]
+  fn ipv6_literals_are_preserved() {
+    let normalize = |server| normalize_server(server).unwrap();
+
+    assert_eq!(normalize("https://[2001:db8::1]:8443"), "https://[2001:db8::1]:8443");
+    assert_eq!(normalize("[2001:db8::1]:8443"), "https://[2001:db8::1]:8443");
+    assert_eq!(normalize("https://[2001:db8::1]"), "https://[2001:db8::1]");
+    // The default port is omitted
+    assert_eq!(normalize("https://[2001:db8::1]:443"), "https://[2001:db8::1]");
+    assert_eq!(normalize("2001:db8::1"), "https://[2001:db8::1]");
+    assert_eq!(
+      normalize("https://[2001:db8::1]:8443/gp/"),
+      "https://[2001:db8::1]:8443"
+    );
+    assert_eq!(normalize("[2001:DB8::1]/gp"), "https://[2001:db8::1]");
+
+    // The `server` param of the login form
+    let server = |server| remove_url_scheme(&normalize(server));
+    assert_eq!(server("https://[2001:db8::1]:8443/gp"), "[2001:db8::1]:8443");
+    assert_eq!(server("[2001:db8::1]"), "[2001:db8::1]");
+    assert_eq!(server("2001:db8::1"), "[2001:db8::1]");
+
+    assert_eq!(
+      remove_url_scheme("HTTPS://[2001:db8::1]:8443/gp"),
+      "[2001:db8::1]:8443/gp"
+    );
+    assert_eq!(remove_url_scheme("[2001:db8::1]:8443"), "[2001:db8::1]:8443");
+  }
+
+  #[test]
+  fn validate_server_rejects_invalid_servers() {
+    assert_eq!(validate_server(" "), Err(ServerUrlError::Empty));
+    assert_eq!(
+      validate_server("ftp://vpn.example.com"),
+      Err(ServerUrlError::UnsupportedScheme("ftp".to_string()))
+    );
+    assert_eq!(
+      validate_server("https://"),
+      Err(ServerUrlError::MissingHost("https://".to_string()))
+    );
+    assert_eq!(
+      validate_server("https://:8443/gp"),
+      Err(ServerUrlError::MissingHost("https://:8443/gp".to_string()))
+    );
+    assert_eq!(
+      validate_server("198.51.100.1:65536"),
+      Err(ServerUrlError::InvalidPort("198.51.100.1:65536".to_string()))
+    );
+    assert_eq!(
+      validate_server("vpn.example.com:https"),
+      Err(ServerUrlError::InvalidPort("vpn.example.com:https".to_string()))
+    );
+    assert_eq!(
+      validate_server("vpn.example.com:0"),
+      Err(ServerUrlError::InvalidPort("vpn.example.com:0".to_string()))
+    );
+    assert!(matches!(
+      validate_server("vpn example.com"),
+      Err(ServerUrlError::Invalid(_, _))
+    ));
+
+    let err = validate_server("198.51.100.1:99999").unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "Invalid port in the server '198.51.100.1:99999', expected 1-65535"
+    );
+  }
+
+  #[test]
+  fn detect_maintenance_page() {
+    let html = include_str!("../../tests/files/portal_maintenance.html");
+    assert_eq!(
+      detect_maintenance(html).as_deref(),
+      Some("The VPN portal is undergoing scheduled maintenance until 02:00 UTC.")
+    );
+
+    assert!(detect_maintenance(include_str!("../../tests/files/portal_config.xml")).is_none());
+    assert!(detect_maintenance("<html><body>Login</body></html>").is_none());
+  }
 }
@@ -4,7 +4,7 @@ use anyhow::bail;
 use log::warn;
 use openssl::pkey::PKey;
 use pem::parse_many;
-use reqwest::Identity;
+use reqwest::{Certificate, Identity};
 
 #[derive(Debug, thiserror::Error)]
 pub enum RequestIdentityError {
@@ -82,6 +82,26 @@ fn create_identity_from_pkcs12(pkcs12: &str, passphrase: Option<&str>) -> anyhow
   Ok(identity)
 }
 
+/// Load the root certificates of the PEM bundle, e.g., of a private CA. The other PEM blocks
+/// are skipped, but at least one certificate is required.
+pub fn load_ca_bundle(path: &str) -> anyhow::Result<Vec<Certificate>> {
+  let bundle = fs::read(path).map_err(|err| anyhow::anyhow!("Failed to read the CA file {}: {}", path, err))?;
+  let pems = parse_many(&bundle).map_err(|err| anyhow::anyhow!("Failed to parse the CA file {}: {}", path, err))?;
+
+  let certs = pems
+    .iter()
+    .filter(|pem| pem.tag() == "CERTIFICATE")
+    .map(|pem| Certificate::from_der(pem.contents()))
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|err| anyhow::anyhow!("Invalid certificate in the CA file {}: {}", path, err))?;
+
+  if certs.is_empty() {
+    bail!("No certificate found in the CA file {}", path);
+  }
+
+  Ok(certs)
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -0,0 +1,173 @@
+use std::future::Future;
+
+use log::info;
+
+use crate::{error::PortalError, gp_params::RetryPolicy, utils::GpResponseError};
+
+/// Run the request, and retry it with the exponential backoff of the policy while it fails
+/// with a transient error.
+pub(crate) async fn with_retry<T, F, Fut>(policy: &RetryPolicy, request: F) -> anyhow::Result<T>
+where
+  F: Fn() -> Fut,
+  Fut: Future<Output = anyhow::Result<T>>,
+{
+  retry(policy, request, true).await
+}
+
+/// Same as `with_retry`, but do not retry the timed out request, which may have been processed by
+/// the server, e.g., the gateway login that has already sent the push MFA notification.
+pub(crate) async fn with_retry_except_timeout<T, F, Fut>(policy: &RetryPolicy, request: F) -> anyhow::Result<T>
+where
+  F: Fn() -> Fut,
+  Fut: Future<Output = anyhow::Result<T>>,
+{
+  retry(policy, request, false).await
+}
+
+async fn retry<T, F, Fut>(policy: &RetryPolicy, request: F, retry_timeout: bool) -> anyhow::Result<T>
+where
+  F: Fn() -> Fut,
+  Fut: Future<Output = anyhow::Result<T>>,
+{
+  let mut attempt = 1;
+
+  loop {
+    let err = match request().await {
+      Ok(value) => return Ok(value),
+      Err(err) => err,
+    };
+
+    if attempt >= policy.max_attempts() || !is_transient(&err, retry_timeout) {
+      return Err(err);
+    }
+
+    let delay = policy.delay(attempt);
+    info!(
+      "Request failed with a transient error, retrying in {}ms ({}/{}): {}",
+      delay.as_millis(),
+      attempt,
+      policy.max_attempts(),
+      err
+    );
+    tokio::time::sleep(delay).await;
+    attempt += 1;
+  }
+}
+
+/// The connection errors, timeouts and 5xx responses are transient, the 4xx responses and the
+/// authentication failures are not, e.g., the 512 of the wrong password, which may lock the account
+/// if submitted again. The timeouts are transient only if `retry_timeout` is set.
+fn is_transient(err: &anyhow::Error, retry_timeout: bool) -> bool {
+  err.chain().any(|err| {
+    match err.downcast_ref::<PortalError>() {
+      Some(PortalError::NetworkError(err)) => return err.is_connect() || (retry_timeout && err.is_timeout()),
+      Some(PortalError::Timeout(_)) => return retry_timeout,
+      _ => {}
+    }
+
+    err
+      .downcast_ref::<GpResponseError>()
+      .is_some_and(|err| err.status.is_server_error() && !err.is_auth_failed())
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+  };
+
+  use anyhow::anyhow;
+  use reqwest::StatusCode;
+
+  use super::*;
+
+  #[test]
+  fn delay_doubles_after_each_attempt() {
+    let policy = RetryPolicy::new(4, Duration::from_millis(100)).with_jitter(Duration::ZERO);
+
+    assert_eq!(policy.delay(1), Duration::from_millis(100));
+    assert_eq!(policy.delay(2), Duration::from_millis(200));
+    assert_eq!(policy.delay(3), Duration::from_millis(400));
+
+    let policy = policy.with_jitter(Duration::from_millis(50));
+    let delay = policy.delay(1);
+    assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(150));
+  }
+
+  #[tokio::test]
+  async fn only_transient_errors_are_retried() {
+    let policy = RetryPolicy::new(3, Duration::from_millis(1));
+
+    let attempts = AtomicU32::new(0);
+    let result: anyhow::Result<()> = with_retry(&policy, || async {
+      attempts.fetch_add(1, Ordering::SeqCst);
+      Err(anyhow!(PortalError::Forbidden("denied".to_string())))
+    })
+    .await;
+    assert!(result.is_err());
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+    let attempts = AtomicU32::new(0);
+    let result = with_retry(&policy, || async {
+      match attempts.fetch_add(1, Ordering::SeqCst) {
+        0 => Err(anyhow!(GpResponseError {
+          status: StatusCode::BAD_GATEWAY,
+          reason: "Bad Gateway".to_string(),
+          body: String::new(),
+          maintenance: None,
+        })),
+        _ => Ok("ok"),
+      }
+    })
+    .await;
+    assert_eq!(result.unwrap(), "ok");
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+  }
+
+  #[tokio::test]
+  async fn auth_failures_are_not_retried() {
+    let policy = RetryPolicy::new(3, Duration::from_millis(1));
+    let auth_failed = |status: u16, reason: &str| GpResponseError {
+      status: StatusCode::from_u16(status).unwrap(),
+      reason: reason.to_string(),
+      body: String::new(),
+      maintenance: None,
+    };
+
+    for err in [auth_failed(512, "<none>"), auth_failed(503, "auth-failed")] {
+      let attempts = AtomicU32::new(0);
+      let result: anyhow::Result<()> = with_retry(&policy, || async {
+        attempts.fetch_add(1, Ordering::SeqCst);
+        Err(anyhow!(auth_failed(err.status.as_u16(), &err.reason)).context("Gateway login error"))
+      })
+      .await;
+      assert!(result.is_err());
+      assert_eq!(attempts.load(Ordering::SeqCst), 1, "{}", err);
+    }
+  }
+
+  #[tokio::test]
+  async fn timeouts_are_retried_only_if_allowed() {
+    let policy = RetryPolicy::new(3, Duration::from_millis(1));
+
+    let attempts = AtomicU32::new(0);
+    let result: anyhow::Result<()> = with_retry_except_timeout(&policy, || async {
+      attempts.fetch_add(1, Ordering::SeqCst);
+      Err(anyhow!(PortalError::Timeout(Duration::from_secs(30))))
+    })
+    .await;
+    assert!(result.is_err());
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+    let attempts = AtomicU32::new(0);
+    let result: anyhow::Result<()> = with_retry(&policy, || async {
+      attempts.fetch_add(1, Ordering::SeqCst);
+      Err(anyhow!(PortalError::Timeout(Duration::from_secs(30))))
+    })
+    .await;
+    assert!(result.is_err());
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+  }
+}
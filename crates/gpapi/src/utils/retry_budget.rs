@@ -0,0 +1,62 @@
+use std::{
+  sync::{Arc, OnceLock},
+  time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, Copy)]
+#[error("The total retry budget of {}s is exhausted", .0.as_secs())]
+pub struct RetryBudgetExhausted(Duration);
+
+/// A deadline shared by all the steps of the connect flow, i.e., prelogin, getconfig,
+/// gateway login and HIP, so that the retries of each step cannot multiply the total time.
+/// The deadline starts with the first request, not with the prompts before it, and the clones
+/// share the same deadline.
+#[derive(Debug, Clone)]
+pub struct RetryBudget {
+  total: Duration,
+  deadline: Arc<OnceLock<Instant>>,
+}
+
+impl RetryBudget {
+  pub fn new(total: Duration) -> Self {
+    Self {
+      total,
+      deadline: Default::default(),
+    }
+  }
+
+  pub fn total(&self) -> Duration {
+    self.total
+  }
+
+  /// The remaining time of the budget, or an error if it is exhausted. The first call starts the budget.
+  pub fn remaining(&self) -> Result<Duration, RetryBudgetExhausted> {
+    let deadline = self.deadline.get_or_init(|| Instant::now() + self.total);
+    deadline
+      .checked_duration_since(Instant::now())
+      .filter(|remaining| !remaining.is_zero())
+      .ok_or(RetryBudgetExhausted(self.total))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn budget_is_shared_by_clones() {
+    let budget = RetryBudget::new(Duration::from_millis(50));
+    let cloned = budget.clone();
+
+    // Not started until the first request
+    std::thread::sleep(Duration::from_millis(60));
+    assert!(cloned.remaining().is_ok());
+
+    std::thread::sleep(Duration::from_millis(60));
+
+    assert!(budget.remaining().is_err());
+    assert!(cloned.remaining().is_err());
+  }
+}
@@ -0,0 +1,89 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::bail;
+use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer};
+
+/// The time step of the TOTP, see RFC 6238
+const TOTP_STEP_SECS: u64 = 30;
+const TOTP_DIGITS: u32 = 6;
+
+/// Generate the current TOTP code from the base32 encoded secret, e.g., the secret of the
+/// `otpauth://` URI of the authenticator apps.
+pub fn generate_totp(secret: &str) -> anyhow::Result<String> {
+  let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+  generate_totp_at(secret, now)
+}
+
+/// Generate the TOTP code at the given Unix time, with HMAC-SHA1 and 6 digits
+pub fn generate_totp_at(secret: &str, unix_time: u64) -> anyhow::Result<String> {
+  let key = decode_base32(secret)?;
+  let counter = unix_time / TOTP_STEP_SECS;
+
+  let pkey = PKey::hmac(&key)?;
+  let mut signer = Signer::new(MessageDigest::sha1(), &pkey)?;
+  signer.update(&counter.to_be_bytes())?;
+  let hmac = signer.sign_to_vec()?;
+
+  // Dynamic truncation, see RFC 4226
+  let offset = (hmac[hmac.len() - 1] & 0x0f) as usize;
+  let code = u32::from_be_bytes([hmac[offset], hmac[offset + 1], hmac[offset + 2], hmac[offset + 3]]) & 0x7fff_ffff;
+  let code = code % 10u32.pow(TOTP_DIGITS);
+
+  Ok(format!("{:0width$}", code, width = TOTP_DIGITS as usize))
+}
+
+/// Decode the base32 secret, the padding, whitespace and case are ignored
+fn decode_base32(secret: &str) -> anyhow::Result<Vec<u8>> {
+  const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+  let mut bytes = Vec::new();
+  let mut buffer = 0u32;
+  let mut bits = 0;
+
+  for c in secret.chars().filter(|c| !c.is_whitespace() && *c != '=' && *c != '-') {
+    let c = c.to_ascii_uppercase() as u8;
+    let Some(value) = ALPHABET.iter().position(|&a| a == c) else {
+      bail!("Invalid character in the TOTP secret");
+    };
+
+    buffer = (buffer << 5) | value as u32;
+    bits += 5;
+    if bits >= 8 {
+      bits -= 8;
+      bytes.push((buffer >> bits) as u8);
+      buffer &= (1 << bits) - 1;
+    }
+  }
+
+  if bytes.is_empty() {
+    bail!("The TOTP secret is empty");
+  }
+
+  Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // The SHA1 secret of the RFC 6238 test vectors, i.e., "12345678901234567890"
+  const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+  #[test]
+  fn generates_rfc_6238_codes() {
+    assert_eq!(generate_totp_at(RFC_SECRET, 59).unwrap(), "287082");
+    assert_eq!(generate_totp_at(RFC_SECRET, 1111111109).unwrap(), "081804");
+    assert_eq!(generate_totp_at(RFC_SECRET, 1234567890).unwrap(), "005924");
+    // The secret is case-insensitive and may be grouped
+    assert_eq!(
+      generate_totp_at("gezd gnbv gy3t qojq gezd gnbv gy3t qojq", 59).unwrap(),
+      "287082"
+    );
+  }
+
+  #[test]
+  fn rejects_invalid_secret() {
+    assert!(generate_totp_at("not-base32!", 59).is_err());
+    assert!(generate_totp_at("", 59).is_err());
+  }
+}
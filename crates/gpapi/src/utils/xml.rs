@@ -1,4 +1,4 @@
-use xmltree::Element;
+use xmltree::{Element, XMLNode};
 
 pub(crate) trait ElementExt {
   /// Recursively find all descendants with the given name
@@ -21,6 +21,9 @@ pub(crate) trait ElementExt {
 
   /// Get the attribute value by name
   fn attr(&self, name: &str) -> Option<&str>;
+
+  /// Get the text including the CDATA sections, the whitespace around the CDATA sections is ignored
+  fn text_content(&self) -> Option<String>;
 }
 
 impl ElementExt for Element {
@@ -84,4 +87,20 @@ impl ElementExt for Element {
   fn attr(&self, name: &str) -> Option<&str> {
     self.attributes.get(name).map(|s| s.as_str())
   }
+
+  fn text_content(&self) -> Option<String> {
+    let has_cdata = self.children.iter().any(|child| matches!(child, XMLNode::CData(_)));
+    let parts = self
+      .children
+      .iter()
+      .filter_map(|child| match child {
+        XMLNode::CData(data) => Some(data.as_str()),
+        XMLNode::Text(text) if has_cdata && text.trim().is_empty() => None,
+        XMLNode::Text(text) => Some(text.as_str()),
+        _ => None,
+      })
+      .collect::<Vec<_>>();
+
+    if parts.is_empty() { None } else { Some(parts.concat()) }
+  }
 }
@@ -0,0 +1,49 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use axum::{Router, extract::State, http::HeaderMap, response::IntoResponse, routing::post};
+use gpapi::{gp_params::GpParams, portal::prelogin};
+
+const PRELOGIN_STANDARD_XML: &str = include_str!("files/prelogin_standard.xml");
+
+type Captured = Arc<Mutex<Vec<Option<String>>>>;
+
+#[tokio::test]
+async fn accept_language_header_is_sent_when_configured() -> anyhow::Result<()> {
+  let captured = Captured::default();
+  let server_url = start_mock_server(captured.clone()).await?;
+
+  let gp_params = GpParams::builder()
+    .user_agent("gpapi-test/1.0")
+    .accept_language(Some("de-DE".to_string()))
+    .build();
+  prelogin(&server_url, &gp_params).await?;
+
+  // Omitted by default
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+  prelogin(&server_url, &gp_params).await?;
+
+  let captured = captured.lock().expect("captured lock poisoned");
+  assert_eq!(*captured, [Some("de-DE".to_string()), None]);
+
+  Ok(())
+}
+
+async fn start_mock_server(captured: Captured) -> anyhow::Result<String> {
+  let app = Router::new()
+    .route("/global-protect/prelogin.esp", post(handle_prelogin))
+    .with_state(captured);
+
+  common::start_mock_server(app).await
+}
+
+async fn handle_prelogin(State(captured): State<Captured>, headers: HeaderMap) -> impl IntoResponse {
+  let accept_language = headers
+    .get("accept-language")
+    .and_then(|v| v.to_str().ok())
+    .map(|v| v.to_string());
+  captured.lock().expect("captured lock poisoned").push(accept_language);
+
+  PRELOGIN_STANDARD_XML
+}
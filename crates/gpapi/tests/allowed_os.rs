@@ -0,0 +1,65 @@
+mod common;
+
+use axum::{Router, routing::post};
+use gpapi::{
+  credential::{Credential, PasswordCredential},
+  error::PortalError,
+  gp_params::{ClientOs, GpParams},
+  portal::retrieve_config,
+};
+
+const PORTAL_CONFIG: &str = r#"<policy>
+  <portal-userauthcookie>cookie</portal-userauthcookie>
+  <allowed-os>
+    <member>Windows</member>
+    <member>macOS</member>
+  </allowed-os>
+  <gateways><external><list>
+    <entry name="gw1.example.com"><description>gw1</description></entry>
+  </list></external></gateways>
+</policy>"#;
+
+#[tokio::test]
+async fn disallowed_os_is_rejected_with_clear_error() -> anyhow::Result<()> {
+  let server_url = start_mock_server().await?;
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+  let gp_params = GpParams::builder()
+    .user_agent("gpapi-test/1.0")
+    .client_os(ClientOs::Linux)
+    .build();
+
+  let err = retrieve_config(&server_url, &cred, &gp_params).await.unwrap_err();
+  let err @ PortalError::OsNotAllowed { .. } = err else {
+    panic!("unexpected error: {err}");
+  };
+
+  assert!(err.is_os_not_allowed());
+  assert_eq!(err.suggested_os(), Some(ClientOs::Windows));
+  assert_eq!(
+    err.to_string(),
+    "The portal does not allow the client OS Linux, allowed: Windows, macOS"
+  );
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn allowed_os_passes() -> anyhow::Result<()> {
+  let server_url = start_mock_server().await?;
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+  let gp_params = GpParams::builder()
+    .user_agent("gpapi-test/1.0")
+    .client_os(ClientOs::Mac)
+    .build();
+
+  let config = retrieve_config(&server_url, &cred, &gp_params).await?;
+  assert_eq!(config.gateways()[0].name(), "gw1");
+
+  Ok(())
+}
+
+async fn start_mock_server() -> anyhow::Result<String> {
+  let app = Router::new().route("/global-protect/getconfig.esp", post(|| async { PORTAL_CONFIG }));
+
+  common::start_mock_server(app).await
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use std::{
+  collections::{HashMap, VecDeque},
+  sync::{Arc, Mutex},
+};
+
+use axum::{
+  Router,
+  extract::{Form, State},
+  response::IntoResponse,
+  routing::post,
+};
+use gpapi::{
+  credential::{AuthCookieCredential, Credential},
+  gateway::gateway_login,
+  gp_params::GpParams,
+  portal::retrieve_config,
+};
+
+const GATEWAY_LOGIN_XML: &str = include_str!("files/gateway_login.xml");
+
+#[derive(Clone, Default)]
+struct MockState {
+  configs: Arc<Mutex<VecDeque<String>>>,
+  // The (endpoint, portal-userauthcookie, portal-prelogonuserauthcookie) of each request
+  captured: Arc<Mutex<Vec<(String, String, String)>>>,
+}
+
+fn portal_config(user_auth_cookie: &str, prelogon_user_auth_cookie: &str) -> String {
+  format!(
+    r#"<policy>
+  <portal-userauthcookie>{user_auth_cookie}</portal-userauthcookie>
+  <portal-prelogonuserauthcookie>{prelogon_user_auth_cookie}</portal-prelogonuserauthcookie>
+  <gateways><external><list>
+    <entry name="gw1.example.com"><description>gw1</description></entry>
+  </list></external></gateways>
+</policy>"#
+  )
+}
+
+#[tokio::test]
+async fn refreshed_auth_cookies_are_used_for_gateway_login() -> anyhow::Result<()> {
+  let state = MockState::default();
+  state.configs.lock().expect("configs lock poisoned").extend([
+    portal_config("new-cookie", "new-prelogon-cookie"),
+    // Not refreshed, the cookies of the credential are kept
+    portal_config("", ""),
+  ]);
+  let server_url = start_mock_server(state.clone()).await?;
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  let cred = Credential::AuthCookie(AuthCookieCredential::new("alice", "old-cookie", "old-prelogon-cookie"));
+  let portal_config = retrieve_config(&server_url, &cred, &gp_params).await?;
+  assert_eq!(portal_config.auth_cookie().user_auth_cookie(), "new-cookie");
+  gateway_login(&server_url, &portal_config.auth_cookie().into(), &gp_params).await?;
+
+  let portal_config = retrieve_config(&server_url, &cred, &gp_params).await?;
+  assert_eq!(portal_config.auth_cookie().user_auth_cookie(), "old-cookie");
+  gateway_login(&server_url, &portal_config.auth_cookie().into(), &gp_params).await?;
+
+  let captured = state.captured.lock().expect("captured lock poisoned");
+  let expected = [
+    ("getconfig", "old-cookie", "old-prelogon-cookie"),
+    ("login", "new-cookie", "new-prelogon-cookie"),
+    ("getconfig", "old-cookie", "old-prelogon-cookie"),
+    ("login", "old-cookie", "old-prelogon-cookie"),
+  ]
+  .map(|(endpoint, cookie, prelogon_cookie)| (endpoint.to_string(), cookie.to_string(), prelogon_cookie.to_string()));
+  assert_eq!(*captured, expected);
+
+  Ok(())
+}
+
+async fn start_mock_server(state: MockState) -> anyhow::Result<String> {
+  let app = Router::new()
+    .route("/global-protect/getconfig.esp", post(handle_getconfig))
+    .route("/ssl-vpn/login.esp", post(handle_gateway_login))
+    .with_state(state);
+
+  common::start_mock_server(app).await
+}
+
+fn capture(state: &MockState, endpoint: &str, params: &HashMap<String, String>) {
+  let param = |name: &str| params.get(name).cloned().unwrap_or_default();
+  state.captured.lock().expect("captured lock poisoned").push((
+    endpoint.to_string(),
+    param("portal-userauthcookie"),
+    param("portal-prelogonuserauthcookie"),
+  ));
+}
+
+async fn handle_getconfig(
+  State(state): State<MockState>,
+  Form(params): Form<HashMap<String, String>>,
+) -> impl IntoResponse {
+  capture(&state, "getconfig", &params);
+  state
+    .configs
+    .lock()
+    .expect("configs lock poisoned")
+    .pop_front()
+    .unwrap_or_default()
+}
+
+async fn handle_gateway_login(
+  State(state): State<MockState>,
+  Form(params): Form<HashMap<String, String>>,
+) -> impl IntoResponse {
+  capture(&state, "login", &params);
+  GATEWAY_LOGIN_XML
+}
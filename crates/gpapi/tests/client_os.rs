@@ -0,0 +1,98 @@
+mod common;
+
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+};
+
+use axum::{
+  Router,
+  extract::{Form, State},
+  response::IntoResponse,
+  routing::post,
+};
+use gpapi::{
+  credential::{Credential, PasswordCredential},
+  gateway::{GatewayLogin, gateway_login},
+  gp_params::{ClientOs, GpParams},
+  portal::prelogin,
+};
+
+const PRELOGIN_STANDARD_XML: &str = include_str!("files/prelogin_standard.xml");
+const GATEWAY_LOGIN_XML: &str = include_str!("files/gateway_login.xml");
+
+/// The `clientos` and `os-version` params of each request
+type Captured = Arc<Mutex<Vec<(Option<String>, Option<String>)>>>;
+
+#[tokio::test]
+async fn os_overrides_are_sent_at_login() -> anyhow::Result<()> {
+  let captured = Captured::default();
+  let server_url = start_mock_server(captured.clone()).await?;
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+
+  let gp_params = GpParams::builder()
+    .user_agent("gpapi-test/1.0")
+    .client_os(ClientOs::Windows)
+    .os_version("Microsoft Windows 10 Enterprise , 64-bit".to_string())
+    .build();
+  assert_eq!(gp_params.client_os(), "Windows");
+  assert_eq!(gp_params.os_version(), Some("Microsoft Windows 10 Enterprise , 64-bit"));
+
+  prelogin(&server_url, &gp_params).await?;
+  let GatewayLogin::Cookie(_) = gateway_login(&server_url, &cred, &gp_params).await? else {
+    anyhow::bail!("expected gateway cookie, got MFA challenge");
+  };
+
+  let expected = (
+    Some("Windows".to_string()),
+    Some("Microsoft Windows 10 Enterprise , 64-bit".to_string()),
+  );
+  let captured = captured.lock().expect("captured lock poisoned");
+  assert_eq!(*captured, [expected.clone(), expected]);
+
+  Ok(())
+}
+
+#[test]
+fn unusual_os_versions_are_detected() {
+  assert!(ClientOs::Linux.is_usual_os_version("Linux Ubuntu 24.04.3 LTS"));
+  assert!(ClientOs::Windows.is_usual_os_version("Microsoft Windows 11 Pro , 64-bit"));
+  assert!(ClientOs::Mac.is_usual_os_version("Apple Mac OS X 13.4.0"));
+  assert!(ClientOs::Mac.is_usual_os_version("macOS 14.5"));
+
+  assert!(!ClientOs::Windows.is_usual_os_version("Linux Ubuntu 24.04.3 LTS"));
+  assert!(!ClientOs::Linux.is_usual_os_version("Ubuntu 24.04"));
+  assert!(!ClientOs::Mac.is_usual_os_version(""));
+}
+
+async fn start_mock_server(captured: Captured) -> anyhow::Result<String> {
+  let app = Router::new()
+    .route("/global-protect/prelogin.esp", post(handle_prelogin))
+    .route("/ssl-vpn/login.esp", post(handle_gateway_login))
+    .with_state(captured);
+
+  common::start_mock_server(app).await
+}
+
+fn capture(captured: &Captured, params: &HashMap<String, String>) {
+  captured
+    .lock()
+    .expect("captured lock poisoned")
+    .push((params.get("clientos").cloned(), params.get("os-version").cloned()));
+}
+
+async fn handle_prelogin(
+  State(captured): State<Captured>,
+  Form(params): Form<HashMap<String, String>>,
+) -> impl IntoResponse {
+  capture(&captured, &params);
+  PRELOGIN_STANDARD_XML
+}
+
+async fn handle_gateway_login(
+  State(captured): State<Captured>,
+  Form(params): Form<HashMap<String, String>>,
+) -> impl IntoResponse {
+  capture(&captured, &params);
+  GATEWAY_LOGIN_XML
+}
@@ -0,0 +1,19 @@
+use std::net::SocketAddr;
+
+use axum::Router;
+use tokio::net::TcpListener;
+
+/// Serve the mock portal or gateway on a random local port, return its URL. The handlers can extract the
+/// `ConnectInfo` of the client.
+pub async fn start_mock_server(app: Router) -> anyhow::Result<String> {
+  let listener = TcpListener::bind("127.0.0.1:0").await?;
+  let addr: SocketAddr = listener.local_addr()?;
+  tokio::spawn(async move {
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    if let Err(err) = axum::serve(listener, app).await {
+      eprintln!("mock server failed: {err}");
+    }
+  });
+
+  Ok(format!("http://{}", addr))
+}
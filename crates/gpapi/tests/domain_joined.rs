@@ -0,0 +1,66 @@
+mod common;
+
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+};
+
+use axum::{
+  Router,
+  extract::{Form, State},
+  response::IntoResponse,
+  routing::post,
+};
+use gpapi::{
+  credential::{Credential, PasswordCredential},
+  gateway::{GatewayLogin, gateway_login},
+  gp_params::GpParams,
+};
+
+const GATEWAY_LOGIN_XML: &str = include_str!("files/gateway_login.xml");
+
+type Captured = Arc<Mutex<Vec<Option<String>>>>;
+
+#[tokio::test]
+async fn domain_joined_param_is_sent_at_gateway_login() -> anyhow::Result<()> {
+  let captured = Captured::default();
+  let server_url = start_mock_server(captured.clone()).await?;
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+
+  for domain_joined in [Some(true), Some(false), None] {
+    let gp_params = GpParams::builder()
+      .user_agent("gpapi-test/1.0")
+      .domain_joined(domain_joined)
+      .build();
+
+    let GatewayLogin::Cookie(_) = gateway_login(&server_url, &cred, &gp_params).await? else {
+      anyhow::bail!("expected gateway cookie, got MFA challenge");
+    };
+  }
+
+  // Not reported by default
+  let captured = captured.lock().expect("captured lock poisoned");
+  assert_eq!(*captured, [Some("yes".to_string()), Some("no".to_string()), None]);
+
+  Ok(())
+}
+
+async fn start_mock_server(captured: Captured) -> anyhow::Result<String> {
+  let app = Router::new()
+    .route("/ssl-vpn/login.esp", post(handle_gateway_login))
+    .with_state(captured);
+
+  common::start_mock_server(app).await
+}
+
+async fn handle_gateway_login(
+  State(captured): State<Captured>,
+  Form(params): Form<HashMap<String, String>>,
+) -> impl IntoResponse {
+  captured
+    .lock()
+    .expect("captured lock poisoned")
+    .push(params.get("domain-joined").cloned());
+
+  GATEWAY_LOGIN_XML
+}
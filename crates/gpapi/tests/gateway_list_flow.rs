@@ -0,0 +1,122 @@
+mod common;
+
+use std::{
+  collections::HashMap,
+  sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+  },
+};
+
+use axum::{
+  Router,
+  extract::{Form, State},
+  http::StatusCode,
+  response::IntoResponse,
+  routing::post,
+};
+use common::start_mock_server;
+use gpapi::{
+  credential::{Credential, PasswordCredential},
+  gp_params::GpParams,
+  portal::retrieve_config,
+};
+
+const PORTAL_CONFIG_XML: &str = include_str!("files/portal_config_gateway_list.xml");
+const GATEWAY_LIST_2_XML: &str = include_str!("files/gateway_list_2.xml");
+const GATEWAY_LIST_3_XML: &str = include_str!("files/gateway_list_3.xml");
+const PORTAL_CONFIG_BROKEN_LIST_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<policy>
+  <gateways>
+    <external>
+      <list>
+        <entry name="gw1.example.com"><priority>1</priority></entry>
+      </list>
+    </external>
+    <gateway-list-url>/global-protect/gateways-broken.esp</gateway-list-url>
+    <gateway-list-url>/global-protect/gateways-3.esp</gateway-list-url>
+  </gateways>
+</policy>"#;
+
+#[derive(Clone, Default)]
+struct MockState {
+  list_2_requests: Arc<AtomicUsize>,
+  list_3_requests: Arc<AtomicUsize>,
+}
+
+#[tokio::test]
+async fn retrieve_config_merges_external_gateway_lists() -> anyhow::Result<()> {
+  let state = MockState::default();
+  let server_url = start_mock_server(mock_app(state.clone())).await?;
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+  let portal_config = retrieve_config(&server_url, &cred, &gp_params).await?;
+
+  let gateways = portal_config
+    .gateways()
+    .iter()
+    .map(|gateway| gateway.server().to_string())
+    .collect::<Vec<_>>();
+  assert_eq!(gateways, ["gw1.example.com", "gw2.example.com", "gw3.example.com"]);
+
+  // The reference loop is fetched only once
+  assert_eq!(state.list_2_requests.load(Ordering::SeqCst), 1);
+  assert_eq!(state.list_3_requests.load(Ordering::SeqCst), 1);
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn unavailable_gateway_list_is_skipped() -> anyhow::Result<()> {
+  let app = Router::new()
+    .route(
+      "/global-protect/getconfig.esp",
+      post(|| async { PORTAL_CONFIG_BROKEN_LIST_XML }),
+    )
+    .route(
+      "/global-protect/gateways-broken.esp",
+      post(|| async { StatusCode::SERVICE_UNAVAILABLE }),
+    )
+    .route("/global-protect/gateways-3.esp", post(|| async { GATEWAY_LIST_3_XML }));
+  let server_url = start_mock_server(app).await?;
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+  let portal_config = retrieve_config(&server_url, &cred, &gp_params).await?;
+
+  let gateways = portal_config
+    .gateways()
+    .iter()
+    .map(|gateway| gateway.server().to_string())
+    .collect::<Vec<_>>();
+  assert_eq!(gateways, ["gw1.example.com", "gw3.example.com"]);
+
+  Ok(())
+}
+
+fn mock_app(state: MockState) -> Router {
+  Router::new()
+    .route("/global-protect/getconfig.esp", post(handle_getconfig))
+    .route("/global-protect/gateways-2.esp", post(handle_gateway_list_2))
+    .route("/global-protect/gateways-3.esp", post(handle_gateway_list_3))
+    .with_state(state)
+}
+
+async fn handle_getconfig(Form(_params): Form<HashMap<String, String>>) -> impl IntoResponse {
+  PORTAL_CONFIG_XML
+}
+
+async fn handle_gateway_list_2(
+  State(state): State<MockState>,
+  Form(params): Form<HashMap<String, String>>,
+) -> impl IntoResponse {
+  assert_eq!(params.get("user").map(String::as_str), Some("alice"));
+  state.list_2_requests.fetch_add(1, Ordering::SeqCst);
+  GATEWAY_LIST_2_XML
+}
+
+async fn handle_gateway_list_3(State(state): State<MockState>) -> impl IntoResponse {
+  state.list_3_requests.fetch_add(1, Ordering::SeqCst);
+  GATEWAY_LIST_3_XML
+}
@@ -0,0 +1,214 @@
+mod common;
+
+use std::time::Duration;
+
+use axum::{
+  Router,
+  http::StatusCode,
+  response::{Html, IntoResponse},
+  routing::post,
+};
+use common::start_mock_server;
+use gpapi::{
+  credential::{AuthCookieCredential, Credential, PasswordCredential},
+  error::{GatewayLoginError, PortalError},
+  gateway::{GatewayLogin, gateway_login},
+  gp_params::GpParams,
+  utils::retry_budget::RetryBudget,
+};
+
+const MAINTENANCE_HTML: &str = include_str!("files/portal_maintenance.html");
+
+#[tokio::test]
+async fn rejected_credential_is_reported_as_auth_rejected() -> anyhow::Result<()> {
+  let app = Router::new().route(
+    "/ssl-vpn/login.esp",
+    post(|| async {
+      (
+        StatusCode::UNAUTHORIZED,
+        [("x-private-pan-globalprotect", "auth-failed")],
+        "",
+      )
+    }),
+  );
+
+  let err = login(&start_mock_server(app).await?).await.unwrap_err();
+  let GatewayLoginError::AuthRejected { status, reason } = &err else {
+    panic!("unexpected error: {err}");
+  };
+  assert_eq!(*status, StatusCode::UNAUTHORIZED);
+  assert_eq!(reason, "auth-failed");
+  assert!(err.is_auth_rejected());
+  assert_eq!(err.to_string(), "Gateway login error: auth-failed");
+
+  // The 512 of the wrong password
+  let app = Router::new().route(
+    "/ssl-vpn/login.esp",
+    post(|| async { (StatusCode::from_u16(512).unwrap(), "") }),
+  );
+  let err = login(&start_mock_server(app).await?).await.unwrap_err();
+  assert!(
+    matches!(&err, GatewayLoginError::AuthRejected { status, .. } if status.as_u16() == 512),
+    "unexpected error: {err}"
+  );
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn rejected_cookie_is_reported_as_cookie_expired() -> anyhow::Result<()> {
+  let app = Router::new().route(
+    "/ssl-vpn/login.esp",
+    post(|| async {
+      (
+        StatusCode::from_u16(512).unwrap(),
+        [("x-private-pan-globalprotect", "auth-failed")],
+        "",
+      )
+    }),
+  );
+  let server_url = start_mock_server(app).await?;
+
+  let cred = Credential::from(&AuthCookieCredential::new("alice", "expired-cookie", ""));
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+  let err = gateway_login(&server_url, &cred, &gp_params).await.unwrap_err();
+  assert!(err.is_cookie_expired(), "unexpected error: {err}");
+  assert_eq!(
+    err.to_string(),
+    "The authentication cookie has expired or is invalid: auth-failed"
+  );
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn expired_cookie_response_is_reported_as_cookie_expired() -> anyhow::Result<()> {
+  let app = Router::new().route(
+    "/ssl-vpn/login.esp",
+    post(|| async { r#"<response status="error"><error>Invalid Cookie</error></response>"# }),
+  );
+
+  // Reported explicitly, whatever the credential
+  let err = login(&start_mock_server(app).await?).await.unwrap_err();
+  assert!(
+    matches!(&err, GatewayLoginError::CookieExpired(message) if message.contains("Invalid Cookie")),
+    "unexpected error: {err}"
+  );
+  assert!(!err.is_auth_rejected());
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn malformed_mfa_challenge_is_reported_as_mfa_parse() -> anyhow::Result<()> {
+  let app = Router::new().route(
+    "/ssl-vpn/login.esp",
+    post(|| async { "var respStatus = \"Challenge\";" }),
+  );
+
+  let err = login(&start_mock_server(app).await?).await.unwrap_err();
+  assert!(matches!(err, GatewayLoginError::MfaParse(_)), "unexpected error: {err}");
+  assert_eq!(
+    err.to_string(),
+    "Failed to parse MFA challenge: var respStatus = \"Challenge\";"
+  );
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn malformed_token_is_reported_as_token_parse() -> anyhow::Result<()> {
+  let app = Router::new().route(
+    "/ssl-vpn/login.esp",
+    post(|| async { "<jnlp><application-desc><argument>(null)</argument></application-desc></jnlp>" }),
+  );
+
+  let err = login(&start_mock_server(app).await?).await.unwrap_err();
+  assert!(
+    matches!(err, GatewayLoginError::TokenParse(_)),
+    "unexpected error: {err}"
+  );
+  assert_eq!(
+    err.to_string(),
+    "Failed to read authcookie from args: index 1 is missing, the response has 1 argument(s)"
+  );
+
+  let app = Router::new().route("/ssl-vpn/login.esp", post(|| async { "not xml" }));
+  let err = login(&start_mock_server(app).await?).await.unwrap_err();
+  assert!(
+    matches!(err, GatewayLoginError::TokenParse(_)),
+    "unexpected error: {err}"
+  );
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn maintenance_page_is_reported_as_maintenance() -> anyhow::Result<()> {
+  let app = Router::new().route(
+    "/ssl-vpn/login.esp",
+    post(|| async { (StatusCode::SERVICE_UNAVAILABLE, Html(MAINTENANCE_HTML)).into_response() }),
+  );
+
+  let err = login(&start_mock_server(app).await?).await.unwrap_err();
+  assert!(
+    matches!(err, GatewayLoginError::Maintenance(_)),
+    "unexpected error: {err}"
+  );
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn unreachable_gateway_is_reported_as_network() -> anyhow::Result<()> {
+  // Nothing listens on the port, so that the connection is refused
+  let err = login("http://127.0.0.1:1").await.unwrap_err();
+  assert!(matches!(err, GatewayLoginError::Network(_)), "unexpected error: {err}");
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn other_failures_are_reported_by_kind() -> anyhow::Result<()> {
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+  let gp_params = GpParams::builder()
+    .user_agent("gpapi-test/1.0")
+    .retry_budget(Some(RetryBudget::new(Duration::ZERO)))
+    .build();
+
+  let err = gateway_login("http://127.0.0.1:1", &cred, &gp_params)
+    .await
+    .unwrap_err();
+  assert!(
+    matches!(err, GatewayLoginError::RetryBudgetExhausted(_)),
+    "unexpected error: {err}"
+  );
+
+  let app = Router::new().route("/ssl-vpn/login.esp", post(|| async { "x".repeat(2048) }));
+  let gp_params = GpParams::builder()
+    .user_agent("gpapi-test/1.0")
+    .max_response_size(1024)
+    .build();
+  let err = gateway_login(&start_mock_server(app).await?, &cred, &gp_params)
+    .await
+    .unwrap_err();
+  assert!(
+    matches!(err, GatewayLoginError::Portal(PortalError::ResponseTooLarge(1024))),
+    "unexpected error: {err}"
+  );
+
+  let err = login("https://").await.unwrap_err();
+  assert!(
+    matches!(err, GatewayLoginError::Unexpected(_)),
+    "unexpected error: {err}"
+  );
+
+  Ok(())
+}
+
+async fn login(server_url: &str) -> Result<GatewayLogin, GatewayLoginError> {
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  gateway_login(server_url, &cred, &gp_params).await
+}
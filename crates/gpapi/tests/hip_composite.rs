@@ -0,0 +1,100 @@
+mod common;
+
+use std::{
+  collections::HashMap,
+  fs::{self, Permissions},
+  os::unix::fs::PermissionsExt,
+  path::Path,
+  sync::{Arc, Mutex},
+};
+
+use axum::{
+  Router,
+  extract::{Form, State},
+  response::IntoResponse,
+  routing::post,
+};
+use gpapi::{gateway::hip::hip_report_composite, gp_params::GpParams};
+
+const COOKIE: &str = "authcookie=abc&portal=vpn&user=alice&domain=corp&computer=host&preferred-ip=10.0.0.2";
+
+const HOST_INFO_SECTION: &str = r#"<hip-report name="hip-report">
+  <md5-sum>xxx</md5-sum>
+  <categories>
+    <entry name="host-info"><host-name>host</host-name></entry>
+  </categories>
+</hip-report>"#;
+
+const ANTI_MALWARE_SECTION: &str = r#"<hip-report name="hip-report">
+  <categories>
+    <entry name="host-info"><host-name>duplicated</host-name></entry>
+    <entry name="anti-malware"><list/></entry>
+  </categories>
+</hip-report>"#;
+
+type Captured = Arc<Mutex<Vec<String>>>;
+
+#[tokio::test]
+async fn composite_hip_report_contains_all_sections() -> anyhow::Result<()> {
+  let captured = Captured::default();
+  let server_url = start_mock_server(captured.clone()).await?;
+
+  let dir = tempfile::tempdir()?;
+  let host_info_wrapper = write_wrapper(&dir.path().join("host_info.sh"), HOST_INFO_SECTION)?;
+  let anti_malware_wrapper = write_wrapper(&dir.path().join("anti_malware.sh"), ANTI_MALWARE_SECTION)?;
+
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+  hip_report_composite(
+    &server_url,
+    COOKIE,
+    &[&host_info_wrapper, &anti_malware_wrapper],
+    &gp_params,
+  )
+  .await?;
+
+  let captured = captured.lock().expect("captured lock poisoned");
+  assert_eq!(captured.len(), 1);
+
+  let report = &captured[0];
+  assert!(report.contains(r#"<entry name="host-info"><host-name>host</host-name></entry>"#));
+  assert!(report.contains(r#"<entry name="anti-malware">"#));
+  // The section already in the first report is not duplicated
+  assert!(!report.contains("duplicated"));
+
+  Ok(())
+}
+
+fn write_wrapper(path: &Path, report: &str) -> anyhow::Result<String> {
+  fs::write(path, format!("#!/bin/sh\ncat <<'EOF'\n{}\nEOF\n", report))?;
+  fs::set_permissions(path, Permissions::from_mode(0o755))?;
+
+  Ok(path.display().to_string())
+}
+
+async fn start_mock_server(captured: Captured) -> anyhow::Result<String> {
+  let app = Router::new()
+    .route("/ssl-vpn/getconfig.esp", post(handle_getconfig))
+    .route("/ssl-vpn/hipreportcheck.esp", post(handle_hip_check))
+    .route("/ssl-vpn/hipreport.esp", post(handle_hip_report))
+    .with_state(captured);
+
+  common::start_mock_server(app).await
+}
+
+async fn handle_getconfig() -> impl IntoResponse {
+  "<response><ip-address>10.0.0.2</ip-address></response>"
+}
+
+async fn handle_hip_check() -> impl IntoResponse {
+  "<response><hip-report-needed>yes</hip-report-needed></response>"
+}
+
+async fn handle_hip_report(
+  State(captured): State<Captured>,
+  Form(params): Form<HashMap<String, String>>,
+) -> impl IntoResponse {
+  let report = params.get("report").cloned().unwrap_or_default();
+  captured.lock().expect("captured lock poisoned").push(report);
+
+  "<response><status>success</status></response>"
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use std::{
+  fs::{self, Permissions},
+  os::unix::fs::PermissionsExt,
+  path::Path,
+};
+
+use axum::{Router, response::IntoResponse, routing::post};
+use gpapi::{error::PortalError, gateway::hip::hip_report, gp_params::GpParams};
+
+const COOKIE: &str = "authcookie=abc&portal=vpn&user=alice&domain=corp&computer=host&preferred-ip=10.0.0.2";
+
+const HIP_REPORT: &str = r#"<hip-report name="hip-report">
+  <categories>
+    <entry name="host-info"><host-name>host</host-name></entry>
+  </categories>
+</hip-report>"#;
+
+const HIP_FAILURE_RESPONSE: &str = r#"<response status="error">
+  <error>Anti-malware definitions are out of date</error>
+  <quarantine>
+    <url>https://remediation.example.com/fix?id=42</url>
+  </quarantine>
+</response>"#;
+
+#[tokio::test]
+async fn hip_failure_carries_remediation_url() -> anyhow::Result<()> {
+  let server_url = start_mock_server().await?;
+
+  let dir = tempfile::tempdir()?;
+  let wrapper = write_wrapper(&dir.path().join("hip.sh"), HIP_REPORT)?;
+
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+  let err = hip_report(&server_url, COOKIE, &wrapper, &gp_params).await.unwrap_err();
+  let err @ PortalError::HipCheckFailed { .. } = err else {
+    panic!("unexpected error: {err}");
+  };
+
+  assert!(err.is_hip_check_failed());
+  assert_eq!(err.remediation_url(), Some("https://remediation.example.com/fix?id=42"));
+  assert_eq!(
+    err.to_string(),
+    "HIP check failed: Anti-malware definitions are out of date"
+  );
+
+  Ok(())
+}
+
+fn write_wrapper(path: &Path, report: &str) -> anyhow::Result<String> {
+  fs::write(path, format!("#!/bin/sh\ncat <<'EOF'\n{}\nEOF\n", report))?;
+  fs::set_permissions(path, Permissions::from_mode(0o755))?;
+
+  Ok(path.display().to_string())
+}
+
+async fn start_mock_server() -> anyhow::Result<String> {
+  let app = Router::new()
+    .route("/ssl-vpn/getconfig.esp", post(handle_getconfig))
+    .route("/ssl-vpn/hipreportcheck.esp", post(handle_hip_check))
+    .route("/ssl-vpn/hipreport.esp", post(handle_hip_report));
+
+  common::start_mock_server(app).await
+}
+
+async fn handle_getconfig() -> impl IntoResponse {
+  "<response><ip-address>10.0.0.2</ip-address></response>"
+}
+
+async fn handle_hip_check() -> impl IntoResponse {
+  "<response><hip-report-needed>yes</hip-report-needed></response>"
+}
+
+async fn handle_hip_report() -> impl IntoResponse {
+  HIP_FAILURE_RESPONSE
+}
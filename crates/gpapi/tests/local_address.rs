@@ -0,0 +1,67 @@
+mod common;
+
+use std::{
+  net::{IpAddr, SocketAddr},
+  sync::{Arc, Mutex},
+};
+
+use axum::{
+  Router,
+  extract::{ConnectInfo, State},
+  response::IntoResponse,
+  routing::post,
+};
+use gpapi::{gp_params::GpParams, portal::prelogin};
+
+const PRELOGIN_STANDARD_XML: &str = include_str!("files/prelogin_standard.xml");
+
+/// The source addresses of the requests
+type Peers = Arc<Mutex<Vec<IpAddr>>>;
+
+#[test]
+fn local_address_is_built() -> anyhow::Result<()> {
+  let local_address: IpAddr = "192.0.2.10".parse()?;
+
+  let gp_params = GpParams::builder().local_address(local_address).build();
+  assert_eq!(gp_params.local_address(), Some(local_address));
+  assert_eq!(gp_params.clone().local_address(), Some(local_address));
+
+  assert_eq!(GpParams::builder().build().local_address(), None);
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn requests_are_sent_from_the_local_address() -> anyhow::Result<()> {
+  let peers = Peers::default();
+  let server_url = start_mock_server(peers.clone()).await?;
+
+  // Any address of 127.0.0.0/8 is local on Linux, the mock server listens on 127.0.0.1
+  let local_address: IpAddr = "127.0.0.2".parse()?;
+  let gp_params = GpParams::builder()
+    .user_agent("gpapi-test/1.0")
+    .local_address(local_address)
+    .build();
+  prelogin(&server_url, &gp_params).await?;
+
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+  prelogin(&server_url, &gp_params).await?;
+
+  let peers = peers.lock().expect("peers lock poisoned");
+  assert_eq!(*peers, [local_address, "127.0.0.1".parse::<IpAddr>()?]);
+
+  Ok(())
+}
+
+async fn start_mock_server(peers: Peers) -> anyhow::Result<String> {
+  let app = Router::new()
+    .route("/global-protect/prelogin.esp", post(handle_prelogin))
+    .with_state(peers);
+
+  common::start_mock_server(app).await
+}
+
+async fn handle_prelogin(State(peers): State<Peers>, ConnectInfo(peer): ConnectInfo<SocketAddr>) -> impl IntoResponse {
+  peers.lock().expect("peers lock poisoned").push(peer.ip());
+  PRELOGIN_STANDARD_XML
+}
@@ -0,0 +1,145 @@
+mod common;
+
+use std::collections::HashMap;
+
+use axum::{
+  Router,
+  extract::{Form, State},
+  http::{HeaderMap, header::HOST},
+  response::IntoResponse,
+  routing::post,
+};
+use gpapi::{
+  credential::{Credential, PasswordCredential},
+  flow::{LoginHandler, login, login_with},
+  gateway::MfaChallenge,
+  gp_params::GpParams,
+  portal::Prelogin,
+};
+
+const PRELOGIN_STANDARD_XML: &str = include_str!("files/prelogin_standard.xml");
+const PRELOGIN_SAML_XML: &str = include_str!("files/prelogin_saml.xml");
+const PORTAL_CONFIG_XML: &str = include_str!("files/portal_config.xml");
+const GATEWAY_LOGIN_XML: &str = include_str!("files/gateway_login.xml");
+
+#[tokio::test]
+async fn portal_login_returns_the_gateway_cookie() -> anyhow::Result<()> {
+  let server_url = start_mock_server(PRELOGIN_STANDARD_XML, false).await?;
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  let result = login(&server_url, &cred, &gp_params).await?;
+  assert_eq!(result.gateway().name(), "vpn_gateway");
+  assert_eq!(result.gateway().server(), server_url);
+  assert!(result.cookie().contains("authcookie=xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx"));
+  assert!(result.portal_config().is_some());
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn gateway_login_skips_the_portal() -> anyhow::Result<()> {
+  let server_url = start_mock_server(PRELOGIN_STANDARD_XML, false).await?;
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+  let mut gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+  gp_params.set_is_gateway(true);
+
+  let result = login(&server_url, &cred, &gp_params).await?;
+  assert_eq!(result.gateway().server(), server_url);
+  assert!(result.cookie().contains("authcookie=xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx"));
+  assert!(result.portal_config().is_none());
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn password_is_rejected_for_saml_prelogin() -> anyhow::Result<()> {
+  let server_url = start_mock_server(PRELOGIN_SAML_XML, false).await?;
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  let err = login(&server_url, &cred, &gp_params).await.unwrap_err();
+  assert!(
+    err.to_string().contains("requires the SAML authentication"),
+    "unexpected error: {err}"
+  );
+
+  Ok(())
+}
+
+struct OtpHandler;
+
+impl LoginHandler for OtpHandler {
+  async fn credential(&self, _server: &str, prelogin: &Prelogin) -> anyhow::Result<Credential> {
+    assert!(matches!(prelogin, Prelogin::Standard(_)));
+    Ok(Credential::from(PasswordCredential::new("alice", "secret")))
+  }
+
+  async fn mfa_response(&self, challenge: &MfaChallenge) -> anyhow::Result<String> {
+    assert_eq!(challenge.message(), "Enter the TOTP code");
+    Ok("123456".to_string())
+  }
+}
+
+#[tokio::test]
+async fn mfa_challenge_is_answered_by_the_handler() -> anyhow::Result<()> {
+  let server_url = start_mock_server(PRELOGIN_STANDARD_XML, true).await?;
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  let result = login_with(&server_url, &gp_params, &OtpHandler).await?;
+  assert!(result.cookie().contains("authcookie=xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx"));
+
+  // Without the handler, the challenge cannot be answered
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+  let err = login(&server_url, &cred, &gp_params).await.unwrap_err();
+  assert!(
+    err.to_string().contains("Enter the TOTP code"),
+    "unexpected error: {err}"
+  );
+
+  Ok(())
+}
+
+#[derive(Clone)]
+struct MockState {
+  prelogin: &'static str,
+  mfa: bool,
+}
+
+async fn start_mock_server(prelogin: &'static str, mfa: bool) -> anyhow::Result<String> {
+  let state = MockState { prelogin, mfa };
+  let app = Router::new()
+    .route("/global-protect/prelogin.esp", post(handle_prelogin))
+    .route("/ssl-vpn/prelogin.esp", post(handle_prelogin))
+    .route("/global-protect/getconfig.esp", post(handle_getconfig))
+    .route("/ssl-vpn/login.esp", post(handle_gateway_login))
+    .with_state(state);
+
+  common::start_mock_server(app).await
+}
+
+async fn handle_prelogin(State(state): State<MockState>) -> impl IntoResponse {
+  state.prelogin
+}
+
+// Point the gateways back to the mock server
+async fn handle_getconfig(headers: HeaderMap) -> impl IntoResponse {
+  let host = headers
+    .get(HOST)
+    .and_then(|host| host.to_str().ok())
+    .unwrap_or_default();
+  PORTAL_CONFIG_XML.replace("xxx.xxx.xxx.xxx", &format!("http://{}", host))
+}
+
+async fn handle_gateway_login(
+  State(state): State<MockState>,
+  Form(params): Form<HashMap<String, String>>,
+) -> impl IntoResponse {
+  let input_str = params.get("inputStr").map(String::as_str).unwrap_or_default();
+  if state.mfa && input_str != "challenge-1" {
+    return "var respStatus = \"Challenge\";\nvar respMsg = \"Enter the TOTP code\";\nthisForm.inputStr.value = \"challenge-1\";\n"
+      .to_string();
+  }
+
+  GATEWAY_LOGIN_XML.to_string()
+}
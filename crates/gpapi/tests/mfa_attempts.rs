@@ -0,0 +1,151 @@
+mod common;
+
+use std::{
+  sync::{
+    Arc,
+    atomic::{AtomicU32, Ordering},
+  },
+  time::{Duration, Instant},
+};
+
+use axum::{
+  Router,
+  extract::State,
+  http::StatusCode,
+  response::{IntoResponse, Response},
+  routing::post,
+};
+use gpapi::{
+  credential::{Credential, PasswordCredential},
+  error::GatewayLoginError,
+  gateway::{GatewayLogin, MfaLimiter, gateway_login},
+  gp_params::GpParams,
+};
+
+const MIN_DELAY: Duration = Duration::from_millis(20);
+
+/// How the mock gateway responds after the given number of wrong responses
+#[derive(Clone, Copy)]
+enum Lockout {
+  Never,
+  ErrorAfter(u32),
+  ChallengeAfter(u32),
+}
+
+#[derive(Clone)]
+struct MockState {
+  requests: Arc<AtomicU32>,
+  lockout: Lockout,
+}
+
+#[tokio::test]
+async fn mfa_attempts_are_capped() -> anyhow::Result<()> {
+  let (server_url, requests) = start_mock_server(Lockout::Never).await?;
+  let mut mfa = MfaLimiter::new(3, MIN_DELAY);
+
+  let started = Instant::now();
+  let err = respond_until_failure(&server_url, &mut mfa).await?;
+  assert!(
+    matches!(err, GatewayLoginError::MfaAttemptsExceeded(3)),
+    "unexpected error: {err}"
+  );
+  assert!(err.is_mfa_exhausted());
+  assert_eq!(mfa.attempts(), 3);
+
+  // The login and the 3 responses, no request after the cap
+  assert_eq!(requests.load(Ordering::SeqCst), 4);
+  // The responses are spaced with the doubled delay
+  assert!(started.elapsed() >= MIN_DELAY * 3, "{:?}", started.elapsed());
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn lockout_error_stops_the_attempts() -> anyhow::Result<()> {
+  let (server_url, requests) = start_mock_server(Lockout::ErrorAfter(2)).await?;
+  let mut mfa = MfaLimiter::new(5, MIN_DELAY);
+
+  let err = respond_until_failure(&server_url, &mut mfa).await?;
+  let GatewayLoginError::LockedOut(message) = &err else {
+    panic!("unexpected error: {err}");
+  };
+  assert_eq!(message, "Account locked out");
+  assert_eq!(mfa.attempts(), 2);
+  assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn lockout_challenge_stops_the_attempts() -> anyhow::Result<()> {
+  let (server_url, _) = start_mock_server(Lockout::ChallengeAfter(1)).await?;
+  let mut mfa = MfaLimiter::new(5, MIN_DELAY);
+
+  let err = respond_until_failure(&server_url, &mut mfa).await?;
+  assert!(
+    matches!(err, GatewayLoginError::LockedOut(_)),
+    "unexpected error: {err}"
+  );
+  assert_eq!(mfa.attempts(), 1);
+
+  Ok(())
+}
+
+/// Keep responding to the challenges with a wrong OTP, as the CLI does until it gives up
+async fn respond_until_failure(server_url: &str, mfa: &mut MfaLimiter) -> anyhow::Result<GatewayLoginError> {
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  let mut login = gateway_login(server_url, &cred, &gp_params).await?;
+  loop {
+    let GatewayLogin::Mfa(challenge) = login else {
+      anyhow::bail!("expected the MFA challenge");
+    };
+
+    if let Err(err) = mfa.check() {
+      return Ok(err);
+    }
+    login = match mfa.submit(server_url, &cred, &challenge, "000000", &gp_params).await {
+      Ok(login) => login,
+      Err(err) => return Ok(err),
+    };
+  }
+}
+
+async fn start_mock_server(lockout: Lockout) -> anyhow::Result<(String, Arc<AtomicU32>)> {
+  let state = MockState {
+    requests: Arc::default(),
+    lockout,
+  };
+  let requests = Arc::clone(&state.requests);
+  let app = Router::new()
+    .route("/ssl-vpn/login.esp", post(handle_gateway_login))
+    .with_state(state);
+
+  Ok((common::start_mock_server(app).await?, requests))
+}
+
+fn challenge(message: &str) -> String {
+  format!(
+    "var respStatus = \"Challenge\";\nvar respMsg = \"{}\";\nthisForm.inputStr.value = \"challenge\";\n",
+    message
+  )
+}
+
+async fn handle_gateway_login(State(state): State<MockState>) -> Response {
+  // The first request is the login, the others are the wrong responses
+  let wrong_responses = state.requests.fetch_add(1, Ordering::SeqCst);
+
+  match state.lockout {
+    Lockout::ErrorAfter(n) if wrong_responses >= n => (
+      StatusCode::FORBIDDEN,
+      [("x-private-pan-globalprotect", "Account locked out")],
+      "",
+    )
+      .into_response(),
+    Lockout::ChallengeAfter(n) if wrong_responses >= n => {
+      challenge("Too many failed attempts, try again later").into_response()
+    }
+    _ => challenge("Enter the TOTP code").into_response(),
+  }
+}
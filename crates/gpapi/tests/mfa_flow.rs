@@ -0,0 +1,96 @@
+mod common;
+
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+};
+
+use axum::{
+  Router,
+  extract::{Form, State},
+  response::IntoResponse,
+  routing::post,
+};
+use gpapi::{
+  credential::{Credential, PasswordCredential},
+  gateway::{GatewayLogin, gateway_login, submit_mfa},
+  gp_params::GpParams,
+};
+
+const GATEWAY_LOGIN_XML: &str = include_str!("files/gateway_login.xml");
+
+type Captured = Arc<Mutex<Vec<(Option<String>, Option<String>)>>>;
+
+#[tokio::test]
+async fn chained_mfa_challenges_are_answered_in_order() -> anyhow::Result<()> {
+  let captured = Captured::default();
+  let server_url = start_mock_server(captured.clone()).await?;
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  let GatewayLogin::Mfa(challenge) = gateway_login(&server_url, &cred, &gp_params).await? else {
+    anyhow::bail!("expected the first MFA challenge");
+  };
+  assert_eq!(challenge.message(), "Enter the TOTP code");
+  assert_eq!(challenge.input_str(), "challenge-1");
+
+  // The chained challenge is returned instead of an error
+  let GatewayLogin::Mfa(challenge) =
+    submit_mfa(&server_url, &cred, challenge.input_str(), "111111", &gp_params).await?
+  else {
+    anyhow::bail!("expected the second MFA challenge");
+  };
+  assert_eq!(challenge.message(), "Enter the SMS code");
+  assert_eq!(challenge.input_str(), "challenge-2");
+
+  let GatewayLogin::Cookie(token) = submit_mfa(&server_url, &cred, challenge.input_str(), "222222", &gp_params).await?
+  else {
+    anyhow::bail!("expected the gateway cookie");
+  };
+  assert_eq!(token.user(), "user");
+
+  let captured = captured.lock().expect("captured lock poisoned");
+  assert_eq!(
+    *captured,
+    [
+      (None, Some("secret".to_string())),
+      (Some("challenge-1".to_string()), Some("111111".to_string())),
+      (Some("challenge-2".to_string()), Some("222222".to_string())),
+    ]
+  );
+
+  Ok(())
+}
+
+async fn start_mock_server(captured: Captured) -> anyhow::Result<String> {
+  let app = Router::new()
+    .route("/ssl-vpn/login.esp", post(handle_gateway_login))
+    .with_state(captured);
+
+  common::start_mock_server(app).await
+}
+
+fn challenge(message: &str, input_str: &str) -> String {
+  format!(
+    "var respStatus = \"Challenge\";\nvar respMsg = \"{}\";\nthisForm.inputStr.value = \"{}\";\n",
+    message, input_str
+  )
+}
+
+async fn handle_gateway_login(
+  State(captured): State<Captured>,
+  Form(params): Form<HashMap<String, String>>,
+) -> impl IntoResponse {
+  let input_str = params.get("inputStr").cloned().filter(|s| !s.is_empty());
+  let passwd = params.get("passwd").cloned();
+  captured
+    .lock()
+    .expect("captured lock poisoned")
+    .push((input_str.clone(), passwd.clone()));
+
+  match (input_str.as_deref(), passwd.as_deref()) {
+    (Some("challenge-1"), Some("111111")) => challenge("Enter the SMS code", "challenge-2"),
+    (Some("challenge-2"), Some("222222")) => GATEWAY_LOGIN_XML.to_string(),
+    _ => challenge("Enter the TOTP code", "challenge-1"),
+  }
+}
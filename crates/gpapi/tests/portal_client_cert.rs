@@ -0,0 +1,43 @@
+mod common;
+
+use axum::{Router, routing::post};
+use gpapi::{
+  credential::{Credential, PasswordCredential},
+  gp_params::GpParams,
+  portal::{client_cert_required_from_xml, retrieve_config},
+};
+
+const PORTAL_CONFIG_CLIENT_CERT_XML: &str = include_str!("files/portal_config_client_cert.xml");
+const PORTAL_CONFIG_XML: &str = include_str!("files/portal_config.xml");
+
+#[tokio::test]
+async fn client_cert_requirement_is_parsed() -> anyhow::Result<()> {
+  assert!(client_cert_required(PORTAL_CONFIG_CLIENT_CERT_XML.to_string()).await?);
+  assert!(client_cert_required_from_xml(PORTAL_CONFIG_CLIENT_CERT_XML)?);
+
+  let not_required = PORTAL_CONFIG_CLIENT_CERT_XML.replace(
+    "<client-cert-required>yes</client-cert-required>",
+    "<client-cert-required>no</client-cert-required>",
+  );
+  assert!(!client_cert_required(not_required).await?);
+
+  assert!(!client_cert_required(PORTAL_CONFIG_XML.to_string()).await?);
+
+  Ok(())
+}
+
+async fn client_cert_required(config: String) -> anyhow::Result<bool> {
+  let server_url = start_mock_server(config).await?;
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+  let portal_config = retrieve_config(&server_url, &cred, &gp_params).await?;
+
+  Ok(portal_config.client_cert_required())
+}
+
+async fn start_mock_server(config: String) -> anyhow::Result<String> {
+  let app = Router::new().route("/global-protect/getconfig.esp", post(move || async move { config }));
+
+  common::start_mock_server(app).await
+}
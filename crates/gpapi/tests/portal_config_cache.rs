@@ -0,0 +1,74 @@
+mod common;
+
+use std::{
+  collections::VecDeque,
+  sync::{Arc, Mutex},
+};
+
+use axum::{Router, extract::State, response::IntoResponse, routing::post};
+use gpapi::{
+  credential::{Credential, PasswordCredential},
+  gp_params::GpParams,
+  portal::{PortalConfigCache, retrieve_config_cached},
+};
+
+type Responses = Arc<Mutex<VecDeque<String>>>;
+
+fn portal_config(config_version: &str, gateway: &str, auth_cookie: &str) -> String {
+  format!(
+    r#"<policy>
+  <portal-config-version>{config_version}</portal-config-version>
+  <portal-userauthcookie>{auth_cookie}</portal-userauthcookie>
+  <gateways><external><list>
+    <entry name="{gateway}.example.com"><description>{gateway}</description></entry>
+  </list></external></gateways>
+</policy>"#
+  )
+}
+
+#[tokio::test]
+async fn unchanged_config_version_reuses_cached_config() -> anyhow::Result<()> {
+  let responses = Responses::default();
+  responses.lock().expect("responses lock poisoned").extend([
+    portal_config("7", "gw1", "cookie-1"),
+    // The gateways are not re-parsed while the version is unchanged
+    portal_config("7", "gw2", "cookie-2"),
+    portal_config("8", "gw3", "cookie-3"),
+  ]);
+  let server_url = start_mock_server(responses).await?;
+
+  let cache = PortalConfigCache::default();
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+
+  let config = retrieve_config_cached(&server_url, &cred, &gp_params, &cache).await?;
+  assert_eq!(config.config_version(), Some("7"));
+  assert_eq!(config.gateways()[0].name(), "gw1");
+
+  let config = retrieve_config_cached(&server_url, &cred, &gp_params, &cache).await?;
+  assert_eq!(config.gateways()[0].name(), "gw1");
+  // The auth cookie is always refreshed
+  assert_eq!(config.auth_cookie().user_auth_cookie(), "cookie-2");
+
+  let config = retrieve_config_cached(&server_url, &cred, &gp_params, &cache).await?;
+  assert_eq!(config.config_version(), Some("8"));
+  assert_eq!(config.gateways()[0].name(), "gw3");
+
+  Ok(())
+}
+
+async fn start_mock_server(responses: Responses) -> anyhow::Result<String> {
+  let app = Router::new()
+    .route("/global-protect/getconfig.esp", post(handle_getconfig))
+    .with_state(responses);
+
+  common::start_mock_server(app).await
+}
+
+async fn handle_getconfig(State(responses): State<Responses>) -> impl IntoResponse {
+  responses
+    .lock()
+    .expect("responses lock poisoned")
+    .pop_front()
+    .unwrap_or_default()
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use std::{
+  net::IpAddr,
+  sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+  },
+};
+
+use axum::{Router, routing::post};
+use gpapi::{
+  credential::{Credential, PasswordCredential},
+  gp_params::GpParams,
+  portal::{ConfigChange, PortalConfig, retrieve_config},
+};
+
+const PORTAL_CONFIG_XML: &str = include_str!("files/portal_config.xml");
+const PORTAL_CONFIG_SPLIT_DNS_XML: &str = include_str!("files/portal_config_split_dns.xml");
+
+#[tokio::test]
+async fn drift_between_config_versions_is_detected() -> anyhow::Result<()> {
+  let (applied, refreshed) = retrieve_twice(PORTAL_CONFIG_XML, PORTAL_CONFIG_SPLIT_DNS_XML.to_string()).await?;
+
+  assert_eq!(
+    applied.drift(&refreshed),
+    [
+      ConfigChange::SearchDomainAdded("corp.example.com".into()),
+      ConfigChange::SearchDomainAdded("example.com".into()),
+      ConfigChange::SplitDnsDomainAdded("internal.example.com".into()),
+      ConfigChange::SplitDnsDomainAdded("lab.example.net".into()),
+      ConfigChange::GatewayAdded("gw1.example.com".into()),
+      ConfigChange::GatewayRemoved("xxx.xxx.xxx.xxx".into()),
+      ConfigChange::GatewayRemoved("gw2.example.com".into()),
+    ]
+  );
+
+  // The other way round
+  let drift = refreshed.drift(&applied);
+  assert!(drift.contains(&ConfigChange::SearchDomainRemoved("corp.example.com".into())));
+  assert!(drift.contains(&ConfigChange::SplitDnsDomainRemoved("lab.example.net".into())));
+  assert!(drift.contains(&ConfigChange::GatewayAdded("gw2.example.com".into())));
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn mtu_change_is_detected() -> anyhow::Result<()> {
+  let with_mtu = PORTAL_CONFIG_SPLIT_DNS_XML.replace("</policy>", "<mtu>1300</mtu></policy>");
+  let (applied, refreshed) = retrieve_twice(PORTAL_CONFIG_SPLIT_DNS_XML, with_mtu).await?;
+
+  let drift = applied.drift(&refreshed);
+  assert_eq!(
+    drift,
+    [ConfigChange::SuggestedMtu {
+      old: None,
+      new: Some(1300)
+    }]
+  );
+  assert_eq!(drift[0].to_string(), "suggested MTU changed: <none> -> 1300");
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn dns_drift_from_the_live_tunnel_is_detected() -> anyhow::Result<()> {
+  let with_dns = PORTAL_CONFIG_SPLIT_DNS_XML.replace("</policy>", "<dns><member>10.0.0.53</member></dns></policy>");
+  let (applied, refreshed) = retrieve_twice(PORTAL_CONFIG_SPLIT_DNS_XML, with_dns).await?;
+  assert_eq!(
+    applied.drift(&refreshed),
+    [ConfigChange::DnsServerAdded("10.0.0.53".into())]
+  );
+
+  // The live tunnel uses the gateway DNS server and one of the search domains only
+  let dns_servers = ["10.0.0.1".parse::<IpAddr>()?];
+  let search_domains = ["Corp.Example.com".to_string()];
+  let drift = refreshed.live_drift(&dns_servers, &search_domains);
+  assert_eq!(
+    drift,
+    [
+      ConfigChange::DnsServerNotLive("10.0.0.53".into()),
+      ConfigChange::SearchDomainNotLive("example.com".into()),
+    ]
+  );
+  assert_eq!(
+    drift[0].to_string(),
+    "DNS server not used by the live tunnel: 10.0.0.53"
+  );
+
+  let dns_servers = ["10.0.0.53".parse::<IpAddr>()?];
+  let search_domains = ["corp.example.com".to_string(), "example.com".to_string()];
+  assert!(refreshed.live_drift(&dns_servers, &search_domains).is_empty());
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn unchanged_config_has_no_drift() -> anyhow::Result<()> {
+  let (applied, refreshed) = retrieve_twice(PORTAL_CONFIG_XML, PORTAL_CONFIG_XML.to_string()).await?;
+  assert!(applied.drift(&refreshed).is_empty());
+
+  Ok(())
+}
+
+/// Retrieve the config twice from the portal, which serves the first config and then the second one
+async fn retrieve_twice(first: &'static str, second: String) -> anyhow::Result<(PortalConfig, PortalConfig)> {
+  let server_url = start_mock_server(first, second).await?;
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+
+  let applied = retrieve_config(&server_url, &cred, &gp_params).await?;
+  let refreshed = retrieve_config(&server_url, &cred, &gp_params).await?;
+
+  Ok((applied, refreshed))
+}
+
+async fn start_mock_server(first: &'static str, second: String) -> anyhow::Result<String> {
+  let requests = Arc::new(AtomicUsize::new(0));
+  let handler = move || {
+    let config = if requests.fetch_add(1, Ordering::SeqCst) == 0 {
+      first.to_string()
+    } else {
+      second.clone()
+    };
+    async move { config }
+  };
+  let app = Router::new().route("/global-protect/getconfig.esp", post(handler));
+
+  common::start_mock_server(app).await
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use axum::{Router, response::IntoResponse, routing::post};
+use common::start_mock_server;
+use gpapi::{
+  error::PortalError,
+  gp_params::GpParams,
+  portal::{discover_from_candidates, discover_portal},
+};
+
+const PRELOGIN_STANDARD_XML: &str = include_str!("files/prelogin_standard.xml");
+
+#[tokio::test]
+async fn discovery_picks_the_candidate_that_responds() -> anyhow::Result<()> {
+  // Nothing listens on this candidate
+  let closed = std::net::TcpListener::bind("127.0.0.1:0")?;
+  let closed_url = format!("http://{}", closed.local_addr()?);
+  drop(closed);
+
+  // This candidate is up but is not a portal
+  let not_portal_url = start_mock_server(Router::new()).await?;
+  let portal_url =
+    start_mock_server(Router::new().route("/global-protect/prelogin.esp", post(handle_prelogin))).await?;
+
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+  let candidates = vec![closed_url, not_portal_url, portal_url.clone()];
+
+  let discovered = discover_from_candidates(&candidates, &gp_params).await;
+  assert_eq!(discovered, Some(portal_url));
+
+  let discovered = discover_from_candidates(&candidates[..2], &gp_params).await;
+  assert_eq!(discovered, None);
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn discovery_failure_lists_the_tried_candidates() -> anyhow::Result<()> {
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  // The reserved `.invalid` TLD never resolves
+  let err = discover_portal("example.invalid.", &gp_params).await.unwrap_err();
+  let PortalError::DiscoveryFailed { domain, candidates } = err else {
+    panic!("unexpected error: {err}");
+  };
+  assert_eq!(domain, "example.invalid");
+  assert_eq!(candidates[0], "vpn.example.invalid");
+  assert_eq!(candidates.len(), 4);
+
+  Ok(())
+}
+
+async fn handle_prelogin() -> impl IntoResponse {
+  PRELOGIN_STANDARD_XML
+}
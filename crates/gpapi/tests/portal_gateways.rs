@@ -0,0 +1,40 @@
+mod common;
+
+use axum::{Router, routing::post};
+use gpapi::{
+  credential::{AuthCookieCredential, Credential},
+  gp_params::GpParams,
+  portal::retrieve_config,
+};
+
+const PORTAL_CONFIG_XML: &str = include_str!("files/portal_config.xml");
+
+#[tokio::test]
+async fn gateways_expose_address_priority_and_manual_flag() -> anyhow::Result<()> {
+  let server_url = start_mock_server().await?;
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  let cred = Credential::AuthCookie(AuthCookieCredential::new("alice", "cookie", "prelogon-cookie"));
+  let portal_config = retrieve_config(&server_url, &cred, &gp_params).await?;
+
+  let gateways: Vec<_> = portal_config
+    .gateways()
+    .into_iter()
+    .map(|gateway| (gateway.name(), gateway.address(), gateway.priority(), gateway.manual()))
+    .collect();
+  assert_eq!(
+    gateways,
+    [
+      ("vpn_gateway", "xxx.xxx.xxx.xxx", 1, false),
+      ("manual_gateway", "gw2.example.com", 2, true),
+    ]
+  );
+
+  Ok(())
+}
+
+async fn start_mock_server() -> anyhow::Result<String> {
+  let app = Router::new().route("/global-protect/getconfig.esp", post(|| async { PORTAL_CONFIG_XML }));
+
+  common::start_mock_server(app).await
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use axum::{Router, routing::post};
+use gpapi::{
+  credential::{Credential, PasswordCredential},
+  gp_params::GpParams,
+  portal::retrieve_config,
+};
+
+const PORTAL_CONFIG_IPSEC_XML: &str = include_str!("files/portal_config_ipsec.xml");
+const PORTAL_CONFIG_XML: &str = include_str!("files/portal_config.xml");
+
+#[tokio::test]
+async fn ipsec_capability_is_parsed() -> anyhow::Result<()> {
+  assert_eq!(ipsec_supported(PORTAL_CONFIG_IPSEC_XML.to_string()).await?, Some(true));
+
+  let disabled = PORTAL_CONFIG_IPSEC_XML.replace("<ipsec>yes</ipsec>", "<ipsec>no</ipsec>");
+  assert_eq!(ipsec_supported(disabled).await?, Some(false));
+
+  // DTLS and ESP are both disabled by `no-dtls`
+  let no_dtls = PORTAL_CONFIG_IPSEC_XML.replace("</policy>", "<no-dtls>yes</no-dtls></policy>");
+  assert_eq!(ipsec_supported(no_dtls).await?, Some(false));
+
+  assert_eq!(ipsec_supported(PORTAL_CONFIG_XML.to_string()).await?, None);
+
+  Ok(())
+}
+
+async fn ipsec_supported(config: String) -> anyhow::Result<Option<bool>> {
+  let server_url = start_mock_server(config).await?;
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+  let portal_config = retrieve_config(&server_url, &cred, &gp_params).await?;
+
+  Ok(portal_config.ipsec_supported())
+}
+
+async fn start_mock_server(config: String) -> anyhow::Result<String> {
+  let app = Router::new().route("/global-protect/getconfig.esp", post(move || async move { config }));
+
+  common::start_mock_server(app).await
+}
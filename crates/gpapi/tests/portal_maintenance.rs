@@ -0,0 +1,50 @@
+mod common;
+
+use axum::{Router, http::StatusCode, response::Html, routing::post};
+use gpapi::{
+  credential::{Credential, PasswordCredential},
+  error::PortalError,
+  gp_params::GpParams,
+  portal::{prelogin, retrieve_config},
+};
+
+const MAINTENANCE_HTML: &str = include_str!("files/portal_maintenance.html");
+const MAINTENANCE_MESSAGE: &str = "The VPN portal is undergoing scheduled maintenance until 02:00 UTC.";
+
+#[tokio::test]
+async fn maintenance_page_is_reported_as_maintenance() -> anyhow::Result<()> {
+  let server_url = start_mock_server().await?;
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+
+  // Returned with 200 instead of the XML response
+  let err = prelogin(&server_url, &gp_params).await.unwrap_err();
+  assert_maintenance(&err);
+
+  // Returned with 503
+  let err = retrieve_config(&server_url, &cred, &gp_params).await.unwrap_err();
+  assert_maintenance(&err);
+
+  Ok(())
+}
+
+fn assert_maintenance(err: &PortalError) {
+  let PortalError::Maintenance(message) = err else {
+    panic!("unexpected error: {err}");
+  };
+  assert_eq!(message, MAINTENANCE_MESSAGE);
+}
+
+async fn start_mock_server() -> anyhow::Result<String> {
+  let app = Router::new()
+    .route(
+      "/global-protect/prelogin.esp",
+      post(|| async { Html(MAINTENANCE_HTML) }),
+    )
+    .route(
+      "/global-protect/getconfig.esp",
+      post(|| async { (StatusCode::SERVICE_UNAVAILABLE, Html(MAINTENANCE_HTML)) }),
+    );
+
+  common::start_mock_server(app).await
+}
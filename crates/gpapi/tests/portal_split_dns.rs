@@ -0,0 +1,49 @@
+mod common;
+
+use axum::{Router, routing::post};
+use gpapi::{
+  credential::{Credential, PasswordCredential},
+  gp_params::GpParams,
+  portal::{DnsConfig, dns_config_from_xml, retrieve_config},
+};
+
+const PORTAL_CONFIG_SPLIT_DNS_XML: &str = include_str!("files/portal_config_split_dns.xml");
+const PORTAL_CONFIG_XML: &str = include_str!("files/portal_config.xml");
+
+#[tokio::test]
+async fn split_dns_is_parsed() -> anyhow::Result<()> {
+  let dns_config = dns_config(PORTAL_CONFIG_SPLIT_DNS_XML.to_string()).await?;
+  assert_eq!(dns_config.search_domains(), ["corp.example.com", "example.com"]);
+  assert_eq!(
+    dns_config.split_dns_domains(),
+    ["internal.example.com", "lab.example.net"]
+  );
+
+  // The same settings are read from the saved config
+  assert_eq!(dns_config_from_xml(PORTAL_CONFIG_SPLIT_DNS_XML)?, dns_config);
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn missing_split_dns_is_empty() -> anyhow::Result<()> {
+  assert!(dns_config(PORTAL_CONFIG_XML.to_string()).await?.is_empty());
+
+  Ok(())
+}
+
+async fn dns_config(config: String) -> anyhow::Result<DnsConfig> {
+  let server_url = start_mock_server(config).await?;
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+  let portal_config = retrieve_config(&server_url, &cred, &gp_params).await?;
+
+  Ok(portal_config.dns_config().clone())
+}
+
+async fn start_mock_server(config: String) -> anyhow::Result<String> {
+  let app = Router::new().route("/global-protect/getconfig.esp", post(move || async move { config }));
+
+  common::start_mock_server(app).await
+}
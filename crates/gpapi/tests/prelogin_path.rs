@@ -0,0 +1,84 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use axum::{
+  Router,
+  extract::State,
+  http::{StatusCode, Uri},
+  routing::post,
+};
+use gpapi::{gp_params::GpParams, portal::prelogin};
+
+const PRELOGIN_STANDARD_XML: &str = include_str!("files/prelogin_standard.xml");
+
+type Requests = Arc<Mutex<Vec<String>>>;
+
+#[tokio::test]
+async fn portal_prelogin_falls_back_to_the_gateway_path() -> anyhow::Result<()> {
+  let (server_url, requests) = start_mock_server("/ssl-vpn/prelogin.esp").await?;
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  let prelogin = prelogin(&server_url, &gp_params).await?;
+  assert_eq!(prelogin.region(), "US");
+  // Still a portal prelogin, only the path differs
+  assert!(!prelogin.is_gateway());
+  assert_eq!(
+    *requests.lock().unwrap(),
+    ["/global-protect/prelogin.esp", "/ssl-vpn/prelogin.esp"]
+  );
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn gateway_prelogin_does_not_fall_back() -> anyhow::Result<()> {
+  let (server_url, requests) = start_mock_server("/global-protect/prelogin.esp").await?;
+  let mut gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+  gp_params.set_is_gateway(true);
+
+  assert!(prelogin(&server_url, &gp_params).await.is_err());
+  assert_eq!(*requests.lock().unwrap(), ["/ssl-vpn/prelogin.esp"]);
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn prelogin_path_overrides_the_default_paths() -> anyhow::Result<()> {
+  let (server_url, requests) = start_mock_server("/vhost/global-protect/prelogin.esp").await?;
+  let gp_params = GpParams::builder()
+    .user_agent("gpapi-test/1.0")
+    .prelogin_path("/vhost/global-protect/prelogin.esp".to_string())
+    .build();
+
+  let prelogin = prelogin(&server_url, &gp_params).await?;
+  assert_eq!(prelogin.region(), "US");
+  assert_eq!(*requests.lock().unwrap(), ["/vhost/global-protect/prelogin.esp"]);
+
+  // The overridden path is the only one tried
+  let (server_url, requests) = start_mock_server("/ssl-vpn/prelogin.esp").await?;
+  assert!(prelogin(&server_url, &gp_params).await.is_err());
+  assert_eq!(*requests.lock().unwrap(), ["/vhost/global-protect/prelogin.esp"]);
+
+  Ok(())
+}
+
+async fn start_mock_server(prelogin_path: &str) -> anyhow::Result<(String, Requests)> {
+  let requests = Requests::default();
+  let app = Router::new()
+    .route(prelogin_path, post(handle_prelogin))
+    .fallback(handle_not_found)
+    .with_state(Arc::clone(&requests));
+
+  Ok((common::start_mock_server(app).await?, requests))
+}
+
+async fn handle_prelogin(State(requests): State<Requests>, uri: Uri) -> &'static str {
+  requests.lock().unwrap().push(uri.path().to_string());
+  PRELOGIN_STANDARD_XML
+}
+
+async fn handle_not_found(State(requests): State<Requests>, uri: Uri) -> StatusCode {
+  requests.lock().unwrap().push(uri.path().to_string());
+  StatusCode::NOT_FOUND
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use axum::{Router, http::StatusCode, routing::post};
+use common::start_mock_server;
+use gpapi::{gp_params::GpParams, portal::prelogin_race};
+
+const PRELOGIN_STANDARD_XML: &str = include_str!("files/prelogin_standard.xml");
+const SLOW_DELAY: Duration = Duration::from_secs(5);
+
+#[tokio::test]
+async fn fastest_portal_wins() -> anyhow::Result<()> {
+  let slow = start_mock_server(slow_app()).await?;
+  let fast = start_mock_server(ok_app()).await?;
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  let started = Instant::now();
+  let (portal, prelogin) = prelogin_race(&[slow, fast.clone()], &gp_params).await?;
+  assert_eq!(portal, fast);
+  assert_eq!(prelogin.region(), "US");
+  // The slow prelogin is cancelled instead of awaited
+  assert!(started.elapsed() < SLOW_DELAY, "{:?}", started.elapsed());
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn failed_portal_is_skipped() -> anyhow::Result<()> {
+  let failing = start_mock_server(Router::new().fallback(|| async { StatusCode::NOT_FOUND })).await?;
+  let ok = start_mock_server(ok_app()).await?;
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  let (portal, _) = prelogin_race(&[failing.clone(), ok.clone()], &gp_params).await?;
+  assert_eq!(portal, ok);
+
+  // The error of the first portal is reported when all of them fail
+  let failing_too = start_mock_server(Router::new().fallback(|| async { StatusCode::FORBIDDEN })).await?;
+  let err = prelogin_race(&[failing, failing_too], &gp_params).await.unwrap_err();
+  assert!(err.to_string().contains("not found"), "unexpected error: {err}");
+
+  assert!(prelogin_race(&[], &gp_params).await.is_err());
+
+  Ok(())
+}
+
+fn ok_app() -> Router {
+  Router::new().route("/global-protect/prelogin.esp", post(|| async { PRELOGIN_STANDARD_XML }))
+}
+
+fn slow_app() -> Router {
+  Router::new().route(
+    "/global-protect/prelogin.esp",
+    post(|| async {
+      tokio::time::sleep(SLOW_DELAY).await;
+      PRELOGIN_STANDARD_XML
+    }),
+  )
+}
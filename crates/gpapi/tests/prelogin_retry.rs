@@ -0,0 +1,87 @@
+mod common;
+
+use std::{
+  sync::{
+    Arc,
+    atomic::{AtomicU32, Ordering},
+  },
+  time::Duration,
+};
+
+use axum::{Router, extract::State, http::StatusCode, response::IntoResponse, routing::post};
+use gpapi::{
+  gp_params::{GpParams, RetryPolicy},
+  portal::prelogin,
+};
+
+const PRELOGIN_STANDARD_XML: &str = include_str!("files/prelogin_standard.xml");
+
+#[derive(Clone)]
+struct MockState {
+  // The status returned before the prelogin succeeds
+  failure: StatusCode,
+  failures: u32,
+  attempts: Arc<AtomicU32>,
+}
+
+#[tokio::test]
+async fn prelogin_retries_server_errors() -> anyhow::Result<()> {
+  let (server_url, attempts) = start_mock_server(StatusCode::BAD_GATEWAY, 2).await?;
+  let gp_params = GpParams::builder()
+    .user_agent("gpapi-test/1.0")
+    .retry_policy(RetryPolicy::new(3, Duration::from_millis(10)))
+    .build();
+
+  prelogin(&server_url, &gp_params).await?;
+  assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn prelogin_does_not_retry_client_errors() -> anyhow::Result<()> {
+  let (server_url, attempts) = start_mock_server(StatusCode::UNAUTHORIZED, 2).await?;
+  let gp_params = GpParams::builder()
+    .user_agent("gpapi-test/1.0")
+    .retry_policy(RetryPolicy::new(3, Duration::from_millis(10)))
+    .build();
+
+  assert!(prelogin(&server_url, &gp_params).await.is_err());
+  assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn prelogin_is_attempted_once_by_default() -> anyhow::Result<()> {
+  let (server_url, attempts) = start_mock_server(StatusCode::SERVICE_UNAVAILABLE, 1).await?;
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  assert!(prelogin(&server_url, &gp_params).await.is_err());
+  assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+  Ok(())
+}
+
+async fn start_mock_server(failure: StatusCode, failures: u32) -> anyhow::Result<(String, Arc<AtomicU32>)> {
+  let attempts = Arc::new(AtomicU32::new(0));
+  let state = MockState {
+    failure,
+    failures,
+    attempts: Arc::clone(&attempts),
+  };
+  let app = Router::new()
+    .route("/global-protect/prelogin.esp", post(handle_prelogin))
+    .with_state(state);
+
+  Ok((common::start_mock_server(app).await?, attempts))
+}
+
+async fn handle_prelogin(State(state): State<MockState>) -> impl IntoResponse {
+  let attempt = state.attempts.fetch_add(1, Ordering::SeqCst);
+  if attempt < state.failures {
+    return (state.failure, "temporarily failed").into_response();
+  }
+
+  PRELOGIN_STANDARD_XML.into_response()
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use axum::{Router, extract::State, http::HeaderMap, response::IntoResponse, routing::post};
+use gpapi::{gp_params::GpParams, portal::prelogin};
+
+const PRELOGIN_STANDARD_XML: &str = include_str!("files/prelogin_standard.xml");
+
+type Captured = Arc<Mutex<Vec<Option<String>>>>;
+
+#[tokio::test]
+async fn requests_are_sent_through_the_proxy() -> anyhow::Result<()> {
+  let captured = Captured::default();
+  let proxy_url = start_mock_proxy(captured.clone()).await?;
+
+  let gp_params = GpParams::builder()
+    .user_agent("gpapi-test/1.0")
+    .proxy(Some(proxy_url.clone()))
+    .build();
+  assert_eq!(gp_params.proxy(), Some(proxy_url.as_str()));
+
+  // The portal is not resolvable, it is reachable only through the proxy
+  prelogin("http://portal.example.invalid", &gp_params).await?;
+
+  let captured = captured.lock().expect("captured lock poisoned");
+  assert_eq!(*captured, [Some("portal.example.invalid".to_string())]);
+
+  Ok(())
+}
+
+/// A plain HTTP proxy receives the requests in the absolute form, which are routed by the path
+async fn start_mock_proxy(captured: Captured) -> anyhow::Result<String> {
+  let app = Router::new()
+    .route("/global-protect/prelogin.esp", post(handle_prelogin))
+    .with_state(captured);
+
+  common::start_mock_server(app).await
+}
+
+async fn handle_prelogin(State(captured): State<Captured>, headers: HeaderMap) -> impl IntoResponse {
+  let host = headers.get("host").and_then(|v| v.to_str().ok()).map(|v| v.to_string());
+  captured.lock().expect("captured lock poisoned").push(host);
+
+  PRELOGIN_STANDARD_XML
+}
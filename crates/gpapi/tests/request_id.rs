@@ -0,0 +1,115 @@
+mod common;
+
+use std::sync::{Mutex, Once};
+
+use axum::{Router, routing::post};
+use gpapi::{
+  credential::{Credential, PasswordCredential},
+  gateway::{GatewayLogin, gateway_login},
+  gp_params::GpParams,
+  portal::{prelogin, retrieve_config},
+};
+use log::{LevelFilter, Log, Metadata, Record};
+
+const PRELOGIN_STANDARD_XML: &str = include_str!("files/prelogin_standard.xml");
+const PORTAL_CONFIG_XML: &str = include_str!("files/portal_config.xml");
+const GATEWAY_LOGIN_XML: &str = include_str!("files/gateway_login.xml");
+
+/// Capture the log lines of all tests, each test picks its own by the request ID
+static CAPTURED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+struct CaptureLogger;
+
+impl Log for CaptureLogger {
+  fn enabled(&self, _metadata: &Metadata) -> bool {
+    true
+  }
+
+  fn log(&self, record: &Record) {
+    CAPTURED
+      .lock()
+      .expect("captured lock poisoned")
+      .push(record.args().to_string());
+  }
+
+  fn flush(&self) {}
+}
+
+fn init_logger() {
+  static INIT: Once = Once::new();
+  INIT.call_once(|| {
+    log::set_logger(&CaptureLogger).expect("logger already set");
+    log::set_max_level(LevelFilter::Debug);
+  });
+}
+
+fn captured_lines(request_id: &str) -> Vec<String> {
+  let prefix = format!("[{}] ", request_id);
+  CAPTURED
+    .lock()
+    .expect("captured lock poisoned")
+    .iter()
+    .filter(|line| line.starts_with(&prefix))
+    .cloned()
+    .collect()
+}
+
+#[tokio::test]
+async fn request_id_is_logged_across_the_flow() -> anyhow::Result<()> {
+  init_logger();
+  let server_url = start_mock_server().await?;
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+
+  let mut gp_params = GpParams::builder()
+    .user_agent("gpapi-test/1.0")
+    .request_id("flow-1234")
+    .build();
+  assert_eq!(gp_params.request_id().as_str(), "flow-1234");
+
+  prelogin(&server_url, &gp_params).await?;
+  retrieve_config(&server_url, &cred, &gp_params).await?;
+  gp_params.set_is_gateway(true);
+  let GatewayLogin::Cookie(_) = gateway_login(&server_url, &cred, &gp_params).await? else {
+    anyhow::bail!("expected gateway cookie, got MFA challenge");
+  };
+
+  let lines = captured_lines("flow-1234");
+  for message in [
+    "[flow-1234] Portal prelogin with user_agent: gpapi-test/1.0",
+    "[flow-1234] Retrieve the portal config, user_agent: gpapi-test/1.0",
+    "[flow-1234] Perform gateway login, user_agent: gpapi-test/1.0",
+  ] {
+    assert!(lines.iter().any(|line| line == message), "{message} not in {lines:#?}");
+  }
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn each_flow_has_its_own_request_id() -> anyhow::Result<()> {
+  init_logger();
+  let server_url = start_mock_server().await?;
+
+  let builder = GpParams::builder();
+  let (first, second) = (builder.build(), builder.build());
+  assert_ne!(first.request_id(), second.request_id());
+  assert_eq!(first.request_id().as_str().len(), 36);
+
+  // The cloned params keep the ID of the flow
+  assert_eq!(first.clone().request_id(), first.request_id());
+
+  prelogin(&server_url, &first).await?;
+  assert!(!captured_lines(first.request_id().as_str()).is_empty());
+  assert!(captured_lines(second.request_id().as_str()).is_empty());
+
+  Ok(())
+}
+
+async fn start_mock_server() -> anyhow::Result<String> {
+  let app = Router::new()
+    .route("/global-protect/prelogin.esp", post(|| async { PRELOGIN_STANDARD_XML }))
+    .route("/global-protect/getconfig.esp", post(|| async { PORTAL_CONFIG_XML }))
+    .route("/ssl-vpn/login.esp", post(|| async { GATEWAY_LOGIN_XML }));
+
+  common::start_mock_server(app).await
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use std::{
+  convert::Infallible,
+  time::{Duration, Instant},
+};
+
+use axum::{Router, body::Body, routing::post};
+use common::start_mock_server;
+use futures_util::stream;
+use gpapi::{
+  error::PortalError,
+  gp_params::{DEFAULT_MAX_RESPONSE_SIZE, DEFAULT_REQUEST_TIMEOUT, GpParams},
+  portal::prelogin,
+};
+
+const PRELOGIN_STANDARD_XML: &str = include_str!("files/prelogin_standard.xml");
+const PRELOGIN_PATH: &str = "/global-protect/prelogin.esp";
+
+#[test]
+fn response_limits_have_defaults() {
+  let gp_params = GpParams::builder().build();
+  assert_eq!(gp_params.max_response_size(), DEFAULT_MAX_RESPONSE_SIZE);
+  assert_eq!(gp_params.request_timeout(), DEFAULT_REQUEST_TIMEOUT);
+
+  let gp_params = GpParams::builder()
+    .max_response_size(1024)
+    .request_timeout(Duration::from_secs(5))
+    .build();
+  assert_eq!(gp_params.max_response_size(), 1024);
+  assert_eq!(gp_params.request_timeout(), Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn oversized_response_is_rejected() -> anyhow::Result<()> {
+  let gp_params = GpParams::builder()
+    .user_agent("gpapi-test/1.0")
+    .max_response_size(64 * 1024)
+    .build();
+
+  // With the content length
+  let server_url = start_mock_server(Router::new().route(PRELOGIN_PATH, post(oversized))).await?;
+  let err = prelogin(&server_url, &gp_params).await.unwrap_err();
+  assert!(
+    matches!(err, PortalError::ResponseTooLarge(65536)),
+    "unexpected error: {err}"
+  );
+
+  // Streamed without the content length
+  let server_url = start_mock_server(Router::new().route(PRELOGIN_PATH, post(oversized_stream))).await?;
+  let err = prelogin(&server_url, &gp_params).await.unwrap_err();
+  assert!(
+    matches!(err, PortalError::ResponseTooLarge(65536)),
+    "unexpected error: {err}"
+  );
+
+  // The response within the limit is accepted
+  let app = Router::new().route(PRELOGIN_PATH, post(|| async { PRELOGIN_STANDARD_XML }));
+  prelogin(&start_mock_server(app).await?, &gp_params).await?;
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn stalled_response_times_out() -> anyhow::Result<()> {
+  let server_url = start_mock_server(Router::new().route(PRELOGIN_PATH, post(stalled))).await?;
+  let gp_params = GpParams::builder()
+    .user_agent("gpapi-test/1.0")
+    .request_timeout(Duration::from_millis(300))
+    .build();
+
+  let started = Instant::now();
+  let err = prelogin(&server_url, &gp_params).await.unwrap_err();
+  assert!(err.is_timeout(), "unexpected error: {err}");
+  assert!(started.elapsed() < Duration::from_secs(5));
+
+  Ok(())
+}
+
+async fn oversized() -> String {
+  "x".repeat(128 * 1024)
+}
+
+async fn oversized_stream() -> Body {
+  let chunks = (0..32).map(|_| Ok::<_, Infallible>("x".repeat(4 * 1024)));
+  Body::from_stream(stream::iter(chunks))
+}
+
+/// Send the beginning of the response, then never finish it
+async fn stalled() -> Body {
+  let start = stream::iter([Ok::<_, Infallible>("<prelogin-response>")]);
+  Body::from_stream(futures_util::StreamExt::chain(start, stream::pending()))
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use std::{collections::HashMap, time::Duration};
+
+use axum::{Router, extract::Form, response::IntoResponse, routing::post};
+use gpapi::{
+  credential::{Credential, PasswordCredential},
+  error::PortalError,
+  gp_params::GpParams,
+  portal::{prelogin, retrieve_config},
+  utils::retry_budget::RetryBudget,
+};
+
+const PRELOGIN_STANDARD_XML: &str = include_str!("files/prelogin_standard.xml");
+const PORTAL_CONFIG_XML: &str = include_str!("files/portal_config.xml");
+
+const STEP_DELAY: Duration = Duration::from_millis(600);
+
+#[tokio::test]
+async fn retry_budget_is_consumed_across_steps() -> anyhow::Result<()> {
+  let server_url = start_mock_server().await?;
+  let budget = RetryBudget::new(Duration::from_secs(1));
+  let gp_params = GpParams::builder()
+    .user_agent("gpapi-test/1.0")
+    .retry_budget(Some(budget.clone()))
+    .build();
+
+  // The budget starts with the first request, e.g., not while prompting for the password
+  tokio::time::sleep(STEP_DELAY).await;
+
+  // The first step fits in the budget
+  prelogin(&server_url, &gp_params).await?;
+  let remaining = budget.remaining()?;
+  assert!(remaining < Duration::from_secs(1) - STEP_DELAY + Duration::from_millis(100));
+
+  // The second step is cut off by the remaining budget
+  let cred = Credential::from(PasswordCredential::new("alice", "secret"));
+  let err = retrieve_config(&server_url, &cred, &gp_params).await.unwrap_err();
+  assert!(matches!(err, PortalError::NetworkError(_)), "unexpected error: {err}");
+
+  // Any further step aborts immediately
+  let err = prelogin(&server_url, &gp_params).await.unwrap_err();
+  assert!(err.is_retry_budget_exhausted(), "unexpected error: {err}");
+
+  Ok(())
+}
+
+async fn start_mock_server() -> anyhow::Result<String> {
+  let app = Router::new()
+    .route("/global-protect/prelogin.esp", post(handle_prelogin))
+    .route("/global-protect/getconfig.esp", post(handle_getconfig));
+
+  common::start_mock_server(app).await
+}
+
+async fn handle_prelogin(Form(_params): Form<HashMap<String, String>>) -> impl IntoResponse {
+  tokio::time::sleep(STEP_DELAY).await;
+  PRELOGIN_STANDARD_XML
+}
+
+async fn handle_getconfig(Form(_params): Form<HashMap<String, String>>) -> impl IntoResponse {
+  tokio::time::sleep(STEP_DELAY).await;
+  PORTAL_CONFIG_XML
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use std::{
+  collections::HashMap,
+  fs,
+  sync::{Arc, Mutex},
+};
+
+use axum::{
+  Router,
+  extract::{Form, State},
+  response::IntoResponse,
+  routing::post,
+};
+use gpapi::{credential::Credential, gp_params::GpParams, portal::retrieve_config};
+
+const PORTAL_CONFIG_XML: &str = include_str!("files/portal_config.xml");
+
+#[derive(Clone, Default)]
+struct MockState {
+  getconfig_params: Arc<Mutex<Option<HashMap<String, String>>>>,
+}
+
+#[tokio::test]
+async fn saml_assertion_file_drives_getconfig() -> anyhow::Result<()> {
+  let dir = tempfile::tempdir()?;
+  let assertion_file = dir.path().join("assertion.json");
+  fs::write(
+    &assertion_file,
+    r#"{"success":{"username":"alice@example.com","preloginCookie":"prelogin-cookie-from-file"}}"#,
+  )?;
+
+  let state = MockState::default();
+  let server_url = start_mock_server(state.clone()).await?;
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  let cred = Credential::from_saml_assertion(&fs::read_to_string(&assertion_file)?)?;
+  let portal_config = retrieve_config(&server_url, &cred, &gp_params).await?;
+  assert!(!portal_config.gateways().is_empty());
+
+  let params = state
+    .getconfig_params
+    .lock()
+    .expect("params lock poisoned")
+    .clone()
+    .expect("getconfig should be requested");
+  assert_eq!(params.get("user").map(String::as_str), Some("alice@example.com"));
+  assert_eq!(
+    params.get("prelogin-cookie").map(String::as_str),
+    Some("prelogin-cookie-from-file")
+  );
+
+  Ok(())
+}
+
+async fn start_mock_server(state: MockState) -> anyhow::Result<String> {
+  let app = Router::new()
+    .route("/global-protect/getconfig.esp", post(handle_getconfig))
+    .with_state(state);
+
+  common::start_mock_server(app).await
+}
+
+async fn handle_getconfig(
+  State(state): State<MockState>,
+  Form(params): Form<HashMap<String, String>>,
+) -> impl IntoResponse {
+  state
+    .getconfig_params
+    .lock()
+    .expect("params lock poisoned")
+    .replace(params);
+  PORTAL_CONFIG_XML
+}
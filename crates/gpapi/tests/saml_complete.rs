@@ -0,0 +1,127 @@
+mod common;
+
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+};
+
+use axum::{
+  Router,
+  extract::{Form, State},
+  response::IntoResponse,
+  routing::post,
+};
+use gpapi::{credential::Credential, flow::complete_saml, gp_params::GpParams, portal::retrieve_config};
+
+const PRELOGIN_SAML_XML: &str = include_str!("files/prelogin_saml.xml");
+const PRELOGIN_STANDARD_XML: &str = include_str!("files/prelogin_standard.xml");
+const PORTAL_CONFIG_XML: &str = include_str!("files/portal_config.xml");
+
+/// Captured from the globalprotectcallback of a completed login, the base64 encoded SAML response
+/// with `<saml-username>xyz@email.com</saml-username>` and `<prelogin-cookie>prelogin-cookie</prelogin-cookie>`
+const CALLBACK_TOKEN: &str = "globalprotectcallback:PGh0bWw+PCEtLSA8c2FtbC1hdXRoLXN0YXR1cz4xPC9zYW1sLWF1dGgtc3RhdHVzPjxwcmVsb2dpbi1jb29raWU+cHJlbG9naW4tY29va2llPC9wcmVsb2dpbi1jb29raWU+PHNhbWwtdXNlcm5hbWU+eHl6QGVtYWlsLmNvbTwvc2FtbC11c2VybmFtZT48c2FtbC1zbG8+bm88L3NhbWwtc2xvPjxzYW1sLVNlc3Npb25Ob3RPbk9yQWZ0ZXI+PC9zYW1sLVNlc3Npb25Ob3RPbk9yQWZ0ZXI+IC0tPjwvaHRtbD4=";
+
+#[derive(Clone)]
+struct MockState {
+  prelogin: &'static str,
+  getconfig_params: Arc<Mutex<Option<HashMap<String, String>>>>,
+}
+
+#[tokio::test]
+async fn callback_token_completes_saml() -> anyhow::Result<()> {
+  let (server_url, getconfig_params) = start_mock_server(PRELOGIN_SAML_XML).await?;
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  let cred = complete_saml(&server_url, CALLBACK_TOKEN, &gp_params).await?;
+  assert_eq!(cred.username(), "xyz@email.com");
+  assert_eq!(cred.prelogin_cookie(), Some("prelogin-cookie"));
+  assert_eq!(cred.token(), None);
+
+  // The credential is accepted by getconfig
+  retrieve_config(&server_url, &Credential::Prelogin(cred), &gp_params).await?;
+  let params = getconfig_params
+    .lock()
+    .expect("params lock poisoned")
+    .clone()
+    .expect("getconfig should be requested");
+  assert_eq!(params.get("user").map(String::as_str), Some("xyz@email.com"));
+  assert_eq!(
+    params.get("prelogin-cookie").map(String::as_str),
+    Some("prelogin-cookie")
+  );
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn cas_token_completes_saml() -> anyhow::Result<()> {
+  let (server_url, _) = start_mock_server(PRELOGIN_SAML_XML).await?;
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  let token = "globalprotectcallback:cas-as=1&un=alice@example.com&token=cas-token-value";
+  let cred = complete_saml(&server_url, token, &gp_params).await?;
+  assert_eq!(cred.username(), "alice@example.com");
+  assert_eq!(cred.prelogin_cookie(), None);
+  assert_eq!(cred.token(), Some("cas-token-value"));
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn invalid_token_or_server_is_rejected() -> anyhow::Result<()> {
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+
+  let (server_url, _) = start_mock_server(PRELOGIN_SAML_XML).await?;
+  assert!(complete_saml(&server_url, "", &gp_params).await.is_err());
+  assert!(
+    complete_saml(&server_url, "globalprotectcallback:cas-as=1&un=alice", &gp_params)
+      .await
+      .is_err()
+  );
+
+  // The token of a SAML login is not sent to the server of the standard authentication
+  let (server_url, getconfig_params) = start_mock_server(PRELOGIN_STANDARD_XML).await?;
+  let err = complete_saml(&server_url, CALLBACK_TOKEN, &gp_params)
+    .await
+    .unwrap_err();
+  assert!(
+    err.to_string().contains("does not use the SAML authentication"),
+    "{err}"
+  );
+  assert!(getconfig_params.lock().expect("params lock poisoned").is_none());
+
+  Ok(())
+}
+
+async fn start_mock_server(
+  prelogin: &'static str,
+) -> anyhow::Result<(String, Arc<Mutex<Option<HashMap<String, String>>>>)> {
+  let state = MockState {
+    prelogin,
+    getconfig_params: Default::default(),
+  };
+  let getconfig_params = Arc::clone(&state.getconfig_params);
+
+  let app = Router::new()
+    .route("/global-protect/prelogin.esp", post(handle_prelogin))
+    .route("/global-protect/getconfig.esp", post(handle_getconfig))
+    .with_state(state);
+
+  Ok((common::start_mock_server(app).await?, getconfig_params))
+}
+
+async fn handle_prelogin(State(state): State<MockState>) -> impl IntoResponse {
+  state.prelogin
+}
+
+async fn handle_getconfig(
+  State(state): State<MockState>,
+  Form(params): Form<HashMap<String, String>>,
+) -> impl IntoResponse {
+  state
+    .getconfig_params
+    .lock()
+    .expect("params lock poisoned")
+    .replace(params);
+  PORTAL_CONFIG_XML
+}
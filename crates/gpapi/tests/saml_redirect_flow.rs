@@ -73,8 +73,8 @@ async fn saml_redirect_flow_uses_expected_endpoints_and_cookies() -> anyhow::Res
   let auth_cookie_cred = Credential::from(portal_config.auth_cookie());
   let gateway_login_res = gateway_login(&server_url, &auth_cookie_cred, &gp_params).await?;
   let cookie = match gateway_login_res {
-    GatewayLogin::Cookie(cookie) => cookie,
-    GatewayLogin::Mfa(_, _) => anyhow::bail!("expected gateway cookie, got MFA challenge"),
+    GatewayLogin::Cookie(cookie) => cookie.to_string(),
+    GatewayLogin::Mfa(_) => anyhow::bail!("expected gateway cookie, got MFA challenge"),
   };
   assert!(cookie.contains("authcookie=xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx"));
   assert!(cookie.contains("portal=XXX-GP-Gateway-N"));
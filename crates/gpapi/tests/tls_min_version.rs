@@ -0,0 +1,46 @@
+mod common;
+
+use axum::{Router, routing::post};
+use gpapi::{
+  gp_params::{GpParams, TlsVersion},
+  portal::prelogin,
+};
+use reqwest::Client;
+
+const PRELOGIN_STANDARD_XML: &str = include_str!("files/prelogin_standard.xml");
+
+#[test]
+fn min_tls_version_defaults_to_tls_1_2() {
+  let gp_params = GpParams::builder().build();
+  assert_eq!(gp_params.min_tls_version(), TlsVersion::Tls1_2);
+}
+
+#[test]
+fn min_tls_version_is_stored_and_applied() {
+  for version in [TlsVersion::Tls1_0, TlsVersion::Tls1_1, TlsVersion::Tls1_2] {
+    let gp_params = GpParams::builder().min_tls_version(version).build();
+    assert_eq!(gp_params.min_tls_version(), version);
+
+    // The native TLS backend rejects the versions it cannot enforce when the client is built
+    Client::try_from(&gp_params).unwrap_or_else(|err| panic!("TLS {}: {}", version.as_str(), err));
+  }
+}
+
+#[tokio::test]
+async fn plain_http_requests_are_not_affected() -> anyhow::Result<()> {
+  let server_url = start_mock_server().await?;
+
+  let gp_params = GpParams::builder()
+    .user_agent("gpapi-test/1.0")
+    .min_tls_version(TlsVersion::Tls1_0)
+    .build();
+  prelogin(&server_url, &gp_params).await?;
+
+  Ok(())
+}
+
+async fn start_mock_server() -> anyhow::Result<String> {
+  let app = Router::new().route("/global-protect/prelogin.esp", post(|| async { PRELOGIN_STANDARD_XML }));
+
+  common::start_mock_server(app).await
+}
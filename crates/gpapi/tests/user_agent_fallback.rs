@@ -0,0 +1,74 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use axum::{
+  Router,
+  extract::State,
+  http::{HeaderMap, StatusCode},
+  response::IntoResponse,
+  routing::post,
+};
+use gpapi::{error::PortalError, gp_params::GpParams, portal::prelogin};
+
+const PRELOGIN_STANDARD_XML: &str = include_str!("files/prelogin_standard.xml");
+const ACCEPTED_USER_AGENT: &str = "PAN GlobalProtect";
+
+type Captured = Arc<Mutex<Vec<String>>>;
+
+#[tokio::test]
+async fn prelogin_retries_with_fallback_user_agent_on_forbidden() -> anyhow::Result<()> {
+  let captured = Captured::default();
+  let server_url = start_mock_server(captured.clone()).await?;
+
+  let gp_params = GpParams::builder()
+    .user_agent("gpapi-test/1.0")
+    .user_agent_fallbacks(vec!["gpapi-test/2.0".to_string(), ACCEPTED_USER_AGENT.to_string()])
+    .build();
+  prelogin(&server_url, &gp_params).await?;
+
+  let captured = captured.lock().expect("captured lock poisoned");
+  assert_eq!(*captured, ["gpapi-test/1.0", "gpapi-test/2.0", ACCEPTED_USER_AGENT]);
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn prelogin_fails_with_forbidden_without_fallback() -> anyhow::Result<()> {
+  let captured = Captured::default();
+  let server_url = start_mock_server(captured.clone()).await?;
+
+  let gp_params = GpParams::builder().user_agent("gpapi-test/1.0").build();
+  let err = prelogin(&server_url, &gp_params).await.unwrap_err();
+
+  assert!(matches!(err, PortalError::Forbidden(_)), "unexpected error: {err}");
+  assert_eq!(captured.lock().expect("captured lock poisoned").len(), 1);
+
+  Ok(())
+}
+
+async fn start_mock_server(captured: Captured) -> anyhow::Result<String> {
+  let app = Router::new()
+    .route("/global-protect/prelogin.esp", post(handle_prelogin))
+    .with_state(captured);
+
+  common::start_mock_server(app).await
+}
+
+async fn handle_prelogin(State(captured): State<Captured>, headers: HeaderMap) -> impl IntoResponse {
+  let user_agent = headers
+    .get("user-agent")
+    .and_then(|v| v.to_str().ok())
+    .unwrap_or_default()
+    .to_string();
+  captured
+    .lock()
+    .expect("captured lock poisoned")
+    .push(user_agent.clone());
+
+  if user_agent == ACCEPTED_USER_AGENT {
+    (StatusCode::OK, PRELOGIN_STANDARD_XML)
+  } else {
+    (StatusCode::FORBIDDEN, "")
+  }
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use axum::{Router, extract::State, http::HeaderMap, response::IntoResponse, routing::post};
+use gpapi::{
+  gp_params::{ClientOs, GpParams},
+  portal::prelogin,
+};
+
+const PRELOGIN_STANDARD_XML: &str = include_str!("files/prelogin_standard.xml");
+
+type Captured = Arc<Mutex<Vec<String>>>;
+
+#[tokio::test]
+async fn user_agent_template_is_populated_with_the_os() -> anyhow::Result<()> {
+  let captured = Captured::default();
+  let server_url = start_mock_server(captured.clone()).await?;
+
+  let gp_params = GpParams::builder()
+    .user_agent_template("PAN GlobalProtect/6.0 ({os} {os_version})")
+    .client_os(ClientOs::Windows)
+    .os_version("Microsoft Windows 11 Pro, 64-bit".to_string())
+    .build();
+  prelogin(&server_url, &gp_params).await?;
+
+  // The placeholder of the missing OS version is left empty
+  let gp_params = GpParams::builder()
+    .user_agent_template("{os} gpapi-test/1.0 {os_version}")
+    .build();
+  prelogin(&server_url, &gp_params).await?;
+
+  let captured = captured.lock().expect("captured lock poisoned");
+  assert_eq!(
+    *captured,
+    [
+      "PAN GlobalProtect/6.0 (Windows Microsoft Windows 11 Pro, 64-bit)",
+      "Linux gpapi-test/1.0"
+    ]
+  );
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn exact_user_agent_is_left_untouched() -> anyhow::Result<()> {
+  let captured = Captured::default();
+  let server_url = start_mock_server(captured.clone()).await?;
+
+  let gp_params = GpParams::builder()
+    .user_agent("gpapi-test/1.0 ({os})")
+    .os_version("6.1".to_string())
+    .build();
+  prelogin(&server_url, &gp_params).await?;
+
+  // The exact user agent replaces the template set before
+  let gp_params = GpParams::builder()
+    .user_agent_template("{os} {os_version}")
+    .user_agent("gpapi-test/2.0")
+    .build();
+  prelogin(&server_url, &gp_params).await?;
+
+  let captured = captured.lock().expect("captured lock poisoned");
+  assert_eq!(*captured, ["gpapi-test/1.0 ({os})", "gpapi-test/2.0"]);
+
+  Ok(())
+}
+
+async fn start_mock_server(captured: Captured) -> anyhow::Result<String> {
+  let app = Router::new()
+    .route("/global-protect/prelogin.esp", post(handle_prelogin))
+    .with_state(captured);
+
+  common::start_mock_server(app).await
+}
+
+async fn handle_prelogin(State(captured): State<Captured>, headers: HeaderMap) -> impl IntoResponse {
+  let user_agent = headers
+    .get("user-agent")
+    .and_then(|v| v.to_str().ok())
+    .unwrap_or_default()
+    .to_string();
+  captured.lock().expect("captured lock poisoned").push(user_agent);
+
+  PRELOGIN_STANDARD_XML
+}
@@ -1,7 +1,21 @@
-use crate::Vpn;
-use log::{debug, info, trace, warn};
+use crate::{Vpn, VpnLogLine};
+use log::{Level, debug, info, trace, warn};
 use std::borrow::Cow;
-use std::ffi::{c_char, c_int, c_void};
+use std::ffi::{CStr, c_char, c_int, c_void};
+use std::sync::{Mutex, mpsc::Sender};
+
+/// The markers of the secrets in the openconnect logs, the values following them are redacted
+pub(crate) const SECRET_MARKERS: [&str; 4] = ["pin-value=", "authcookie=", "prelogin-cookie=", "Cookie: "];
+
+/// The sizes of the tunnel info buffers, must match the C definitions
+const DNS_SERVERS_SIZE: usize = 256;
+const SEARCH_DOMAINS_SIZE: usize = 1024;
+const IP_ADDRESSES_SIZE: usize = 128;
+const TUNNEL_INFO_SIZE: usize = 8192;
+const DTLS_CIPHER_SIZE: usize = 128;
+
+/// The log sink of the current connection, openconnect supports only one connection at a time
+static LOG_SINK: Mutex<Option<Sender<VpnLogLine>>> = Mutex::new(None);
 
 /// ConnectOptions struct for FFI, the field names and order must match the C definition.
 #[repr(C)]
@@ -11,6 +25,8 @@ pub(crate) struct ConnectOptions {
 
   pub server: *const c_char,
   pub cookie: *const c_char,
+  /// `http://` or `socks5://`, null to connect directly
+  pub proxy: *const c_char,
 
   pub user_agent: *const c_char,
   pub os: *const c_char,
@@ -20,6 +36,7 @@ pub(crate) struct ConnectOptions {
   pub script: *const c_char,
   pub interface: *const c_char,
   pub script_tun: u32,
+  pub script_env: *const c_char,
 
   pub certificate: *const c_char,
   pub sslkey: *const c_char,
@@ -32,11 +49,17 @@ pub(crate) struct ConnectOptions {
   pub reconnect_timeout: u32,
   pub mtu: u32,
   pub disable_ipv6: u32,
+  pub disable_ipv4: u32,
   pub no_dtls: u32,
+  pub dtls_ciphers: *const c_char,
 
   pub dpd_interval: u32,
 }
 
+/// Check the peer certificate against the pin, 0 if it matches
+#[cfg(test)]
+type CheckPinFn = extern "C" fn(*mut c_void, *const c_char) -> c_int;
+
 #[link(name = "vpn")]
 unsafe extern "C" {
   #[link_name = "vpn_connect"]
@@ -44,16 +67,137 @@ unsafe extern "C" {
 
   #[link_name = "vpn_disconnect"]
   fn vpn_disconnect();
+
+  #[link_name = "vpn_request_stats"]
+  fn vpn_request_stats();
+
+  #[link_name = "vpn_get_stats"]
+  fn vpn_get_stats(rx_bytes: *mut u64, tx_bytes: *mut u64);
+
+  #[link_name = "vpn_get_dns_info"]
+  fn vpn_get_dns_info(
+    dns_servers: *mut c_char,
+    dns_servers_size: usize,
+    search_domains: *mut c_char,
+    search_domains_size: usize,
+  );
+
+  #[link_name = "vpn_get_ip_addresses"]
+  fn vpn_get_ip_addresses(ip_addresses: *mut c_char, size: usize);
+
+  #[link_name = "vpn_get_tunnel_info"]
+  fn vpn_get_tunnel_info(tunnel_info: *mut c_char, size: usize);
+
+  #[link_name = "vpn_get_dtls_cipher"]
+  fn vpn_get_dtls_cipher(dtls_cipher: *mut c_char, size: usize);
+
+  #[link_name = "vpn_lib_version"]
+  fn vpn_lib_version() -> *const c_char;
+
+  #[cfg(test)]
+  #[link_name = "vpn_system_trust"]
+  fn vpn_system_trust(servercert: *const c_char) -> c_int;
+
+  #[cfg(test)]
+  #[link_name = "vpn_verify_peer_cert"]
+  fn vpn_verify_peer_cert(
+    vpninfo: *mut c_void,
+    servercert: *const c_char,
+    reason: *const c_char,
+    check_pin: CheckPinFn,
+  ) -> c_int;
 }
 
 pub(crate) fn connect(options: &ConnectOptions) -> i32 {
   unsafe { vpn_connect(options, on_vpn_connected) }
 }
 
+pub(crate) fn set_log_sink(sink: Option<Sender<VpnLogLine>>) {
+  *LOG_SINK.lock().unwrap() = sink;
+}
+
+fn send_to_log_sink(level: Level, message: &str) {
+  if let Some(sink) = LOG_SINK.lock().unwrap().as_ref() {
+    // The receiver may have been dropped, which is not an error for the connection
+    let _ = sink.send(VpnLogLine {
+      level,
+      message: message.to_string(),
+    });
+  }
+}
+
 pub(crate) fn disconnect() {
   unsafe { vpn_disconnect() }
 }
 
+pub(crate) fn request_stats() {
+  unsafe { vpn_request_stats() }
+}
+
+pub(crate) fn get_stats() -> (u64, u64) {
+  let mut rx_bytes = 0;
+  let mut tx_bytes = 0;
+  unsafe { vpn_get_stats(&mut rx_bytes, &mut tx_bytes) };
+
+  (rx_bytes, tx_bytes)
+}
+
+/// Convert the NUL-terminated string copied into the buffer by the C side
+fn buf_to_string(buf: &[c_char]) -> String {
+  unsafe { CStr::from_ptr(buf.as_ptr()) }.to_string_lossy().into_owned()
+}
+
+/// Get the space-separated DNS servers and search domains pushed by the gateway
+pub(crate) fn get_dns_info() -> (String, String) {
+  let mut dns_servers = [0; DNS_SERVERS_SIZE];
+  let mut search_domains = [0; SEARCH_DOMAINS_SIZE];
+  unsafe {
+    vpn_get_dns_info(
+      dns_servers.as_mut_ptr(),
+      dns_servers.len(),
+      search_domains.as_mut_ptr(),
+      search_domains.len(),
+    )
+  };
+
+  (buf_to_string(&dns_servers), buf_to_string(&search_domains))
+}
+
+/// Get the space-separated IPv4 and IPv6 addresses assigned to the tunnel
+pub(crate) fn get_ip_addresses() -> String {
+  let mut ip_addresses = [0; IP_ADDRESSES_SIZE];
+  unsafe { vpn_get_ip_addresses(ip_addresses.as_mut_ptr(), ip_addresses.len()) };
+
+  buf_to_string(&ip_addresses)
+}
+
+/// Get the `key=value` lines of the tunnel settings pushed by the gateway
+pub(crate) fn get_tunnel_info() -> String {
+  let mut tunnel_info = [0; TUNNEL_INFO_SIZE];
+  unsafe { vpn_get_tunnel_info(tunnel_info.as_mut_ptr(), tunnel_info.len()) };
+
+  buf_to_string(&tunnel_info)
+}
+
+/// Get the cipher of the DTLS or ESP transport saved with the statistics, None if not established
+pub(crate) fn get_dtls_cipher() -> Option<String> {
+  let mut dtls_cipher = [0; DTLS_CIPHER_SIZE];
+  unsafe { vpn_get_dtls_cipher(dtls_cipher.as_mut_ptr(), dtls_cipher.len()) };
+
+  let dtls_cipher = buf_to_string(&dtls_cipher);
+  (!dtls_cipher.is_empty()).then_some(dtls_cipher)
+}
+
+/// Get the version of the libopenconnect actually linked, if the library exports it
+pub(crate) fn lib_version() -> Option<String> {
+  let version = unsafe { vpn_lib_version() };
+
+  if version.is_null() {
+    return None;
+  }
+  Some(unsafe { CStr::from_ptr(version) }.to_string_lossy().into_owned())
+}
+
 #[unsafe(no_mangle)]
 extern "C" fn on_vpn_connected(pipe_fd: i32, vpn: *mut c_void) {
   let vpn = unsafe { &*(vpn as *const Vpn) };
@@ -68,7 +212,7 @@ extern "C" fn vpn_log(level: i32, message: *const c_char) {
   let message = unsafe { std::ffi::CStr::from_ptr(message) };
   let message = message.to_str().unwrap_or("Invalid log message");
   // Strip the trailing newline
-  let message = redact_pkcs11_pin(message.trim_end_matches('\n'));
+  let message = redact_secrets(message.trim_end_matches('\n'), &SECRET_MARKERS);
 
   if level == 0 {
     warn!("{}", message);
@@ -85,24 +229,165 @@ extern "C" fn vpn_log(level: i32, message: *const c_char) {
     );
     debug!("{}", message);
   }
-}
 
-fn redact_pkcs11_pin(message: &str) -> Cow<'_, str> {
-  let marker = "pin-value=";
-  let Some(start) = message.find(marker) else {
-    return Cow::Borrowed(message);
+  let level = match level {
+    0 => Level::Warn,
+    1 => Level::Info,
+    3 => Level::Trace,
+    _ => Level::Debug,
   };
+  send_to_log_sink(level, &message);
+}
 
-  let value_start = start + marker.len();
-  let tail = &message[value_start..];
-  let value_end_rel = tail
-    .find(|ch: char| ch == '&' || ch == ';' || ch == ' ' || ch == '\'' || ch == '"' || ch == ')')
-    .unwrap_or(tail.len());
+/// Replace the values following the markers with `<redacted>`, e.g., the PKCS#11 PIN and the cookies
+pub(crate) fn redact_secrets<'a>(message: &'a str, markers: &[&str]) -> Cow<'a, str> {
+  markers.iter().fold(Cow::Borrowed(message), |message, marker| {
+    match redact_marker(&message, marker) {
+      Some(redacted) => Cow::Owned(redacted),
+      None => message,
+    }
+  })
+}
+
+/// Redact all the values following the marker, None if the marker is not found.
+/// The value of a header marker, e.g., `Cookie: `, is the rest of the line with all its cookie pairs.
+fn redact_marker(message: &str, marker: &str) -> Option<String> {
+  if !message.contains(marker) {
+    return None;
+  }
 
-  let value_end = value_start + value_end_rel;
   let mut redacted = String::with_capacity(message.len());
-  redacted.push_str(&message[..value_start]);
-  redacted.push_str("<redacted>");
-  redacted.push_str(&message[value_end..]);
-  Cow::Owned(redacted)
+  let mut rest = message;
+  while let Some(start) = rest.find(marker) {
+    let value_start = start + marker.len();
+    redacted.push_str(&rest[..value_start]);
+    redacted.push_str("<redacted>");
+
+    let tail = &rest[value_start..];
+    let value_end = if marker.ends_with(": ") {
+      tail.find(['\r', '\n', '\'', '"'])
+    } else {
+      tail.find(['&', ';', ' ', '\'', '"', ')'])
+    };
+    let value_end = value_end.unwrap_or(tail.len());
+    rest = &tail[value_end..];
+  }
+  redacted.push_str(rest);
+
+  Some(redacted)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::mpsc;
+
+  use super::*;
+
+  #[test]
+  fn log_sink_receives_redacted_messages() {
+    let (tx, rx) = mpsc::channel();
+    set_log_sink(Some(tx));
+
+    vpn_log(1, c"Using pkcs11:token=TOKEN?pin-value=123456\n".as_ptr());
+    vpn_log(0, c"Connection failed".as_ptr());
+    set_log_sink(None);
+    vpn_log(1, c"Not sent".as_ptr());
+
+    let lines = rx.try_iter().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0].level, Level::Info);
+    assert_eq!(lines[0].message, "Using pkcs11:token=TOKEN?pin-value=<redacted>");
+    assert_eq!(lines[1].level, Level::Warn);
+    assert_eq!(lines[1].message, "Connection failed");
+  }
+
+  extern "C" fn pin_matches(_vpninfo: *mut c_void, _pin: *const c_char) -> c_int {
+    0
+  }
+
+  extern "C" fn pin_mismatches(_vpninfo: *mut c_void, _pin: *const c_char) -> c_int {
+    1
+  }
+
+  #[test]
+  fn pinned_cert_is_checked_even_if_trusted_by_the_system() {
+    let pins = c"sha256:old,pin-sha256:new=".as_ptr();
+
+    // The system trust would accept a CA-trusted certificate without asking to verify it
+    assert_eq!(unsafe { vpn_system_trust(pins) }, 0);
+    assert_eq!(unsafe { vpn_system_trust(std::ptr::null()) }, 1);
+
+    let verify = |servercert: *const c_char, check_pin: CheckPinFn| unsafe {
+      vpn_verify_peer_cert(
+        std::ptr::null_mut(),
+        servercert,
+        c"signer not found".as_ptr(),
+        check_pin,
+      )
+    };
+    assert_eq!(verify(pins, pin_mismatches), 1);
+    assert_eq!(verify(pins, pin_matches), 0);
+    assert_eq!(verify(std::ptr::null(), pin_mismatches), 0);
+  }
+
+  #[test]
+  fn redacts_auth_cookie() {
+    assert_eq!(
+      redact_secrets(
+        "GET /ssl-vpn/getconfig.esp?authcookie=abc123&portal=gw",
+        &SECRET_MARKERS
+      ),
+      "GET /ssl-vpn/getconfig.esp?authcookie=<redacted>&portal=gw"
+    );
+    // The portal cookies end with `authcookie=` as well
+    assert_eq!(
+      redact_secrets("portal-userauthcookie=abc123", &SECRET_MARKERS),
+      "portal-userauthcookie=<redacted>"
+    );
+  }
+
+  #[test]
+  fn redacts_prelogin_cookie() {
+    assert_eq!(
+      redact_secrets("prelogin-cookie=abc123; path=/", &SECRET_MARKERS),
+      "prelogin-cookie=<redacted>; path=/"
+    );
+    assert_eq!(
+      redact_secrets(r#"<input name="x" value='prelogin-cookie=abc123'>"#, &SECRET_MARKERS),
+      r#"<input name="x" value='prelogin-cookie=<redacted>'>"#
+    );
+  }
+
+  #[test]
+  fn redacts_cookie_header() {
+    assert_eq!(
+      redact_secrets("Cookie: SESSID=abc123; authcookie=def456", &SECRET_MARKERS),
+      "Cookie: <redacted>"
+    );
+    assert_eq!(
+      redact_secrets("Cookie: a=1; b=2; c=3\nHost: vpn.example.com", &SECRET_MARKERS),
+      "Cookie: <redacted>\nHost: vpn.example.com"
+    );
+    assert_eq!(
+      redact_secrets("Set-Cookie: SESSID=abc123; path=/", &SECRET_MARKERS),
+      "Set-Cookie: <redacted>"
+    );
+    assert_eq!(
+      redact_secrets(r#"header "Cookie: abc123""#, &SECRET_MARKERS),
+      r#"header "Cookie: <redacted>""#
+    );
+  }
+
+  #[test]
+  fn redacts_only_the_given_markers() {
+    let message = "authcookie=abc123&pin-value=123456";
+    assert_eq!(
+      redact_secrets(message, &["pin-value="]),
+      "authcookie=abc123&pin-value=<redacted>"
+    );
+    assert!(matches!(
+      redact_secrets("Connection failed", &SECRET_MARKERS),
+      Cow::Borrowed(_)
+    ));
+  }
 }
@@ -1,7 +1,9 @@
 #include <openconnect.h>
+#include <pthread.h>
 #include <stdarg.h>
 #include <stdio.h>
 #include <stdlib.h>
+#include <string.h>
 #include <sys/utsname.h>
 #include <unistd.h>
 
@@ -11,18 +13,84 @@ void *g_user_data;
 
 static int g_cmd_pipe_fd;
 static const char *g_vpnc_script;
+static char *g_vpnc_script_cmd;
 static const char *g_vpnc_interface;
 static int g_script_tun;
 static vpn_connected_callback on_vpn_connected;
+static uint64_t g_rx_bytes;
+static uint64_t g_tx_bytes;
+/* Guards the tunnel info below, written on the mainloop thread and copied out by the getters */
+static pthread_mutex_t g_info_lock = PTHREAD_MUTEX_INITIALIZER;
+static char g_dns_servers[VPN_DNS_SERVERS_SIZE];
+static char g_search_domains[VPN_SEARCH_DOMAINS_SIZE];
+static char g_ip_addresses[VPN_IP_ADDRESSES_SIZE];
+static char g_tunnel_info[VPN_TUNNEL_INFO_SIZE];
+static char g_dtls_cipher[VPN_DTLS_CIPHER_SIZE];
+static const char *g_servercert;
+
+/* Check the peer certificate against the comma-separated pins, 0 if any of them matches */
+static int check_pinned_cert(void *_vpninfo, const char *pins,
+			     vpn_check_pin_fn check_pin)
+{
+	char *copy = strdup(pins);
+	if (copy == NULL) {
+		ERROR("Failed to allocate memory for the pinned certificates");
+		return 1;
+	}
+
+	int ret = 1;
+	char *saveptr = NULL;
+	for (char *pin = strtok_r(copy, ",", &saveptr); pin != NULL;
+	     pin = strtok_r(NULL, ",", &saveptr)) {
+		if (check_pin(_vpninfo, pin) == 0) {
+			ret = 0;
+			break;
+		}
+	}
+
+	free(copy);
+	return ret;
+}
+
+static int check_peer_cert_hash(void *_vpninfo, const char *pin)
+{
+	return openconnect_check_peer_cert_hash(_vpninfo, pin);
+}
+
+/* Whether to trust the system CAs. openconnect asks `validate_peer_cert` only
+ * when the system check fails, so it is disabled for the pin to be checked
+ * against the CA-trusted certificates too */
+int vpn_system_trust(const char *servercert)
+{
+	return servercert == NULL;
+}
 
-/* Validate the peer certificate */
-static int validate_peer_cert(__attribute__((unused)) void *_vpninfo,
-			      const char *reason)
+/* Verify the peer certificate against the pinned ones if any, 0 to accept */
+int vpn_verify_peer_cert(void *_vpninfo, const char *servercert,
+			 const char *reason, vpn_check_pin_fn check_pin)
 {
+	if (servercert) {
+		if (check_pinned_cert(_vpninfo, servercert, check_pin)) {
+			ERROR("The server certificate does not match any of the pinned %s",
+			      servercert);
+			return 1;
+		}
+
+		INFO("Accepting the pinned server certificate though %s",
+		     reason);
+		return 0;
+	}
+
 	INFO("Accepting the server certificate though %s", reason);
 	return 0;
 }
 
+static int validate_peer_cert(void *_vpninfo, const char *reason)
+{
+	return vpn_verify_peer_cert(_vpninfo, g_servercert, reason,
+				    check_peer_cert_hash);
+}
+
 /* Print progress messages */
 static void print_progress(__attribute__((unused)) void *_vpninfo, int level,
 			   const char *format, ...)
@@ -40,6 +108,125 @@ static void print_progress(__attribute__((unused)) void *_vpninfo, int level,
 	}
 }
 
+/* Save the latest traffic statistics, and the cipher of the UDP transport if established */
+static void stats_handler(void *_vpninfo, const struct oc_stats *stats)
+{
+	const char *cipher = openconnect_get_dtls_cipher(_vpninfo);
+
+	__atomic_store_n(&g_rx_bytes, stats->rx_bytes, __ATOMIC_RELAXED);
+	__atomic_store_n(&g_tx_bytes, stats->tx_bytes, __ATOMIC_RELAXED);
+
+	pthread_mutex_lock(&g_info_lock);
+	snprintf(g_dtls_cipher, sizeof(g_dtls_cipher), "%s", cipher ? cipher : "");
+	pthread_mutex_unlock(&g_info_lock);
+}
+
+/* Save the DNS servers and search domains pushed by the gateway */
+static void save_dns_info(void *_vpninfo)
+{
+	const struct oc_ip_info *ip_info;
+	size_t len = 0;
+
+	g_dns_servers[0] = '\0';
+	g_search_domains[0] = '\0';
+
+	if (openconnect_get_ip_info(_vpninfo, &ip_info, NULL, NULL) || !ip_info) {
+		DEBUG("Failed to get the IP info, DNS info is not available");
+		return;
+	}
+
+	for (int i = 0; i < 3; i++) {
+		if (!ip_info->dns[i]) {
+			continue;
+		}
+
+		len += snprintf(g_dns_servers + len, sizeof(g_dns_servers) - len,
+				"%s%s", len ? " " : "", ip_info->dns[i]);
+		if (len >= sizeof(g_dns_servers)) {
+			break;
+		}
+	}
+
+	if (ip_info->domain) {
+		snprintf(g_search_domains, sizeof(g_search_domains), "%s",
+			 ip_info->domain);
+	}
+}
+
+/* Save the IPv4 and IPv6 addresses assigned to the tunnel */
+static void save_ip_addresses(void *_vpninfo)
+{
+	const struct oc_ip_info *ip_info;
+
+	g_ip_addresses[0] = '\0';
+
+	if (openconnect_get_ip_info(_vpninfo, &ip_info, NULL, NULL) || !ip_info) {
+		DEBUG("Failed to get the IP info, the addresses are not available");
+		return;
+	}
+
+	snprintf(g_ip_addresses, sizeof(g_ip_addresses), "%s%s%s",
+		 ip_info->addr ? ip_info->addr : "",
+		 ip_info->addr && ip_info->addr6 ? " " : "",
+		 ip_info->addr6 ? ip_info->addr6 : "");
+}
+
+/* Append the `key=value` line to the tunnel info, the line is dropped if it does not fit */
+static void append_tunnel_info(size_t *len, const char *key, const char *value)
+{
+	if (!value || !*value) {
+		return;
+	}
+
+	size_t size = sizeof(g_tunnel_info) - *len;
+	int n = snprintf(g_tunnel_info + *len, size, "%s=%s\n", key, value);
+	if (n < 0 || (size_t)n >= size) {
+		g_tunnel_info[*len] = '\0';
+		return;
+	}
+
+	*len += n;
+}
+
+/* Save the tunnel settings pushed by the gateway as the `key=value` lines, parsed by `TunnelInfo` */
+static void save_tunnel_info(void *_vpninfo)
+{
+	const struct oc_ip_info *ip_info;
+	const struct oc_split_include *route;
+	char mtu[16];
+	size_t len = 0;
+
+	g_tunnel_info[0] = '\0';
+
+	if (openconnect_get_ip_info(_vpninfo, &ip_info, NULL, NULL) || !ip_info) {
+		DEBUG("Failed to get the IP info, the tunnel info is not available");
+		return;
+	}
+
+	append_tunnel_info(&len, "ifname", openconnect_get_ifname(_vpninfo));
+	append_tunnel_info(&len, "addr", ip_info->addr);
+	append_tunnel_info(&len, "netmask", ip_info->netmask);
+	append_tunnel_info(&len, "addr6", ip_info->addr6);
+	append_tunnel_info(&len, "netmask6", ip_info->netmask6);
+
+	for (int i = 0; i < 3; i++) {
+		append_tunnel_info(&len, "dns", ip_info->dns[i]);
+	}
+	append_tunnel_info(&len, "domain", ip_info->domain);
+
+	for (route = ip_info->split_includes; route; route = route->next) {
+		append_tunnel_info(&len, "include", route->route);
+	}
+	for (route = ip_info->split_excludes; route; route = route->next) {
+		append_tunnel_info(&len, "exclude", route->route);
+	}
+
+	if (ip_info->mtu > 0) {
+		snprintf(mtu, sizeof(mtu), "%d", ip_info->mtu);
+		append_tunnel_info(&len, "mtu", mtu);
+	}
+}
+
 static void setup_tun_handler(void *_vpninfo)
 {
 	int ret;
@@ -51,20 +238,37 @@ static void setup_tun_handler(void *_vpninfo)
 	}
 
 	if (!ret) {
+		pthread_mutex_lock(&g_info_lock);
+		save_dns_info(_vpninfo);
+		save_ip_addresses(_vpninfo);
+		save_tunnel_info(_vpninfo);
+		pthread_mutex_unlock(&g_info_lock);
 		on_vpn_connected(g_cmd_pipe_fd, g_user_data);
 	}
 }
 
+/* Free the vpnc-script command once the connection is over, also when it fails to start */
+static void free_vpnc_script_cmd()
+{
+	g_vpnc_script = NULL;
+	free(g_vpnc_script_cmd);
+	g_vpnc_script_cmd = NULL;
+}
+
 /* Initialize VPN connection */
 int vpn_connect(const vpn_options *options, vpn_connected_callback callback)
 {
 	struct openconnect_info *vpninfo;
 	struct utsname utsbuf;
 
+	// Left by the previous connection if it was not freed
+	free_vpnc_script_cmd();
+
 	g_user_data = options->user_data;
 	g_vpnc_script = options->script;
 	g_vpnc_interface = options->interface;
 	g_script_tun = options->script_tun;
+	g_servercert = options->servercert;
 	on_vpn_connected = callback;
 
 	INFO("USER_AGENT: %s", options->user_agent);
@@ -72,28 +276,60 @@ int vpn_connect(const vpn_options *options, vpn_connected_callback callback)
 	INFO("CLIENT_VERSION: %s", options->client_version);
 	INFO("VPNC_SCRIPT: %s", options->script);
 	INFO("SCRIPT_TUN: %d", g_script_tun);
+	INFO("SCRIPT_ENV: %s", options->script_env);
+	INFO("SERVERCERT: %s", options->servercert);
 	INFO("CSD_USER: %d", options->csd_uid);
 	INFO("CSD_WRAPPER: %s", options->csd_wrapper);
 	INFO("RECONNECT_TIMEOUT: %d", options->reconnect_timeout);
 	INFO("MTU: %d", options->mtu);
 	INFO("DISABLE_IPV6: %d", options->disable_ipv6);
+	INFO("DISABLE_IPV4: %d", options->disable_ipv4);
 	INFO("NO_DTLS: %d", options->no_dtls);
+	INFO("DTLS_CIPHERS: %s", options->dtls_ciphers);
 	INFO("DPD_INTERVAL: %d", options->dpd_interval);
 
+	// openconnect runs the script with `/bin/sh -c`, prefix the environment
+	// assignments to override the variables it sets, e.g., the DNS servers
+	if (options->script_env && !g_script_tun) {
+		size_t len = strlen(options->script_env) + strlen(options->script) + 7;
+		g_vpnc_script_cmd = malloc(len);
+		if (!g_vpnc_script_cmd) {
+			ERROR("Failed to allocate the vpnc-script command");
+			return 1;
+		}
+
+		snprintf(g_vpnc_script_cmd, len, "%s exec %s",
+			 options->script_env, options->script);
+		g_vpnc_script = g_vpnc_script_cmd;
+	}
+
 	vpninfo =
 	    openconnect_vpninfo_new(options->user_agent, validate_peer_cert,
 				    NULL, NULL, print_progress, NULL);
 
 	if (!vpninfo) {
 		ERROR("openconnect_vpninfo_new failed");
+		free_vpnc_script_cmd();
 		return 1;
 	}
 
 	openconnect_set_loglevel(vpninfo, PRG_TRACE);
+	openconnect_set_system_trust(vpninfo, vpn_system_trust(g_servercert));
 	openconnect_init_ssl();
 	openconnect_set_protocol(vpninfo, "gp");
 	openconnect_parse_url(vpninfo, options->server);
 	openconnect_set_cookie(vpninfo, options->cookie);
+
+	// The proxy may contain the credentials, which are not logged
+	if (options->proxy) {
+		INFO("Connecting through the proxy");
+		if (openconnect_set_http_proxy(vpninfo, options->proxy)) {
+			ERROR("Failed to set the proxy");
+			openconnect_vpninfo_free(vpninfo);
+			free_vpnc_script_cmd();
+			return 1;
+		}
+	}
 	openconnect_set_useragent(vpninfo, options->user_agent);
 
 	if (options->os) {
@@ -133,6 +369,12 @@ int vpn_connect(const vpn_options *options, vpn_connected_callback callback)
 		openconnect_disable_ipv6(vpninfo);
 	}
 
+	// openconnect cannot disable IPv4, the vpnc-script environment drops the
+	// IPv4 settings instead, which is not available to the script-tun
+	if (options->disable_ipv4 && g_script_tun) {
+		ERROR("IPv4 cannot be disabled with the script-tun, ignored");
+	}
+
 	if (options->dpd_interval > 0) {
 		openconnect_set_dpd(vpninfo, options->dpd_interval);
 	}
@@ -140,6 +382,7 @@ int vpn_connect(const vpn_options *options, vpn_connected_callback callback)
 	g_cmd_pipe_fd = openconnect_setup_cmd_pipe(vpninfo);
 	if (g_cmd_pipe_fd < 0) {
 		ERROR("openconnect_setup_cmd_pipe failed");
+		free_vpnc_script_cmd();
 		return 1;
 	}
 
@@ -150,15 +393,22 @@ int vpn_connect(const vpn_options *options, vpn_connected_callback callback)
 	// Essential step
 	if (openconnect_make_cstp_connection(vpninfo) != 0) {
 		ERROR("openconnect_make_cstp_connection failed");
+		free_vpnc_script_cmd();
 		return 1;
 	}
 
+	// Must be set before the DTLS setup, NULL keeps the default ciphers
+	if (options->dtls_ciphers) {
+		openconnect_set_dtls_ciphers(vpninfo, options->dtls_ciphers);
+	}
+
 	if (options->no_dtls || openconnect_setup_dtls(vpninfo, 60) != 0) {
 		openconnect_disable_dtls(vpninfo);
 	}
 
 	// Essential step
 	openconnect_set_setup_tun_handler(vpninfo, setup_tun_handler);
+	openconnect_set_stats_handler(vpninfo, stats_handler);
 
 	while (1) {
 		int ret = openconnect_mainloop(vpninfo,
@@ -167,6 +417,7 @@ int vpn_connect(const vpn_options *options, vpn_connected_callback callback)
 		if (ret) {
 			INFO("openconnect_mainloop returned %d, exiting", ret);
 			openconnect_vpninfo_free(vpninfo);
+			free_vpnc_script_cmd();
 			return ret;
 		}
 
@@ -186,3 +437,72 @@ void vpn_disconnect()
 		      "be stopped");
 	}
 }
+
+/* Ask the mainloop to refresh the traffic statistics */
+void vpn_request_stats()
+{
+	char cmd = OC_CMD_STATS;
+
+	if (g_cmd_pipe_fd <= 0) {
+		return;
+	}
+
+	if (write(g_cmd_pipe_fd, &cmd, 1) < 0) {
+		DEBUG("Failed to write to command pipe, stats may be outdated");
+	}
+}
+
+/* Get the latest traffic statistics */
+void vpn_get_stats(uint64_t *rx_bytes, uint64_t *tx_bytes)
+{
+	*rx_bytes = __atomic_load_n(&g_rx_bytes, __ATOMIC_RELAXED);
+	*tx_bytes = __atomic_load_n(&g_tx_bytes, __ATOMIC_RELAXED);
+}
+
+/* Copy the DNS servers and search domains saved when the tunnel was set up,
+ * both are space-separated and truncated to the buffer sizes */
+void vpn_get_dns_info(char *dns_servers, size_t dns_servers_size,
+		      char *search_domains, size_t search_domains_size)
+{
+	pthread_mutex_lock(&g_info_lock);
+	snprintf(dns_servers, dns_servers_size, "%s", g_dns_servers);
+	snprintf(search_domains, search_domains_size, "%s", g_search_domains);
+	pthread_mutex_unlock(&g_info_lock);
+}
+
+/* Copy the space-separated addresses saved when the tunnel was set up */
+void vpn_get_ip_addresses(char *ip_addresses, size_t size)
+{
+	pthread_mutex_lock(&g_info_lock);
+	snprintf(ip_addresses, size, "%s", g_ip_addresses);
+	pthread_mutex_unlock(&g_info_lock);
+}
+
+/* Copy the DTLS or ESP cipher saved with the statistics, empty if the traffic goes over TLS */
+void vpn_get_dtls_cipher(char *dtls_cipher, size_t size)
+{
+	pthread_mutex_lock(&g_info_lock);
+	snprintf(dtls_cipher, size, "%s", g_dtls_cipher);
+	pthread_mutex_unlock(&g_info_lock);
+}
+
+/* Copy the tunnel settings saved when the tunnel was set up */
+void vpn_get_tunnel_info(char *tunnel_info, size_t size)
+{
+	pthread_mutex_lock(&g_info_lock);
+	snprintf(tunnel_info, size, "%s", g_tunnel_info);
+	pthread_mutex_unlock(&g_info_lock);
+}
+
+/* Resolved at runtime, an old or stripped libopenconnect may not export it */
+#pragma weak openconnect_get_version
+
+/* Get the version of the linked libopenconnect, NULL if it cannot be detected */
+const char *vpn_lib_version()
+{
+	if (!openconnect_get_version) {
+		return NULL;
+	}
+
+	return openconnect_get_version();
+}
@@ -1,15 +1,26 @@
 #include <openconnect.h>
 #include <stdarg.h>
+#include <stdint.h>
 #include <stdio.h>
 #include <stdlib.h>
 
+/* The sizes of the tunnel info buffers, the Rust side copies into buffers of the same sizes */
+#define VPN_DNS_SERVERS_SIZE 256
+#define VPN_SEARCH_DOMAINS_SIZE 1024
+#define VPN_IP_ADDRESSES_SIZE 128
+#define VPN_TUNNEL_INFO_SIZE 8192
+#define VPN_DTLS_CIPHER_SIZE 128
+
 typedef void (*vpn_connected_callback)(int cmd_pipe_fd, void *user_data);
+/* Check the peer certificate against the pin, 0 if it matches */
+typedef int (*vpn_check_pin_fn)(void *vpninfo, const char *pin);
 
 typedef struct vpn_options {
 	void *user_data;
 
 	const char *server;
 	const char *cookie;
+	const char *proxy;
 
 	const char *user_agent;
 	const char *os;
@@ -19,10 +30,12 @@ typedef struct vpn_options {
 	const char *script;
 	const char *interface;
 	const int script_tun;
+	const char *script_env;
 
 	const char *certificate;
 	const char *sslkey;
 	const char *key_password;
+	/* The comma-separated fingerprints, any of them is accepted */
 	const char *servercert;
 
 	const uid_t csd_uid;
@@ -31,13 +44,26 @@ typedef struct vpn_options {
 	const int reconnect_timeout;
 	const int mtu;
 	const int disable_ipv6;
+	const int disable_ipv4;
 	const int no_dtls;
+	const char *dtls_ciphers;
 
 	const int dpd_interval;
 } vpn_options;
 
 int vpn_connect(const vpn_options *options, vpn_connected_callback callback);
 void vpn_disconnect();
+void vpn_request_stats();
+void vpn_get_stats(uint64_t *rx_bytes, uint64_t *tx_bytes);
+void vpn_get_dns_info(char *dns_servers, size_t dns_servers_size,
+		      char *search_domains, size_t search_domains_size);
+void vpn_get_ip_addresses(char *ip_addresses, size_t size);
+void vpn_get_tunnel_info(char *tunnel_info, size_t size);
+void vpn_get_dtls_cipher(char *dtls_cipher, size_t size);
+const char *vpn_lib_version();
+int vpn_system_trust(const char *servercert);
+int vpn_verify_peer_cert(void *vpninfo, const char *servercert,
+			 const char *reason, vpn_check_pin_fn check_pin);
 
 extern void vpn_log(int level, const char *msg);
 
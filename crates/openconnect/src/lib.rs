@@ -1,6 +1,12 @@
 mod ffi;
+mod saved_env;
+mod transport;
+mod tunnel;
 mod vpn;
 mod vpn_utils;
 
+pub use saved_env::*;
+pub use transport::*;
+pub use tunnel::*;
 pub use vpn::*;
 pub use vpn_utils::*;
@@ -0,0 +1,139 @@
+use std::{fs, io, path::Path, process::Command};
+
+use log::{info, warn};
+
+/// The variable of the saved environment holding the vpnc-script path
+const SAVED_SCRIPT_VAR: &str = "GPCLIENT_VPNC_SCRIPT";
+
+/// The vpnc-script environment of the connected tunnel, saved to run the teardown when the openconnect
+/// mainloop is stuck, e.g., by `gpclient disconnect --timeout`
+#[derive(Debug)]
+pub struct SavedScriptEnv {
+  script: String,
+  env: Vec<(String, String)>,
+}
+
+impl SavedScriptEnv {
+  /// Load the environment saved by the vpnc-script command, None if the tunnel never connected
+  pub fn load(path: impl AsRef<Path>) -> io::Result<Option<Self>> {
+    let content = match fs::read_to_string(path) {
+      Ok(content) => content,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+      Err(err) => return Err(err),
+    };
+
+    Ok(Self::parse(&content))
+  }
+
+  fn parse(content: &str) -> Option<Self> {
+    let mut script = None;
+    let mut env = vec![];
+
+    // The lines of the multi-line values are skipped, the vpnc-script variables are single-line
+    for (name, value) in content.lines().filter_map(|line| line.split_once('=')) {
+      if name == SAVED_SCRIPT_VAR {
+        script = Some(value.to_string());
+      } else if name != "reason" && is_env_name(name) {
+        env.push((name.to_string(), value.to_string()));
+      }
+    }
+
+    script.map(|script| Self { script, env })
+  }
+
+  /// Run the vpnc-script with `reason=disconnect` and the saved tunnel environment
+  pub fn run_teardown(&self) -> io::Result<()> {
+    info!("Running the vpnc-script teardown with the saved environment");
+
+    let status = Command::new(&self.script)
+      .envs(self.env.iter().map(|(name, value)| (name, value)))
+      .env("reason", "disconnect")
+      .status()?;
+
+    if !status.success() {
+      warn!("The vpnc-script exited with {} during the teardown", status);
+      return Err(io::Error::other(format!("vpnc-script exited with {}", status)));
+    }
+
+    Ok(())
+  }
+}
+
+/// Build the command prefixed to the vpnc-script to save its environment on connect, and remove it on
+/// disconnect. The paths are single-quoted, None if they cannot be.
+pub(crate) fn build_save_env_command(script: &str, path: &str) -> Option<String> {
+  if script.contains('\'') || path.contains('\'') {
+    return None;
+  }
+
+  Some(format!(
+    "case \"$reason\" in connect) (umask 077; env {}='{}' > '{}');; disconnect) rm -f '{}';; esac;",
+    SAVED_SCRIPT_VAR, script, path, path
+  ))
+}
+
+fn is_env_name(name: &str) -> bool {
+  !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{fs, os::unix::fs::PermissionsExt, process::Command};
+
+  use super::*;
+
+  #[test]
+  fn teardown_runs_with_the_saved_environment() {
+    let dir = tempfile::tempdir().unwrap();
+    let saved = dir.path().join("gpclient.env");
+    let output = dir.path().join("output");
+    let script = dir.path().join("vpnc-script");
+    fs::write(
+      &script,
+      format!(
+        "#!/bin/sh\necho \"$reason $TUNDEV $INTERNAL_IP4_ADDRESS\" >> {}\n",
+        output.display()
+      ),
+    )
+    .unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+    // Run the script the same way openconnect does on connect
+    let command = build_save_env_command(script.to_str().unwrap(), saved.to_str().unwrap()).unwrap();
+    let status = Command::new("/bin/sh")
+      .arg("-c")
+      .arg(format!("{} exec {}", command, script.display()))
+      .env("reason", "connect")
+      .env("TUNDEV", "gpd0")
+      .env("INTERNAL_IP4_ADDRESS", "10.0.0.2")
+      .status()
+      .unwrap();
+    assert!(status.success());
+    assert_eq!(fs::metadata(&saved).unwrap().permissions().mode() & 0o077, 0);
+
+    let env = SavedScriptEnv::load(&saved)
+      .unwrap()
+      .expect("environment should be saved");
+    env.run_teardown().unwrap();
+    assert_eq!(
+      fs::read_to_string(&output).unwrap(),
+      "connect gpd0 10.0.0.2\ndisconnect gpd0 10.0.0.2\n"
+    );
+
+    // The file is removed by the normal teardown
+    let status = Command::new("/bin/sh")
+      .arg("-c")
+      .arg(format!("{} exec {}", command, script.display()))
+      .env("reason", "disconnect")
+      .status()
+      .unwrap();
+    assert!(status.success());
+    assert!(SavedScriptEnv::load(&saved).unwrap().is_none());
+  }
+
+  #[test]
+  fn unquotable_paths_are_not_saved() {
+    assert!(build_save_env_command("/usr/bin/vpnc-script", "/tmp/it's.env").is_none());
+    assert!(build_save_env_command("/tmp/it's", "/var/run/gpclient.env").is_none());
+  }
+}
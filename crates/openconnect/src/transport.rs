@@ -0,0 +1,169 @@
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+  Dtls,
+  Esp,
+  Tls,
+}
+
+impl Transport {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Transport::Dtls => "dtls",
+      Transport::Esp => "esp",
+      Transport::Tls => "tls",
+    }
+  }
+
+  /// DTLS and ESP are both UDP transports set up after the TLS tunnel
+  pub fn is_udp(&self) -> bool {
+    matches!(self, Transport::Dtls | Transport::Esp)
+  }
+}
+
+impl FromStr for Transport {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.trim().to_lowercase().as_str() {
+      "dtls" => Ok(Transport::Dtls),
+      "esp" => Ok(Transport::Esp),
+      "tls" => Ok(Transport::Tls),
+      _ => Err(format!(
+        "Unknown transport: {}, expected one of dtls, esp, tls",
+        s.trim()
+      )),
+    }
+  }
+}
+
+/// The order in which the transports are attempted. OpenConnect always establishes the TLS tunnel
+/// first, then the UDP transport if it comes first in the order, the traffic falls back to TLS if
+/// the UDP transport fails and `tls` follows it, otherwise the tunnel is disconnected. The UDP
+/// transport of GlobalProtect is ESP, `dtls` and `esp` select the same one.
+///
+/// Only the orders that make a difference are accepted: `esp,tls` (the default), `esp` and `tls`,
+/// with `dtls` in place of `esp`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransportOrder(Vec<Transport>);
+
+impl Default for TransportOrder {
+  fn default() -> Self {
+    Self(vec![Transport::Esp, Transport::Tls])
+  }
+}
+
+impl TransportOrder {
+  pub fn transports(&self) -> &[Transport] {
+    &self.0
+  }
+
+  /// Whether the UDP transport is attempted, i.e., it comes before TLS
+  pub fn uses_udp(&self) -> bool {
+    self.0.first().is_some_and(|t| t.is_udp())
+  }
+
+  /// Whether the traffic falls back to TLS when the UDP transport is not established
+  pub fn falls_back_to_tls(&self) -> bool {
+    self.0.contains(&Transport::Tls)
+  }
+}
+
+impl FromStr for TransportOrder {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut transports: Vec<Transport> = Vec::new();
+
+    for item in s.split(',').filter(|item| !item.trim().is_empty()) {
+      let transport = item.parse::<Transport>()?;
+      if transports.contains(&transport) {
+        return Err(format!("Duplicate transport: {}", transport));
+      }
+      if transports.contains(&Transport::Tls) {
+        return Err(format!("The transport {} after tls is never attempted", transport));
+      }
+      if transport.is_udp() && transports.iter().any(|t| t.is_udp()) {
+        return Err(String::from(
+          "dtls and esp select the same transport, the ESP of GlobalProtect, specify only one of them",
+        ));
+      }
+      transports.push(transport);
+    }
+
+    if transports.is_empty() {
+      return Err(String::from("The transport order cannot be empty"));
+    }
+
+    Ok(Self(transports))
+  }
+}
+
+impl fmt::Display for Transport {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+impl fmt::Display for TransportOrder {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let order = self.0.iter().map(|t| t.as_str()).collect::<Vec<_>>().join(",");
+    write!(f, "{}", order)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_transport_order() {
+    let order: TransportOrder = "esp,tls".parse().unwrap();
+    assert_eq!(order, TransportOrder::default());
+    assert!(order.uses_udp() && order.falls_back_to_tls());
+
+    let order: TransportOrder = " DTLS ".parse().unwrap();
+    assert_eq!(order.transports(), &[Transport::Dtls]);
+    assert!(order.uses_udp() && !order.falls_back_to_tls());
+    assert_eq!(order.to_string(), "dtls");
+
+    let order: TransportOrder = "tls".parse().unwrap();
+    assert!(!order.uses_udp() && order.falls_back_to_tls());
+  }
+
+  #[test]
+  fn rejects_unknown_transport() {
+    let err = "esp,quic".parse::<TransportOrder>().unwrap_err();
+    assert!(err.contains("Unknown transport: quic"));
+  }
+
+  #[test]
+  fn rejects_duplicate_and_empty_order() {
+    assert!(
+      "esp,tls,esp"
+        .parse::<TransportOrder>()
+        .unwrap_err()
+        .contains("Duplicate")
+    );
+    assert!(",".parse::<TransportOrder>().is_err());
+  }
+
+  #[test]
+  fn rejects_order_without_effect() {
+    // Both select ESP
+    assert!(
+      "dtls,esp,tls"
+        .parse::<TransportOrder>()
+        .unwrap_err()
+        .contains("same transport")
+    );
+    // Nothing is attempted after TLS
+    assert!(
+      "tls,esp"
+        .parse::<TransportOrder>()
+        .unwrap_err()
+        .contains("never attempted")
+    );
+  }
+}
@@ -0,0 +1,156 @@
+use std::{
+  fmt,
+  net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+/// The tunnel settings pushed by the gateway, available once connected
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TunnelInfo {
+  /// The name of the tun device, e.g., `tun0`
+  pub interface: Option<String>,
+  pub ipv4: Option<Ipv4Addr>,
+  pub netmask: Option<Ipv4Addr>,
+  pub ipv6: Option<Ipv6Addr>,
+  /// The IPv6 address with the prefix length, e.g., `fd00::2/64`
+  pub netmask6: Option<String>,
+  pub dns_servers: Vec<IpAddr>,
+  pub search_domains: Vec<String>,
+  /// The split-tunnel routes, e.g., `10.0.0.0/255.0.0.0`, empty if all the traffic is tunneled
+  pub split_includes: Vec<String>,
+  pub split_excludes: Vec<String>,
+  pub mtu: Option<u32>,
+}
+
+impl TunnelInfo {
+  /// Parse the `key=value` lines saved by the FFI, the unknown keys and invalid values are ignored
+  pub(crate) fn parse(payload: &str) -> Self {
+    let mut info = Self::default();
+
+    for (key, value) in payload.lines().filter_map(|line| line.split_once('=')) {
+      let value = value.trim();
+      match key.trim() {
+        "ifname" => info.interface = Some(value.to_string()),
+        "addr" => info.ipv4 = value.parse().ok(),
+        "netmask" => info.netmask = value.parse().ok(),
+        "addr6" => info.ipv6 = value.parse().ok(),
+        "netmask6" => info.netmask6 = Some(value.to_string()),
+        "dns" => info.dns_servers.extend(value.parse::<IpAddr>().ok()),
+        "domain" => info.search_domains.extend(value.split_whitespace().map(String::from)),
+        "include" => info.split_includes.push(value.to_string()),
+        "exclude" => info.split_excludes.push(value.to_string()),
+        "mtu" => info.mtu = value.parse().ok(),
+        _ => {}
+      }
+    }
+
+    info
+  }
+
+  /// The IPv4 and IPv6 addresses assigned to the tunnel
+  pub fn ip_addresses(&self) -> Vec<IpAddr> {
+    let ipv4 = self.ipv4.map(IpAddr::V4);
+    let ipv6 = self.ipv6.map(IpAddr::V6);
+
+    ipv4.into_iter().chain(ipv6).collect()
+  }
+}
+
+impl fmt::Display for TunnelInfo {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let join = |items: Vec<String>| {
+      if items.is_empty() {
+        String::from("<none>")
+      } else {
+        items.join(",")
+      }
+    };
+    let to_strings = |items: &[IpAddr]| items.iter().map(|ip| ip.to_string()).collect();
+
+    let ipv4 = match (self.ipv4, self.netmask) {
+      (Some(ip), Some(netmask)) => format!("{}/{}", ip, netmask),
+      (Some(ip), None) => ip.to_string(),
+      _ => String::from("<none>"),
+    };
+    let ipv6 = match (&self.netmask6, self.ipv6) {
+      (Some(netmask6), _) => netmask6.clone(),
+      (None, Some(ip)) => ip.to_string(),
+      _ => String::from("<none>"),
+    };
+    let routes = if self.split_includes.is_empty() {
+      String::from("all")
+    } else {
+      self.split_includes.join(",")
+    };
+
+    write!(
+      f,
+      "ipv4={}, ipv6={}, dns={}, search={}, routes={}, excluded={}, mtu={}",
+      ipv4,
+      ipv6,
+      join(to_strings(&self.dns_servers)),
+      join(self.search_domains.clone()),
+      routes,
+      join(self.split_excludes.clone()),
+      self.mtu.map_or(String::from("<none>"), |mtu| mtu.to_string())
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_the_tunnel_info() {
+    let payload = "ifname=tun0\n\
+                   addr=10.0.0.2\n\
+                   netmask=255.255.255.0\n\
+                   addr6=fd00::2\n\
+                   netmask6=fd00::2/64\n\
+                   dns=10.0.0.53\n\
+                   dns=fd00::53\n\
+                   domain=corp.example.com lab.example.net\n\
+                   include=10.0.0.0/255.0.0.0\n\
+                   include=fd00::/64\n\
+                   exclude=10.1.0.0/255.255.0.0\n\
+                   mtu=1400\n";
+
+    let info = TunnelInfo::parse(payload);
+    assert_eq!(info.interface.as_deref(), Some("tun0"));
+    assert_eq!(info.ipv4, Some(Ipv4Addr::new(10, 0, 0, 2)));
+    assert_eq!(info.netmask, Some(Ipv4Addr::new(255, 255, 255, 0)));
+    assert_eq!(info.ipv6, Some("fd00::2".parse().unwrap()));
+    assert_eq!(info.netmask6.as_deref(), Some("fd00::2/64"));
+    assert_eq!(
+      info.dns_servers,
+      ["10.0.0.53".parse::<IpAddr>().unwrap(), "fd00::53".parse().unwrap()]
+    );
+    assert_eq!(info.search_domains, ["corp.example.com", "lab.example.net"]);
+    assert_eq!(info.split_includes, ["10.0.0.0/255.0.0.0", "fd00::/64"]);
+    assert_eq!(info.split_excludes, ["10.1.0.0/255.255.0.0"]);
+    assert_eq!(info.mtu, Some(1400));
+    assert_eq!(info.ip_addresses().len(), 2);
+
+    assert_eq!(
+      info.to_string(),
+      "ipv4=10.0.0.2/255.255.255.0, ipv6=fd00::2/64, dns=10.0.0.53,fd00::53, \
+       search=corp.example.com,lab.example.net, routes=10.0.0.0/255.0.0.0,fd00::/64, \
+       excluded=10.1.0.0/255.255.0.0, mtu=1400"
+    );
+  }
+
+  #[test]
+  fn ignores_invalid_and_missing_values() {
+    let info = TunnelInfo::parse("addr=not-an-ip\ndns=10.0.0.53\nunknown=value\nno separator\nmtu=auto\n");
+    assert_eq!(info.ipv4, None);
+    assert_eq!(info.dns_servers.len(), 1);
+    assert_eq!(info.mtu, None);
+
+    let info = TunnelInfo::parse("");
+    assert_eq!(info, TunnelInfo::default());
+    assert_eq!(
+      info.to_string(),
+      "ipv4=<none>, ipv6=<none>, dns=<none>, search=<none>, routes=all, excluded=<none>, mtu=<none>"
+    );
+  }
+}
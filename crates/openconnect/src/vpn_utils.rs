@@ -2,6 +2,8 @@ use std::{env, io, path::Path};
 
 use is_executable::IsExecutable;
 
+use crate::ffi;
+
 const VPNC_SCRIPT_LOCATIONS: &[&str] = &[
   "/usr/local/share/vpnc-scripts/vpnc-script",
   "/usr/local/sbin/vpnc-script",
@@ -26,26 +28,12 @@ const CSD_WRAPPER_LOCATIONS: &[&str] = &[
   "/opt/homebrew/opt/openconnect/libexec/openconnect/hipreport.sh",
 ];
 
-fn find_executable(locations: &[&'static str]) -> Option<&'static str> {
-  for location in locations.iter() {
-    let path = Path::new(location);
-    if path.is_executable() {
-      return Some(*location);
-    }
-  }
-
-  None
-}
-
-pub fn find_vpnc_script() -> Option<&'static str> {
-  find_executable(&VPNC_SCRIPT_LOCATIONS)
-}
-
 fn is_executable_file(path: &str) -> bool {
   Path::new(path).is_executable()
 }
 
-fn resolve_csd_wrapper(override_path: Option<&str>, locations: &[&str]) -> Option<String> {
+/// The override path if it is executable, otherwise the first executable of the locations
+fn resolve_executable(override_path: Option<&str>, locations: &[&str]) -> Option<String> {
   if let Some(path) = override_path.filter(|p| !p.is_empty()) {
     if is_executable_file(path) {
       return Some(path.to_string());
@@ -58,9 +46,49 @@ fn resolve_csd_wrapper(override_path: Option<&str>, locations: &[&str]) -> Optio
     .map(|path| (*path).to_string())
 }
 
+pub fn find_vpnc_script() -> Option<String> {
+  let override_path = env::var("GPCLIENT_VPNC_SCRIPT").ok();
+  resolve_executable(override_path.as_deref(), VPNC_SCRIPT_LOCATIONS)
+}
+
 pub fn find_csd_wrapper() -> Option<String> {
   let override_path = env::var("GPCLIENT_HIP_WRAPPER").ok();
-  resolve_csd_wrapper(override_path.as_deref(), CSD_WRAPPER_LOCATIONS)
+  resolve_executable(override_path.as_deref(), CSD_WRAPPER_LOCATIONS)
+}
+
+/// The version of the libopenconnect linked at runtime, which may differ from the one built against
+pub fn linked_openconnect_version() -> Option<String> {
+  ffi::lib_version()
+}
+
+/// The maximum interface name length on Linux, i.e., IFNAMSIZ - 1
+const MAX_IFNAME_LEN: usize = 15;
+
+/// The name is passed to the kernel as is, which rejects the names it cannot use as a path component
+pub fn validate_interface_name(name: &str) -> Result<(), String> {
+  if name.is_empty() || name.len() > MAX_IFNAME_LEN {
+    return Err(format!("{} must be 1 to {} characters long", name, MAX_IFNAME_LEN));
+  }
+
+  let invalid_char = |c: char| c == '/' || c == ':' || c.is_whitespace() || c.is_control();
+  if name == "." || name == ".." || name.chars().any(invalid_char) {
+    return Err(format!("{} is not a valid interface name", name));
+  }
+
+  Ok(())
+}
+
+/// A domain name of the labels of letters, digits and hyphens, the trailing dot is allowed. The name is
+/// safe in the shell command, e.g., of the vpnc-script, and cannot be taken as an option.
+pub fn is_valid_domain_name(name: &str) -> bool {
+  let name = name.strip_suffix('.').unwrap_or(name);
+  !name.is_empty()
+    && name.split('.').all(|label| {
+      !label.is_empty()
+        && !label.starts_with('-')
+        && !label.ends_with('-')
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    })
 }
 
 /// If file exists, check if it is executable
@@ -83,11 +111,46 @@ mod tests {
     fs::{self, OpenOptions},
     os::unix::fs::PermissionsExt,
     path::{Path, PathBuf},
+    sync::Mutex,
     time::{SystemTime, UNIX_EPOCH},
   };
 
   use super::*;
 
+  /// Serializes the tests changing the override variables, the environment is shared by the test threads
+  static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+  /// Look up with the variable set to an executable, then to a non-executable file
+  fn assert_env_override(var: &str, find: fn() -> Option<String>) {
+    let _guard = ENV_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    let executable = unique_path("env-override-exec");
+    let non_executable = unique_path("env-override-noexec");
+    create_executable(&executable);
+    create_non_executable(&non_executable);
+
+    unsafe { env::set_var(var, &executable) };
+    let resolved = find();
+    unsafe { env::set_var(var, &non_executable) };
+    let ignored = find();
+    unsafe { env::remove_var(var) };
+
+    assert_eq!(resolved.as_deref(), executable.to_str());
+    assert_ne!(ignored.as_deref(), non_executable.to_str());
+
+    let _ = fs::remove_file(executable);
+    let _ = fs::remove_file(non_executable);
+  }
+
+  #[test]
+  fn vpnc_script_is_overridden_by_the_environment() {
+    assert_env_override("GPCLIENT_VPNC_SCRIPT", find_vpnc_script);
+  }
+
+  #[test]
+  fn csd_wrapper_is_overridden_by_the_environment() {
+    assert_env_override("GPCLIENT_HIP_WRAPPER", find_csd_wrapper);
+  }
+
   fn create_executable(path: &Path) {
     OpenOptions::new()
       .create(true)
@@ -124,7 +187,7 @@ mod tests {
     create_executable(&override_path);
     create_executable(&fallback_path);
 
-    let resolved = resolve_csd_wrapper(override_path.to_str(), &[fallback_path.to_str().unwrap()]);
+    let resolved = resolve_executable(override_path.to_str(), &[fallback_path.to_str().unwrap()]);
     assert_eq!(resolved.as_deref(), override_path.to_str());
 
     let _ = fs::remove_file(override_path);
@@ -138,13 +201,64 @@ mod tests {
     create_non_executable(&override_path);
     create_executable(&fallback_path);
 
-    let resolved = resolve_csd_wrapper(override_path.to_str(), &[fallback_path.to_str().unwrap()]);
+    let resolved = resolve_executable(override_path.to_str(), &[fallback_path.to_str().unwrap()]);
+    assert_eq!(resolved.as_deref(), fallback_path.to_str());
+
+    let _ = fs::remove_file(override_path);
+    let _ = fs::remove_file(fallback_path);
+  }
+
+  #[test]
+  fn resolve_vpnc_script_prefers_override_when_executable() {
+    let override_path = unique_path("vpnc-override");
+    let fallback_path = unique_path("vpnc-fallback");
+    create_executable(&override_path);
+    create_executable(&fallback_path);
+
+    let resolved = resolve_executable(override_path.to_str(), &[fallback_path.to_str().unwrap()]);
+    assert_eq!(resolved.as_deref(), override_path.to_str());
+
+    let _ = fs::remove_file(override_path);
+    let _ = fs::remove_file(fallback_path);
+  }
+
+  #[test]
+  fn resolve_vpnc_script_uses_fallback_when_override_not_executable() {
+    let override_path = unique_path("vpnc-override-noexec");
+    let missing_path = unique_path("vpnc-override-missing");
+    let fallback_path = unique_path("vpnc-fallback-exec");
+    create_non_executable(&override_path);
+    create_executable(&fallback_path);
+
+    let locations = [fallback_path.to_str().unwrap()];
+    let resolved = resolve_executable(override_path.to_str(), &locations);
+    assert_eq!(resolved.as_deref(), fallback_path.to_str());
+    let resolved = resolve_executable(missing_path.to_str(), &locations);
+    assert_eq!(resolved.as_deref(), fallback_path.to_str());
+    let resolved = resolve_executable(Some(""), &locations);
     assert_eq!(resolved.as_deref(), fallback_path.to_str());
 
     let _ = fs::remove_file(override_path);
     let _ = fs::remove_file(fallback_path);
   }
 
+  #[test]
+  fn rejects_too_long_interface_name() {
+    assert!(validate_interface_name("a-very-long-ifname").is_err());
+    assert!(validate_interface_name("").is_err());
+    assert!(validate_interface_name("tun0").is_ok());
+  }
+
+  #[test]
+  fn domain_names_are_validated() {
+    for name in ["example.com", "example.com.", "intranet", "gw-1.example.com"] {
+      assert!(is_valid_domain_name(name), "{:?}", name);
+    }
+    for name in ["", ".", ".example.com", "a..b", "-f", "gw-.lab", "$(id).com"] {
+      assert!(!is_valid_domain_name(name), "{:?}", name);
+    }
+  }
+
   #[test]
   fn check_executable_rejects_non_executable_file() {
     let file = unique_path("hip-not-exec");